@@ -0,0 +1,43 @@
+//! 埋め込みの出版社CSV(csv/isbn.csv)から、出版社記号から出版社名を引くphfの表を作る
+//! 壊れた行はビルドのエラーにする
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    let csv_path = "csv/isbn.csv";
+    println!("cargo:rerun-if-changed={}", csv_path);
+    let text = fs::read_to_string(csv_path).unwrap_or_else(|e| panic!("cannot read {}: {}", csv_path, e));
+
+    // CSVにはヘッダー行がなく、出版社名にカンマや引用符は含まれない
+    let mut map = phf_codegen::OrderedMap::new();
+    let mut codes = HashSet::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((code, name)) = line.split_once(',') else {
+            panic!("{}:{}: expected `code,name`: {:?}", csv_path, line_number, line);
+        };
+        if code.is_empty() || code.len() > 7 || !code.chars().all(|c| c.is_ascii_digit()) {
+            panic!("{}:{}: invalid publisher code: {:?}", csv_path, line_number, code);
+        }
+        if name.is_empty() || name.contains(',') || name.contains('"') {
+            panic!("{}:{}: invalid publisher name: {:?}", csv_path, line_number, name);
+        }
+        if !codes.insert(code) {
+            panic!("{}:{}: duplicate publisher code: {}", csv_path, line_number, code);
+        }
+        map.entry(code, &format!("{:?}", name));
+    }
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("publishers.rs");
+    let code = format!(
+        "/// 埋め込みの出版社CSVの出版社記号と出版社名(CSVの順)\nstatic PUBLISHERS: phf::OrderedMap<&'static str, &'static str> = {};\n",
+        map.build()
+    );
+    fs::write(&out, code).unwrap_or_else(|e| panic!("cannot write {}: {}", out.display(), e));
+}
@@ -0,0 +1,209 @@
+//! 設定ファイル、履歴、蔵書のバックアップ
+//! 1つのSQLite Archive(sqlar)にまとめるので、別のマシンに写して戻せる(`sqlite3 -A` でも取り出せる)
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::provider::Result;
+
+/// sqlarの表
+/// dataは圧縮しないで入れる(szとdataの長さが同じ場合は圧縮していないことになる)
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sqlar (
+    name TEXT PRIMARY KEY,
+    mode INT,
+    mtime INT,
+    sz INT,
+    data BLOB
+);
+";
+
+/// 通常のファイルで0644のmode
+const MODE: u32 = 0o100644;
+
+/// アーカイブに入れるファイル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// アーカイブの中の名前
+    pub name: &'static str,
+    /// このマシンでの場所
+    pub path: PathBuf,
+    /// SQLiteのデータベースかどうか(書き込み中でも壊れないように写してから入れる)
+    pub database: bool,
+}
+
+impl Item {
+    pub fn config(path: PathBuf) -> Item {
+        Item { name: "config.toml", path, database: false }
+    }
+
+    pub fn history(path: PathBuf) -> Item {
+        Item { name: "history.sqlite3", path, database: true }
+    }
+
+    pub fn library(path: PathBuf) -> Item {
+        Item { name: "library.sqlite3", path, database: true }
+    }
+}
+
+/// ファイルの中身を読む
+/// データベースはVACUUM INTOで一時ファイルに写してから読む
+fn read(item: &Item) -> Result<Vec<u8>> {
+    if !item.database {
+        return Ok(std::fs::read(&item.path)?);
+    }
+    let copy = std::env::temp_dir().join(format!("isbn-backup-{}-{}", std::process::id(), item.name));
+    let _ = std::fs::remove_file(&copy);
+    let conn = Connection::open(&item.path)?;
+    conn.execute("VACUUM INTO ?1", params![copy.to_string_lossy()])?;
+    let bytes = std::fs::read(&copy);
+    let _ = std::fs::remove_file(&copy);
+    Ok(bytes?)
+}
+
+/// 存在するファイルをアーカイブに書き出し、入れたファイルの名前を返す
+/// 一時ファイルに書き出してから置き換えるので、途中で失敗しても前のアーカイブは残る
+pub fn backup(archive: &Path, items: &[Item]) -> Result<Vec<&'static str>> {
+    let temporary = archive.with_extension("tmp");
+    if temporary.exists() {
+        std::fs::remove_file(&temporary)?;
+    }
+    match write_archive(&temporary, items) {
+        Ok(names) => {
+            std::fs::rename(&temporary, archive)?;
+            Ok(names)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temporary);
+            Err(e)
+        }
+    }
+}
+
+/// 新しいアーカイブのファイルに書き出す
+fn write_archive(archive: &Path, items: &[Item]) -> Result<Vec<&'static str>> {
+    let mut conn = Connection::open(archive)?;
+    conn.execute_batch(SCHEMA)?;
+    let tx = conn.transaction()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let mut names = Vec::new();
+    for item in items.iter().filter(|item| item.path.is_file()) {
+        let data = read(item).map_err(|e| format!("{}: {}", item.path.display(), e))?;
+        tx.execute(
+            "INSERT INTO sqlar (name, mode, mtime, sz, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![item.name, MODE, now, data.len(), data],
+        )?;
+        names.push(item.name);
+    }
+    tx.commit()?;
+    Ok(names)
+}
+
+/// アーカイブのファイルを元の場所に書き戻し、戻したファイルの名前を返す
+/// forceでなければ、すでにあるファイルは上書きしないで、何も書き戻さずにエラーにする
+pub fn restore(archive: &Path, items: &[Item], force: bool) -> Result<Vec<&'static str>> {
+    if !archive.is_file() {
+        return Err(format!("{} not found", archive.display()).into());
+    }
+    let conn = Connection::open(archive)?;
+    let mut files = Vec::new();
+    for item in items {
+        let row = conn
+            .query_row("SELECT sz, data FROM sqlar WHERE name = ?1", params![item.name], |row| {
+                Ok((row.get::<_, usize>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .optional()?;
+        match row {
+            Some((size, data)) if size == data.len() => files.push((item, data)),
+            Some(_) => return Err(format!("{} is compressed in the archive", item.name).into()),
+            None => (),
+        }
+    }
+    if !force {
+        if let Some((item, _)) = files.iter().find(|(item, _)| item.path.exists()) {
+            return Err(format!("{} already exists (use --force to overwrite)", item.path.display()).into());
+        }
+    }
+    for (item, data) in &files {
+        if let Some(dir) = item.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&item.path, data)?;
+    }
+    Ok(files.into_iter().map(|(item, _)| item.name).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("isbn-backup-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = temp_dir("roundtrip");
+        let config = Item::config(dir.join("config.toml"));
+        let history = Item::history(dir.join("history.sqlite3"));
+        let library = Item::library(dir.join("library.sqlite3"));
+        std::fs::write(&config.path, "provider = [\"openbd\"]\n").unwrap();
+        let conn = Connection::open(&history.path).unwrap();
+        conn.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (42);").unwrap();
+        drop(conn);
+        let items = [config.clone(), history.clone(), library];
+        let archive = dir.join("backup.sqlar");
+        assert_eq!(backup(&archive, &items).unwrap(), vec!["config.toml", "history.sqlite3"]);
+
+        let moved = dir.join("moved");
+        let items: Vec<Item> = items.iter().map(|item| Item { path: moved.join(item.name), ..item.clone() }).collect();
+        assert_eq!(restore(&archive, &items, false).unwrap(), vec!["config.toml", "history.sqlite3"]);
+        assert_eq!(std::fs::read_to_string(&items[0].path).unwrap(), "provider = [\"openbd\"]\n");
+        let conn = Connection::open(&items[1].path).unwrap();
+        assert_eq!(conn.query_row("SELECT x FROM t", [], |row| row.get::<_, i64>(0)).unwrap(), 42);
+        assert!(!items[2].path.exists());
+
+        // すでにあるファイルは--forceでなければ上書きしない
+        std::fs::write(&items[0].path, "").unwrap();
+        assert!(restore(&archive, &items, false).unwrap_err().to_string().contains("already exists"));
+        assert_eq!(std::fs::read_to_string(&items[0].path).unwrap(), "");
+        restore(&archive, &items, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&items[0].path).unwrap(), "provider = [\"openbd\"]\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_backup_failure_keeps_archive() {
+        let dir = temp_dir("failure");
+        let config = Item::config(dir.join("config.toml"));
+        std::fs::write(&config.path, "provider = [\"openbd\"]\n").unwrap();
+        let archive = dir.join("backup.sqlar");
+        let items = [config.clone()];
+        backup(&archive, &items).unwrap();
+
+        // データベースとして読めないファイルがあると途中で失敗する
+        let history = Item::history(dir.join("history.sqlite3"));
+        std::fs::write(&history.path, "not a database").unwrap();
+        std::fs::write(&config.path, "").unwrap();
+        assert!(backup(&archive, &[config.clone(), history]).is_err());
+        assert!(!archive.with_extension("tmp").exists());
+
+        // 前のアーカイブがそのまま残っている
+        assert_eq!(restore(&archive, &items, true).unwrap(), vec!["config.toml"]);
+        assert_eq!(std::fs::read_to_string(&config.path).unwrap(), "provider = [\"openbd\"]\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_missing_archive() {
+        let dir = temp_dir("missing");
+        let items = [Item::config(dir.join("config.toml"))];
+        assert!(restore(&dir.join("backup.sqlar"), &items, false).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
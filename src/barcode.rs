@@ -0,0 +1,599 @@
+//! EAN-13のバーコード(ISBNや書籍JANコード)と、価格を表すEAN-5のアドオン
+
+use crate::drawing::{Drawing, Rect, Text, TEXT_HEIGHT};
+use crate::isbn::ParseIsbnError;
+use crate::validate::check;
+
+/// 左側の奇数パリティ(Lパターン)の数字の模様(7モジュール、1が黒)
+/// 右側(Rパターン)はこれを反転したもの、左側の偶数パリティ(Gパターン)はRパターンを左右反転したもの
+const L_PATTERNS: [u8; 10] =
+    [0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011, 0b0110001, 0b0101111, 0b0111011, 0b0110111, 0b0001011];
+
+/// 先頭の数字ごとの、左側6桁のパリティ(1が偶数パリティ、上の桁から)
+const PARITIES: [u8; 10] =
+    [0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110, 0b011010];
+
+/// 左の余白の幅(先頭の数字をここに書く)
+const LEFT_QUIET_ZONE: u32 = 11;
+/// 右の余白の幅
+const RIGHT_QUIET_ZONE: u32 = 7;
+/// EAN-13のバーコード部分の幅
+const SYMBOL_WIDTH: u32 = 95;
+/// バーの高さ
+const BAR_HEIGHT: u32 = 60;
+/// ガードバーはほかのバーより下に伸ばす
+const GUARD_EXTENSION: u32 = 5;
+/// 数字1文字の幅
+const DIGIT_CELL: u32 = 7;
+/// バーコードの上に書く文字(ISBN)の1文字の幅
+const CAPTION_CELL: u32 = 6;
+/// EAN-13とアドオンの間の余白
+const ADDON_GAP: u32 = 9;
+/// アドオンの右の余白
+const ADDON_QUIET_ZONE: u32 = 5;
+/// アドオンの幅(スタートガード5、数字7×5、区切り2×4)
+const ADDON_WIDTH: u32 = 48;
+
+/// EAN-5のチェックサムごとの、5桁のパリティ(1が偶数パリティ、上の桁から)
+const ADDON_PARITIES: [u8; 10] =
+    [0b11000, 0b10100, 0b10010, 0b10001, 0b01100, 0b00110, 0b00011, 0b01010, 0b01001, 0b00101];
+
+/// チェックディジットを確かめたEAN-13のコード
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ean13 {
+    digits: String,
+}
+
+impl Ean13 {
+    /// 13桁のコードを解析する
+    /// ハイフンと空白は無視する
+    pub fn parse(code: &str) -> Result<Self, ParseIsbnError> {
+        let digits: String = code.chars().filter(|c| *c != '-' && *c != ' ').collect();
+        let length = digits.chars().count();
+        if length != 13 {
+            return Err(ParseIsbnError::InvalidLength(length));
+        }
+        check(&digits)?;
+        Ok(Ean13 { digits })
+    }
+
+    /// ハイフンなしの13桁
+    pub fn as_str(&self) -> &str {
+        &self.digits
+    }
+
+    /// 左のガードバーから右のガードバーまでの95モジュールの模様(trueが黒)
+    pub fn modules(&self) -> Vec<bool> {
+        let digits: Vec<usize> = self.digits.bytes().map(|b| usize::from(b - b'0')).collect();
+        let parity = PARITIES[digits[0]];
+        let mut modules = Vec::with_capacity(SYMBOL_WIDTH as usize);
+        push_bits(&mut modules, 0b101, 3);
+        for (i, digit) in digits[1..7].iter().enumerate() {
+            let even = parity & (0b100000 >> i) != 0;
+            let pattern = if even { g_pattern(*digit) } else { L_PATTERNS[*digit] };
+            push_bits(&mut modules, pattern, 7);
+        }
+        push_bits(&mut modules, 0b01010, 5);
+        for digit in &digits[7..] {
+            push_bits(&mut modules, !L_PATTERNS[*digit] & 0b1111111, 7);
+        }
+        push_bits(&mut modules, 0b101, 3);
+        modules
+    }
+
+    /// 余白と、バーの下の数字を含めたバーコードの図形
+    /// captionを指定した場合(「ISBN978-4-...」など)はバーの上に書く
+    pub fn drawing(&self, caption: Option<&str>) -> Drawing {
+        self.drawing_with_addon(caption, None)
+    }
+
+    /// アドオンを指定した場合は、右に並べて描く
+    /// アドオンのバーは数字を上に書く分だけ短くする
+    pub fn drawing_with_addon(&self, caption: Option<&str>, addon: Option<&Ean5>) -> Drawing {
+        let caption_width = caption.map_or(0, |caption| CAPTION_CELL * caption.chars().count() as u32);
+        let width = (LEFT_QUIET_ZONE + SYMBOL_WIDTH + RIGHT_QUIET_ZONE).max(caption_width + 2);
+        // 上に書く文字が長い場合は、その分バーコードを右にずらして中央に置く
+        let left = LEFT_QUIET_ZONE + (width - LEFT_QUIET_ZONE - SYMBOL_WIDTH - RIGHT_QUIET_ZONE) / 2;
+        let top = if caption.is_some() { TEXT_HEIGHT + 3 } else { 2 };
+        let mut drawing = Drawing {
+            width,
+            height: top + BAR_HEIGHT + GUARD_EXTENSION + TEXT_HEIGHT,
+            ..Default::default()
+        };
+        if let Some(caption) = caption {
+            let x = (width - caption_width) / 2;
+            drawing.texts.push(Text { x, y: 1, cell: CAPTION_CELL, text: caption.to_string() });
+        }
+        let guards = [0..3, 45..50, 92..95];
+        for (x, width) in runs(&self.modules()) {
+            let guard = guards.iter().any(|guard| guard.contains(&x));
+            let height = if guard { BAR_HEIGHT + GUARD_EXTENSION } else { BAR_HEIGHT };
+            drawing.bars.push(Rect { x: left + x, y: top, width, height });
+        }
+        let y = top + BAR_HEIGHT + 1;
+        let digit = |x: u32, text: &str| Text { x, y, cell: DIGIT_CELL, text: text.to_string() };
+        drawing.texts.push(digit(left - DIGIT_CELL - 1, &self.digits[..1]));
+        drawing.texts.push(digit(left + 3, &self.digits[1..7]));
+        drawing.texts.push(digit(left + 50, &self.digits[7..]));
+        if let Some(addon) = addon {
+            let x = left + SYMBOL_WIDTH + ADDON_GAP;
+            drawing.width = x + ADDON_WIDTH + ADDON_QUIET_ZONE;
+            let addon_top = top + TEXT_HEIGHT + 1;
+            for (offset, width) in runs(&addon.modules()) {
+                let height = BAR_HEIGHT + GUARD_EXTENSION - TEXT_HEIGHT - 1;
+                drawing.bars.push(Rect { x: x + offset, y: addon_top, width, height });
+            }
+            // 数字は区切りを含めた9モジュールごとに、各桁の模様の上に書く
+            drawing.texts.push(Text { x: x + 4, y: top, cell: 9, text: addon.as_str().to_string() });
+        }
+        drawing
+    }
+}
+
+/// 価格などを表すEAN-5のアドオン
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ean5 {
+    digits: String,
+}
+
+impl Ean5 {
+    /// 5桁の数字を解析する
+    pub fn parse(code: &str) -> Result<Self, ParseIsbnError> {
+        if let Some(c) = code.chars().find(|c| !c.is_ascii_digit()) {
+            return Err(ParseIsbnError::InvalidCharacter(c));
+        }
+        let length = code.chars().count();
+        if length != 5 {
+            return Err(ParseIsbnError::InvalidLength(length));
+        }
+        Ok(Ean5 { digits: code.to_string() })
+    }
+
+    /// 5桁の数字
+    pub fn as_str(&self) -> &str {
+        &self.digits
+    }
+
+    /// パリティを決めるチェックサム(奇数桁の合計の3倍と偶数桁の合計の9倍の和の1の位)
+    pub fn checksum(&self) -> u8 {
+        let sum: u32 = self
+            .digits
+            .bytes()
+            .enumerate()
+            .map(|(i, b)| u32::from(b - b'0') * if i % 2 == 0 { 3 } else { 9 })
+            .sum();
+        (sum % 10) as u8
+    }
+
+    /// スタートガードから最後の数字までの48モジュールの模様(trueが黒)
+    pub fn modules(&self) -> Vec<bool> {
+        let parity = ADDON_PARITIES[usize::from(self.checksum())];
+        let mut modules = Vec::with_capacity(ADDON_WIDTH as usize);
+        push_bits(&mut modules, 0b01011, 5);
+        for (i, b) in self.digits.bytes().enumerate() {
+            if i > 0 {
+                push_bits(&mut modules, 0b01, 2);
+            }
+            let digit = usize::from(b - b'0');
+            let even = parity & (0b10000 >> i) != 0;
+            push_bits(&mut modules, if even { g_pattern(digit) } else { L_PATTERNS[digit] }, 7);
+        }
+        modules
+    }
+
+    /// 書籍の価格としての意味(「USD 24.95」など)
+    /// 1桁目が通貨を表し、90000(価格なし)や通貨の分からないものはNoneを返す
+    pub fn price(&self) -> Option<String> {
+        let currency = match self.digits.as_bytes()[0] {
+            b'0' | b'1' => "GBP",
+            b'3' => "AUD",
+            b'4' => "NZD",
+            b'5' => "USD",
+            b'6' => "CAD",
+            _ => return None,
+        };
+        let amount: u32 = self.digits[1..].parse().ok()?;
+        Some(format!("{} {}.{:02}", currency, amount / 100, amount % 100))
+    }
+}
+
+/// 画像を読み取るときに試す行(列)の数
+const SCAN_LINES: usize = 64;
+
+/// 照明のむらがある行を読むときに、周りの明るさを比べる範囲(行の長さをこの数で割った画素数ずつに区切る)
+/// バーコードが行に占める幅は画像によって違うので、いくつかの大きさを試す
+const LOCAL_WINDOW_DIVISORS: [usize; 3] = [16, 32, 64];
+
+/// 照明のむらがある行で、周りの明暗の差がこれより小さい画素は白とする(余白の白がノイズで黒にならないようにする)
+const LOCAL_CONTRAST: u8 = 32;
+
+/// 読み取るときの、1桁の模様の幅のずれの許容範囲(4本のバーとスペースのずれの合計、単位はモジュール幅)
+const DECODE_TOLERANCE: f64 = 1.5;
+
+impl Ean13 {
+    /// 画像の1行の明るさ(0が黒)からEAN-13を読み取る
+    /// 行の中の最も暗い画素と最も明るい画素の中間を境に白黒に分ける
+    /// 読めなければ、照明のむらで明るさが場所によって違うものとして、周りの画素の明るさで白黒に分ける
+    pub fn decode_luma(row: &[u8]) -> Option<Self> {
+        let (min, max) = row.iter().fold((u8::MAX, 0), |(min, max), value| (min.min(*value), max.max(*value)));
+        // 明暗の差がない行(余白など)は読まない
+        if max.saturating_sub(min) < 32 {
+            return None;
+        }
+        let threshold = (u16::from(min) + u16::from(max)) / 2;
+        let binarized: Vec<bool> = row.iter().map(|value| u16::from(*value) < threshold).collect();
+        Self::decode_row(&binarized).or_else(|| {
+            LOCAL_WINDOW_DIVISORS
+                .iter()
+                .find_map(|divisor| Self::decode_row(&binarize_local(row, (row.len() / divisor).max(2))))
+        })
+    }
+
+    /// 白黒に分けた1行の画素(trueが黒)からEAN-13を読み取る
+    /// チェックディジットが合うものだけを返し、左右が逆の場合(画像が逆さま)も読む
+    pub fn decode_row(row: &[bool]) -> Option<Self> {
+        let reversed: Vec<bool> = row.iter().rev().copied().collect();
+        let decoded = [row, reversed.as_slice()].into_iter().find_map(|row| {
+            let runs = run_lengths(row);
+            // 先頭が黒のバーから、ガードバーを含む59本のバーとスペースを試す
+            (0..runs.len().saturating_sub(58)).step_by(2).find_map(|start| decode_runs(&runs[start..start + 59]))
+        });
+        decoded
+    }
+
+    /// グレースケールの画像(1行ずつ左上から、0が黒)からEAN-13を読み取る
+    /// 中央の行から上下に向かって横に読み、読めなければ縦に(90度回転した画像として)読む
+    pub fn decode_image(width: usize, height: usize, luma: &[u8]) -> Option<Self> {
+        Self::decode_image_by(width, height, luma, |_| true)
+    }
+
+    /// 画像から、acceptが真になるEAN-13を読み取る
+    /// 書籍JANコードの2段目を飛ばして1段目のISBNを読む場合などに使う
+    pub fn decode_image_by(width: usize, height: usize, luma: &[u8], accept: impl Fn(&Self) -> bool) -> Option<Self> {
+        if width == 0 || height == 0 || luma.len() < width * height {
+            return None;
+        }
+        let decode = |line: &[u8]| Self::decode_luma(line).filter(&accept);
+        scan_lines(height).into_iter().find_map(|y| decode(&luma[y * width..(y + 1) * width])).or_else(|| {
+            scan_lines(width).into_iter().find_map(|x| {
+                let column: Vec<u8> = (0..height).map(|y| luma[y * width + x]).collect();
+                decode(&column)
+            })
+        })
+    }
+}
+
+/// 画像を読み取るときに試す行(列)の位置
+/// 中央から始めて、上下(左右)に交互に広げる
+fn scan_lines(length: usize) -> Vec<usize> {
+    let step = (length / SCAN_LINES).max(1);
+    let center = length / 2;
+    let mut lines = vec![center];
+    for offset in (step..=center).step_by(step) {
+        lines.extend([center + offset, center - offset].into_iter().filter(|line| *line < length));
+    }
+    lines
+}
+
+/// 周りの最も暗い画素と最も明るい画素の中間より暗い画素を黒とする
+/// 行をblock_size画素ずつに区切り、画素のある区切りと両隣の区切りを周りとする
+fn binarize_local(row: &[u8], block_size: usize) -> Vec<bool> {
+    let blocks: Vec<(u8, u8)> = row
+        .chunks(block_size)
+        .map(|block| block.iter().fold((u8::MAX, 0), |(min, max), value| (min.min(*value), max.max(*value))))
+        .collect();
+    row.iter()
+        .enumerate()
+        .map(|(x, value)| {
+            let block = x / block_size;
+            let (min, max) = blocks[block.saturating_sub(1)..(block + 2).min(blocks.len())]
+                .iter()
+                .fold((u8::MAX, 0), |(min, max), (low, high)| (min.min(*low), max.max(*high)));
+            // 周りに明暗の差がない画素(余白など)は白とする
+            max.saturating_sub(min) >= LOCAL_CONTRAST && u16::from(*value) * 2 < u16::from(min) + u16::from(max)
+        })
+        .collect()
+}
+
+/// 白黒が続く長さ(最初の黒から)
+fn run_lengths(row: &[bool]) -> Vec<u32> {
+    let Some(first) = row.iter().position(|dark| *dark) else {
+        return Vec::new();
+    };
+    let mut runs = Vec::new();
+    let mut length = 0;
+    let mut color = true;
+    for dark in &row[first..] {
+        if *dark == color {
+            length += 1;
+        } else {
+            runs.push(length);
+            length = 1;
+            color = *dark;
+        }
+    }
+    runs.push(length);
+    runs
+}
+
+/// 7ビットの模様の、白黒が続く長さ(上の桁から4つ)
+fn pattern_widths(bits: u8) -> [f64; 4] {
+    let mut widths = [0.0; 4];
+    let mut index = 0;
+    let mut previous = bits & 0b1000000 != 0;
+    for i in (0..7).rev() {
+        let bit = bits & (1 << i) != 0;
+        if bit != previous {
+            index += 1;
+            previous = bit;
+        }
+        widths[index.min(3)] += 1.0;
+    }
+    widths
+}
+
+/// 4つの幅を1桁の7モジュールにそろえて、最も近い模様の数字と、偶数パリティかどうかを返す
+/// 左側はLパターンとGパターン、右側はRパターン(Lパターンと同じ幅)と比べる
+fn decode_digit(runs: &[u32], left: bool) -> Option<(u8, bool)> {
+    let total: u32 = runs.iter().sum();
+    let scale = 7.0 / f64::from(total);
+    let widths: Vec<f64> = runs.iter().map(|run| f64::from(*run) * scale).collect();
+    let distance =
+        |pattern: [f64; 4]| widths.iter().zip(pattern).map(|(width, expected)| (width - expected).abs()).sum();
+    let mut candidates: Vec<(f64, u8, bool)> = Vec::new();
+    for digit in 0..10u8 {
+        candidates.push((distance(pattern_widths(L_PATTERNS[usize::from(digit)])), digit, false));
+        if left {
+            candidates.push((distance(pattern_widths(g_pattern(usize::from(digit)))), digit, true));
+        }
+    }
+    let (distance, digit, even) = candidates.into_iter().min_by(|a, b| a.0.total_cmp(&b.0))?;
+    (distance < DECODE_TOLERANCE).then_some((digit, even))
+}
+
+/// ガードバーで始まる59本のバーとスペースを読む
+fn decode_runs(runs: &[u32]) -> Option<Ean13> {
+    // ガードバーの3本はどれも1モジュールに近い幅になる
+    let module = f64::from(runs.iter().sum::<u32>()) / 95.0;
+    let is_guard = |runs: &[u32]| runs.iter().all(|run| (f64::from(*run) / module - 1.0).abs() < 0.7);
+    if !is_guard(&runs[..3]) || !is_guard(&runs[27..32]) || !is_guard(&runs[56..]) {
+        return None;
+    }
+    let mut digits = String::new();
+    let mut parity = 0;
+    for i in 0..6 {
+        let (digit, even) = decode_digit(&runs[3 + i * 4..7 + i * 4], true)?;
+        digits.push(char::from(b'0' + digit));
+        if even {
+            parity |= 0b100000 >> i;
+        }
+    }
+    for i in 0..6 {
+        let (digit, _) = decode_digit(&runs[32 + i * 4..36 + i * 4], false)?;
+        digits.push(char::from(b'0' + digit));
+    }
+    let first = PARITIES.iter().position(|p| *p == parity)?;
+    Ean13::parse(&format!("{}{}", first, digits)).ok()
+}
+
+/// Gパターン(Rパターンを左右反転したもの)
+fn g_pattern(digit: usize) -> u8 {
+    let r = !L_PATTERNS[digit] & 0b1111111;
+    (0..7).fold(0, |g, i| g | (((r >> i) & 1) << (6 - i)))
+}
+
+/// 上の桁からlengthビットを並べる
+fn push_bits(modules: &mut Vec<bool>, bits: u8, length: u32) {
+    for i in (0..length).rev() {
+        modules.push(bits & (1 << i) != 0);
+    }
+}
+
+/// 黒いモジュールが続く部分の、始まりの位置と幅
+pub(crate) fn runs(modules: &[bool]) -> Vec<(u32, u32)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (x, dark) in modules.iter().chain([&false]).enumerate() {
+        match (dark, start) {
+            (true, None) => start = Some(x as u32),
+            (false, Some(begin)) => {
+                runs.push((begin, x as u32 - begin));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_string(modules: &[bool]) -> String {
+        modules.iter().map(|dark| if *dark { '1' } else { '0' }).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ean13::parse("978-4-10-101001-4").unwrap().as_str(), "9784101010014");
+        assert!(matches!(Ean13::parse("9784101010015"), Err(ParseIsbnError::InvalidCheckDigit { .. })));
+        assert!(matches!(Ean13::parse("4101010013"), Err(ParseIsbnError::InvalidLength(10))));
+        // ISBN以外のEAN-13(書籍JANコードの2段目)も扱える
+        assert!(Ean13::parse("1920193010008").is_ok());
+    }
+
+    #[test]
+    fn test_g_pattern() {
+        assert_eq!(g_pattern(0), 0b0100111);
+        assert_eq!(g_pattern(9), 0b0010111);
+    }
+
+    #[test]
+    fn test_modules() {
+        let modules = Ean13::parse("4901234567894").unwrap().modules();
+        assert_eq!(modules.len(), 95);
+        let modules = to_string(&modules);
+        // 先頭の4で左側のパリティはLGLLGGになる(9はL、0はG、1はLの模様)
+        assert_eq!(&modules[..3], "101");
+        assert_eq!(&modules[3..10], "0001011");
+        assert_eq!(&modules[10..17], "0100111");
+        assert_eq!(&modules[17..24], "0011001");
+        assert_eq!(&modules[45..50], "01010");
+        // 右側の5はRパターン
+        assert_eq!(&modules[50..57], "1001110");
+        assert_eq!(&modules[92..], "101");
+    }
+
+    #[test]
+    fn test_runs() {
+        assert_eq!(runs(&[true, true, false, true]), vec![(0, 2), (3, 1)]);
+        assert_eq!(runs(&[false]), Vec::new());
+    }
+
+    #[test]
+    fn test_pattern_widths() {
+        assert_eq!(pattern_widths(0b0001101), [3.0, 2.0, 1.0, 1.0]);
+        assert_eq!(pattern_widths(0b0100111), [1.0, 1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_decode_row() {
+        let ean = Ean13::parse("9784101010014").unwrap();
+        // 1モジュールを3画素にして、前後に余白を付ける
+        let mut row = vec![false; 20];
+        row.extend(ean.modules().iter().flat_map(|dark| [*dark; 3]));
+        row.extend([false; 20]);
+        assert_eq!(Ean13::decode_row(&row), Some(ean.clone()));
+        row.reverse();
+        assert_eq!(Ean13::decode_row(&row), Some(ean.clone()));
+        // 明るさの並びからも読める
+        let luma: Vec<u8> = row.iter().map(|dark| if *dark { 30 } else { 220 }).collect();
+        assert_eq!(Ean13::decode_luma(&luma), Some(ean));
+        assert_eq!(Ean13::decode_row(&[false; 100]), None);
+        assert_eq!(Ean13::decode_luma(&[128; 100]), None);
+    }
+
+    #[test]
+    fn test_scan_lines() {
+        assert_eq!(scan_lines(5), vec![2, 3, 1, 4, 0]);
+        assert_eq!(scan_lines(256)[..3], [128, 132, 124]);
+        assert_eq!(scan_lines(1), vec![0]);
+    }
+
+    #[test]
+    fn test_decode_image() {
+        let ean = Ean13::parse("9784101010014").unwrap();
+        let bitmap = ean.drawing(Some("ISBN978-4-10-101001-4")).to_bitmap(2);
+        let luma: Vec<u8> = bitmap.rows().flatten().map(|dark| if *dark { 0 } else { 255 }).collect();
+        let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+        assert_eq!(Ean13::decode_image(width, height, &luma), Some(ean.clone()));
+        // 90度回転した画像
+        let rotated: Vec<u8> =
+            (0..width).flat_map(|x| (0..height).map(move |y| (x, y))).map(|(x, y)| luma[y * width + x]).collect();
+        assert_eq!(Ean13::decode_image(height, width, &rotated), Some(ean));
+        assert_eq!(Ean13::decode_image(width, height, &vec![255; width * height]), None);
+        assert_eq!(Ean13::decode_image_by(width, height, &luma, |ean| !ean.as_str().starts_with("978")), None);
+    }
+
+    /// 写真に写したようなグレースケールの画像を作る
+    /// 1モジュールをscale画素(小数でもよい)にして画素ごとに黒の面積で中間の明るさにし、sigma画素の幅でぼかす
+    /// 明るさは左端をlight倍、右端を1倍として、その間で変わる
+    fn photo(ean: &Ean13, scale: f64, sigma: f64, light: f64) -> (usize, usize, Vec<u8>) {
+        let mut modules = vec![false; 11];
+        modules.extend(ean.modules());
+        modules.extend([false; 11]);
+        let width = (modules.len() as f64 * scale).ceil() as usize;
+        // 画素の範囲に含まれる黒いモジュールの割合
+        let coverage: Vec<f64> = (0..width)
+            .map(|x| {
+                let (left, right) = (x as f64 / scale, (x + 1) as f64 / scale);
+                let covered: f64 = (left.floor() as usize..(right.ceil() as usize).min(modules.len()))
+                    .filter(|i| modules[*i])
+                    .map(|i| right.min(i as f64 + 1.0) - left.max(i as f64))
+                    .sum();
+                covered * scale
+            })
+            .collect();
+        let radius = (sigma * 3.0).ceil() as isize;
+        let kernel: Vec<f64> = (-radius..=radius).map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp()).collect();
+        let total: f64 = kernel.iter().sum();
+        let at = |x: isize| coverage[x.clamp(0, width as isize - 1) as usize];
+        let row: Vec<u8> = (0..width)
+            .map(|x| {
+                let offset = x as isize - radius;
+                let blurred: f64 = kernel.iter().enumerate().map(|(i, k)| k * at(offset + i as isize)).sum();
+                let dark = blurred / total;
+                let illumination = light + (1.0 - light) * x as f64 / (width - 1) as f64;
+                ((20.0 + 215.0 * (1.0 - dark)) * illumination).round() as u8
+            })
+            .collect();
+        let height = 12;
+        (width, height, row.repeat(height))
+    }
+
+    #[test]
+    fn test_decode_photo() {
+        let ean = Ean13::parse("9784101010014").unwrap();
+        // ぼかした画像
+        let (width, height, luma) = photo(&ean, 3.0, 1.2, 1.0);
+        assert_eq!(Ean13::decode_image(width, height, &luma), Some(ean.clone()));
+        // 1モジュールが整数の画素にならない画像
+        for scale in [1.7, 2.37, 3.5, 4.21] {
+            let (width, height, luma) = photo(&ean, scale, 0.6, 1.0);
+            assert_eq!(Ean13::decode_image(width, height, &luma), Some(ean.clone()), "scale {}", scale);
+        }
+        // 片側が暗い画像(行全体の明暗の中間では白黒に分けられない)
+        let (width, height, luma) = photo(&ean, 3.0, 0.6, 0.3);
+        assert_eq!(Ean13::decode_image(width, height, &luma), Some(ean.clone()));
+        // すべてを合わせた画像
+        for (scale, sigma, light) in [(2.63, 1.0, 0.45), (2.2, 1.3, 0.5), (4.21, 1.5, 0.4)] {
+            let (width, height, luma) = photo(&ean, scale, sigma, light);
+            assert_eq!(Ean13::decode_image(width, height, &luma), Some(ean.clone()), "scale {}", scale);
+        }
+    }
+
+    #[test]
+    fn test_drawing() {
+        let ean = Ean13::parse("9784101010014").unwrap();
+        let drawing = ean.drawing(None);
+        assert_eq!(drawing.width, 113);
+        let widths: u32 = drawing.bars.iter().map(|bar| bar.width).sum();
+        assert_eq!(widths as usize, ean.modules().iter().filter(|dark| **dark).count());
+        assert_eq!(drawing.bars[0].x, 11);
+        let texts: Vec<&str> = drawing.texts.iter().map(|text| text.text.as_str()).collect();
+        assert_eq!(texts, vec!["9", "784101", "010014"]);
+        let drawing = ean.drawing(Some("ISBN978-4-10-101001-4"));
+        assert_eq!(drawing.width, 128);
+        assert_eq!(drawing.texts[0].text, "ISBN978-4-10-101001-4");
+    }
+
+    #[test]
+    fn test_ean5() {
+        let addon = Ean5::parse("52495").unwrap();
+        // 5*3 + 2*9 + 4*3 + 9*9 + 5*3 = 141
+        assert_eq!(addon.checksum(), 1);
+        let modules = addon.modules();
+        assert_eq!(modules.len(), 48);
+        // スタートガード、1桁目(5)はGパターン、区切り、2桁目(2)はLパターン
+        let bits: String = modules[..21].iter().map(|dark| if *dark { '1' } else { '0' }).collect();
+        assert_eq!(bits, "010110111001010010011");
+        assert_eq!(addon.price().as_deref(), Some("USD 24.95"));
+        assert_eq!(Ean5::parse("90000").unwrap().price(), None);
+        assert_eq!(Ean5::parse("5249"), Err(ParseIsbnError::InvalidLength(4)));
+        assert_eq!(Ean5::parse("5249x"), Err(ParseIsbnError::InvalidCharacter('x')));
+    }
+
+    #[test]
+    fn test_drawing_with_addon() {
+        let ean = Ean13::parse("9781234567897").unwrap();
+        let addon = Ean5::parse("52495").unwrap();
+        let drawing = ean.drawing_with_addon(None, Some(&addon));
+        assert_eq!(drawing.width, 11 + 95 + 9 + 48 + 5);
+        let addon_bars: Vec<&Rect> = drawing.bars.iter().filter(|bar| bar.x >= 11 + 95 + 9).collect();
+        assert_eq!(addon_bars[0].x, 11 + 95 + 9 + 1);
+        assert!(addon_bars.iter().all(|bar| bar.y > drawing.bars[0].y));
+        assert_eq!(drawing.texts.last().unwrap().text, "52495");
+        assert_eq!(ean.drawing_with_addon(None, None), ean.drawing(None));
+    }
+}
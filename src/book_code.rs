@@ -0,0 +1,180 @@
+//! 日本図書コード(ISBNと分類コード・価格)と、書籍JANコードの2段目のバーコード
+
+use std::fmt;
+
+use crate::barcode::Ean13;
+use crate::c_code::CCode;
+use crate::drawing::Drawing;
+use crate::hyphen::hyphenate;
+use crate::isbn::{check_digit_13, ParseIsbnError};
+
+/// 2段目のバーコードの先頭3桁(価格の種類)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceFlag {
+    /// 192: 本体価格(税抜)
+    Net,
+    /// 191: 税込の価格(以前の表示の本で使われていた)
+    TaxIncluded,
+}
+
+impl PriceFlag {
+    /// 2段目のバーコードの先頭3桁
+    pub fn prefix(self) -> &'static str {
+        match self {
+            PriceFlag::Net => "192",
+            PriceFlag::TaxIncluded => "191",
+        }
+    }
+}
+
+/// 書籍JANコードの2段目(分類コード4桁と価格5桁)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceCode {
+    pub flag: PriceFlag,
+    /// 分類コード(Cコード)
+    pub c_code: CCode,
+    /// 価格(円)
+    pub price: u32,
+}
+
+/// 2段目のバーコードに入る価格の上限(5桁)
+const MAX_PRICE: u32 = 99999;
+
+impl PriceCode {
+    /// 分類コードと本体価格から作る
+    /// 分類コードは「C0193」のように先頭のCがあってもよい
+    pub fn new(c_code: &str, price: u32) -> Result<Self, ParseIsbnError> {
+        let c_code = CCode::parse(c_code)?;
+        if price > MAX_PRICE {
+            return Err(ParseIsbnError::InvalidFormat);
+        }
+        Ok(PriceCode { flag: PriceFlag::Net, c_code, price })
+    }
+
+    /// 2段目のバーコードの13桁を解析する
+    /// チェックディジットが合わない場合と、先頭が191でも192でもない場合はエラーにする
+    pub fn parse(code: &str) -> Result<Self, ParseIsbnError> {
+        Self::from_ean(&Ean13::parse(code)?)
+    }
+
+    /// 読み取ったEAN-13を2段目のバーコードとして解析する
+    /// 分類コードの内容が割り当てられていない場合もエラーにする
+    pub fn from_ean(ean: &Ean13) -> Result<Self, ParseIsbnError> {
+        let digits = ean.as_str();
+        let flag = match &digits[..3] {
+            "192" => PriceFlag::Net,
+            "191" => PriceFlag::TaxIncluded,
+            prefix => return Err(ParseIsbnError::InvalidPrefix(prefix.to_string())),
+        };
+        // 13桁の数字であることは確かめてあるので、価格は必ず解析できる
+        let price = digits[7..12].parse().unwrap_or_default();
+        Ok(PriceCode { flag, c_code: CCode::parse(&digits[3..7])?, price })
+    }
+
+    /// チェックディジットを付けた2段目のバーコードの13桁
+    pub fn ean13(&self) -> Ean13 {
+        let body = format!("{}{}{:05}", self.flag.prefix(), self.c_code.digits(), self.price);
+        let check = check_digit_13(&body);
+        // 12桁の数字にチェックディジットを付けたので、必ず解析できる
+        Ean13::parse(&format!("{}{}", body, check)).expect("price code must be a valid EAN-13")
+    }
+}
+
+impl fmt::Display for PriceCode {
+    /// 本に印刷する表記(「C0193 ¥750E」のように、本体価格には末尾にEを付ける)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let suffix = if self.flag == PriceFlag::Net { "E" } else { "" };
+        write!(f, "{} ¥{}{}", self.c_code, self.price, suffix)
+    }
+}
+
+/// 日本図書コード(ISBNと2段目の分類コード・価格)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookCode {
+    pub isbn: Ean13,
+    pub price: PriceCode,
+}
+
+/// 2段のバーコードの間の余白(単位はモジュール幅)
+const ROW_GAP: u32 = 4;
+
+impl BookCode {
+    /// 1段目のISBNと2段目の分類コード・価格を組にする
+    /// 1段目は978か979で始まるISBNでなければエラーにする
+    pub fn new(isbn: Ean13, price: PriceCode) -> Result<Self, ParseIsbnError> {
+        let prefix = &isbn.as_str()[..3];
+        if prefix != "978" && prefix != "979" {
+            return Err(ParseIsbnError::InvalidPrefix(prefix.to_string()));
+        }
+        Ok(BookCode { isbn, price })
+    }
+
+    /// 上にISBN、下に分類コードと価格のバーコードを並べた図形
+    pub fn drawing(&self) -> Drawing {
+        let caption = hyphenate(self.isbn.as_str()).ok().map(|hyphenated| format!("ISBN{}", hyphenated));
+        let isbn = self.isbn.drawing(caption.as_deref());
+        isbn.stack(self.price.ean13().drawing(Some(&self.price.to_string())), ROW_GAP)
+    }
+}
+
+impl fmt::Display for BookCode {
+    /// 日本図書コードの表記(「ISBN978-4-10-101001-4 C0193 ¥750E」)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let isbn = hyphenate(self.isbn.as_str()).unwrap_or_else(|_| self.isbn.as_str().to_string());
+        write!(f, "ISBN{} {}", isbn, self.price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let code = PriceCode::new("C0193", 750).unwrap();
+        assert_eq!(code.c_code.digits(), "0193");
+        assert_eq!(code.ean13().as_str(), "1920193007503");
+        assert_eq!(code.to_string(), "C0193 ¥750E");
+        assert_eq!(PriceCode::new("0193", 1000).unwrap().ean13().as_str(), "1920193010008");
+        assert_eq!(PriceCode::new("C019", 750), Err(ParseIsbnError::InvalidFormat));
+        assert_eq!(PriceCode::new("C0193", 100000), Err(ParseIsbnError::InvalidFormat));
+        assert_eq!(PriceCode::new("C01a3", 750), Err(ParseIsbnError::InvalidCharacter('a')));
+    }
+
+    #[test]
+    fn test_parse() {
+        let code = PriceCode::parse("1920193007503").unwrap();
+        let c_code = CCode { target: 0, form: 1, content: 93 };
+        assert_eq!(code, PriceCode { flag: PriceFlag::Net, c_code, price: 750 });
+        let code = PriceCode::parse("1910193007504").unwrap();
+        assert_eq!(code.flag, PriceFlag::TaxIncluded);
+        assert_eq!(code.to_string(), "C0193 ¥750");
+        assert_eq!(PriceCode::parse("9784101010014"), Err(ParseIsbnError::InvalidPrefix(String::from("978"))));
+        assert!(PriceCode::parse("1920193007506").is_err());
+        // 分類コードの内容(96)が割り当てられていない
+        assert_eq!(
+            PriceCode::parse("1920196007500"),
+            Err(ParseIsbnError::UnknownClassification(String::from("C0196")))
+        );
+    }
+
+    #[test]
+    fn test_book_code() {
+        let isbn = Ean13::parse("9784101010014").unwrap();
+        let code = BookCode::new(isbn, PriceCode::new("C0193", 750).unwrap()).unwrap();
+        assert_eq!(code.to_string(), "ISBN978-4-10-101001-4 C0193 ¥750E");
+        let drawing = code.drawing();
+        let upper = code.isbn.drawing(Some("ISBN978-4-10-101001-4"));
+        assert_eq!(drawing.height, upper.height * 2 + ROW_GAP);
+        let price = PriceCode::new("C0193", 750).unwrap();
+        assert!(BookCode::new(price.ean13(), price).is_err());
+        // 2段の画像からは、どちらの段も読み取れる
+        let bitmap = drawing.to_bitmap(2);
+        let luma: Vec<u8> = bitmap.rows().flatten().map(|dark| if *dark { 0 } else { 255 }).collect();
+        let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+        let isbn = Ean13::decode_image_by(width, height, &luma, |ean| PriceCode::from_ean(ean).is_err());
+        assert_eq!(isbn, Some(code.isbn.clone()));
+        let price = Ean13::decode_image_by(width, height, &luma, |ean| PriceCode::from_ean(ean).is_ok());
+        assert_eq!(price, Some(code.price.ean13()));
+    }
+}
@@ -0,0 +1,96 @@
+//! ブクログの本棚のエクスポート(CSV)
+//! 本棚にある本のISBNを履歴に記録し、ランダムに本を探すときに持っている本を除く
+
+use isbn::{to_isbn13, verify_check_digit, Validation};
+
+/// 本棚にある本
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShelfBook {
+    pub isbn13: String,
+    pub title: String,
+}
+
+/// ISBNの列の値をハイフンなしのISBN13にする
+/// ISBNでない値(本以外のアイテムのIDなど)はNoneを返す
+fn isbn_13(value: &str) -> Option<String> {
+    let digits: String = value.chars().filter(|c| *c != '-').collect();
+    match (digits.len(), verify_check_digit(&digits)) {
+        (13, Validation::Valid) => Some(digits),
+        (10, Validation::Valid) => to_isbn13(&digits).ok(),
+        _ => None,
+    }
+}
+
+/// エクスポートしたファイルの中身を文字列にする
+/// ブクログのエクスポートはShift_JISなので、UTF-8として読めなければShift_JISとして読む
+pub fn decode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(_) => encoding_rs::SHIFT_JIS.decode(bytes).0.into_owned(),
+    }
+}
+
+/// エクスポートしたCSVから本棚にある本を読む
+/// 列はサービスID、アイテムID、13桁ISBN、カテゴリ、評価、読書状況、レビュー、タグ、読書メモ、登録日時、読了日、タイトルの順
+/// 13桁ISBNが空の場合はアイテムID(本の場合はISBN10)を使う
+/// 本と、ISBNのない行(見出しの行や本以外のアイテム)の数を返す
+pub fn parse(text: &str) -> (Vec<ShelfBook>, usize) {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let mut books = Vec::new();
+    let mut skipped = 0;
+    for record in rdr.records() {
+        let book = record.ok().and_then(|record| {
+            let isbn13 = record.get(2).and_then(isbn_13).or_else(|| record.get(1).and_then(isbn_13))?;
+            let title = record.get(11).unwrap_or_default().to_string();
+            Some(ShelfBook { isbn13, title })
+        });
+        match book {
+            Some(book) => books.push(book),
+            None => skipped += 1,
+        }
+    }
+    (books, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = "\
+\"1\",\"4101010013\",\"9784101010014\",\"小説\",\"5\",\"読み終わった\",\"\",\"漱石,文庫\",\"\",\"2024-05-01 12:34:56\",\"2024-05-10 00:00:00\",\"吾輩は猫である\",\"夏目漱石\",\"新潮社\",\"2003\",\"本・雑誌\",\"610\"
+\"1\",\"4798171549\",\"\",\"技術書\",\"\",\"積読\",\"\",\"\",\"\",\"2024-05-02 00:00:00\",\"\",\"実践Rustプログラミング入門\",\"初田直也\",\"秀和システム\",\"2020\",\"本・雑誌\",\"\"
+\"1\",\"B0123ABCDE\",\"\",\"\",\"\",\"積読\",\"\",\"\",\"\",\"2024-05-03 00:00:00\",\"\",\"電子書籍\",\"\",\"\",\"\",\"Kindle\",\"\"
+";
+
+    #[test]
+    fn test_parse() {
+        let (books, skipped) = parse(EXPORT);
+        assert_eq!(
+            books,
+            vec![
+                ShelfBook { isbn13: String::from("9784101010014"), title: String::from("吾輩は猫である") },
+                ShelfBook { isbn13: String::from("9784798171548"), title: String::from("実践Rustプログラミング入門") },
+            ]
+        );
+        assert_eq!(skipped, 1);
+    }
+
+    #[test]
+    fn test_decode() {
+        let (bytes, _, _) = encoding_rs::SHIFT_JIS.encode(EXPORT);
+        assert_eq!(decode(&bytes), EXPORT);
+        assert_eq!(decode(format!("\u{feff}{}", EXPORT).as_bytes()), EXPORT);
+    }
+
+    #[test]
+    fn test_isbn_13() {
+        assert_eq!(isbn_13("978-4-10-101001-4").as_deref(), Some("9784101010014"));
+        assert_eq!(isbn_13("4101010013").as_deref(), Some("9784101010014"));
+        assert_eq!(isbn_13("4101010012"), None);
+        assert_eq!(isbn_13("B0123ABCDE"), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! 日本図書コードの分類コード(Cコード)
+//! 1桁目が販売対象、2桁目が発行形態、3・4桁目が内容を表す
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::isbn::ParseIsbnError;
+
+/// 1桁目の販売対象
+const TARGETS: [&str; 10] = [
+    "一般",
+    "教養",
+    "実用",
+    "専門",
+    "検定教科書・消費者直接販売・その他",
+    "婦人",
+    "学参I(小中学生対象)",
+    "学参II(高校生対象)",
+    "児童",
+    "雑誌扱い",
+];
+
+/// 2桁目の発行形態
+const FORMS: [&str; 10] =
+    ["単行本", "文庫", "新書", "全集・双書", "ムック・その他", "事・辞典", "図鑑", "絵本", "磁性媒体など", "コミック"];
+
+/// 3・4桁目の内容(割り当てられているものだけ)
+const CONTENTS: &[(u8, &str)] = &[
+    (0, "総記"),
+    (1, "百科事典"),
+    (2, "年鑑・雑誌"),
+    (4, "情報科学"),
+    (10, "哲学"),
+    (11, "心理(学)"),
+    (12, "倫理(学)"),
+    (14, "宗教"),
+    (15, "仏教"),
+    (16, "キリスト教"),
+    (20, "歴史総記"),
+    (21, "日本歴史"),
+    (22, "外国歴史"),
+    (23, "伝記"),
+    (25, "地理"),
+    (26, "旅行"),
+    (30, "社会科学総記"),
+    (31, "政治-含む国防軍事"),
+    (32, "法律"),
+    (33, "経済・財政・統計"),
+    (34, "経営"),
+    (36, "社会"),
+    (37, "教育"),
+    (39, "民族・風習"),
+    (40, "自然科学総記"),
+    (41, "数学"),
+    (42, "物理学"),
+    (43, "化学"),
+    (44, "天文・地学"),
+    (45, "生物学"),
+    (47, "医学・歯学・薬学"),
+    (50, "工学・工業総記"),
+    (51, "土木"),
+    (52, "建築"),
+    (53, "機械"),
+    (54, "電気"),
+    (55, "電子通信"),
+    (56, "海事"),
+    (57, "採鉱・冶金"),
+    (58, "その他の工業"),
+    (60, "産業総記"),
+    (61, "農林業"),
+    (62, "水産業"),
+    (63, "商業"),
+    (65, "交通・通信"),
+    (70, "芸術総記"),
+    (71, "絵画・彫刻"),
+    (72, "写真・工芸"),
+    (73, "音楽・舞踊"),
+    (74, "演劇・映画"),
+    (75, "体育・スポーツ"),
+    (76, "諸芸・娯楽"),
+    (77, "家事"),
+    (79, "コミックス・劇画"),
+    (80, "語学総記"),
+    (81, "日本語"),
+    (82, "英米語"),
+    (84, "ドイツ語"),
+    (85, "フランス語"),
+    (87, "各国語"),
+    (90, "文学総記"),
+    (91, "日本文学総記"),
+    (92, "日本文学詩歌"),
+    (93, "日本文学、小説・物語"),
+    (95, "日本文学、評論、随筆、その他"),
+    (97, "外国文学小説"),
+    (98, "外国文学、その他"),
+];
+
+/// 分類コード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CCode {
+    /// 販売対象(0〜9)
+    pub target: u8,
+    /// 発行形態(0〜9)
+    pub form: u8,
+    /// 内容(00〜99のうち割り当てられているもの)
+    pub content: u8,
+}
+
+impl CCode {
+    /// 販売対象・発行形態・内容から作る
+    /// 範囲の外の数字と、割り当てられていない内容はエラーにする
+    pub fn new(target: u8, form: u8, content: u8) -> Result<Self, ParseIsbnError> {
+        let code = CCode { target, form, content };
+        if target > 9 || form > 9 || content > 99 {
+            return Err(ParseIsbnError::InvalidFormat);
+        }
+        if code.content_name().is_none() {
+            return Err(ParseIsbnError::UnknownClassification(code.to_string()));
+        }
+        Ok(code)
+    }
+
+    /// 「C0193」か「0193」の形式を解析する
+    pub fn parse(code: &str) -> Result<Self, ParseIsbnError> {
+        let digits = code.strip_prefix(['C', 'c']).unwrap_or(code);
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_digit()) {
+            return Err(ParseIsbnError::InvalidCharacter(c));
+        }
+        if digits.len() != 4 {
+            return Err(ParseIsbnError::InvalidFormat);
+        }
+        let digits = digits.as_bytes();
+        let digit = |i: usize| digits[i] - b'0';
+        CCode::new(digit(0), digit(1), digit(2) * 10 + digit(3))
+    }
+
+    /// 販売対象の名前
+    pub fn target_name(&self) -> &'static str {
+        TARGETS[usize::from(self.target)]
+    }
+
+    /// 発行形態の名前
+    pub fn form_name(&self) -> &'static str {
+        FORMS[usize::from(self.form)]
+    }
+
+    /// 内容の名前
+    /// newとparseで作ったものは必ずSomeになる
+    pub fn content_name(&self) -> Option<&'static str> {
+        CONTENTS.iter().find(|(content, _)| *content == self.content).map(|(_, name)| *name)
+    }
+
+    /// 販売対象・発行形態・内容の説明(「一般 / 単行本 / 日本文学、小説・物語」)
+    pub fn describe(&self) -> String {
+        format!("{} / {} / {}", self.target_name(), self.form_name(), self.content_name().unwrap_or("不明"))
+    }
+
+    /// 「C」を除いた4桁の数字
+    pub fn digits(&self) -> String {
+        format!("{}{}{:02}", self.target, self.form, self.content)
+    }
+}
+
+impl fmt::Display for CCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "C{}", self.digits())
+    }
+}
+
+impl FromStr for CCode {
+    type Err = ParseIsbnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CCode::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let code = CCode::parse("C0193").unwrap();
+        assert_eq!(code, CCode { target: 0, form: 1, content: 93 });
+        assert_eq!(code.to_string(), "C0193");
+        assert_eq!(code.digits(), "0193");
+        assert_eq!("8795".parse::<CCode>(), CCode::new(8, 7, 95));
+        assert_eq!(CCode::parse("C019"), Err(ParseIsbnError::InvalidFormat));
+        assert_eq!(CCode::parse("C01x3"), Err(ParseIsbnError::InvalidCharacter('x')));
+        // 96は割り当てられていない
+        assert_eq!(CCode::parse("C0196"), Err(ParseIsbnError::UnknownClassification(String::from("C0196"))));
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(CCode::new(3, 0, 4).unwrap().to_string(), "C3004");
+        assert_eq!(CCode::new(10, 0, 4), Err(ParseIsbnError::InvalidFormat));
+        assert_eq!(CCode::new(0, 0, 100), Err(ParseIsbnError::InvalidFormat));
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(CCode::parse("C0193").unwrap().describe(), "一般 / 文庫 / 日本文学、小説・物語");
+        assert_eq!(CCode::parse("C3055").unwrap().describe(), "専門 / 単行本 / 電子通信");
+        assert_eq!(CCode::parse("C9979").unwrap().form_name(), "コミック");
+    }
+}
@@ -0,0 +1,130 @@
+//! Calibreのライブラリに追加できる、本ごとのmetadata.opfと書影の書き出し
+
+use std::path::{Path, PathBuf};
+
+use crate::cover;
+use crate::export::{author_names, date_parts, escape_xml};
+use crate::output::Discovery;
+use crate::provider::Result;
+use crate::record::BookRecord;
+
+/// OPF 2.0のmetadata.opf
+/// 件名はCalibreのタグになり、シリーズは巻次の番号と合わせてcalibre:seriesのmetaにする
+/// coverは同じディレクトリに置いた書影のファイル名
+pub fn opf(record: &BookRecord, cover: Option<&str>) -> String {
+    let identifier = format!("<dc:identifier opf:scheme=\"ISBN\" id=\"isbn_id\">{}</dc:identifier>", record.isbn);
+    let mut metadata = vec![identifier];
+    metadata.push(format!("<dc:title>{}</dc:title>", escape_xml(&record.title)));
+    for author in author_names(record) {
+        metadata.push(format!("<dc:creator opf:role=\"aut\">{}</dc:creator>", escape_xml(&author)));
+    }
+    if let Some(publisher) = &record.publisher {
+        metadata.push(format!("<dc:publisher>{}</dc:publisher>", escape_xml(publisher)));
+    }
+    // 月や日が分からない場合は1月や1日にする
+    let parts = date_parts(record);
+    if let Some(year) = parts.first() {
+        let part = |i: usize| parts.get(i).copied().unwrap_or(1);
+        metadata.push(format!("<dc:date>{:04}-{:02}-{:02}</dc:date>", year, part(1), part(2)));
+    }
+    if let Some(language) = &record.language {
+        metadata.push(format!("<dc:language>{}</dc:language>", escape_xml(language)));
+    }
+    for subject in &record.subjects {
+        metadata.push(format!("<dc:subject>{}</dc:subject>", escape_xml(subject)));
+    }
+    if let Some(series) = &record.series {
+        metadata.push(format!("<meta name=\"calibre:series\" content=\"{}\"/>", escape_xml(series)));
+        if let Some(number) = record.volume_number() {
+            metadata.push(format!("<meta name=\"calibre:series_index\" content=\"{}\"/>", number));
+        }
+    }
+    let mut opf = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" unique-identifier=\"isbn_id\" version=\"2.0\">\n",
+        "  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n"
+    ));
+    for element in metadata {
+        opf.push_str(&format!("    {}\n", element));
+    }
+    opf.push_str("  </metadata>\n");
+    if let Some(cover) = cover {
+        opf.push_str(&format!(
+            "  <guide>\n    <reference type=\"cover\" title=\"Cover\" href=\"{}\"/>\n  </guide>\n",
+            escape_xml(cover)
+        ));
+    }
+    opf.push_str("</package>\n");
+    opf
+}
+
+/// 見つかった本のmetadata.opfと書影を「ディレクトリ/ISBN13/」に書き出し、書き出したディレクトリを返す
+/// 書影は--save-coverで保存したものがあればそれを写し、なければダウンロードする
+/// 書影がない場合と、ダウンロードできなかった場合はmetadata.opfだけを書き出す
+pub async fn write_book(client: &reqwest::Client, dir: &Path, discovery: &Discovery) -> Result<PathBuf> {
+    let book_dir = dir.join(&discovery.isbn13);
+    std::fs::create_dir_all(&book_dir)?;
+    let cover = match (&discovery.cover_path, &discovery.metadata.cover_url) {
+        (Some(path), _) => {
+            let name = format!("cover.{}", path.extension().and_then(|extension| extension.to_str()).unwrap_or("jpg"));
+            std::fs::copy(path, book_dir.join(&name))?;
+            Some(name)
+        }
+        (None, Some(url)) => match cover::fetch(client, url).await {
+            Ok((bytes, content_type)) => {
+                let name = format!("cover.{}", cover::extension(url, content_type.as_deref()));
+                std::fs::write(book_dir.join(&name), bytes)?;
+                Some(name)
+            }
+            Err(e) => {
+                eprintln!("cannot download the cover {}: {}", url, e);
+                None
+            }
+        },
+        (None, None) => None,
+    };
+    std::fs::write(book_dir.join("metadata.opf"), opf(&discovery.metadata, cover.as_deref()))?;
+    Ok(book_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opf() {
+        let record = BookRecord {
+            isbn: String::from("9784101010014"),
+            title: String::from("吾輩は猫である"),
+            authors: vec![String::from("夏目漱石 著")],
+            publisher: Some(String::from("新潮社")),
+            issued: Some(String::from("2003.6")),
+            series: Some(String::from("新潮文庫")),
+            volume: Some(String::from("上")),
+            subjects: vec![String::from("小説 (日本)")],
+            ..Default::default()
+        };
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="isbn_id" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:identifier opf:scheme="ISBN" id="isbn_id">9784101010014</dc:identifier>
+    <dc:title>吾輩は猫である</dc:title>
+    <dc:creator opf:role="aut">夏目漱石</dc:creator>
+    <dc:publisher>新潮社</dc:publisher>
+    <dc:date>2003-06-01</dc:date>
+    <dc:subject>小説 (日本)</dc:subject>
+    <meta name="calibre:series" content="新潮文庫"/>
+    <meta name="calibre:series_index" content="1"/>
+  </metadata>
+  <guide>
+    <reference type="cover" title="Cover" href="cover.jpg"/>
+  </guide>
+</package>
+"#;
+        assert_eq!(opf(&record, Some("cover.jpg")), expected);
+        let record = BookRecord { title: String::from("A & B"), ..record };
+        let opf = opf(&record, None);
+        assert!(opf.contains("<dc:title>A &amp; B</dc:title>"));
+        assert!(!opf.contains("<guide>"));
+    }
+}
@@ -0,0 +1,100 @@
+//! 長い実行を途中から再開するためのチェックポイント(~/.local/share/isbn/checkpoint.json)
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 中断した時点の探し方と進み具合
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Checkpoint {
+    /// 接頭記号
+    pub prefix: String,
+    /// 国記号
+    pub group: String,
+    /// 出版社記号(出版社を指定して探す場合)
+    pub publisher: Option<String>,
+    /// 出版社の書籍コードを順番にすべて試す
+    pub scan: bool,
+    /// 書籍コードをランダムな順番で試す場合の並べ替えの鍵
+    pub shuffle: Option<u64>,
+    /// 見つける本の冊数
+    pub count: usize,
+    /// 問い合わせを終えた回数
+    /// 同時に問い合わせる場合も、ここまでの問い合わせはすべて終えている
+    pub cursor: usize,
+    /// cursor回目までに並べ終えた書籍コードの数(順番に試す場合は、次の回はこの位置から始める)
+    /// まとめて問い合わせる件数はAPIによって違うので、別のAPIで再開しても同じ位置から続けられるように覚えておく
+    pub offset: usize,
+    /// 試したISBNの数
+    pub attempts: usize,
+    /// 見つかった本のISBN13
+    pub found: Vec<String>,
+}
+
+impl Checkpoint {
+    /// 既定のチェックポイントファイルの場所
+    /// 履歴ファイルと同じディレクトリに置く
+    pub fn default_path() -> Option<PathBuf> {
+        let data_home = match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("share"),
+        };
+        Some(data_home.join("isbn").join("checkpoint.json"))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Checkpoint> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// 書き込み途中で中断しても壊れないように、一時ファイルに書いてから置き換える
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, json)?;
+        fs::rename(&temporary, path)
+    }
+
+    /// 最後まで終えた場合は、次の実行で再開しないように消す
+    pub fn remove(path: &Path) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load() {
+        let path = std::env::temp_dir().join(format!("isbn-checkpoint-{}", std::process::id())).join("checkpoint.json");
+        let checkpoint = Checkpoint {
+            prefix: String::from("978"),
+            group: String::from("4"),
+            publisher: Some(String::from("7981")),
+            scan: true,
+            shuffle: Some(42),
+            count: 3,
+            cursor: 120,
+            offset: 1200,
+            attempts: 120,
+            found: vec![String::from("9784798171548")],
+        };
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), checkpoint);
+
+        Checkpoint::remove(&path).unwrap();
+        assert!(Checkpoint::load(&path).is_err());
+        // 消えていても失敗にしない
+        Checkpoint::remove(&path).unwrap();
+        let _ = fs::remove_dir(path.parent().unwrap());
+    }
+}
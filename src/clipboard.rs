@@ -0,0 +1,81 @@
+//! 見つかった本のURLやISBNをクリップボードにコピーする
+//! OSのクリップボードのコマンド(pbcopy, clip, wl-copy, xclip, xsel)に渡す
+
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use crate::output::Discovery;
+
+/// クリップボードにコピーするもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CopyTarget {
+    /// --linksの最初のサービスのURL
+    Url,
+    /// ハイフンなしのISBN13
+    Isbn,
+}
+
+impl CopyTarget {
+    /// コピーする文字列
+    /// URLがない場合(979始まりのISBNでブクログだけを指定した場合など)はNoneを返す
+    pub fn text(self, discovery: &Discovery) -> Option<String> {
+        match self {
+            CopyTarget::Url => discovery.url().map(String::from),
+            CopyTarget::Isbn => Some(discovery.isbn13.clone()),
+        }
+    }
+}
+
+/// 試すクリップボードのコマンドと引数
+/// Linuxでは、Waylandのwl-copy、X11のxclip、xselの順に試す
+fn commands() -> &'static [(&'static str, &'static [&'static str])] {
+    if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else if cfg!(windows) {
+        &[("clip", &[])]
+    } else {
+        &[("wl-copy", &[]), ("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"])]
+    }
+}
+
+/// 文字列をクリップボードにコピーする
+/// 使えるコマンドがない場合はエラーにする
+pub fn copy(text: &str) -> io::Result<()> {
+    for (program, args) in commands() {
+        let mut child = match Command::new(program).args(*args).stdin(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if status.success() {
+            return Ok(());
+        }
+        return Err(io::Error::other(format!("{} exited with {}", program, status)));
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "no clipboard command found (install wl-copy, xclip or xsel)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::links::{Link, LinkService};
+    use crate::record::BookRecord;
+
+    #[test]
+    fn test_text() {
+        let record = BookRecord { isbn: String::from("9784101010014"), ..Default::default() };
+        let mut discovery = Discovery::new(record, 1);
+        assert_eq!(CopyTarget::Isbn.text(&discovery).as_deref(), Some("9784101010014"));
+        assert_eq!(CopyTarget::Url.text(&discovery).as_deref(), Some("https://booklog.jp/item/1/4101010013"));
+        let amazon = Link { service: LinkService::Amazon, url: String::from("https://www.amazon.co.jp/dp/4101010013") };
+        discovery.links = vec![amazon];
+        assert_eq!(CopyTarget::Url.text(&discovery).as_deref(), Some("https://www.amazon.co.jp/dp/4101010013"));
+        let record = BookRecord { isbn: String::from("9791234567896"), ..Default::default() };
+        assert_eq!(CopyTarget::Url.text(&Discovery::new(record, 1)), None);
+    }
+}
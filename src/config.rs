@@ -0,0 +1,253 @@
+//! 設定ファイル(~/.config/isbn/config.toml)と環境変数(ISBN_*)
+//! コマンドラインで指定しなかった項目の既定値として使い、環境変数は設定ファイルより優先する
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fmt;
+use std::io;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use crate::links::LinkService;
+use crate::output::OutputFormat;
+use crate::{LogLevel, Provider, PublisherWeight};
+
+/// 設定ファイルの内容
+/// すべての項目は省略できる
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 書誌情報の検索に使うAPIの順番
+    pub provider: Option<Vec<Provider>>,
+    /// 問い合わせの間隔(ミリ秒)
+    pub interval: Option<u64>,
+    /// 同時に問い合わせる数
+    pub concurrency: Option<usize>,
+    /// 本が見つからなかった場合に問い合わせるISBNの数の上限
+    pub max_attempts: Option<usize>,
+    /// 失敗したリクエストを再試行する回数
+    pub max_retries: Option<u32>,
+    /// HTTP(S)プロキシのURL
+    pub proxy: Option<String>,
+    /// 接続のタイムアウト(秒)
+    pub connect_timeout: Option<u64>,
+    /// 1回のリクエスト全体のタイムアウト(秒)
+    pub timeout: Option<u64>,
+    /// 試したISBNと見つかった本の履歴ファイル
+    pub history: Option<PathBuf>,
+    /// 蔵書の目録のファイル
+    pub library: Option<PathBuf>,
+    /// 接頭記号(978または979)
+    pub prefix: Option<String>,
+    /// 国記号
+    pub group: Option<String>,
+    /// 出版社一覧のCSV
+    pub publisher_file: Option<PathBuf>,
+    /// 日本の出版社をランダムに選ぶときの重み
+    pub publisher_weight: Option<PublisherWeight>,
+    /// 日本の出版社をランダムに選ぶときに、この出版社記号の出版社だけから選ぶ
+    pub allow_publishers: Option<Vec<String>>,
+    /// 日本の出版社をランダムに選ぶときに、選ばない出版社記号
+    pub block_publishers: Option<Vec<String>>,
+    pub format: Option<OutputFormat>,
+    /// URLを表示するサービス
+    pub links: Option<Vec<LinkService>>,
+    /// ログを出力する詳しさ
+    pub log_level: Option<LogLevel>,
+    pub rakuten_app_id: Option<String>,
+    pub rakuten_affiliate_id: Option<String>,
+    pub calil_app_key: Option<String>,
+    pub calil_systems: Option<Vec<String>>,
+    /// serveで1つのIPアドレスから受け付ける、1分あたりのリクエストの数
+    pub ip_rate_limit: Option<NonZeroU32>,
+    /// serveで1つのIPアドレスから続けて受け付けるリクエストの数
+    pub ip_rate_burst: Option<NonZeroU32>,
+    /// serveですべてのクライアントを通して受け付ける、1分あたりのリクエストの数
+    pub global_rate_limit: Option<NonZeroU32>,
+    /// serveですべてのクライアントを通して続けて受け付けるリクエストの数
+    pub global_rate_burst: Option<NonZeroU32>,
+}
+
+/// 設定ファイルを読み込めなかった理由
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// 環境変数の名前と値
+    Env(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Env(name, value) => write!(f, "{}: invalid value {:?}", name, value),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// 既定の設定ファイルの場所
+    /// XDG_CONFIG_HOMEが設定されていればその下、なければ ~/.config の下
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join("isbn").join("config.toml"))
+    }
+
+    /// 設定ファイルを読み込む
+    /// pathを指定しなかった場合は既定の場所から読み込み、ファイルがなければ空の設定にする
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(ConfigError::Io(path, e)),
+        };
+        Self::parse(&text).map_err(|e| ConfigError::Parse(path, e))
+    }
+
+    /// ISBN_で始まる環境変数の値で上書きする
+    /// ISBN_PROVIDER, ISBN_ALLOW_PUBLISHERS, ISBN_BLOCK_PUBLISHERS, ISBN_LINKS, ISBN_CALIL_SYSTEMSはカンマ区切りで指定する
+    pub fn merge_env(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<Config, ConfigError> {
+        for (name, value) in vars {
+            let invalid = || ConfigError::Env(name.clone(), value.clone());
+            let list = || value.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty());
+            match name.as_str() {
+                "ISBN_PROVIDER" => {
+                    let providers: Result<Vec<Provider>, _> = list().map(|item| Provider::from_str(&item, true)).collect();
+                    self.provider = Some(providers.map_err(|_| invalid())?);
+                }
+                "ISBN_INTERVAL" => self.interval = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_CONCURRENCY" => self.concurrency = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_MAX_ATTEMPTS" => self.max_attempts = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_MAX_RETRIES" => self.max_retries = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_PROXY" => self.proxy = Some(value),
+                "ISBN_CONNECT_TIMEOUT" => self.connect_timeout = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_TIMEOUT" => self.timeout = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_HISTORY" => self.history = Some(PathBuf::from(value)),
+                "ISBN_LIBRARY" => self.library = Some(PathBuf::from(value)),
+                "ISBN_PREFIX" => self.prefix = Some(value),
+                "ISBN_GROUP" => self.group = Some(value),
+                "ISBN_PUBLISHER_FILE" => self.publisher_file = Some(PathBuf::from(value)),
+                "ISBN_PUBLISHER_WEIGHT" => {
+                    self.publisher_weight = Some(PublisherWeight::from_str(&value, true).map_err(|_| invalid())?)
+                }
+                "ISBN_ALLOW_PUBLISHERS" => self.allow_publishers = Some(list().collect()),
+                "ISBN_BLOCK_PUBLISHERS" => self.block_publishers = Some(list().collect()),
+                "ISBN_FORMAT" => self.format = Some(OutputFormat::from_str(&value, true).map_err(|_| invalid())?),
+                "ISBN_LINKS" => {
+                    let links: Result<Vec<LinkService>, _> = list().map(|item| LinkService::from_str(&item, true)).collect();
+                    self.links = Some(links.map_err(|_| invalid())?);
+                }
+                "ISBN_LOG_LEVEL" => self.log_level = Some(LogLevel::from_str(&value, true).map_err(|_| invalid())?),
+                "ISBN_RAKUTEN_APP_ID" => self.rakuten_app_id = Some(value),
+                "ISBN_RAKUTEN_AFFILIATE_ID" => self.rakuten_affiliate_id = Some(value),
+                "ISBN_CALIL_APP_KEY" => self.calil_app_key = Some(value),
+                "ISBN_CALIL_SYSTEMS" => self.calil_systems = Some(list().collect()),
+                "ISBN_IP_RATE_LIMIT" => self.ip_rate_limit = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_IP_RATE_BURST" => self.ip_rate_burst = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_GLOBAL_RATE_LIMIT" => self.global_rate_limit = Some(value.parse().map_err(|_| invalid())?),
+                "ISBN_GLOBAL_RATE_BURST" => self.global_rate_burst = Some(value.parse().map_err(|_| invalid())?),
+                _ => {}
+            }
+        }
+        Ok(self)
+    }
+
+    /// 設定ファイルの文字列を解析する
+    pub fn parse(text: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            r#"
+provider = ["openbd", "ndl"]
+interval = 500
+max_attempts = 100
+prefix = "978"
+group = "4"
+publisher_weight = "size"
+block_publishers = ["316", "407"]
+format = "jsonl"
+links = ["booklog", "amazon"]
+calil_systems = ["Tokyo_Setagaya"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.provider, Some(vec![Provider::Openbd, Provider::Ndl]));
+        assert_eq!(config.interval, Some(500));
+        assert_eq!(config.max_attempts, Some(100));
+        assert_eq!(config.publisher_weight, Some(PublisherWeight::Size));
+        assert_eq!(config.allow_publishers, None);
+        assert_eq!(config.block_publishers, Some(vec![String::from("316"), String::from("407")]));
+        assert_eq!(config.format, Some(OutputFormat::Jsonl));
+        assert_eq!(config.links, Some(vec![LinkService::Booklog, LinkService::Amazon]));
+        assert_eq!(config.rakuten_app_id, None);
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_unknown() {
+        // 書き間違いに気づけるように、知らない項目や値はエラーにする
+        assert!(Config::parse("intervl = 500").is_err());
+        assert!(Config::parse(r#"provider = ["amazon"]"#).is_err());
+    }
+
+    #[test]
+    fn test_merge_env() {
+        let config = Config { interval: Some(500), group: Some(String::from("0")), ..Default::default() };
+        let vars = [
+            ("ISBN_PROVIDER", "openbd, ndl"),
+            ("ISBN_INTERVAL", "2000"),
+            ("ISBN_FORMAT", "csv"),
+            ("ISBN_LINKS", "all"),
+            ("ISBN_LOG_LEVEL", "debug"),
+            ("ISBN_ALLOW_PUBLISHERS", "10,7981"),
+            ("ISBN_CALIL_SYSTEMS", "Tokyo_Setagaya,Tokyo_Meguro"),
+            ("ISBN_IP_RATE_LIMIT", "30"),
+            ("HOME", "/root"),
+        ];
+        let config = config.merge_env(vars.map(|(name, value)| (name.to_string(), value.to_string()))).unwrap();
+        assert_eq!(config.provider, Some(vec![Provider::Openbd, Provider::Ndl]));
+        assert_eq!(config.interval, Some(2000));
+        assert_eq!(config.format, Some(OutputFormat::Csv));
+        assert_eq!(config.links, Some(vec![LinkService::All]));
+        assert_eq!(config.log_level, Some(LogLevel::Debug));
+        assert_eq!(config.allow_publishers, Some(vec![String::from("10"), String::from("7981")]));
+        assert_eq!(config.calil_systems, Some(vec![String::from("Tokyo_Setagaya"), String::from("Tokyo_Meguro")]));
+        assert_eq!(config.ip_rate_limit, NonZeroU32::new(30));
+        // 環境変数で指定しなかった項目は設定ファイルの値のまま
+        assert_eq!(config.group.as_deref(), Some("0"));
+
+        let vars = [(String::from("ISBN_MAX_ATTEMPTS"), String::from("many"))];
+        assert!(Config::default().merge_env(vars).is_err());
+        // 0回では何も受け付けられないのでエラーにする
+        let vars = [(String::from("ISBN_GLOBAL_RATE_LIMIT"), String::from("0"))];
+        assert!(Config::default().merge_env(vars).is_err());
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let path = Path::new("/nonexistent/isbn/config.toml");
+        assert!(Config::load(Some(path)).is_err());
+    }
+}
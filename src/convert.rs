@@ -0,0 +1,54 @@
+use crate::isbn::{check_digit_10, check_digit_13, ParseIsbnError};
+use crate::validate;
+
+/// ISBN10をISBN13に変換する
+/// 接頭記号978を付け、チェックディジットを計算し直す
+pub fn to_isbn13(isbn10: &str) -> Result<String, ParseIsbnError> {
+    let digits = normalize(isbn10, 10)?;
+    let mut body = String::from("978") + &digits[..9];
+    body.push(check_digit_13(&body));
+    Ok(body)
+}
+
+/// ISBN13をISBN10に変換する
+/// 接頭記号を外し、チェックディジットを計算し直す
+/// ISBN10の形式を持たない979始まりのISBNはエラーになる
+pub fn to_isbn10(isbn13: &str) -> Result<String, ParseIsbnError> {
+    let digits = normalize(isbn13, 13)?;
+    if !digits.starts_with("978") {
+        return Err(ParseIsbnError::NoIsbn10);
+    }
+    let mut body = String::from(&digits[3..12]);
+    body.push(check_digit_10(&body));
+    Ok(body)
+}
+
+/// ハイフンとスペースを取り除き、指定した桁数の正しいISBNであることを確かめる
+fn normalize(isbn: &str, length: usize) -> Result<String, ParseIsbnError> {
+    let digits: String = isbn.chars().filter(|c| *c != '-' && *c != ' ').collect();
+    validate::check(&digits)?;
+    if digits.len() != length {
+        return Err(ParseIsbnError::InvalidLength(digits.len()));
+    }
+    Ok(digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_isbn13() {
+        assert_eq!(to_isbn13("4101092052"), Ok(String::from("9784101092058")));
+        assert_eq!(to_isbn13("4-10-109205-2"), Ok(String::from("9784101092058")));
+        assert_eq!(to_isbn13("9784101092058"), Err(ParseIsbnError::InvalidLength(13)));
+    }
+
+    #[test]
+    fn test_to_isbn10() {
+        assert_eq!(to_isbn10("978-4-7981-7154-8"), Ok(String::from("4798171549")));
+        assert_eq!(to_isbn10("9784101092058"), Ok(String::from("4101092052")));
+        assert_eq!(to_isbn10("9791032305690"), Err(ParseIsbnError::NoIsbn10));
+        assert_eq!(to_isbn10("4101092052"), Err(ParseIsbnError::InvalidLength(10)));
+    }
+}
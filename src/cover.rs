@@ -0,0 +1,62 @@
+//! 書影の画像のダウンロード
+
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::provider::Result;
+
+/// 画像の拡張子
+/// Content-Typeで判断し、分からない場合はURLの拡張子、それも分からない場合はjpgにする
+pub fn extension(url: &str, content_type: Option<&str>) -> &'static str {
+    let from_type = content_type.and_then(|content_type| {
+        match content_type.split(';').next().unwrap_or_default().trim() {
+            "image/jpeg" => Some("jpg"),
+            "image/png" => Some("png"),
+            "image/gif" => Some("gif"),
+            "image/webp" => Some("webp"),
+            _ => None,
+        }
+    });
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let from_url = path.rsplit_once('.').and_then(|(_, extension)| match extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => Some("jpg"),
+        "png" => Some("png"),
+        "gif" => Some("gif"),
+        "webp" => Some("webp"),
+        _ => None,
+    });
+    from_type.or(from_url).unwrap_or("jpg")
+}
+
+/// 書影の画像のファイルの中身と、Content-Type
+pub async fn fetch(client: &reqwest::Client, url: &str) -> Result<(Vec<u8>, Option<String>)> {
+    debug!(url = %url, "downloading cover");
+    let response = client.get(url).send().await?.error_for_status()?;
+    let content_type =
+        response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(String::from);
+    Ok((response.bytes().await?.to_vec(), content_type))
+}
+
+/// 書影をディレクトリに「ISBN13.拡張子」の名前で保存し、保存したファイルのパスを返す
+/// ディレクトリがなければ作る
+pub async fn download(client: &reqwest::Client, url: &str, dir: &Path, isbn13: &str) -> Result<PathBuf> {
+    let (bytes, content_type) = fetch(client, url).await?;
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.{}", isbn13, extension(url, content_type.as_deref())));
+    std::fs::write(&path, &bytes)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension("https://cover.openbd.jp/9784101010014.jpg", None), "jpg");
+        assert_eq!(extension("https://example.com/cover.PNG?size=large", None), "png");
+        assert_eq!(extension("http://books.google.com/books/content?id=1", Some("image/png; charset=binary")), "png");
+        assert_eq!(extension("http://books.google.com/books/content?id=1", None), "jpg");
+        assert_eq!(extension("https://example.com/1.jpg", Some("application/octet-stream")), "jpg");
+    }
+}
@@ -0,0 +1,232 @@
+//! バーコードなどの白黒の図形と、SVGや画素の並びへの描画
+
+use std::fmt::Write;
+
+/// 塗りつぶす長方形(単位はモジュール幅)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 等幅で並べる文字列
+/// 1文字ごとにcellの幅を使い、高さは文字の大きさ(TEXT_HEIGHT)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    pub x: u32,
+    pub y: u32,
+    pub cell: u32,
+    pub text: String,
+}
+
+/// 白地に黒で描く図形
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Drawing {
+    pub width: u32,
+    pub height: u32,
+    pub bars: Vec<Rect>,
+    pub texts: Vec<Text>,
+}
+
+/// 文字の高さ(単位はモジュール幅)
+pub const TEXT_HEIGHT: u32 = 7;
+
+/// 画素の並びにするときの5x7の字形(1行5ビット、上の行から)
+/// 字形のない文字は空白にする
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        '-' => [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+        'I' => [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'N' => [0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001, 0b10001],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        '¥' => [0b10001, 0b01010, 0b11111, 0b00100, 0b11111, 0b00100, 0b00100],
+        _ => [0; 7],
+    }
+}
+
+/// 白黒の画素の並び(trueが黒)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    /// 白紙の画素の並び
+    pub fn new(width: u32, height: u32) -> Self {
+        Bitmap { width, height, pixels: vec![false; (width * height) as usize] }
+    }
+
+    /// 画素が黒かどうか
+    /// 範囲の外は白とみなす
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height && self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// 長方形を黒く塗る
+    /// 範囲の外にはみ出した部分は描かない
+    pub fn fill(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                self.pixels[(y * self.width + x) as usize] = true;
+            }
+        }
+    }
+
+    /// 1行ずつの画素の並び
+    pub fn rows(&self) -> impl Iterator<Item = &[bool]> {
+        self.pixels.chunks(self.width.max(1) as usize)
+    }
+}
+
+impl Drawing {
+    /// SVGの文書にする
+    /// 1モジュール幅をscale pxとして大きさを指定し、文字はOCR-Bか等幅のフォントで描く
+    pub fn to_svg(&self, scale: u32) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+            self.width * scale,
+            self.height * scale,
+            self.width,
+            self.height
+        );
+        let _ = writeln!(svg, r#"<rect width="{}" height="{}" fill="white"/>"#, self.width, self.height);
+        for bar in &self.bars {
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="black"/>"#,
+                bar.x, bar.y, bar.width, bar.height
+            );
+        }
+        for text in &self.texts {
+            let length = text.cell * text.text.chars().count() as u32;
+            let _ = writeln!(
+                svg,
+                concat!(
+                    r#"<text x="{}" y="{}" font-family="OCR-B, monospace" font-size="{}" "#,
+                    r#"textLength="{}" lengthAdjust="spacingAndGlyphs">{}</text>"#
+                ),
+                text.x,
+                text.y + TEXT_HEIGHT,
+                TEXT_HEIGHT + 2,
+                length,
+                escape(&text.text)
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// 下にgapの余白を空けてotherを並べる
+    /// 幅は広い方に合わせ、狭い方は中央に置く
+    pub fn stack(self, other: Drawing, gap: u32) -> Drawing {
+        let width = self.width.max(other.width);
+        let mut drawing = Drawing { width, height: self.height + gap + other.height, ..Default::default() };
+        let lower = self.height + gap;
+        for (part, y) in [(self, 0), (other, lower)] {
+            let x = (width - part.width) / 2;
+            drawing.bars.extend(part.bars.into_iter().map(|bar| Rect { x: bar.x + x, y: bar.y + y, ..bar }));
+            drawing.texts.extend(part.texts.into_iter().map(|text| Text { x: text.x + x, y: text.y + y, ..text }));
+        }
+        drawing
+    }
+
+    /// 1モジュール幅をscale画素にした画素の並びにする
+    /// 文字は5x7の字形を各文字の枠の中央に描く
+    pub fn to_bitmap(&self, scale: u32) -> Bitmap {
+        let mut bitmap = Bitmap::new(self.width * scale, self.height * scale);
+        for bar in &self.bars {
+            bitmap.fill(bar.x * scale, bar.y * scale, bar.width * scale, bar.height * scale);
+        }
+        for text in &self.texts {
+            for (i, c) in text.text.chars().enumerate() {
+                let left = text.x + text.cell * i as u32 + text.cell.saturating_sub(5) / 2;
+                for (row, bits) in glyph(c).iter().enumerate() {
+                    for column in 0..5 {
+                        if bits & (0b10000 >> column) != 0 {
+                            bitmap.fill((left + column) * scale, (text.y + row as u32) * scale, scale, scale);
+                        }
+                    }
+                }
+            }
+        }
+        bitmap
+    }
+}
+
+/// SVGの文字列に含められない文字をエスケープする
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_svg() {
+        let drawing = Drawing {
+            width: 10,
+            height: 20,
+            bars: vec![Rect { x: 1, y: 0, width: 2, height: 10 }],
+            texts: vec![Text { x: 0, y: 12, cell: 5, text: String::from("<1>") }],
+        };
+        let svg = drawing.to_svg(3);
+        let header = r#"<svg xmlns="http://www.w3.org/2000/svg" width="30" height="60" viewBox="0 0 10 20">"#;
+        assert!(svg.starts_with(header));
+        assert!(svg.contains(r#"<rect x="1" y="0" width="2" height="10" fill="black"/>"#));
+        assert!(svg.contains(r#"textLength="15""#));
+        assert!(svg.contains("&lt;1&gt;</text>"));
+    }
+
+    #[test]
+    fn test_stack() {
+        let bars = vec![Rect { x: 1, y: 1, width: 1, height: 1 }];
+        let upper = Drawing { width: 10, height: 5, bars, ..Default::default() };
+        let bars = vec![Rect { x: 0, y: 0, width: 1, height: 1 }];
+        let lower = Drawing { width: 6, height: 3, bars, ..Default::default() };
+        let drawing = upper.stack(lower, 2);
+        assert_eq!((drawing.width, drawing.height), (10, 10));
+        assert_eq!(drawing.bars[1], Rect { x: 2, y: 7, width: 1, height: 1 });
+    }
+
+    #[test]
+    fn test_to_bitmap() {
+        let drawing = Drawing {
+            width: 10,
+            height: 20,
+            bars: vec![Rect { x: 1, y: 0, width: 2, height: 10 }],
+            texts: vec![Text { x: 0, y: 12, cell: 7, text: String::from("-") }],
+        };
+        let bitmap = drawing.to_bitmap(2);
+        assert_eq!((bitmap.width, bitmap.height), (20, 40));
+        assert!(!bitmap.get(1, 0));
+        assert!(bitmap.get(2, 0));
+        assert!(bitmap.get(5, 19));
+        assert!(!bitmap.get(6, 0));
+        assert!(!bitmap.get(2, 20));
+        // 「-」は字形の4行目だけが黒い
+        assert!(bitmap.get(2, (12 + 3) * 2));
+        assert!(!bitmap.get(2, (12 + 2) * 2));
+        assert!(!bitmap.get(100, 100));
+        assert_eq!(bitmap.rows().count(), 40);
+    }
+}
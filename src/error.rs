@@ -0,0 +1,70 @@
+use std::error::Error;
+
+use thiserror::Error;
+
+use crate::isbn::ParseIsbnError;
+
+/// ISBNの生成・解析、埋め込みデータの読み込み、書誌情報の取得で起きるエラー
+#[derive(Debug, Error)]
+pub enum IsbnError {
+    /// ISBN文字列の解析に失敗した
+    #[error(transparent)]
+    Parse(#[from] ParseIsbnError),
+    /// 接頭記号と国記号の組に対応する登録グループがない
+    #[error("unknown registration group: {prefix}-{group}")]
+    UnknownGroup { prefix: String, group: String },
+    /// 登録グループに出版社記号の範囲が割り当てられていない
+    #[error("registration group {prefix}-{group} has no assigned range")]
+    NoAssignedRange { prefix: String, group: String },
+    /// 国記号と出版社記号が長く、書籍コードの桁が残らない
+    #[error("group {group} and publisher {publisher} leave no digits for the publication code")]
+    TooLong { group: String, publisher: String },
+    /// RangeMessageの範囲の規則が不正
+    #[error("invalid range: {0:?}")]
+    InvalidRange(String),
+    /// 出版社CSVの読み込みに失敗した
+    #[error("invalid publisher csv: {0}")]
+    Csv(#[from] csv::Error),
+    /// HTTPリクエストに失敗した
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+    /// XMLの解析に失敗した
+    #[error("invalid xml: {0}")]
+    Xml(#[from] quick_xml::DeError),
+    /// 設定ファイルやAPIの応答など、その他のエラー
+    #[error(transparent)]
+    Other(#[from] Box<dyn Error + Send + Sync>),
+    /// エラーが起きた対象(ISBNやファイル名など)を添えたエラー
+    #[error("{context}: {source}")]
+    Context { context: String, source: Box<IsbnError> },
+}
+
+/// エラーに対象を添える
+pub trait Context<T> {
+    fn context(self, context: impl Into<String>) -> Result<T, IsbnError>;
+}
+
+impl<T, E: Into<IsbnError>> Context<T> for Result<T, E> {
+    fn context(self, context: impl Into<String>) -> Result<T, IsbnError> {
+        self.map_err(|e| IsbnError::Context { context: context.into(), source: Box::new(e.into()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context() {
+        let result: Result<(), ParseIsbnError> = Err(ParseIsbnError::InvalidLength(12));
+        let e = result.context("978479817154").unwrap_err();
+        assert_eq!(e.to_string(), "978479817154: invalid length: 12 digits");
+        assert!(matches!(e, IsbnError::Context { source, .. } if matches!(*source, IsbnError::Parse(_))));
+    }
+
+    #[test]
+    fn test_other() {
+        let e = IsbnError::from(Box::<dyn Error + Send + Sync>::from("connection refused"));
+        assert_eq!(e.to_string(), "connection refused");
+    }
+}
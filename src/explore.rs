@@ -0,0 +1,151 @@
+//! 見つかった本に関連する本(同じ著者の本や、同じ分類の本、シリーズの1巻目)
+//! 国立国会図書館サーチのSRUで検索する
+
+use tracing::warn;
+
+use crate::provider::retry::RetryPolicy;
+use crate::provider::sru::{CqlQuery, NdlSru, RecordSchema};
+use crate::provider::MetadataProvider;
+use crate::record::BookRecord;
+
+/// 著者名の後ろに付く役割の語
+const ROLES: &[&str] = &["著", "作", "文", "訳", "編", "編著", "共著", "監修", "絵", "画", "原作", "作画"];
+
+/// 書誌の著者から、検索に使う著者名を取り出す
+/// 「夏目漱石 著」のような役割の語は取り除く
+pub fn author_name(author: &str) -> Option<String> {
+    let mut words: Vec<&str> = author.split_whitespace().collect();
+    while words.len() > 1 && words.last().is_some_and(|word| ROLES.contains(word)) {
+        words.pop();
+    }
+    let name = words.join(" ");
+    (!name.is_empty()).then_some(name)
+}
+
+/// 分類記号の整数部分(3桁まで)
+/// 「913.6」は「913」になり、同じ分類の本を前方一致で探すのに使う
+fn ndc_class(ndc: &str) -> Option<String> {
+    let class: String = ndc.trim().chars().take_while(|c| c.is_ascii_digit()).take(3).collect();
+    (!class.is_empty()).then_some(class)
+}
+
+/// 関連する本を探す検索条件
+/// 最初の著者の本と、同じ分類の本の順に探す
+fn related_queries(record: &BookRecord) -> Vec<CqlQuery> {
+    let author = record.authors.iter().find_map(|author| author_name(author));
+    let ndc = record.ndc.as_deref().and_then(ndc_class);
+    [
+        author.map(|author| CqlQuery { creator: Some(author), ..Default::default() }),
+        ndc.map(|ndc| CqlQuery { ndc: Some(ndc), ..Default::default() }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// 見つかった本に関連する本を、検索条件ごとにcount件まで探す
+/// 見つかった本自身と、重複する本は含めない
+/// 検索できなかった条件は飛ばす
+pub async fn related(
+    client: &reqwest::Client,
+    policy: &RetryPolicy,
+    record: &BookRecord,
+    count: usize,
+) -> Vec<BookRecord> {
+    let sru = NdlSru { client: client.clone(), schema: RecordSchema::DcndlSimple };
+    let mut related: Vec<BookRecord> = Vec::new();
+    for query in related_queries(record) {
+        let cql = query.to_cql();
+        // 見つかった本自身が含まれる場合があるので1件多く取得する
+        let records = match policy.retry(sru.name(), || sru.search(&cql, 1, count + 1)).await {
+            Ok(response) => response.records(),
+            Err(e) => {
+                warn!(cql = %cql, error = %e, "cannot search related books");
+                continue;
+            }
+        };
+        let records: Vec<BookRecord> = records
+            .into_iter()
+            .filter(|other| other.isbn != record.isbn && related.iter().all(|related| related.isbn != other.isbn))
+            .take(count)
+            .collect();
+        related.extend(records);
+    }
+    related
+}
+
+/// 1巻目を探すときに取得する件数
+const VOLUME_SEARCH_SIZE: usize = 50;
+
+/// 見つかった本がシリーズの2巻目以降の場合に、同じタイトルと著者の1巻目を探す
+/// 1巻目の場合や巻次がない場合、見つからなかった場合はNoneを返す
+pub async fn first_volume(client: &reqwest::Client, policy: &RetryPolicy, record: &BookRecord) -> Option<BookRecord> {
+    if record.volume_number().is_none_or(|number| number <= 1) || record.title.is_empty() {
+        return None;
+    }
+    let query = first_volume_query(record);
+    let cql = query.to_cql();
+    let sru = NdlSru { client: client.clone(), schema: RecordSchema::DcndlSimple };
+    match policy.retry(sru.name(), || sru.search(&cql, 1, VOLUME_SEARCH_SIZE)).await {
+        Ok(response) => response
+            .records()
+            .into_iter()
+            .find(|other| other.title == record.title && other.volume_number() == Some(1)),
+        Err(e) => {
+            warn!(cql = %cql, error = %e, "cannot search the first volume");
+            None
+        }
+    }
+}
+
+/// 1巻目を探す検索条件
+fn first_volume_query(record: &BookRecord) -> CqlQuery {
+    CqlQuery {
+        title: Some(record.title.clone()),
+        creator: record.authors.iter().find_map(|author| author_name(author)),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_author_name() {
+        assert_eq!(author_name("夏目漱石 著").as_deref(), Some("夏目漱石"));
+        assert_eq!(author_name("Lewis Carroll 作 矢川澄子 訳").as_deref(), Some("Lewis Carroll 作 矢川澄子"));
+        assert_eq!(author_name("著").as_deref(), Some("著"));
+        assert_eq!(author_name(" "), None);
+    }
+
+    #[test]
+    fn test_ndc_class() {
+        assert_eq!(ndc_class("913.6").as_deref(), Some("913"));
+        assert_eq!(ndc_class("007.64").as_deref(), Some("007"));
+        assert_eq!(ndc_class("K11"), None);
+    }
+
+    #[test]
+    fn test_related_queries() {
+        let record = BookRecord {
+            authors: vec![String::from("夏目漱石 著")],
+            ndc: Some(String::from("913.6")),
+            ..Default::default()
+        };
+        let cql: Vec<String> = related_queries(&record).iter().map(CqlQuery::to_cql).collect();
+        assert_eq!(cql, vec![r#"creator="夏目漱石""#, r#"ndc="913""#]);
+        assert!(related_queries(&BookRecord::default()).is_empty());
+    }
+
+    #[test]
+    fn test_first_volume_query() {
+        let record = BookRecord {
+            title: String::from("ONE PIECE"),
+            volume: Some(String::from("巻3")),
+            authors: vec![String::from("尾田栄一郎 著")],
+            ..Default::default()
+        };
+        assert_eq!(first_volume_query(&record).to_cql(), r#"title="ONE PIECE" AND creator="尾田栄一郎""#);
+    }
+}
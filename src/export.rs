@@ -0,0 +1,285 @@
+//! 見つかった本の書誌情報を、文献管理ソフトなどで読み込める形式にする
+
+use serde_json::{json, Map, Value};
+
+use crate::explore::author_name;
+use crate::record::BookRecord;
+
+/// 著者名から「著」などの役割の語を除いたもの
+pub fn author_names(record: &BookRecord) -> Vec<String> {
+    record.authors.iter().filter_map(|author| author_name(author)).collect()
+}
+
+/// 出版年月日の年・月・日(分かる部分だけ)
+/// 「2004.3」や「2004-03-05」のように、4桁の年に続く数字を月と日とみなす
+pub fn date_parts(record: &BookRecord) -> Vec<u32> {
+    let Some(issued) = record.issued.as_deref() else {
+        return Vec::new();
+    };
+    let numbers: Vec<&str> = issued.split(|c: char| !c.is_ascii_digit()).filter(|part| !part.is_empty()).collect();
+    let Some(start) = numbers.iter().position(|part| part.len() == 4) else {
+        return Vec::new();
+    };
+    let mut parts: Vec<u32> = Vec::new();
+    for (i, part) in numbers[start..].iter().take(3).enumerate() {
+        let Ok(number) = part.parse::<u32>() else {
+            break;
+        };
+        let valid = match i {
+            0 => true,
+            1 => (1..=12).contains(&number),
+            _ => (1..=31).contains(&number),
+        };
+        if !valid {
+            break;
+        }
+        parts.push(number);
+    }
+    parts
+}
+
+/// BibTeXの値に含められない文字をエスケープする
+fn escape_bibtex(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// BibTeXの@bookの項目
+/// 引用キーは「isbn」とISBN13にし、著者名から「著」などの役割の語を除いて「and」でつなぐ
+/// ページ数はbiblatexのpagetotalにする(pagesは引用したページの範囲になる)
+pub fn bibtex(record: &BookRecord) -> String {
+    let authors = author_names(record);
+    let fields = [
+        ("author", Some(authors.join(" and ")).filter(|authors| !authors.is_empty())),
+        ("title", Some(record.title.clone()).filter(|title| !title.is_empty())),
+        ("publisher", record.publisher.clone()),
+        ("year", record.year().map(|year| year.to_string())),
+        ("series", record.series.clone()),
+        ("volume", record.volume.clone()),
+        ("pagetotal", record.pages.map(|pages| pages.to_string())),
+        ("isbn", Some(record.isbn.clone())),
+    ];
+    let mut entry = format!("@book{{isbn{},\n", record.isbn);
+    for (name, value) in fields {
+        if let Some(value) = value {
+            entry.push_str(&format!("  {} = {{{}}},\n", name, escape_bibtex(&value)));
+        }
+    }
+    entry.push_str("}\n");
+    entry
+}
+
+/// CSL-JSONの1件
+/// 日本語の著者名は姓と名に分けられないので、literalにする
+pub fn csl(record: &BookRecord) -> Value {
+    let mut item = Map::new();
+    item.insert(String::from("id"), json!(format!("isbn{}", record.isbn)));
+    item.insert(String::from("type"), json!("book"));
+    let authors: Vec<Value> = author_names(record).into_iter().map(|author| json!({ "literal": author })).collect();
+    if !authors.is_empty() {
+        item.insert(String::from("author"), Value::Array(authors));
+    }
+    let parts = date_parts(record);
+    if !parts.is_empty() {
+        item.insert(String::from("issued"), json!({ "date-parts": [parts] }));
+    }
+    let fields = [
+        ("title", Some(record.title.clone()).filter(|title| !title.is_empty())),
+        ("collection-title", record.series.clone()),
+        ("volume", record.volume.clone()),
+        ("publisher", record.publisher.clone()),
+        ("number-of-pages", record.pages.map(|pages| pages.to_string())),
+        ("language", record.language.clone()),
+        ("ISBN", Some(record.isbn.clone())),
+        ("URL", record.link.clone()),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            item.insert(String::from(name), Value::String(value));
+        }
+    }
+    Value::Object(item)
+}
+
+/// XMLの文字列に含められない文字をエスケープする
+pub fn escape_xml(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Dublin Core(OAI-DCのoai_dc:dc要素)の1件
+/// ISBNは「urn:isbn:」で始まるURNにし、件名と分類はどちらもdc:subjectにする
+pub fn dublin_core(record: &BookRecord) -> String {
+    let mut elements: Vec<(&str, String)> = Vec::new();
+    elements.extend(Some(record.title.clone()).filter(|title| !title.is_empty()).map(|title| ("title", title)));
+    elements.extend(author_names(record).into_iter().map(|author| ("creator", author)));
+    elements.extend(record.subjects.iter().map(|subject| ("subject", subject.clone())));
+    elements.extend(record.ndc.iter().map(|ndc| ("subject", format!("NDC:{}", ndc))));
+    elements.extend(record.publisher.iter().map(|publisher| ("publisher", publisher.clone())));
+    elements.extend(record.issued.iter().map(|issued| ("date", issued.clone())));
+    elements.push(("type", String::from("Text")));
+    let extent = record.extent.clone().or_else(|| record.pages.map(|pages| format!("{}p", pages)));
+    elements.extend(extent.map(|extent| ("format", extent)));
+    elements.push(("identifier", format!("urn:isbn:{}", record.isbn)));
+    elements.extend(record.link.iter().map(|link| ("identifier", link.clone())));
+    elements.extend(record.language.iter().map(|language| ("language", language.clone())));
+    elements.extend(record.series.iter().map(|series| ("relation", series.clone())));
+    let mut dc = String::from(concat!(
+        "  <oai_dc:dc xmlns:oai_dc=\"http://www.openarchives.org/OAI/2.0/oai_dc/\" ",
+        "xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n"
+    ));
+    for (name, value) in elements {
+        dc.push_str(&format!("    <dc:{}>{}</dc:{}>\n", name, escape_xml(&value), name));
+    }
+    dc.push_str("  </oai_dc:dc>\n");
+    dc
+}
+
+/// Dublin Coreの一覧のXML文書
+/// 1件でもそのまま読み込めるように、records要素で囲む
+pub fn dublin_core_document(records: &[&BookRecord]) -> String {
+    let mut document = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<records>\n");
+    for record in records {
+        document.push_str(&dublin_core(record));
+    }
+    document.push_str("</records>\n");
+    document
+}
+
+/// RISの1件(TY  - BOOKからER  - まで)
+/// 著者は1人ずつAUの行にする
+pub fn ris(record: &BookRecord) -> String {
+    let mut lines = vec![(String::from("TY"), String::from("BOOK"))];
+    lines.extend(author_names(record).into_iter().map(|author| (String::from("AU"), author)));
+    let fields = [
+        ("TI", Some(record.title.clone()).filter(|title| !title.is_empty())),
+        ("T3", record.series.clone()),
+        ("VL", record.volume.clone()),
+        ("PB", record.publisher.clone()),
+        ("PY", record.year().map(|year| year.to_string())),
+        ("SN", Some(record.isbn.clone())),
+        ("UR", record.link.clone()),
+    ];
+    lines.extend(fields.into_iter().filter_map(|(tag, value)| Some((String::from(tag), value?))));
+    lines.push((String::from("ER"), String::new()));
+    // 値の改行はRISでは項目の区切りになるので空白にする
+    lines.iter().map(|(tag, value)| format!("{}  - {}\n", tag, value.replace(['\r', '\n'], " "))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> BookRecord {
+        BookRecord {
+            isbn: String::from("9784101010014"),
+            title: String::from("こころ"),
+            authors: vec![String::from("夏目漱石 著"), String::from("A_B 編")],
+            publisher: Some(String::from("新潮社")),
+            issued: Some(String::from("2004.3")),
+            pages: Some(326),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bibtex() {
+        let expected = "@book{isbn9784101010014,
+  author = {夏目漱石 and A\\_B},
+  title = {こころ},
+  publisher = {新潮社},
+  year = {2004},
+  pagetotal = {326},
+  isbn = {9784101010014},
+}
+";
+        assert_eq!(bibtex(&record()), expected);
+    }
+
+    #[test]
+    fn test_ris() {
+        let expected = "TY  - BOOK
+AU  - 夏目漱石
+AU  - A_B
+TI  - こころ
+PB  - 新潮社
+PY  - 2004
+SN  - 9784101010014
+ER  - 
+";
+        assert_eq!(ris(&record()), expected);
+    }
+
+    #[test]
+    fn test_csl() {
+        let expected = json!({
+            "id": "isbn9784101010014",
+            "type": "book",
+            "author": [{ "literal": "夏目漱石" }, { "literal": "A_B" }],
+            "issued": { "date-parts": [[2004, 3]] },
+            "title": "こころ",
+            "publisher": "新潮社",
+            "number-of-pages": "326",
+            "ISBN": "9784101010014",
+        });
+        assert_eq!(csl(&record()), expected);
+    }
+
+    #[test]
+    fn test_dublin_core() {
+        let record = BookRecord { series: Some(String::from("新潮文庫 <改版>")), ..record() };
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<records>
+  <oai_dc:dc xmlns:oai_dc="http://www.openarchives.org/OAI/2.0/oai_dc/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>こころ</dc:title>
+    <dc:creator>夏目漱石</dc:creator>
+    <dc:creator>A_B</dc:creator>
+    <dc:publisher>新潮社</dc:publisher>
+    <dc:date>2004.3</dc:date>
+    <dc:type>Text</dc:type>
+    <dc:format>326p</dc:format>
+    <dc:identifier>urn:isbn:9784101010014</dc:identifier>
+    <dc:relation>新潮文庫 &lt;改版&gt;</dc:relation>
+  </oai_dc:dc>
+</records>
+"#;
+        assert_eq!(dublin_core_document(&[&record]), expected);
+        let record = BookRecord { subjects: vec![String::from("小説 (日本)")], ndc: Some(String::from("913.6")), ..record };
+        let subjects = "<dc:subject>小説 (日本)</dc:subject>\n    <dc:subject>NDC:913.6</dc:subject>";
+        assert!(dublin_core(&record).contains(subjects));
+    }
+
+    #[test]
+    fn test_date_parts() {
+        let record = |issued: &str| BookRecord { issued: Some(String::from(issued)), ..Default::default() };
+        assert_eq!(date_parts(&record("2004-03-05")), vec![2004, 3, 5]);
+        assert_eq!(date_parts(&record("[2004]")), vec![2004]);
+        assert_eq!(date_parts(&record("2004.13")), vec![2004]);
+        assert_eq!(date_parts(&record("昭和")), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_escape_bibtex() {
+        assert_eq!(escape_bibtex("50% & {x}"), "50\\% \\& \\{x\\}");
+        assert_eq!(escape_bibtex("a\\b"), "a\\textbackslash{}b");
+    }
+}
@@ -0,0 +1,205 @@
+//! ランダムに探して見つかった本を受け入れる条件
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::record::BookRecord;
+
+/// 見つかった本を受け入れる条件
+/// 指定した条件をすべて満たす本だけを受け入れ、条件の項目がない本は受け入れない
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct RecordFilter {
+    /// 日本十進分類法の分類記号(前方一致)
+    pub ndc: Option<String>,
+    /// 出版年の範囲の始まり
+    pub from_year: Option<u32>,
+    /// 出版年の範囲の終わり(この年を含む)
+    pub until_year: Option<u32>,
+    /// 資料の種別
+    pub material: Option<Material>,
+    /// 本文の言語(ISO 639-2の記号、いずれかに一致すればよい)
+    pub languages: Vec<String>,
+    /// 価格(円)の上限
+    pub max_price: Option<u32>,
+    /// ページ数の下限
+    pub min_pages: Option<u32>,
+    /// ページ数の上限
+    pub max_pages: Option<u32>,
+}
+
+/// 資料の種別
+/// 国立国会図書館サーチの書誌にある資料種別(rdfs:labelの名前か、ndltypeのURIの最後の部分)で判断する
+/// openBDの書誌は、商品の形態が書籍なら図書になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Material {
+    /// 図書
+    Book,
+    /// 雑誌・新聞
+    Serial,
+    /// 地図
+    Map,
+    /// 映像資料・録音資料
+    Audiovisual,
+}
+
+impl Material {
+    /// この種別とみなす資料種別の名前
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            Material::Book => &["図書", "Book"],
+            Material::Serial => &["雑誌", "新聞", "Serial", "Periodical", "Newspaper"],
+            Material::Map => &["地図", "Map"],
+            Material::Audiovisual => &["映像資料", "録音資料", "Video", "Sound", "Music"],
+        }
+    }
+
+    pub fn matches(self, material_type: &str) -> bool {
+        self.names().iter().any(|name| material_type.starts_with(name))
+    }
+}
+
+impl RecordFilter {
+    /// 受け入れない本の場合は、満たさなかった条件の名前を返す
+    pub fn rejects(&self, record: &BookRecord) -> Option<&'static str> {
+        if let Some(ndc) = &self.ndc {
+            if !record.ndc.as_deref().is_some_and(|value| value.starts_with(ndc.as_str())) {
+                return Some("ndc");
+            }
+        }
+        if let Some(material) = self.material {
+            if !record.material_type.as_deref().is_some_and(|material_type| material.matches(material_type)) {
+                return Some("material");
+            }
+        }
+        if !self.languages.is_empty() {
+            let language = record.language.as_deref().map(language_code);
+            if !language.is_some_and(|language| self.languages.iter().any(|code| language_code(code) == language)) {
+                return Some("language");
+            }
+        }
+        if let Some(max_price) = self.max_price {
+            if record.price.is_none_or(|price| price > max_price) {
+                return Some("max-price");
+            }
+        }
+        if let Some(min_pages) = self.min_pages {
+            if record.pages.is_none_or(|pages| pages < min_pages) {
+                return Some("min-pages");
+            }
+        }
+        if let Some(max_pages) = self.max_pages {
+            if record.pages.is_none_or(|pages| pages > max_pages) {
+                return Some("max-pages");
+            }
+        }
+        let year = record.year();
+        if let Some(from) = self.from_year {
+            if year.is_none_or(|year| year < from) {
+                return Some("from-year");
+            }
+        }
+        if let Some(until) = self.until_year {
+            if year.is_none_or(|year| year > until) {
+                return Some("until-year");
+            }
+        }
+        None
+    }
+}
+
+/// 言語の記号を、比べられるようにISO 639-2(書誌用)の小文字の記号にそろえる
+/// ISO 639-1の記号とISO 639-2の用語用の記号は、よく使う言語だけを直す
+fn language_code(code: &str) -> String {
+    let code = code.trim().to_ascii_lowercase();
+    let normalized = match code.as_str() {
+        "ja" => "jpn",
+        "en" => "eng",
+        "zh" | "zho" => "chi",
+        "ko" => "kor",
+        "fr" | "fra" => "fre",
+        "de" | "deu" => "ger",
+        "es" => "spa",
+        "it" => "ita",
+        "ru" => "rus",
+        _ => return code,
+    };
+    normalized.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects() {
+        let record = BookRecord { ndc: Some(String::from("913.6")), ..Default::default() };
+        assert_eq!(RecordFilter::default().rejects(&record), None);
+        let filter = RecordFilter { ndc: Some(String::from("913")), ..Default::default() };
+        assert_eq!(filter.rejects(&record), None);
+        let filter_007 = RecordFilter { ndc: Some(String::from("007")), ..Default::default() };
+        assert_eq!(filter_007.rejects(&record), Some("ndc"));
+        // 分類のない本は受け入れない
+        assert_eq!(filter.rejects(&BookRecord::default()), Some("ndc"));
+    }
+
+    #[test]
+    fn test_rejects_year() {
+        let filter = RecordFilter { from_year: Some(2000), until_year: Some(2010), ..Default::default() };
+        let issued = |issued: &str| BookRecord { issued: Some(issued.to_string()), ..Default::default() };
+        assert_eq!(filter.rejects(&issued("2003.4")), None);
+        assert_eq!(filter.rejects(&issued("2010-12-31")), None);
+        assert_eq!(filter.rejects(&issued("[1999]")), Some("from-year"));
+        assert_eq!(filter.rejects(&issued("c2011")), Some("until-year"));
+        assert_eq!(filter.rejects(&BookRecord::default()), Some("from-year"));
+        let filter = RecordFilter { from_year: Some(2000), ..Default::default() };
+        assert_eq!(filter.rejects(&issued("2024")), None);
+    }
+
+    #[test]
+    fn test_rejects_material() {
+        let filter = RecordFilter { material: Some(Material::Book), ..Default::default() };
+        let material = |name: &str| BookRecord { material_type: Some(name.to_string()), ..Default::default() };
+        assert_eq!(filter.rejects(&material("図書")), None);
+        assert_eq!(filter.rejects(&material("Book")), None);
+        assert_eq!(filter.rejects(&material("雑誌")), Some("material"));
+        // 資料種別がわからない書誌(国立国会図書館サーチとopenBD以外)は受け入れない
+        assert_eq!(filter.rejects(&BookRecord::default()), Some("material"));
+    }
+
+    #[test]
+    fn test_rejects_language() {
+        let filter = RecordFilter { languages: vec![String::from("jpn"), String::from("eng")], ..Default::default() };
+        let language = |code: &str| BookRecord { language: Some(code.to_string()), ..Default::default() };
+        assert_eq!(filter.rejects(&language("jpn")), None);
+        assert_eq!(filter.rejects(&language("en")), None);
+        assert_eq!(filter.rejects(&language("fre")), Some("language"));
+        assert_eq!(filter.rejects(&BookRecord::default()), Some("language"));
+        let filter = RecordFilter { languages: vec![String::from("fra")], ..Default::default() };
+        assert_eq!(filter.rejects(&language("FR")), None);
+    }
+
+    #[test]
+    fn test_rejects_price() {
+        let filter = RecordFilter { max_price: Some(1500), ..Default::default() };
+        let price = |price: u32| BookRecord { price: Some(price), ..Default::default() };
+        assert_eq!(filter.rejects(&price(710)), None);
+        assert_eq!(filter.rejects(&price(1500)), None);
+        assert_eq!(filter.rejects(&price(1501)), Some("max-price"));
+        // 価格はopenBDと楽天ブックスの書誌にしかない
+        assert_eq!(filter.rejects(&BookRecord::default()), Some("max-price"));
+    }
+
+    #[test]
+    fn test_rejects_pages() {
+        let filter = RecordFilter { min_pages: Some(100), max_pages: Some(300), ..Default::default() };
+        let pages = |pages: u32| BookRecord { pages: Some(pages), ..Default::default() };
+        assert_eq!(filter.rejects(&pages(100)), None);
+        assert_eq!(filter.rejects(&pages(300)), None);
+        assert_eq!(filter.rejects(&pages(99)), Some("min-pages"));
+        assert_eq!(filter.rejects(&pages(301)), Some("max-pages"));
+        assert_eq!(filter.rejects(&BookRecord::default()), Some("min-pages"));
+    }
+}
@@ -0,0 +1,136 @@
+use rand::Rng;
+use std::sync::OnceLock;
+
+use crate::isbn::Isbn;
+use crate::range::{RangeMessage, Rule};
+
+/// 登録グループ(国記号)と、そのグループでの出版社記号の桁数の規則
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrationGroup {
+    /// 接頭記号(978, 979)
+    pub prefix: String,
+    /// 国記号(日本なら4)
+    pub code: String,
+    /// 国や地域、言語圏の名前
+    pub agency: String,
+    pub rules: Vec<Rule>,
+}
+
+impl RegistrationGroup {
+    /// 埋め込みのRangeMessageにあるすべての登録グループ
+    /// 一度だけ作り、以降は同じものを返す
+    pub fn all() -> &'static [RegistrationGroup] {
+        static GROUPS: OnceLock<Vec<RegistrationGroup>> = OnceLock::new();
+        GROUPS.get_or_init(|| {
+            RangeMessage::embedded()
+                .groups
+                .iter()
+                .filter_map(|group| {
+                    let (prefix, code) = group.prefix.split_once('-')?;
+                    Some(RegistrationGroup {
+                        prefix: prefix.to_string(),
+                        code: code.to_string(),
+                        agency: group.agency.clone(),
+                        rules: group.rules.clone(),
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// 接頭記号と国記号から登録グループを探す
+    pub fn find(prefix: &str, code: &str) -> Option<&'static RegistrationGroup> {
+        Self::all().iter().find(|group| group.prefix == prefix && group.code == code)
+    }
+
+    /// チェックディジットを含むISBN13の数字列から、国記号の桁数を判定して登録グループを探す
+    pub fn of(isbn13: &str) -> Option<&'static RegistrationGroup> {
+        if isbn13.len() != 13 || !isbn13.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let prefix = &isbn13[..3];
+        let group_length = RangeMessage::embedded().find_ucc_prefix(prefix)?.length_of(&isbn13[3..12])?;
+        Self::find(prefix, &isbn13[3..3 + group_length])
+    }
+
+    /// 割り当て済みの範囲からランダムに出版社記号を選ぶ
+    /// 書籍コードが1桁以上残る範囲だけを対象にする
+    pub fn random_registrant(&self) -> Option<String> {
+        self.random_registrant_with_rng(&mut rand::thread_rng())
+    }
+
+    /// 渡した乱数生成器で出版社記号を選ぶ
+    pub fn random_registrant_with_rng(&self, rng: &mut impl Rng) -> Option<String> {
+        let max_length = 8 - self.code.len();
+        let rules: Vec<&Rule> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.length > 0 && rule.length <= max_length)
+            .collect();
+        if rules.is_empty() {
+            return None;
+        }
+        let rule = rules[rng.gen_range(0..rules.len())];
+        let value = rng.gen_range(rule.start..=rule.end);
+        Some(format!("{:07}", value)[..rule.length].to_string())
+    }
+
+    /// このグループのランダムな出版社記号でISBNを生成する
+    pub fn generate(&self) -> Option<Isbn> {
+        self.generate_with_rng(&mut rand::thread_rng())
+    }
+
+    /// 渡した乱数生成器で出版社記号と書籍コードを選んでISBNを生成する
+    pub fn generate_with_rng(&self, rng: &mut impl Rng) -> Option<Isbn> {
+        let registrant = self.random_registrant_with_rng(rng)?;
+        Isbn::new_with_rng(self.prefix.clone(), self.code.clone(), registrant, rng).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        assert_eq!(RegistrationGroup::find("978", "4").unwrap().agency, "Japan");
+        assert_eq!(RegistrationGroup::find("978", "0").unwrap().agency, "English language");
+        assert_eq!(RegistrationGroup::find("979", "10").unwrap().agency, "France");
+        assert!(RegistrationGroup::find("979", "4").is_none());
+        // 表は一度だけ作り、同じものを返す
+        let japan = RegistrationGroup::find("978", "4").unwrap();
+        assert!(std::ptr::eq(japan, RegistrationGroup::of("9784798171548").unwrap()));
+    }
+
+    #[test]
+    fn test_of() {
+        assert_eq!(RegistrationGroup::of("9784798171548").unwrap().code, "4");
+        assert_eq!(RegistrationGroup::of("9791032305690").unwrap().code, "10");
+        assert_eq!(RegistrationGroup::of("9789992158104").unwrap().code, "99921");
+    }
+
+    #[test]
+    fn test_generate() {
+        for (prefix, code) in [("978", "0"), ("978", "4"), ("978", "99921"), ("979", "10")] {
+            let group = RegistrationGroup::find(prefix, code).unwrap();
+            let isbn = group.generate().unwrap();
+            assert_eq!(RegistrationGroup::of(&isbn.create_isbn_13()), Some(group));
+            assert!(Isbn::is_valid(&isbn.create_isbn_13()));
+        }
+    }
+
+    #[test]
+    fn test_generate_with_rng() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        // 同じシードなら同じISBNの並びになる
+        let group = RegistrationGroup::find("978", "0").unwrap();
+        let generate = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..5).map(|_| group.generate_with_rng(&mut rng).unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+    }
+}
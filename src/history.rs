@@ -0,0 +1,215 @@
+//! 試したISBNと見つかった本の履歴(~/.local/share/isbn/history.sqlite3)
+//! 次に実行したときに、見つからなかったISBNと一度表示した本をもう一度問い合わせないようにする
+//! ブクログの本棚から読み込んだ、持っている本も問い合わせない
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::booklog::ShelfBook;
+use crate::record::BookRecord;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS attempts (
+    isbn13 TEXT PRIMARY KEY,
+    found INTEGER NOT NULL,
+    tried_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+CREATE TABLE IF NOT EXISTS books (
+    isbn13 TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    record TEXT NOT NULL,
+    found_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+CREATE TABLE IF NOT EXISTS owned (
+    isbn13 TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    imported_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+";
+
+/// 履歴に記録した、見つかった本
+/// 書誌情報はJSONで保存してあるので、そのうち読み出す項目だけを読む
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct FoundBook {
+    pub isbn: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub publisher: Option<String>,
+    pub issued: Option<String>,
+    pub language: Option<String>,
+    pub cover_url: Option<String>,
+    pub link: Option<String>,
+    /// 見つかった日時(UTCのRFC 3339、例: 2024-05-01T12:34:56Z)
+    #[serde(skip)]
+    pub found_at: String,
+}
+
+/// JSONで保存した列を読み出す
+pub fn from_json<T: DeserializeOwned>(row: &rusqlite::Row, index: usize) -> rusqlite::Result<T> {
+    let json: String = row.get(index)?;
+    serde_json::from_str(&json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+/// SQLiteに保存した履歴
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// 既定の履歴ファイルの場所
+    /// XDG_DATA_HOMEが設定されていればその下、なければ ~/.local/share の下
+    pub fn default_path() -> Option<PathBuf> {
+        let data_home = match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("share"),
+        };
+        Some(data_home.join("isbn").join("history.sqlite3"))
+    }
+
+    /// 履歴ファイルを開く
+    /// ファイルやディレクトリがなければ作る
+    pub fn open(path: &Path) -> rusqlite::Result<History> {
+        if let Some(dir) = path.parent() {
+            // 作れなかった場合は、開くときのエラーで知らせる
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(History { conn })
+    }
+
+
+    /// 以前に試したISBNか(見つからなかったものと、見つかって表示したものの両方)、持っている本のISBNか
+    pub fn contains(&self, isbn_13: &str) -> rusqlite::Result<bool> {
+        let found = self
+            .conn
+            .query_row(
+                "SELECT 1 FROM attempts WHERE isbn13 = ?1 UNION ALL SELECT 1 FROM owned WHERE isbn13 = ?1",
+                params![isbn_13],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    /// ブクログの本棚にある本を、持っている本として記録する
+    /// 新しく記録した冊数を返す
+    pub fn record_owned(&self, books: &[ShelfBook]) -> rusqlite::Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut inserted = 0;
+        for book in books {
+            inserted += tx.execute(
+                "INSERT OR IGNORE INTO owned (isbn13, title) VALUES (?1, ?2)",
+                params![book.isbn13, book.title],
+            )?;
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// 見つからなかったISBNを記録する
+    pub fn record_miss(&self, isbn_13: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO attempts (isbn13, found) VALUES (?1, 0)
+             ON CONFLICT (isbn13) DO UPDATE SET tried_at = excluded.tried_at",
+            params![isbn_13],
+        )?;
+        Ok(())
+    }
+
+    /// 見つかった本を記録する
+    /// 書誌情報はJSONで保存する
+    pub fn record_hit(&self, record: &BookRecord) -> rusqlite::Result<()> {
+        let json = serde_json::to_string(record).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        // 問い合わせの途中で同じ接続から読むので、&selfのままトランザクションを始める
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO attempts (isbn13, found) VALUES (?1, 1)
+             ON CONFLICT (isbn13) DO UPDATE SET found = 1, tried_at = excluded.tried_at",
+            params![record.isbn],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO books (isbn13, title, record) VALUES (?1, ?2, ?3)",
+            params![record.isbn, record.title, json],
+        )?;
+        tx.commit()
+    }
+
+    /// 見つかった本の一覧(新しく見つかった順)
+    pub fn found_books(&self) -> rusqlite::Result<Vec<FoundBook>> {
+        let mut statement = self.conn.prepare(
+            "SELECT record, strftime('%Y-%m-%dT%H:%M:%SZ', found_at, 'unixepoch') FROM books
+             ORDER BY found_at DESC, isbn13",
+        )?;
+        let rows = statement.query_map([], |row| {
+            let book: FoundBook = from_json(row, 0)?;
+            Ok(FoundBook { found_at: row.get(1)?, ..book })
+        })?;
+        rows.collect()
+    }
+
+    /// 見つかった本の書誌情報と、見つかった日時(UNIX時間)
+    pub fn book(&self, isbn_13: &str) -> rusqlite::Result<Option<(BookRecord, i64)>> {
+        self.conn
+            .query_row("SELECT record, found_at FROM books WHERE isbn13 = ?1", params![isbn_13], |row| {
+                Ok((from_json(row, 0)?, row.get(1)?))
+            })
+            .optional()
+    }
+
+    /// 試したISBNの数と、見つかった本の数
+    pub fn counts(&self) -> rusqlite::Result<(usize, usize)> {
+        self.conn.query_row("SELECT COUNT(*), COALESCE(SUM(found), 0) FROM attempts", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        // :memory:はファイルを作らずメモリ上に開く
+        let history = History::open(Path::new(":memory:")).unwrap();
+        assert!(!history.contains("9784798171548").unwrap());
+
+        history.record_miss("9784798171540").unwrap();
+        // 同じISBNを何度記録しても1件
+        history.record_miss("9784798171540").unwrap();
+        let record = BookRecord { isbn: String::from("9784798171548"), title: String::from("実践Rustプログラミング入門"), ..Default::default() };
+        history.record_hit(&record).unwrap();
+
+        assert!(history.contains("9784798171540").unwrap());
+        assert!(history.contains("9784798171548").unwrap());
+        assert_eq!(history.counts().unwrap(), (2, 1));
+
+        let books = history.found_books().unwrap();
+        assert_eq!(books.len(), 1);
+        assert_eq!(books[0].isbn, "9784798171548");
+        assert_eq!(books[0].title, "実践Rustプログラミング入門");
+        assert_eq!(books[0].found_at.len(), "2024-05-01T12:34:56Z".len());
+        assert!(books[0].found_at.ends_with('Z'));
+
+        let (found, found_at) = history.book("9784798171548").unwrap().unwrap();
+        assert_eq!(found, record);
+        assert!(found_at > 0);
+        assert_eq!(history.book("9784798171540").unwrap(), None);
+    }
+
+    #[test]
+    fn test_record_owned() {
+        let history = History::open(Path::new(":memory:")).unwrap();
+        let books = [ShelfBook { isbn13: String::from("9784101010014"), title: String::from("吾輩は猫である") }];
+        assert_eq!(history.record_owned(&books).unwrap(), 1);
+        assert_eq!(history.record_owned(&books).unwrap(), 0);
+        assert!(history.contains("9784101010014").unwrap());
+        // 持っている本は試した回数に数えない
+        assert_eq!(history.counts().unwrap(), (0, 0));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::convert::to_isbn13;
+use crate::isbn::ParseIsbnError;
+use crate::publisher::PublisherRegistry;
+use crate::range::RangeMessage;
+use crate::validate;
+
+/// ISBN13の数字列から、国記号と出版社記号の桁数を求める
+/// 日本のISBNは出版社一覧の出版社記号に一致するところまでを出版社記号とみなし、一致しない場合はRangeMessageの規則を使う
+pub(crate) fn segment_lengths(isbn13: &str) -> Option<(usize, usize)> {
+    segment_lengths_with(RangeMessage::embedded(), PublisherRegistry::embedded(), isbn13)
+}
+
+/// 渡したRangeMessageと出版社一覧で国記号と出版社記号の桁数を求める
+fn segment_lengths_with(
+    range_message: &RangeMessage,
+    publishers: &PublisherRegistry,
+    isbn13: &str,
+) -> Option<(usize, usize)> {
+    let registered = || {
+        let digits = isbn13.strip_prefix("9784")?.get(..8)?;
+        let publisher = publishers.find_by_prefix(digits)?;
+        // 書籍コードが1桁以上残る必要がある
+        (publisher.code.len() < digits.len()).then_some((1, publisher.code.len()))
+    };
+    registered().or_else(|| range_message.segment_lengths(isbn13))
+}
+
+/// ISBN13またはISBN10を、RangeMessageの規則に従ってハイフン区切りにする
+/// ISBN10を渡した場合はISBN10の形式でハイフンを入れる
+pub fn hyphenate(isbn: &str) -> Result<String, ParseIsbnError> {
+    let digits: String = isbn.chars().filter(|c| *c != '-' && *c != ' ').collect();
+    validate::check(&digits)?;
+
+    let isbn13 = if digits.len() == 10 { to_isbn13(&digits)? } else { digits.clone() };
+    let (group_length, registrant_length) = segment_lengths(&isbn13).ok_or(ParseIsbnError::UndefinedRange)?;
+
+    let body = &digits[digits.len() - 10..];
+    let publication_start = group_length + registrant_length;
+    let hyphenated = [
+        &body[..group_length],
+        &body[group_length..publication_start],
+        &body[publication_start..9],
+        &body[9..],
+    ]
+    .join("-");
+    if digits.len() == 13 {
+        Ok(format!("{}-{}", &digits[..3], hyphenated))
+    } else {
+        Ok(hyphenated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hyphenate_isbn13() {
+        assert_eq!(hyphenate("9784798171548"), Ok(String::from("978-4-7981-7154-8")));
+        assert_eq!(hyphenate("978-4101092058"), Ok(String::from("978-4-10-109205-8")));
+        assert_eq!(hyphenate("9780306406157"), Ok(String::from("978-0-306-40615-7")));
+        assert_eq!(hyphenate("9791032305690"), Ok(String::from("979-10-323-0569-0")));
+    }
+
+    #[test]
+    fn test_hyphenate_isbn10() {
+        assert_eq!(hyphenate("4101092052"), Ok(String::from("4-10-109205-2")));
+    }
+
+    #[test]
+    fn test_segment_lengths() {
+        assert_eq!(segment_lengths("9784798171548"), Some((1, 4)));
+        assert_eq!(segment_lengths("9784101092058"), Some((1, 2)));
+        assert_eq!(segment_lengths("9786999999990"), None);
+    }
+
+    #[test]
+    fn test_segment_lengths_prefers_publishers() {
+        use crate::publisher::Publisher;
+        use crate::range::{RangeGroup, Rule};
+
+        // 978-4の出版社記号を全て2桁とするRangeMessage
+        let rule = |length| vec![Rule { start: 0, end: 9_999_999, length }];
+        let range_message = RangeMessage {
+            message_date: String::new(),
+            ucc_prefixes: vec![RangeGroup { prefix: String::from("978"), agency: String::new(), rules: rule(1) }],
+            groups: vec![RangeGroup { prefix: String::from("978-4"), agency: String::from("Japan"), rules: rule(2) }],
+        };
+        let publisher = Publisher { code: String::from("7981"), name: String::from("翔泳社") };
+        let publishers = PublisherRegistry::new(vec![publisher]);
+
+        // 出版社一覧にある出版社記号はRangeMessageより優先する
+        assert_eq!(segment_lengths_with(&range_message, &publishers, "9784798171548"), Some((1, 4)));
+        // 出版社一覧にない場合はRangeMessageの規則を使う
+        assert_eq!(segment_lengths_with(&range_message, &publishers, "9784101092058"), Some((1, 2)));
+        // 日本以外のISBNは出版社一覧を使わない
+        assert_eq!(segment_lengths_with(&range_message, &publishers, "9780306406157"), None);
+    }
+
+    #[test]
+    fn test_hyphenate_error() {
+        assert_eq!(hyphenate("978479817154"), Err(ParseIsbnError::InvalidLength(12)));
+        assert_eq!(hyphenate("9786999999990"), Err(ParseIsbnError::UndefinedRange));
+    }
+}
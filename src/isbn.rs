@@ -0,0 +1,476 @@
+use rand::Rng;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::group::RegistrationGroup;
+use crate::convert::to_isbn13;
+use crate::error::IsbnError;
+use crate::hyphen;
+use crate::publisher::{Publisher, PublisherRegistry};
+use crate::validate::{self, verify_check_digit, Validation};
+
+/// ISBNを構成する各要素とチェックディジットを保持する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Isbn {
+    head_code: String,
+    country_code: String,
+    publisher_code: String,
+    publication_code: String,
+    /// 979始まりのISBNにはISBN10がないのでNone
+    check_digit_10: Option<String>,
+    check_digit_13: String,
+}
+
+impl Isbn {
+    /// 接頭記号・国記号・出版社記号から、書籍コードをランダムに生成したISBNを作る
+    /// 国記号と出版社記号で書籍コードの桁が残らない場合はエラーを返す
+    pub fn new(head_code: String, country_code: String, publisher_code: String) -> Result<Self, IsbnError> {
+        Self::new_with_rng(head_code, country_code, publisher_code, &mut rand::thread_rng())
+    }
+
+    /// 渡した乱数生成器で書籍コードを生成する
+    /// シードを固定した乱数生成器を渡せば、同じISBNを作り直せる
+    pub fn new_with_rng(
+        head_code: String,
+        country_code: String,
+        publisher_code: String,
+        rng: &mut impl Rng,
+    ) -> Result<Self, IsbnError> {
+        let publication_code = Self::generate_publication_code_with_rng(&country_code, &publisher_code, rng)?;
+        Ok(Self::from_parts(head_code, country_code, publisher_code, publication_code))
+    }
+
+    /// 接頭記号・国記号・出版社記号・書籍コードからISBNを作る
+    /// 書籍コードの桁数は確かめないので、publication_code_lengthの桁数で渡す
+    pub fn from_parts(head_code: String, country_code: String, publisher_code: String, publication_code: String) -> Self {
+        let check_digit_10 = Self::isbn_10_check_digit(&head_code, &country_code, &publisher_code, &publication_code);
+        let check_digit_13 = Self::calc_check_digit_13(&head_code, &country_code, &publisher_code, &publication_code);
+        Isbn { head_code, country_code, publisher_code, publication_code, check_digit_10, check_digit_13 }
+    }
+
+    /// 国記号と出版社記号の桁数から書籍コードの桁数を求める
+    /// 書籍コードの桁が残らない場合はNoneを返す
+    pub fn publication_code_length(country_code: &str, publisher_code: &str) -> Option<usize> {
+        9_usize.checked_sub(country_code.len() + publisher_code.len()).filter(|length| *length > 0)
+    }
+
+    /// 既存のISBN10またはISBN13の文字列を解析する
+    /// ハイフンやスペースで区切られている場合はその区切りで各要素に分割する
+    /// 区切りがない場合はRangeMessageの規則から国記号と出版社記号の桁数を推定する
+    pub fn parse(s: &str) -> Result<Self, ParseIsbnError> {
+        let parts: Vec<&str> = s
+            .split(['-', ' '])
+            .filter(|part| !part.is_empty())
+            .collect();
+        let digits: String = parts.concat();
+
+        validate::check(&digits)?;
+        let length = digits.len();
+        if length == 13 && !digits.starts_with("978") && !digits.starts_with("979") {
+            return Err(ParseIsbnError::InvalidPrefix(digits[..3].to_string()));
+        }
+
+        let (head_code, country_code, publisher_code, publication_code) = if parts.len() > 1 {
+            Self::split_by_separator(&parts, length)?
+        } else {
+            Self::split_by_range(&digits)?
+        };
+
+        if RegistrationGroup::find(&head_code, &country_code).is_none() {
+            return Err(ParseIsbnError::UnknownGroup(format!("{}-{}", head_code, country_code)));
+        }
+        let check_digit_10 = Self::isbn_10_check_digit(&head_code, &country_code, &publisher_code, &publication_code);
+        let check_digit_13 = Self::calc_check_digit_13(&head_code, &country_code, &publisher_code, &publication_code);
+
+        Ok(Isbn { head_code, country_code, publisher_code, publication_code, check_digit_10, check_digit_13 })
+    }
+
+    /// 区切り文字で分割された各要素を、接頭記号・国記号・出版社記号・書籍コードに割り当てる
+    fn split_by_separator(parts: &[&str], length: usize) -> Result<(String, String, String, String), ParseIsbnError> {
+        match (length, parts) {
+            (13, [head, country, publisher, publication, _]) if head.len() == 3 => {
+                Ok((head.to_string(), country.to_string(), publisher.to_string(), publication.to_string()))
+            }
+            // ISBN10は接頭記号を持たないので978とみなす
+            (10, [country, publisher, publication, _]) => {
+                Ok((String::from("978"), country.to_string(), publisher.to_string(), publication.to_string()))
+            }
+            _ => Err(ParseIsbnError::InvalidFormat),
+        }
+    }
+
+    /// 区切りのない数字列を、RangeMessageの規則(日本は出版社一覧も)で国記号と出版社記号の桁数を求めて各要素に分割する
+    fn split_by_range(digits: &str) -> Result<(String, String, String, String), ParseIsbnError> {
+        // ISBN10は978を付けたISBN13として桁数を求める
+        let isbn_13 = if digits.len() == 10 { to_isbn13(digits)? } else { digits.to_string() };
+        let (group_length, registrant_length) =
+            hyphen::segment_lengths(&isbn_13).ok_or(ParseIsbnError::UndefinedRange)?;
+        let head_code = &isbn_13[..3];
+        let body = &isbn_13[3..12];
+        Ok((
+            head_code.to_string(),
+            body[..group_length].to_string(),
+            body[group_length..group_length + registrant_length].to_string(),
+            body[group_length + registrant_length..].to_string(),
+        ))
+    }
+
+    /// 日本の出版社記号の一覧から、このISBNの出版社を探す
+    pub fn publisher(&self) -> Option<&'static Publisher> {
+        if self.head_code != "978" || self.country_code != "4" {
+            return None;
+        }
+        PublisherRegistry::embedded().find_by_code(&self.publisher_code)
+    }
+
+    /// ISBN10またはISBN13の文字列が正しいチェックディジットを持つかを判定する
+    pub fn is_valid(s: &str) -> bool {
+        let digits: String = s.chars().filter(|c| *c != '-' && *c != ' ').collect();
+        verify_check_digit(&digits) == Validation::Valid
+    }
+
+    /// 接頭記号(978など)
+    pub fn head_code(&self) -> &str {
+        &self.head_code
+    }
+
+    /// 国記号(グループ記号)
+    pub fn country_code(&self) -> &str {
+        &self.country_code
+    }
+
+    /// 出版社記号
+    pub fn publisher_code(&self) -> &str {
+        &self.publisher_code
+    }
+
+    /// 書籍コード
+    pub fn publication_code(&self) -> &str {
+        &self.publication_code
+    }
+
+    /// ISBNの書籍コードをランダムで生成する
+    /// 書籍コードの桁数は10 - (国コード + 出版社コード + チェックディジット) で求められる
+    /// 必要な桁数に合わせて足りない桁数は0パディングする
+    /// 書籍コードの桁が残らない場合はTooLongを返す
+    pub fn generate_publication_code(country_code: &str, publisher_code: &str) -> Result<String, IsbnError> {
+        Self::generate_publication_code_with_rng(country_code, publisher_code, &mut rand::thread_rng())
+    }
+
+    /// 渡した乱数生成器で書籍コードを生成する
+    pub fn generate_publication_code_with_rng(
+        country_code: &str,
+        publisher_code: &str,
+        rng: &mut impl Rng,
+    ) -> Result<String, IsbnError> {
+        let publication_code_digit =
+            Self::publication_code_length(country_code, publisher_code).ok_or_else(|| IsbnError::TooLong {
+                group: country_code.to_string(),
+                publisher: publisher_code.to_string(),
+            })?;
+
+        // 書籍コードの桁数がわかったので、10の桁数乗未満の数を選び、足りない桁は0で埋める
+        let max_publication_code = 10_usize.pow(publication_code_digit as u32);
+        let publication_code = rng.gen_range(0..max_publication_code);
+        Ok(format!("{:0width$}", publication_code, width = publication_code_digit))
+    }
+
+    /// ISBN13のチェックディジットの計算
+    pub fn calc_check_digit_13(head_code: &str, country_code: &str, publisher_code: &str, publication_code: &str) -> String {
+        check_digit_13(&(String::new() + head_code + country_code + publisher_code + publication_code)).to_string()
+    }
+
+    /// ISBN10のチェックディジットの計算
+    pub fn calc_check_digit_10(country_code: &str, publisher_code: &str, publication_code: &str) -> String {
+        check_digit_10(&(String::new() + country_code + publisher_code + publication_code)).to_string()
+    }
+
+    /// ISBN10のチェックディジットは接頭記号が978の場合だけ計算する
+    fn isbn_10_check_digit(head_code: &str, country_code: &str, publisher_code: &str, publication_code: &str) -> Option<String> {
+        if head_code == "978" {
+            Some(Self::calc_check_digit_10(country_code, publisher_code, publication_code))
+        } else {
+            None
+        }
+    }
+
+    /// ハイフンなしのISBN10を返す
+    /// 979始まりのISBNにはISBN10の形式がないのでNoneを返す
+    pub fn create_isbn_10(&self) -> Option<String> {
+        let check_digit_10 = self.check_digit_10.as_ref()?;
+        Some(
+            String::new()
+                + &self.country_code
+                + &self.publisher_code
+                + &self.publication_code
+                + check_digit_10,
+        )
+    }
+
+    /// ハイフンなしのISBN13を返す
+    pub fn create_isbn_13(&self) -> String {
+        String::new()
+            + &self.head_code
+            + &self.country_code
+            + &self.publisher_code
+            + &self.publication_code
+            + &self.check_digit_13
+    }
+}
+
+/// チェックディジットを除いたISBN13の12桁からチェックディジットを計算する
+pub(crate) fn check_digit_13(isbn_string_without_check_digit: &str) -> char {
+    // 奇数桁の合計
+    let mut odd_total: usize = 0;
+    for num_char in isbn_string_without_check_digit.chars().step_by(2) {
+        let num = num_char as usize - 48;
+        odd_total += num;
+    };
+
+    // 偶数桁の合計
+    let mut even_total: usize = 0;
+    for num_char in isbn_string_without_check_digit.chars().skip(1).step_by(2) {
+        let num = num_char as usize - 48;
+        even_total += num * 3;
+    };
+
+    // チェックディジットの計算
+    let check_digit_surplus = (odd_total + even_total) % 10;
+    if check_digit_surplus == 0 {
+        '0'
+    } else {
+        char::from(b'0' + (10 - check_digit_surplus) as u8)
+    }
+}
+
+/// チェックディジットを除いたISBN10の9桁からチェックディジットを計算する
+pub(crate) fn check_digit_10(isbn_string_without_check_digit: &str) -> char {
+    let mut total: usize = 0;
+    for (i, num_char) in isbn_string_without_check_digit.chars().enumerate() {
+        let num = num_char as usize - 48;
+        total += num * (10 - i);
+    }
+
+    // チェックディジットの計算
+    let check_digit_surplus = total % 11;
+    if check_digit_surplus == 0 {
+        '0'
+    } else if check_digit_surplus == 1 {
+        'X'
+    } else {
+        char::from(b'0' + (11 - check_digit_surplus) as u8)
+    }
+}
+
+impl FromStr for Isbn {
+    type Err = ParseIsbnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Isbn::parse(s)
+    }
+}
+
+/// ISBN文字列の解析に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseIsbnError {
+    /// 桁数が10桁でも13桁でもない
+    InvalidLength(usize),
+    /// 数字(とISBN10末尾のX)以外の文字が含まれている
+    InvalidCharacter(char),
+    /// 区切り文字で分割した要素の数や接頭記号の桁数が正しくない
+    InvalidFormat,
+    /// チェックディジットが一致しない
+    InvalidCheckDigit { expected: String, found: String },
+    /// 接頭記号が978でも979でもない
+    InvalidPrefix(String),
+    /// 登録グループ(国記号)が存在しない
+    UnknownGroup(String),
+    /// 979で始まるISBN13にはISBN10の形式がない
+    NoIsbn10,
+    /// RangeMessageで割り当てられていない範囲のISBN
+    UndefinedRange,
+    /// 分類コード(Cコード)の内容が割り当てられていない
+    UnknownClassification(String),
+}
+
+impl fmt::Display for ParseIsbnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseIsbnError::InvalidLength(length) => write!(f, "invalid length: {} digits", length),
+            ParseIsbnError::InvalidCharacter(c) => write!(f, "invalid character: {:?}", c),
+            ParseIsbnError::InvalidFormat => write!(f, "invalid hyphenation"),
+            ParseIsbnError::InvalidCheckDigit { expected, found } => {
+                write!(f, "invalid check digit: expected {}, found {}", expected, found)
+            }
+            ParseIsbnError::InvalidPrefix(prefix) => write!(f, "invalid prefix: {}", prefix),
+            ParseIsbnError::UnknownGroup(group) => write!(f, "unknown registration group: {}", group),
+            ParseIsbnError::NoIsbn10 => write!(f, "ISBN-13 with a prefix other than 978 has no ISBN-10 form"),
+            ParseIsbnError::UndefinedRange => write!(f, "ISBN is not in any assigned range"),
+            ParseIsbnError::UnknownClassification(code) => write!(f, "unknown C-code content: {}", code),
+        }
+    }
+}
+
+impl Error for ParseIsbnError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_pubalication_code() {
+        // 最大桁数の場合(7桁)
+        let country_code_7 = String::from("4");   // 日本
+        let publisher_code_7 = String::from("1");
+        let publication_code7: String = Isbn::generate_publication_code(&country_code_7, &publisher_code_7).unwrap();
+        assert!(publication_code7.to_string().len() == 7);
+
+        // 6桁の場合
+        let country_code_6 = String::from("4");
+        let publisher_code_6 = String::from("12");
+        let publication_code6 = Isbn::generate_publication_code(&country_code_6, &publisher_code_6).unwrap();
+        assert!(publication_code6.len() == 6);
+
+        // 5桁の場合
+        let country_code_5 = String::from("4");
+        let publisher_code_5 = String::from("123");
+        let publication_code5 = Isbn::generate_publication_code(&country_code_5, &publisher_code_5).unwrap();
+        assert!(publication_code5.len() == 5);
+
+        // 4桁の場合
+        let country_code_4 = String::from("4");
+        let publisher_code_4 = String::from("1234");
+        let publication_code4 = Isbn::generate_publication_code(&country_code_4, &publisher_code_4).unwrap();
+        assert!(publication_code4.len() == 4);
+    }
+
+    #[test]
+    fn test_generate_publication_code_too_long() {
+        // 国記号と出版社記号で9桁を使い切ると書籍コードの桁が残らない
+        assert!(matches!(
+            Isbn::generate_publication_code("4", "12345678"),
+            Err(IsbnError::TooLong { .. })
+        ));
+        // 9桁を超えても桁数の計算が溢れない
+        assert!(matches!(
+            Isbn::new(String::from("978"), String::from("4"), String::from("123456789")),
+            Err(IsbnError::TooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn test_calc_check_digit_10() {
+        // 4-10-109205
+        let country_code = String::from("4");
+        let publisher_code = String::from("10");
+        let publication_code = String::from("109205");
+
+        let check_digit_10: String = Isbn::calc_check_digit_10(&country_code, &publisher_code, &publication_code);
+        assert_eq!(check_digit_10, String::from("2"));
+    }
+
+    #[test]
+    fn test_calc_check_digit_13() {
+        // 978-4-7981-7154-8
+        let head_code = String::from("978");
+        let country_code = String::from("4");
+        let publisher_code = String::from("7981");
+        let publication_code = String::from("7154");
+        let expected = String::from("8");
+
+        let check_digit_13: String = Isbn::calc_check_digit_13(&head_code, &country_code, &publisher_code, &publication_code);
+        assert_eq!(check_digit_13, expected);
+    }
+
+    #[test]
+    fn test_create_isbn_10() {
+        let isbn = Isbn::new(String::from("978"), String::from("4"), String::from("10")).unwrap();
+        assert!(isbn.create_isbn_10().unwrap().len() == 10);
+
+        // 979始まりのISBNにはISBN10がない
+        let isbn = Isbn::new(String::from("979"), String::from("10"), String::from("323")).unwrap();
+        assert_eq!(isbn.create_isbn_10(), None);
+    }
+
+    #[test]
+    fn test_from_parts() {
+        let isbn = Isbn::from_parts(String::from("978"), String::from("4"), String::from("7981"), String::from("7154"));
+        assert_eq!(isbn.create_isbn_13(), "9784798171548");
+        assert_eq!(isbn.create_isbn_10().as_deref(), Some("4798171549"));
+        assert_eq!(Isbn::publication_code_length("4", "7981"), Some(4));
+        assert_eq!(Isbn::publication_code_length("4", "12345678"), None);
+    }
+
+    #[test]
+    fn test_create_isbn_13() {
+        let isbn = Isbn::new(String::from("978"), String::from("4"), String::from("10")).unwrap();
+        assert!(isbn.create_isbn_13().len() == 13);
+    }
+
+    #[test]
+    fn test_parse_hyphenated() {
+        let isbn: Isbn = "978-4-7981-7154-8".parse().unwrap();
+        assert_eq!(isbn.head_code(), "978");
+        assert_eq!(isbn.country_code(), "4");
+        assert_eq!(isbn.publisher_code(), "7981");
+        assert_eq!(isbn.publication_code(), "7154");
+        assert_eq!(isbn.create_isbn_13(), "9784798171548");
+
+        let isbn = Isbn::parse("4 10 109205 2").unwrap();
+        assert_eq!(isbn.publisher_code(), "10");
+        assert_eq!(isbn.create_isbn_10(), Some(String::from("4101092052")));
+
+        let isbn = Isbn::parse("979-10-323-0569-0").unwrap();
+        assert_eq!(isbn.head_code(), "979");
+        assert_eq!(isbn.country_code(), "10");
+        assert_eq!(isbn.create_isbn_10(), None);
+    }
+
+    #[test]
+    fn test_parse_without_separator() {
+        // 新潮社(10)
+        let isbn = Isbn::parse("4101092052").unwrap();
+        assert_eq!(isbn.publisher_code(), "10");
+        assert_eq!(isbn.publication_code(), "109205");
+        assert_eq!(isbn.create_isbn_13(), "9784101092058");
+        assert_eq!(isbn.publisher().unwrap().name, "新潮社");
+
+        let isbn = Isbn::parse("9784798171548").unwrap();
+        assert_eq!(isbn.publisher_code(), "7981");
+        assert_eq!(isbn.publication_code(), "7154");
+
+        let isbn = Isbn::parse("9780306406157").unwrap();
+        assert_eq!(isbn.country_code(), "0");
+        assert_eq!(isbn.publisher_code(), "306");
+        assert_eq!(isbn.publication_code(), "40615");
+        assert_eq!(isbn.publisher(), None);
+
+        let isbn = Isbn::parse("9791032305690").unwrap();
+        assert_eq!(isbn.country_code(), "10");
+        assert_eq!(isbn.publisher_code(), "323");
+
+        assert_eq!(Isbn::parse("9786999999990"), Err(ParseIsbnError::UndefinedRange));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(Isbn::parse("978-4-7981"), Err(ParseIsbnError::InvalidLength(8)));
+        assert_eq!(Isbn::parse("978-4-7981-715a-8"), Err(ParseIsbnError::InvalidCharacter('a')));
+        assert_eq!(Isbn::parse("97-84-7981-7154-8"), Err(ParseIsbnError::InvalidFormat));
+        assert_eq!(Isbn::parse("977-4-7981-7154-9"), Err(ParseIsbnError::InvalidPrefix(String::from("977"))));
+        assert_eq!(Isbn::parse("979-4-7981-7154-7"), Err(ParseIsbnError::UnknownGroup(String::from("979-4"))));
+        assert_eq!(
+            Isbn::parse("978-4-7981-7154-0"),
+            Err(ParseIsbnError::InvalidCheckDigit { expected: String::from("8"), found: String::from("0") })
+        );
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(Isbn::is_valid("978-4-7981-7154-8"));
+        assert!(Isbn::is_valid("4101092052"));
+        assert!(!Isbn::is_valid("978-4-7981-7154-0"));
+        assert!(!Isbn::is_valid("978-4-7981"));
+    }
+}
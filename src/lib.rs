@@ -0,0 +1,29 @@
+//! ISBNの生成・チェックディジット計算・ハイフン区切りと、出版社記号データの読み込みを提供するライブラリ
+
+mod barcode;
+mod book_code;
+mod c_code;
+mod convert;
+mod drawing;
+mod error;
+mod group;
+mod hyphen;
+mod isbn;
+mod permutation;
+mod publisher;
+pub mod range;
+mod trie;
+mod validate;
+
+pub use barcode::{Ean13, Ean5};
+pub use book_code::{BookCode, PriceCode, PriceFlag};
+pub use c_code::CCode;
+pub use convert::{to_isbn10, to_isbn13};
+pub use drawing::{Bitmap, Drawing, Rect, Text, TEXT_HEIGHT};
+pub use error::{Context, IsbnError};
+pub use group::RegistrationGroup;
+pub use hyphen::hyphenate;
+pub use isbn::{Isbn, ParseIsbnError};
+pub use permutation::Permutation;
+pub use publisher::{Publisher, PublisherRegistry};
+pub use validate::{verify_check_digit, Validation};
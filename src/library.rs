@@ -0,0 +1,566 @@
+//! 手元の蔵書の目録(~/.local/share/isbn/library.sqlite3)
+//! 本ごとに書誌情報と見つけた日時、タグ、評価、メモを保存し、タイトルや著者などで全文検索できるようにする
+//! 読みたい本のリスト(ほしい本)も同じファイルに保存する
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::history::from_json;
+use crate::record::BookRecord;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS books (
+    isbn13 TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    record TEXT NOT NULL,
+    note TEXT,
+    discovered_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    rating INTEGER
+);
+CREATE TABLE IF NOT EXISTS tags (
+    isbn13 TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (isbn13, tag)
+);
+CREATE TABLE IF NOT EXISTS wishlist (
+    isbn13 TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    record TEXT NOT NULL,
+    added_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    done_at INTEGER
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS books_fts USING fts5 (
+    isbn13 UNINDEXED,
+    title,
+    authors,
+    description,
+    tokenize = 'trigram'
+);
+";
+
+/// 蔵書の1冊を読み出す列
+const BOOK_COLUMNS: &str =
+    "record, note, strftime('%Y-%m-%dT%H:%M:%SZ', discovered_at, 'unixepoch'), rating FROM books";
+
+/// 蔵書の1冊の行(タグは別の表から読む)
+fn book_row(row: &rusqlite::Row) -> rusqlite::Result<LibraryBook> {
+    Ok(LibraryBook {
+        record: from_json(row, 0)?,
+        tags: Vec::new(),
+        note: row.get(1)?,
+        discovered_at: row.get(2)?,
+        rating: row.get(3)?,
+    })
+}
+
+/// 全文検索の索引を作り直す1冊
+/// 説明にはシリーズ、出版社、件名とメモを入れる
+fn index(conn: &Connection, isbn_13: &str) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM books_fts WHERE isbn13 = ?1", params![isbn_13])?;
+    let book = conn
+        .query_row("SELECT record, note FROM books WHERE isbn13 = ?1", params![isbn_13], |row| {
+            Ok((from_json::<BookRecord>(row, 0)?, row.get::<_, Option<String>>(1)?))
+        })
+        .optional()?;
+    let Some((record, note)) = book else {
+        return Ok(());
+    };
+    let description: Vec<&str> = [record.series.as_deref(), record.publisher.as_deref()]
+        .into_iter()
+        .flatten()
+        .chain(record.subjects.iter().map(String::as_str))
+        .chain(note.as_deref())
+        .collect();
+    conn.execute(
+        "INSERT INTO books_fts (isbn13, title, authors, description) VALUES (?1, ?2, ?3, ?4)",
+        params![isbn_13, record.title, record.authors.join("\n"), description.join("\n")],
+    )?;
+    Ok(())
+}
+
+/// LIKEで部分一致を探すパターン(%と_はそのままの文字として探す)
+fn like_pattern(term: &str) -> String {
+    let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// 蔵書の1冊
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LibraryBook {
+    #[serde(flatten)]
+    pub record: BookRecord,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    /// 見つけた日時(UTCのRFC 3339、例: 2024-05-01T12:34:56Z)
+    pub discovered_at: String,
+    /// 評価(1〜5)
+    pub rating: Option<u8>,
+}
+
+impl LibraryBook {
+    /// 一覧に表示する「ISBN13 タイトル [タグ] ★評価 見つけた日」の1行
+    pub fn line(&self) -> String {
+        let mut line = format!("{} {}", self.record.isbn, self.record.title);
+        if !self.tags.is_empty() {
+            line.push_str(&format!(" [{}]", self.tags.join(", ")));
+        }
+        if let Some(rating) = self.rating {
+            line.push_str(&format!(" {}", stars(rating)));
+        }
+        line.push(' ');
+        line.push_str(self.discovered_at.get(..10).unwrap_or(&self.discovered_at));
+        line
+    }
+
+    /// 指定したタグがすべて付いているか
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+}
+
+/// 評価を5つの星で表す(例: 3なら★★★☆☆)
+pub fn stars(rating: u8) -> String {
+    let rating = usize::from(rating.min(5));
+    format!("{}{}", "★".repeat(rating), "☆".repeat(5 - rating))
+}
+
+/// ほしい本のリストの1冊
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WishlistBook {
+    #[serde(flatten)]
+    pub record: BookRecord,
+    /// リストに加えた日時(UTCのRFC 3339)
+    pub added_at: String,
+    /// 読んだか手に入れて、済みにした日時
+    pub done_at: Option<String>,
+}
+
+impl WishlistBook {
+    /// 一覧に表示する「ISBN13 タイトル 加えた日」の1行(済みの場合は済みにした日も)
+    pub fn line(&self) -> String {
+        let date = |date: &str| date.get(..10).unwrap_or(date).to_string();
+        let mut line = format!("{} {} {}", self.record.isbn, self.record.title, date(&self.added_at));
+        if let Some(done_at) = &self.done_at {
+            line.push_str(&format!(" (done {})", date(done_at)));
+        }
+        line
+    }
+}
+
+/// SQLiteに保存した蔵書
+pub struct Library {
+    conn: Connection,
+}
+
+impl Library {
+    /// 既定の蔵書ファイルの場所
+    /// XDG_DATA_HOMEが設定されていればその下、なければ ~/.local/share の下
+    pub fn default_path() -> Option<PathBuf> {
+        let data_home = match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("share"),
+        };
+        Some(data_home.join("isbn").join("library.sqlite3"))
+    }
+
+    /// 蔵書ファイルを開く
+    /// ファイルやディレクトリがなければ作る
+    pub fn open(path: &Path) -> rusqlite::Result<Library> {
+        if let Some(dir) = path.parent() {
+            // 作れなかった場合は、開くときのエラーで知らせる
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        // 評価がなかった頃の蔵書ファイルには列を足す
+        let rated: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('books') WHERE name = 'rating'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !rated {
+            conn.execute_batch("ALTER TABLE books ADD COLUMN rating INTEGER")?;
+        }
+        let library = Library { conn };
+        library.rebuild_index()?;
+        Ok(library)
+    }
+
+    /// 全文検索の索引の冊数が蔵書と合わない場合(索引がなかった頃の蔵書ファイルなど)は、索引を作り直す
+    fn rebuild_index(&self) -> rusqlite::Result<()> {
+        let (books, indexed): (usize, usize) = self.conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM books), (SELECT COUNT(*) FROM books_fts)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if books == indexed {
+            return Ok(());
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM books_fts", [])?;
+        let isbns: Vec<String> = tx
+            .prepare("SELECT isbn13 FROM books")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for isbn_13 in &isbns {
+            index(&tx, isbn_13)?;
+        }
+        tx.commit()
+    }
+
+    /// 本を加える
+    /// discovered_atは見つけた日時(UNIX時間、Noneの場合は今)
+    /// すでにある本は書誌情報を新しくしてタグを足し、メモは指定した場合だけ書き換える(見つけた日時は変えない)
+    /// 新しく加えた場合はtrueを返す
+    pub fn add(
+        &self,
+        record: &BookRecord,
+        discovered_at: Option<i64>,
+        tags: &[String],
+        note: Option<&str>,
+    ) -> rusqlite::Result<bool> {
+        let json = serde_json::to_string(record).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let tx = self.conn.unchecked_transaction()?;
+        let exists = tx
+            .query_row("SELECT 1 FROM books WHERE isbn13 = ?1", params![record.isbn], |_| Ok(()))
+            .optional()?
+            .is_some();
+        tx.execute(
+            "INSERT INTO books (isbn13, title, record, note, discovered_at)
+             VALUES (?1, ?2, ?3, ?4, COALESCE(?5, strftime('%s', 'now')))
+             ON CONFLICT (isbn13) DO UPDATE SET
+                 title = excluded.title, record = excluded.record, note = COALESCE(excluded.note, note)",
+            params![record.isbn, record.title, json, note, discovered_at],
+        )?;
+        for tag in tags {
+            tx.execute("INSERT OR IGNORE INTO tags (isbn13, tag) VALUES (?1, ?2)", params![record.isbn, tag])?;
+        }
+        index(&tx, &record.isbn)?;
+        tx.commit()?;
+        Ok(!exists)
+    }
+
+    /// 本を取り除く
+    /// 蔵書になかった場合はfalseを返す
+    pub fn remove(&self, isbn_13: &str) -> rusqlite::Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM tags WHERE isbn13 = ?1", params![isbn_13])?;
+        let removed = tx.execute("DELETE FROM books WHERE isbn13 = ?1", params![isbn_13])?;
+        index(&tx, isbn_13)?;
+        tx.commit()?;
+        Ok(removed > 0)
+    }
+
+    /// 1冊の本
+    pub fn get(&self, isbn_13: &str) -> rusqlite::Result<Option<LibraryBook>> {
+        let sql = format!("SELECT {} WHERE isbn13 = ?1", BOOK_COLUMNS);
+        let book = self.conn.query_row(&sql, params![isbn_13], book_row).optional()?;
+        book.map(|book| self.with_tags(book)).transpose()
+    }
+
+    /// 蔵書の一覧(新しく見つけた順)
+    pub fn list(&self) -> rusqlite::Result<Vec<LibraryBook>> {
+        let mut statement = self.conn.prepare(&format!("SELECT {} ORDER BY discovered_at DESC, isbn13", BOOK_COLUMNS))?;
+        let rows = statement.query_map([], book_row)?;
+        rows.map(|row| row.and_then(|book| self.with_tags(book))).collect()
+    }
+
+    /// 本にタグを付ける
+    /// 蔵書にない場合はfalseを返す
+    pub fn tag(&self, isbn_13: &str, tags: &[String]) -> rusqlite::Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        if tx.query_row("SELECT 1 FROM books WHERE isbn13 = ?1", params![isbn_13], |_| Ok(())).optional()?.is_none() {
+            return Ok(false);
+        }
+        for tag in tags {
+            tx.execute("INSERT OR IGNORE INTO tags (isbn13, tag) VALUES (?1, ?2)", params![isbn_13, tag])?;
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// 本からタグを外す
+    /// 外したタグの数を返す
+    pub fn untag(&self, isbn_13: &str, tags: &[String]) -> rusqlite::Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut removed = 0;
+        for tag in tags {
+            removed += tx.execute("DELETE FROM tags WHERE isbn13 = ?1 AND tag = ?2", params![isbn_13, tag])?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// 本を評価する(Noneの場合は評価を消す)
+    /// 蔵書にない場合はfalseを返す
+    pub fn rate(&self, isbn_13: &str, rating: Option<u8>) -> rusqlite::Result<bool> {
+        let updated = self.conn.execute("UPDATE books SET rating = ?2 WHERE isbn13 = ?1", params![isbn_13, rating])?;
+        Ok(updated > 0)
+    }
+
+    /// タイトル、著者、説明(シリーズ、出版社、件名、メモ)を全文検索する
+    /// 空白で区切った語をすべて含む本を、当てはまる度合いの高い順に返す
+    /// 索引は3文字ずつに区切っているので、2文字以下の語は索引を使わずに部分一致で探す
+    pub fn search(&self, query: &str) -> rusqlite::Result<Vec<LibraryBook>> {
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (long, short): (Vec<&str>, Vec<&str>) = terms.into_iter().partition(|term| term.chars().count() >= 3);
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        if !long.is_empty() {
+            // 語を引用符で囲み、FTS5の演算子としてではなく文字列として探す
+            let phrases: Vec<String> = long.iter().map(|term| format!("\"{}\"", term.replace('"', "\"\""))).collect();
+            values.push(phrases.join(" "));
+            conditions.push(format!("books_fts MATCH ?{}", values.len()));
+        }
+        for term in short {
+            values.push(like_pattern(term));
+            let n = values.len();
+            conditions.push(format!(
+                "(title LIKE ?{n} ESCAPE '\\' OR authors LIKE ?{n} ESCAPE '\\' OR description LIKE ?{n} ESCAPE '\\')"
+            ));
+        }
+        let sql = format!("SELECT isbn13 FROM books_fts WHERE {} ORDER BY rank, isbn13", conditions.join(" AND "));
+        let isbns: Vec<String> = self
+            .conn
+            .prepare(&sql)?
+            .query_map(rusqlite::params_from_iter(&values), |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        let books = isbns.iter().map(|isbn_13| self.get(isbn_13)).collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(books.into_iter().flatten().collect())
+    }
+
+    /// ほしい本のリストに加える
+    /// すでにある本は書誌情報だけを新しくする(加えた日時と済みにした日時は変えない)
+    /// 新しく加えた場合はtrueを返す
+    pub fn wish(&self, record: &BookRecord) -> rusqlite::Result<bool> {
+        let json = serde_json::to_string(record).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let tx = self.conn.unchecked_transaction()?;
+        let exists = tx
+            .query_row("SELECT 1 FROM wishlist WHERE isbn13 = ?1", params![record.isbn], |_| Ok(()))
+            .optional()?
+            .is_some();
+        tx.execute(
+            "INSERT INTO wishlist (isbn13, title, record) VALUES (?1, ?2, ?3)
+             ON CONFLICT (isbn13) DO UPDATE SET title = excluded.title, record = excluded.record",
+            params![record.isbn, record.title, json],
+        )?;
+        tx.commit()?;
+        Ok(!exists)
+    }
+
+    /// ほしい本のリスト(新しく加えた順)
+    /// allがfalseの場合は済みにした本を除く
+    pub fn wishlist(&self, all: bool) -> rusqlite::Result<Vec<WishlistBook>> {
+        let mut statement = self.conn.prepare(
+            "SELECT record, strftime('%Y-%m-%dT%H:%M:%SZ', added_at, 'unixepoch'),
+                    strftime('%Y-%m-%dT%H:%M:%SZ', done_at, 'unixepoch') FROM wishlist
+             WHERE ?1 OR done_at IS NULL
+             ORDER BY added_at DESC, isbn13",
+        )?;
+        let rows = statement.query_map(params![all], |row| {
+            Ok(WishlistBook { record: from_json(row, 0)?, added_at: row.get(1)?, done_at: row.get(2)? })
+        })?;
+        rows.collect()
+    }
+
+    /// ほしい本を済みにする
+    /// リストにない場合と、すでに済みの場合はfalseを返す
+    pub fn done(&self, isbn_13: &str) -> rusqlite::Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE wishlist SET done_at = strftime('%s', 'now') WHERE isbn13 = ?1 AND done_at IS NULL",
+            params![isbn_13],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// 読み出した本に、付いているタグを加える
+    fn with_tags(&self, book: LibraryBook) -> rusqlite::Result<LibraryBook> {
+        let mut statement = self.conn.prepare_cached("SELECT tag FROM tags WHERE isbn13 = ?1 ORDER BY tag")?;
+        let tags = statement.query_map(params![book.record.isbn], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+        Ok(LibraryBook { tags, ..book })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(isbn: &str, title: &str) -> BookRecord {
+        BookRecord { isbn: String::from(isbn), title: String::from(title), ..Default::default() }
+    }
+
+    #[test]
+    fn test_add() {
+        let library = Library::open(Path::new(":memory:")).unwrap();
+        let neko = record("9784101010014", "吾輩は猫である");
+        assert!(library.add(&neko, Some(1714566896), &[String::from("文庫")], Some("再読")).unwrap());
+        // すでにある本はタグを足し、メモと見つけた日時はそのままにする
+        let tags = [String::from("小説"), String::from("文庫")];
+        assert!(!library.add(&neko, None, &tags, None).unwrap());
+
+        let book = library.get("9784101010014").unwrap().unwrap();
+        assert_eq!(book.record, neko);
+        assert_eq!(book.tags, vec![String::from("小説"), String::from("文庫")]);
+        assert_eq!(book.note.as_deref(), Some("再読"));
+        assert_eq!(book.discovered_at, "2024-05-01T12:34:56Z");
+        assert_eq!(book.line(), "9784101010014 吾輩は猫である [小説, 文庫] 2024-05-01");
+        assert_eq!(library.get("9784798171548").unwrap(), None);
+    }
+
+    #[test]
+    fn test_list_and_remove() {
+        let library = Library::open(Path::new(":memory:")).unwrap();
+        library.add(&record("9784101010014", "吾輩は猫である"), Some(1000), &[String::from("文庫")], None).unwrap();
+        library.add(&record("9784798171548", "実践Rustプログラミング入門"), Some(2000), &[], None).unwrap();
+        let books = library.list().unwrap();
+        let isbns: Vec<&str> = books.iter().map(|book| book.record.isbn.as_str()).collect();
+        assert_eq!(isbns, vec!["9784798171548", "9784101010014"]);
+        assert_eq!(books[1].tags, vec![String::from("文庫")]);
+
+        assert!(library.remove("9784101010014").unwrap());
+        assert!(!library.remove("9784101010014").unwrap());
+        assert_eq!(library.list().unwrap().len(), 1);
+        // 取り除いた本のタグは残らない
+        library.add(&record("9784101010014", "吾輩は猫である"), None, &[], None).unwrap();
+        assert!(library.get("9784101010014").unwrap().unwrap().tags.is_empty());
+    }
+
+    #[test]
+    fn test_search() {
+        let library = Library::open(Path::new(":memory:")).unwrap();
+        let neko = BookRecord {
+            authors: vec![String::from("夏目漱石 著")],
+            subjects: vec![String::from("小説 (日本)")],
+            ..record("9784101010014", "吾輩は猫である")
+        };
+        library.add(&neko, None, &[], Some("猫が語り手")).unwrap();
+        let rust = BookRecord { publisher: Some(String::from("秀和システム")), ..record("9784798171548", "実践Rust入門") };
+        library.add(&rust, None, &[], None).unwrap();
+        let search = |query: &str| -> Vec<String> {
+            library.search(query).unwrap().into_iter().map(|book| book.record.isbn).collect()
+        };
+        assert_eq!(search("夏目漱石"), vec!["9784101010014"]);
+        // 大文字と小文字は区別しない
+        assert_eq!(search("rust 入門"), vec!["9784798171548"]);
+        // 2文字以下の語とメモ
+        assert_eq!(search("猫 語り手"), vec!["9784101010014"]);
+        assert_eq!(search("日本"), vec!["9784101010014"]);
+        assert!(search("猫 Rust").is_empty());
+        assert!(search("100%").is_empty());
+        assert!(search("\"").is_empty());
+        assert!(search("  ").is_empty());
+        library.remove("9784101010014").unwrap();
+        assert!(search("夏目漱石").is_empty());
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let dir = std::env::temp_dir().join(format!("isbn-library-test-{}", std::process::id()));
+        let path = dir.join("library.sqlite3");
+        let library = Library::open(&path).unwrap();
+        library.add(&record("9784101010014", "吾輩は猫である"), None, &[], None).unwrap();
+        library.conn.execute("DELETE FROM books_fts", []).unwrap();
+        drop(library);
+        // 開き直すと索引を作り直す
+        let library = Library::open(&path).unwrap();
+        assert_eq!(library.search("吾輩は猫").unwrap().len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_tag_and_rate() {
+        let library = Library::open(Path::new(":memory:")).unwrap();
+        library.add(&record("9784101010014", "吾輩は猫である"), Some(1714566896), &[], None).unwrap();
+        assert!(library.tag("9784101010014", &[String::from("sf"), String::from("文庫")]).unwrap());
+        assert!(!library.tag("9784798171548", &[String::from("sf")]).unwrap());
+        assert_eq!(library.untag("9784101010014", &[String::from("文庫"), String::from("小説")]).unwrap(), 1);
+        assert!(library.rate("9784101010014", Some(4)).unwrap());
+        assert!(!library.rate("9784798171548", Some(4)).unwrap());
+
+        let book = library.get("9784101010014").unwrap().unwrap();
+        assert_eq!(book.tags, vec![String::from("sf")]);
+        assert_eq!(book.rating, Some(4));
+        assert!(book.has_tags(&[String::from("sf")]));
+        assert!(!book.has_tags(&[String::from("sf"), String::from("文庫")]));
+        assert_eq!(book.line(), "9784101010014 吾輩は猫である [sf] ★★★★☆ 2024-05-01");
+
+        library.rate("9784101010014", None).unwrap();
+        assert_eq!(library.list().unwrap()[0].rating, None);
+    }
+
+    #[test]
+    fn test_add_rating_column() {
+        // 評価の列がない蔵書ファイルを開くと列を足す
+        let dir = std::env::temp_dir().join(format!("isbn-library-rating-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("library.sqlite3");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE books (isbn13 TEXT PRIMARY KEY, title TEXT NOT NULL, record TEXT NOT NULL, note TEXT,
+             discovered_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')));",
+        )
+        .unwrap();
+        drop(conn);
+        let library = Library::open(&path).unwrap();
+        library.add(&record("9784101010014", "吾輩は猫である"), None, &[], None).unwrap();
+        assert!(library.rate("9784101010014", Some(5)).unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_stars() {
+        assert_eq!(stars(3), "★★★☆☆");
+        assert_eq!(stars(5), "★★★★★");
+    }
+
+    #[test]
+    fn test_wishlist() {
+        let library = Library::open(Path::new(":memory:")).unwrap();
+        assert!(library.wish(&record("9784101010014", "吾輩は猫である")).unwrap());
+        assert!(!library.wish(&record("9784101010014", "吾輩は猫である 上")).unwrap());
+        library.wish(&record("9784798171548", "実践Rustプログラミング入門")).unwrap();
+        assert_eq!(library.wishlist(false).unwrap().len(), 2);
+
+        assert!(library.done("9784101010014").unwrap());
+        assert!(!library.done("9784101010014").unwrap());
+        assert!(!library.done("9784000000000").unwrap());
+        let wishes = library.wishlist(false).unwrap();
+        assert_eq!(wishes.len(), 1);
+        assert_eq!(wishes[0].record.isbn, "9784798171548");
+        assert_eq!(wishes[0].done_at, None);
+        let all = library.wishlist(true).unwrap();
+        let done = all.iter().find(|wish| wish.record.isbn == "9784101010014").unwrap();
+        assert_eq!(done.record.title, "吾輩は猫である 上");
+        assert!(done.done_at.is_some());
+        // 蔵書とは別に保存する
+        assert!(library.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_wishlist_line() {
+        let mut wish = WishlistBook {
+            record: record("9784101010014", "吾輩は猫である"),
+            added_at: String::from("2024-05-01T12:34:56Z"),
+            done_at: None,
+        };
+        assert_eq!(wish.line(), "9784101010014 吾輩は猫である 2024-05-01");
+        wish.done_at = Some(String::from("2024-06-01T00:00:00Z"));
+        assert_eq!(wish.line(), "9784101010014 吾輩は猫である 2024-05-01 (done 2024-06-01)");
+    }
+
+    #[test]
+    fn test_line() {
+        let book = LibraryBook {
+            record: record("9784101010014", "吾輩は猫である"),
+            tags: Vec::new(),
+            note: None,
+            discovered_at: String::from("2024-05-01T12:34:56Z"),
+            rating: None,
+        };
+        assert_eq!(book.line(), "9784101010014 吾輩は猫である 2024-05-01");
+    }
+}
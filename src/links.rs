@@ -0,0 +1,115 @@
+//! 見つかった本のページを開けるサービスのURL
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::output::booklog_url;
+
+/// 本のページのURLを作れるサービス
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkService {
+    /// 以下のすべてのサービス
+    All,
+    /// ブクログ(ISBN10がある本だけ)
+    Booklog,
+    /// Amazon.co.jp(本のASINはISBN10なので、ISBN10がある本だけ)
+    Amazon,
+    /// honto
+    Honto,
+    /// 紀伊國屋書店ウェブストア
+    Kinokuniya,
+    /// 楽天ブックス
+    Rakuten,
+    /// 読書メーター
+    Bookmeter,
+    /// 国立国会図書館サーチ
+    Ndl,
+}
+
+/// サービスの名前とURL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Link {
+    pub service: LinkService,
+    pub url: String,
+}
+
+impl LinkService {
+    /// Allを除いたすべてのサービス
+    const SERVICES: [LinkService; 7] = [
+        LinkService::Booklog,
+        LinkService::Amazon,
+        LinkService::Honto,
+        LinkService::Kinokuniya,
+        LinkService::Rakuten,
+        LinkService::Bookmeter,
+        LinkService::Ndl,
+    ];
+
+    /// 本のページのURL
+    /// ISBN10が必要なサービスでISBN10がない場合(979始まりのISBN)と、Allの場合はNoneを返す
+    pub fn url(self, isbn13: &str, isbn10: Option<&str>) -> Option<String> {
+        match self {
+            LinkService::All => None,
+            LinkService::Booklog => isbn10.and_then(booklog_url),
+            LinkService::Amazon => isbn10.map(|isbn10| format!("https://www.amazon.co.jp/dp/{}", isbn10)),
+            LinkService::Honto => Some(format!("https://honto.jp/netstore/search.html?k={}", isbn13)),
+            LinkService::Kinokuniya => Some(format!("https://www.kinokuniya.co.jp/f/dsg-01-{}", isbn13)),
+            LinkService::Rakuten => Some(format!("https://books.rakuten.co.jp/search?sitem={}", isbn13)),
+            LinkService::Bookmeter => Some(format!("https://bookmeter.com/search?keyword={}", isbn13)),
+            LinkService::Ndl => Some(format!("https://ndlsearch.ndl.go.jp/api/openurl?isbn={}", isbn13)),
+        }
+    }
+}
+
+/// 指定したサービスの本のページのURL
+/// Allはすべてのサービスにし、同じサービスを複数指定した場合は1つにする
+pub fn links(services: &[LinkService], isbn13: &str, isbn10: Option<&str>) -> Vec<Link> {
+    let mut expanded: Vec<LinkService> = Vec::new();
+    for service in services {
+        let services: &[LinkService] =
+            if *service == LinkService::All { &LinkService::SERVICES } else { std::slice::from_ref(service) };
+        for service in services {
+            if !expanded.contains(service) {
+                expanded.push(*service);
+            }
+        }
+    }
+    expanded
+        .into_iter()
+        .filter_map(|service| Some(Link { service, url: service.url(isbn13, isbn10)? }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url() {
+        let isbn10 = Some("4101010013");
+        assert_eq!(
+            LinkService::Amazon.url("9784101010014", isbn10).as_deref(),
+            Some("https://www.amazon.co.jp/dp/4101010013")
+        );
+        assert_eq!(
+            LinkService::Kinokuniya.url("9784101010014", isbn10).as_deref(),
+            Some("https://www.kinokuniya.co.jp/f/dsg-01-9784101010014")
+        );
+        // 979始まりのISBNにはISBN10がない
+        assert_eq!(LinkService::Booklog.url("9791234567896", None), None);
+        assert!(LinkService::Ndl.url("9791234567896", None).is_some());
+    }
+
+    #[test]
+    fn test_links() {
+        let services = [LinkService::Booklog, LinkService::All, LinkService::Amazon];
+        let links = links(&services, "9784101010014", Some("4101010013"));
+        assert_eq!(links.len(), 7);
+        let booklog = Link { service: LinkService::Booklog, url: String::from("https://booklog.jp/item/1/4101010013") };
+        assert_eq!(links[0], booklog);
+        assert_eq!(links[1].service, LinkService::Amazon);
+        assert_eq!(super::links(&[LinkService::Amazon], "9791234567896", None), Vec::new());
+    }
+}
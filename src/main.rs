@@ -1,254 +1,2588 @@
-use csv;
-use serde::Deserialize;
-use std::error::Error;
-use rand::Rng;
-use xmltree::Element;
-
-#[derive(Debug)]
-struct Isbn {
-    head_code: String,
-    country_code: String,
-    publisher_code: String,
-    publication_code: String,
-    check_digit_10: String,
-    check_digit_13: String,
-}
-
-impl Isbn {
-    fn new(head_code: String, country_code: String, publisher_code: String) -> Self {
-        let publication_code = Self::generate_publication_code(&country_code, &publisher_code);
-        let check_digit_10 = Self::calc_check_digit_10(&country_code, &publisher_code, &publication_code);
-        let check_digit_13 = Self::calc_check_digit_13(&head_code, &country_code, &publisher_code, &publication_code);
-        Isbn { head_code, country_code, publisher_code, publication_code, check_digit_10, check_digit_13 }
-    }
-
-    /// ISBNの書籍コードをランダムで生成する
-    /// 書籍コードの桁数は10 - (国コード + 出版社コード + チェックディジット) で求められる
-    /// 必要な桁数に合わせて足りない桁数は0パディングする
-    fn generate_publication_code(country_code: &String, publisher_code: &String) -> String {
-        let country_code_digit = country_code.len();
-        let publisher_code_digit = publisher_code.len();
-        let publication_code_digit = 10 - (country_code_digit + publisher_code_digit + 1);
-
-        // 書籍コードの桁数がわかったので、桁数+1分の100...の文字列を作る
-        let mut max_publication_code_string = String::from("1");
-        for _ in 1..=publication_code_digit {
-            max_publication_code_string.push_str("0");
-        };
-        let max_publication_code: usize = max_publication_code_string.parse().unwrap();
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::stream::{self, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use isbn::{
+    hyphenate, to_isbn10, to_isbn13, verify_check_digit, BookCode, CCode, Context, Drawing, Ean13, Ean5, Isbn,
+    IsbnError, ParseIsbnError, Permutation, PriceCode, Publisher, RegistrationGroup, Validation,
+};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, trace, warn};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+
+mod backup;
+mod booklog;
+mod calibre;
+mod checkpoint;
+mod clipboard;
+mod config;
+mod cover;
+mod explore;
+mod export;
+mod filter;
+mod history;
+mod library;
+mod metrics;
+mod links;
+mod opds;
+mod output;
+mod pdf;
+mod preview;
+mod provider;
+mod publisher_list;
+mod qr;
+mod raster;
+mod record;
+mod server;
+mod throttle;
+
+use backup::Item;
+use checkpoint::Checkpoint;
+use clipboard::CopyTarget;
+use config::Config;
+use filter::{Material, RecordFilter};
+use history::History;
+use library::{stars, Library, LibraryBook, WishlistBook};
+use links::LinkService;
+use output::{print_json, print_json_line, Discovery, GoodreadsRow, OutputFormat, Printer, RunSummary};
+use pdf::LabelSheet;
+use preview::PreviewProtocol;
+
+use provider::calil::{Availability, Calil};
+use provider::googlebooks::GoogleBooks;
+use provider::ndl::Ndl;
+use provider::openbd::OpenBd;
+use provider::openlibrary::OpenLibrary;
+use provider::rakuten::RakutenBooks;
+use provider::ratelimit::RateLimiter;
+use provider::retry::{RetryPolicy, Retrying};
+use provider::sru::{CqlQuery, NdlSru, RecordSchema};
+use provider::{MetadataProvider, ProviderChain};
+use record::BookRecord;
+use server::AppState;
+use throttle::{Limit, Throttle};
+
+/// ISBNからランダムに本を探すツール
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// 設定ファイル(省略時は ~/.config/isbn/config.toml があれば読み込む)
+    /// 設定ファイルの項目はISBN_PROVIDERのようなISBN_で始まる環境変数でも上書きできる
+    #[arg(long, global = true, env = "ISBN_CONFIG")]
+    config: Option<PathBuf>,
+    /// 書誌情報の検索に使うAPI(カンマ区切りで複数指定すると、見つからなかった場合に順番に試す)
+    /// [default: ndl]
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    provider: Vec<Provider>,
+    /// 楽天ウェブサービスのアプリID(楽天ブックスで検索する場合に必要)
+    #[arg(long, global = true, env = "RAKUTEN_APP_ID", hide_env_values = true)]
+    rakuten_app_id: Option<String>,
+    /// 楽天アフィリエイトID(指定するとアフィリエイトURLを表示する)
+    #[arg(long, global = true, env = "RAKUTEN_AFFILIATE_ID", hide_env_values = true)]
+    rakuten_affiliate_id: Option<String>,
+    /// カーリルのアプリケーションキー(図書館の所蔵を調べる場合に必要)
+    #[arg(long, global = true, env = "CALIL_APP_KEY", hide_env_values = true)]
+    calil_app_key: Option<String>,
+    /// 所蔵を調べる図書館システムのID(カンマ区切り、例: Tokyo_Setagaya,Tokyo_Meguro)
+    #[arg(long, global = true, env = "CALIL_SYSTEMS", value_delimiter = ',')]
+    calil_systems: Vec<String>,
+    /// 見つかった本と同じ著者の本や同じ分類の本を、国立国会図書館サーチのSRUでN件ずつ探して一緒に表示する
+    #[arg(long, global = true, value_name = "N", num_args = 0..=1, default_missing_value = "5")]
+    explore: Option<usize>,
+    /// 見つかった本がシリーズの2巻目以降の場合は、国立国会図書館サーチのSRUで1巻目を探して一緒に表示する
+    #[arg(long, global = true)]
+    first_volume: bool,
+    /// 国立国会図書館サーチのSRUで取得する書誌の形式(dcndlとmarcxmlは件名や国立国会図書館分類、形態を含む)
+    /// --provider ndlsruと、search、by-author、search-randomで使う [default: dcndl-simple]
+    #[arg(long, global = true, value_enum, value_name = "SCHEMA")]
+    sru_schema: Option<RecordSchema>,
+    /// 見つかった本の出力形式 [default: text]
+    #[arg(long, global = true, value_enum)]
+    format: Option<OutputFormat>,
+    /// 見つかった本のURLを表示するサービス(カンマ区切り、allですべて) [default: booklog]
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    links: Vec<LinkService>,
+    /// 見つかった本のページを既定のブラウザで開く(サービスを省略した場合は--linksの最初のサービス)
+    #[arg(long, global = true, value_enum, value_name = "SERVICE")]
+    open: Option<Option<LinkService>>,
+    /// 見つかった本のURL(またはISBN)をクリップボードにコピーする
+    #[arg(long, global = true, value_enum, value_name = "WHAT", num_args = 0..=1, default_missing_value = "url")]
+    copy: Option<CopyTarget>,
+    /// 本が見つかるたびにデスクトップ通知でタイトルとURLを知らせる(長い時間探す場合に)
+    #[arg(long, global = true)]
+    notify: bool,
+    /// 見つかった本のURLのQRコードを端末に表示する(PATHを指定した場合はPNGかSVGで書き出し、{isbn}はISBN13にする)
+    #[arg(long, global = true, value_name = "PATH")]
+    qr: Option<Option<PathBuf>>,
+    /// 見つかった本の書影(openBDやGoogle Booksなどが返した場合)を、ディレクトリに「ISBN13.jpg」などの名前で保存する
+    #[arg(long, global = true, value_name = "DIR")]
+    save_cover: Option<PathBuf>,
+    /// 見つかった本ごとに、Calibreのライブラリに追加できるmetadata.opfと書影を「DIR/ISBN13/」に書き出す
+    #[arg(long, global = true, value_name = "DIR")]
+    calibre: Option<PathBuf>,
+    /// 見つかった本の書影を端末に表示する(kittyやsixelに対応していない端末では色付きのブロックで描く)
+    #[arg(long, global = true, value_name = "PROTOCOL", num_args = 0..=1, default_missing_value = "auto")]
+    preview_cover: Option<PreviewProtocol>,
+    /// 見つかった本を1件ずつ書き出すHandlebarsのテンプレート(例: '{{title}} - {{booklog_url}}')
+    /// 指定した場合は--formatより優先する
+    #[arg(long, global = true)]
+    template: Option<String>,
+    /// 本が見つからなかった場合に問い合わせるISBNの数の上限(まとめて問い合わせるAPIでも1件ずつ数える) [default: 10]
+    #[arg(long, global = true)]
+    max_attempts: Option<usize>,
+    /// 見つかるまで問い合わせを繰り返す
+    #[arg(long, global = true, conflicts_with = "max_attempts")]
+    no_limit: bool,
+    /// 問い合わせの間隔(ミリ秒、同時に問い合わせる場合もこの間隔より短くは始めない) [default: 1000]
+    #[arg(long, global = true, value_name = "MILLISECONDS")]
+    interval: Option<u64>,
+    /// ランダムに本を探すときに同時に問い合わせる数 [default: 1]
+    #[arg(long, global = true)]
+    concurrency: Option<usize>,
+    /// HTTP(S)プロキシのURL(省略時は環境変数HTTP_PROXY, HTTPS_PROXY, NO_PROXYに従う)
+    #[arg(long, global = true, value_name = "URL")]
+    proxy: Option<String>,
+    /// 接続のタイムアウト(秒) [default: 10]
+    #[arg(long, global = true, value_name = "SECONDS")]
+    connect_timeout: Option<u64>,
+    /// 1回のリクエスト全体のタイムアウト(秒) [default: 30]
+    #[arg(long, global = true, value_name = "SECONDS")]
+    timeout: Option<u64>,
+    /// 一時的なエラー(429・5xx、タイムアウト、接続の失敗)で失敗したリクエストを再試行する回数 [default: 3]
+    #[arg(long, global = true)]
+    max_retries: Option<u32>,
+    /// 試したISBNと見つかった本を記録する履歴ファイル(省略時は ~/.local/share/isbn/history.sqlite3)
+    /// 履歴にあるISBNは、次に実行したときに問い合わせない
+    #[arg(long, global = true, value_name = "PATH")]
+    history: Option<PathBuf>,
+    /// 履歴を読み書きしない
+    #[arg(long, global = true, conflicts_with = "history")]
+    no_history: bool,
+    /// libraryとwishlistサブコマンドで使う蔵書の目録のファイル(省略時は ~/.local/share/isbn/library.sqlite3)
+    #[arg(long, global = true, value_name = "PATH")]
+    library: Option<PathBuf>,
+    /// 見つかった本を、蔵書の目録のファイルのほしい本のリストに加える
+    #[arg(long, global = true)]
+    wishlist: bool,
+    /// ISBNを生成する乱数のシード(同じシードなら同じ順番でISBNを生成する)
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+    /// 出版社一覧のCSV(ヘッダー行のない「出版社記号,出版社名」、省略時は埋め込みのものか `publishers update` で更新したもの)
+    #[arg(long, global = true, value_name = "PATH")]
+    publisher_file: Option<PathBuf>,
+    /// 日本の出版社をランダムに選ぶときの重み [default: uniform]
+    #[arg(long, global = true, value_enum)]
+    publisher_weight: Option<PublisherWeight>,
+    /// 日本の出版社をランダムに選ぶときに、出版社名がこの正規表現に一致する出版社だけから選ぶ(例: '文庫|コミック')
+    #[arg(long, global = true, value_name = "REGEX")]
+    publisher_filter: Option<Regex>,
+    /// エラー出力に書き出すログの詳しさ(debugでリクエストごとの所要時間、traceでレスポンスの内容も出す) [default: warn]
+    #[arg(long, global = true, value_enum)]
+    log_level: Option<LogLevel>,
+}
 
-        let mut rng = rand::thread_rng();
-        let publication_code = rng.gen_range(0..max_publication_code).to_string();
-        let digit_diff: usize = (max_publication_code_string.len() - 1) - publication_code.len();
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// ランダムなISBNを生成して実在する本を探す(サブコマンド省略時の動作)
+    RandomBook {
+        /// 接頭記号(978または979) [default: 978]
+        #[arg(long)]
+        prefix: Option<String>,
+        /// 国記号 [default: 4]
+        #[arg(long)]
+        group: Option<String>,
+        /// 出版社記号(指定するとこの出版社の本だけを探す)
+        #[arg(long)]
+        publisher: Option<String>,
+        /// 出版社の書籍コードを0から順番にすべて試す(--publisherが必要)
+        #[arg(long, requires = "publisher")]
+        scan: bool,
+        /// --scanで書籍コードをランダムな順番に並べ替えて試す(どの書籍コードも1回ずつ試す)
+        #[arg(long, requires = "scan")]
+        shuffle: bool,
+        /// 見つける本の冊数(見つかるたびに書き出す) [default: 1、--scanの場合はすべて]
+        #[arg(long)]
+        count: Option<usize>,
+        /// 中断した実行をチェックポイントから再開する(探し方と冊数はチェックポイントのものを使う)
+        #[arg(long, conflicts_with_all = ["prefix", "group", "publisher", "scan", "shuffle", "count"])]
+        resume: bool,
+        /// 進み具合を書き出すチェックポイントファイル(省略時は ~/.local/share/isbn/checkpoint.json)
+        #[arg(long, value_name = "PATH")]
+        checkpoint: Option<PathBuf>,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// ランダムなISBNを生成する(通信はしない)
+    Generate {
+        /// 接頭記号(978または979) [default: 978]
+        #[arg(long)]
+        prefix: Option<String>,
+        /// 国記号 [default: 4]
+        #[arg(long)]
+        group: Option<String>,
+        /// 出版社記号(省略時は日本なら出版社一覧から、それ以外は割り当て済みの範囲からランダムに選ぶ)
+        #[arg(long)]
+        publisher: Option<String>,
+        /// 生成する件数
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+    },
+    /// ISBNのチェックディジットとハイフン位置を検証する
+    /// ISBNもファイルも指定しない場合は標準入力から1行1件で読み込む
+    Validate {
+        isbns: Vec<String>,
+        /// 1行1件でISBNを書いたファイル(`-` で標準入力)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+    /// ISBN10とISBN13を相互に変換する
+    /// ISBNを指定しない場合は標準入力から1行1件で読み込み、1行1件で出力する
+    Convert {
+        isbns: Vec<String>,
+        /// 変換先の形式(省略時は入力と逆の形式)
+        #[arg(long)]
+        to: Option<IsbnForm>,
+    },
+    /// 指定したISBNの本を検索し、書誌情報とBooklogのURLを表示する
+    Lookup {
+        isbn: String,
+    },
+    /// 画像(JPEGかPNG)のEAN-13バーコードからISBNを読み取り、lookupと同じように本を検索する
+    Scan {
+        /// バーコードを写した画像ファイル
+        image: PathBuf,
+    },
+    /// ISBNをハイフン区切りにする
+    Hyphenate {
+        isbn: String,
+    },
+    /// ISBNのEAN-13バーコードをSVGかPNGで書き出す(バーの上にISBN、下に数字を書く)
+    Barcode {
+        /// ISBN13(ISBN10はISBN13にしてから描く)
+        isbn: String,
+        /// 書き出すファイル(拡張子が.pngならPNG、それ以外はSVG、省略時は標準出力にSVG)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// 1モジュール(いちばん細いバー)の幅のピクセル数
+        #[arg(long, default_value_t = 3)]
+        scale: u32,
+        /// 分類コード(C0193など、--priceと一緒に指定すると書籍JANコードの2段目も下に描く)
+        #[arg(long, value_name = "CODE", requires = "price")]
+        c_code: Option<String>,
+        /// 本体価格(円)
+        #[arg(long, value_name = "YEN", requires = "c_code")]
+        price: Option<u32>,
+        /// 価格などを表す5桁のアドオン(EAN-5、52495ならUSD 24.95)を右に描く
+        #[arg(long, value_name = "DIGITS", conflicts_with = "c_code")]
+        addon: Option<String>,
+    },
+    /// ブクログの本棚のエクスポート(CSV)を読み込み、持っている本としてランダムに本を探すときに除く
+    /// 持っている本は履歴に記録する(--no-historyを指定した場合は除かない)
+    ImportBooklog {
+        /// ブクログの「本棚のエクスポート」でダウンロードしたCSV(Shift_JISかUTF-8)
+        file: PathBuf,
+    },
+    /// 履歴の見つかった本を、電子書籍リーダーのアプリで読み込めるOPDS 1.2の取得用フィードで書き出す
+    Opds {
+        /// 書き出すファイル(省略時は標準出力)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// ランダムに本を探す、ISBNで書誌を探す、ISBNを検証・変換するAPIをJSONで返すHTTPサーバーを動かす
+    /// GET /random、/lookup/{isbn}、/validate/{isbn}、/convert/{isbn}に応える
+    /// GET /eventsは問い合わせたISBNと見つかった本をServer-Sent Eventsで流す
+    /// /wsのWebSocketでは探し始めたり止めたり、受け入れる条件を変えたりしながら見つかった本を受け取れる
+    /// APIの仕様は/openapi.jsonのOpenAPIの文書と、/docsのSwagger UIで読める
+    /// /metricsは問い合わせた数や見つかった数、リクエストの所要時間をPrometheusの形式で返す
+    /// /healthzは動いているか、/readyzは出版社一覧を読み込めていて書誌情報のAPIに問い合わせられるかを返す
+    /// /randomは1回のリクエストごとに--max-attempts件のISBNまで問い合わせる
+    /// APIのリクエストは--ip-rate-limitと--global-rate-limitで数を制限でき、超えるとRetry-Afterをつけて429を返す
+    Serve {
+        /// 待ち受けるアドレス
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+        /// 1つのIPアドレスから受け付ける、1分あたりのリクエストの数(省略時は制限しない)
+        #[arg(long, value_name = "PER_MINUTE")]
+        ip_rate_limit: Option<NonZeroU32>,
+        /// 1つのIPアドレスから続けて受け付けるリクエストの数(省略時は1分間分)
+        #[arg(long, value_name = "COUNT")]
+        ip_rate_burst: Option<NonZeroU32>,
+        /// すべてのクライアントを通して受け付ける、1分あたりのリクエストの数(省略時は制限しない)
+        #[arg(long, value_name = "PER_MINUTE")]
+        global_rate_limit: Option<NonZeroU32>,
+        /// すべてのクライアントを通して続けて受け付けるリクエストの数(省略時は1分間分)
+        #[arg(long, value_name = "COUNT")]
+        global_rate_burst: Option<NonZeroU32>,
+    },
+    /// 設定ファイル、履歴、蔵書を1つのファイル(SQLite Archive)にまとめて書き出す
+    Backup {
+        /// 書き出すファイル(すでにあれば作り直す)
+        file: PathBuf,
+    },
+    /// backupで書き出したファイルから設定ファイル、履歴、蔵書を、このマシンでの場所に書き戻す
+    /// 書き戻す場所は--config、--history、--libraryと同じように決める
+    Restore {
+        file: PathBuf,
+        /// すでにあるファイルを上書きする
+        #[arg(long)]
+        force: bool,
+    },
+    /// ISBNのバーコードをラベル用紙に並べたPDFを書き出す(1枚に1冊、大きさの単位はmm)
+    Labels {
+        isbns: Vec<String>,
+        /// 1行1件でISBNを書いたファイル(`-` で標準入力)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// 書き出すPDFファイル
+        #[arg(short, long, value_name = "PATH")]
+        output: PathBuf,
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        columns: u32,
+        #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..))]
+        rows: u32,
+        #[arg(long, default_value_t = 210.0)]
+        page_width: f64,
+        #[arg(long, default_value_t = 297.0)]
+        page_height: f64,
+        /// 用紙の左右の余白
+        #[arg(long, default_value_t = 7.0)]
+        margin_x: f64,
+        /// 用紙の上下の余白
+        #[arg(long, default_value_t = 10.0)]
+        margin_y: f64,
+    },
+    /// 書籍JANコードの2段目(192か191で始まる13桁)を分類コードと価格にする
+    BookCode {
+        code: String,
+    },
+    /// 分類コード(Cコード)を確かめ、販売対象・発行形態・内容を表示する
+    CCode {
+        /// C0193や0193の形式の分類コード
+        code: String,
+    },
+    /// 国立国会図書館サーチのSRUで条件を指定して本を検索する
+    Search {
+        /// CQLの検索式(指定した場合は他の条件は無視する)
+        #[arg(long)]
+        cql: Option<String>,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        creator: Option<String>,
+        #[arg(long)]
+        publisher: Option<String>,
+        /// 日本十進分類法の分類記号(前方一致)
+        #[arg(long)]
+        ndc: Option<String>,
+        /// 出版年の範囲の始まり(YYYY, YYYY-MM, YYYY-MM-DD)
+        #[arg(long)]
+        from: Option<String>,
+        /// 出版年の範囲の終わり
+        #[arg(long)]
+        until: Option<String>,
+        /// 表示する最大件数
+        #[arg(long, default_value_t = 10)]
+        max: usize,
+    },
+    /// 国立国会図書館サーチのSRUで、著者の本からランダムに1冊選んで表示する
+    ByAuthor {
+        /// 著者名
+        author: String,
+    },
+    /// 国立国会図書館サーチのSRUで、キーワードに当てはまる本からランダムに1冊選んで表示する
+    SearchRandom {
+        /// タイトルや著者、件名などのいずれかに含まれる語
+        keyword: String,
+    },
+    /// 手元の蔵書の目録(書誌情報と見つけた日時、タグ、メモ)を管理する
+    Library {
+        #[command(subcommand)]
+        command: LibraryCommand,
+    },
+    /// ほしい本のリストを管理する(蔵書の目録のファイルに保存する)
+    Wishlist {
+        #[command(subcommand)]
+        command: WishlistCommand,
+    },
+    /// 出版社一覧(日本の出版社)を出版社記号・出版社名・出版社記号の桁数で表示する
+    /// --publisher-fileか、`publishers update` で更新した出版社一覧があれば、埋め込みのものの代わりに使う
+    Publishers {
+        #[command(subcommand)]
+        command: Option<PublishersCommand>,
+        /// 出版社名の一部か、出版社記号の先頭(省略時はすべて表示する)
+        query: Option<String>,
+        /// 並べ替える項目
+        #[arg(long, value_enum, default_value_t = PublisherSort::Code)]
+        sort: PublisherSort,
+        /// 逆順に並べる
+        #[arg(long)]
+        reverse: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum PublishersCommand {
+    /// 出版社一覧を取得し直して ~/.local/share/isbn/publishers.csv に書き出す
+    /// 既定ではWikidataのISBN出版者記号(P3035)の978-4-で始まるものを取得し、Wikidataにない出版社記号は埋め込みのものを残す
+    Update {
+        /// Wikidataの代わりに使う出版社一覧のCSVかTSVのURL(1列目が出版社記号、2列目が出版社名で、3列目以降は無視する)
+        #[arg(long, env = "ISBN_PUBLISHER_LIST_URL")]
+        url: Option<String>,
+        /// 書き出すファイル(省略時は ~/.local/share/isbn/publishers.csv)
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum LibraryCommand {
+    /// 本を蔵書に加える(履歴にある本は履歴の書誌情報と見つかった日時を使い、なければ検索する)
+    /// すでにある本は書誌情報を新しくしてタグを足す
+    Add {
+        isbn: String,
+        /// 付けるタグ(カンマ区切りで複数指定できる)
+        #[arg(long, value_delimiter = ',')]
+        tag: Vec<String>,
+        /// メモ
+        #[arg(long)]
+        note: Option<String>,
+    },
+    /// 蔵書を新しく見つけた順に表示する
+    List {
+        /// このタグが付いた本だけを表示する(カンマ区切りで複数指定した場合はすべてが付いた本)
+        #[arg(long, value_delimiter = ',')]
+        tag: Vec<String>,
+    },
+    /// 蔵書の本にタグを付ける
+    Tag {
+        isbn: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+        /// タグを付ける代わりに外す
+        #[arg(long)]
+        remove: bool,
+    },
+    /// 蔵書の本を1〜5で評価する
+    Rate {
+        isbn: String,
+        #[arg(value_parser = clap::value_parser!(u8).range(1..=5), required_unless_present = "clear")]
+        rating: Option<u8>,
+        /// 評価を消す
+        #[arg(long, conflicts_with = "rating")]
+        clear: bool,
+    },
+    /// 本を蔵書から取り除く
+    Remove {
+        isbn: String,
+    },
+    /// 蔵書の本の書誌情報とタグ、メモを表示する
+    Show {
+        isbn: String,
+    },
+    /// 蔵書をタイトル、著者、シリーズ、出版社、件名、メモで全文検索する
+    Search {
+        /// 探す語(複数指定した場合はすべてを含む本を探す)
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// このタグが付いた本だけを表示する(カンマ区切りで複数指定した場合はすべてが付いた本)
+        #[arg(long, value_delimiter = ',')]
+        tag: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum WishlistCommand {
+    /// 本をほしい本のリストに加える(履歴にある本は履歴の書誌情報を使い、なければ検索する)
+    Add {
+        isbn: String,
+    },
+    /// ほしい本を新しく加えた順に表示する
+    List {
+        /// 済みにした本も表示する
+        #[arg(long)]
+        all: bool,
+    },
+    /// ほしい本を済みにする(読んだか手に入れた場合)
+    Done {
+        isbn: String,
+    },
+}
+
+/// 出版社一覧を並べ替える項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PublisherSort {
+    /// 出版社記号(ISBNの順)
+    Code,
+    Name,
+    /// 出版社記号の桁数
+    Length,
+}
+
+/// 見つかった本を受け入れる条件
+#[derive(Debug, Clone, Default, clap::Args)]
+struct FilterArgs {
+    /// 日本十進分類法の分類記号(前方一致、例: 913)に当てはまる本だけを受け入れる
+    #[arg(long)]
+    ndc: Option<String>,
+    /// この年以降に出版された本だけを受け入れる
+    #[arg(long, value_name = "YEAR")]
+    from_year: Option<u32>,
+    /// この年までに出版された本だけを受け入れる
+    #[arg(long, value_name = "YEAR")]
+    until_year: Option<u32>,
+    /// この種別の資料だけを受け入れる(国立国会図書館サーチの書誌の資料種別か、openBDの商品の形態で判断する)
+    #[arg(long, value_enum)]
+    material: Option<Material>,
+    /// 本文がこの言語(ISO 639-2の記号、例: jpn,eng)の本だけを受け入れる
+    #[arg(long, value_delimiter = ',')]
+    language: Vec<String>,
+    /// 価格がこの金額(円)以下の本だけを受け入れる(価格はopenBDと楽天ブックスの書誌にある)
+    #[arg(long, value_name = "YEN")]
+    max_price: Option<u32>,
+    /// ページ数がこの数以上の本だけを受け入れる
+    #[arg(long)]
+    min_pages: Option<u32>,
+    /// ページ数がこの数以下の本だけを受け入れる
+    #[arg(long)]
+    max_pages: Option<u32>,
+}
+
+impl From<FilterArgs> for RecordFilter {
+    fn from(args: FilterArgs) -> Self {
+        RecordFilter {
+            ndc: args.ndc,
+            from_year: args.from_year,
+            until_year: args.until_year,
+            material: args.material,
+            languages: args.language,
+            max_price: args.max_price,
+            min_pages: args.min_pages,
+            max_pages: args.max_pages,
+        }
+    }
+}
 
-        if digit_diff == 0 {
-            publication_code
+/// 書誌情報の検索に使うAPI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Provider {
+    /// 国立国会図書館サーチ(OpenSearch)
+    Ndl,
+    /// 国立国会図書館サーチ(SRU)
+    Ndlsru,
+    /// openBD
+    Openbd,
+    /// Open Library
+    Openlibrary,
+    /// 楽天ブックス(アプリIDが必要)
+    Rakuten,
+    /// Google Books
+    Googlebooks,
+}
+
+/// ログの詳しさ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// ログをエラー出力に書き出すようにする
+/// reqwestなど依存しているクレートのログは出さない
+/// すでに設定されている場合(テストで何度も実行した場合)はそのままにする
+fn init_tracing(level: LogLevel) {
+    let filter = Targets::new().with_target("isbn", LevelFilter::from(level));
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(io::stderr).with_ansi(io::stderr().is_terminal()))
+        .with(filter)
+        .try_init()
+        .ok();
+}
+
+/// 出版社をランダムに選ぶときの重み
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PublisherWeight {
+    /// どの出版社も同じ確率で選ぶ
+    #[default]
+    Uniform,
+    /// 書籍コードの数(出版社記号が短いほど多い)に比例した確率で選ぶ
+    Size,
+}
+
+impl PublisherWeight {
+    /// 出版社を選ぶ重み
+    fn of(self, publisher: &Publisher) -> f64 {
+        match self {
+            PublisherWeight::Uniform => 1.0,
+            PublisherWeight::Size => {
+                Isbn::publication_code_length("4", &publisher.code).map_or(0.0, |length| 10f64.powi(length as i32))
+            }
+        }
+    }
+}
+
+/// 日本の出版社をランダムに選ぶときの選び方
+#[derive(Debug, Clone, Default)]
+struct PublisherSelection {
+    weight: PublisherWeight,
+    /// 出版社名がこの正規表現に一致する出版社だけから選ぶ
+    filter: Option<Regex>,
+    /// この出版社記号の出版社だけから選ぶ
+    allow: Option<HashSet<String>>,
+    /// 選ばない出版社記号
+    block: HashSet<String>,
+}
+
+impl PublisherSelection {
+    /// 出版社を選ぶ重み(選ばない出版社は0)
+    fn weight(&self, publisher: &Publisher) -> f64 {
+        let allowed = self.allow.as_ref().is_none_or(|allow| allow.contains(&publisher.code));
+        let matched = self.filter.as_ref().is_none_or(|filter| filter.is_match(&publisher.name));
+        if allowed && matched && !self.block.contains(&publisher.code) {
+            self.weight.of(publisher)
         } else {
-            let mut padded_publication_code: String = String::from(&publication_code);
-            for _ in 1..=digit_diff {
-                padded_publication_code = String::from("0") + &padded_publication_code;
+            0.0
+        }
+    }
+}
+
+/// ISBNの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum IsbnForm {
+    #[value(name = "10")]
+    Isbn10,
+    #[value(name = "13")]
+    Isbn13,
+}
+
+/// 出版社一覧からランダムに出版社を選んでISBNを生成する
+fn random_isbn(selection: &PublisherSelection, rng: &mut impl Rng) -> Option<Isbn> {
+    let publisher = publisher_list::registry().random_weighted_with_rng(rng, |publisher| selection.weight(publisher))?;
+    Isbn::new_with_rng(String::from("978"), String::from("4"), publisher.code.to_string(), rng).ok()
+}
+
+/// 日本の出版社を出版社一覧から選ぶ場合に、選べる出版社が残っていることを確かめる
+/// 出版社を指定した場合や、日本以外のグループの場合は確かめない
+fn check_selection(
+    registration_group: &RegistrationGroup,
+    publisher: Option<&str>,
+    selection: &PublisherSelection,
+) -> Result<(), IsbnError> {
+    let japanese = registration_group.prefix == "978" && registration_group.code == "4";
+    let selectable = || publisher_list::registry().iter().any(|publisher| selection.weight(publisher) > 0.0);
+    if publisher.is_none() && japanese && !selectable() {
+        return Err(IsbnError::Other(
+            "no publishers left to choose from; check --publisher-filter, allow_publishers and block_publishers".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// 登録グループの中でランダムにISBNを生成する
+/// 出版社を指定した場合は書籍コードだけをランダムに選ぶ
+/// 日本の出版社は出版社一覧から選び、それ以外のグループは割り当て済みの範囲から選ぶ
+fn random_isbn_in(
+    registration_group: &RegistrationGroup,
+    publisher: Option<&str>,
+    selection: &PublisherSelection,
+    rng: &mut impl Rng,
+) -> Option<Isbn> {
+    let isbn = match publisher {
+        Some(publisher) => Isbn::new_with_rng(
+            registration_group.prefix.clone(),
+            registration_group.code.clone(),
+            publisher.to_string(),
+            rng,
+        )
+        .ok(),
+        None if registration_group.prefix == "978" && registration_group.code == "4" => random_isbn(selection, rng),
+        None => registration_group.generate_with_rng(rng),
+    };
+    if let Some(isbn) = &isbn {
+        trace!(isbn = %isbn.create_isbn_13(), "generated isbn");
+    }
+    isbn
+}
+
+/// 記号が1桁以上の数字でできていることを確かめる
+fn check_code(name: &str, code: &str) -> Result<(), IsbnError> {
+    match code.chars().find(|c| !c.is_ascii_digit()) {
+        Some(c) => Err(ParseIsbnError::InvalidCharacter(c)).context(name),
+        None if code.is_empty() => Err(ParseIsbnError::InvalidLength(0)).context(name),
+        None => Ok(()),
+    }
+}
+
+/// 出版社記号が数字で、書籍コードの桁が残る長さであることを確かめる
+fn check_publisher(group: &str, publisher: &str) -> Result<(), IsbnError> {
+    check_code("--publisher", publisher)?;
+    if Isbn::publication_code_length(group, publisher).is_none() {
+        return Err(IsbnError::TooLong { group: group.to_string(), publisher: publisher.to_string() });
+    }
+    Ok(())
+}
+
+/// 以前の実行で試したISBNか
+/// 履歴を読めなかった場合は、試していないものとして扱う
+fn in_history(history: Option<&History>, isbn_13: &str) -> bool {
+    match history.map(|history| history.contains(isbn_13)).transpose() {
+        Ok(known) => known.unwrap_or(false),
+        Err(e) => {
+            warn!(error = %e, "cannot read history");
+            false
+        }
+    }
+}
+
+/// まだ試していないISBNを生成する
+/// 試したISBN13は数値にして覚えておき、同じ番号を2回問い合わせないようにする
+/// 履歴がある場合は、以前の実行で試したISBNも除く
+/// 何度生成しても試したものばかりの場合は、範囲を試し尽くしたとみなしてNoneを返す
+fn untried_isbn_in(
+    registration_group: &RegistrationGroup,
+    publisher: Option<&str>,
+    selection: &PublisherSelection,
+    tried: &mut HashSet<u64>,
+    history: Option<&History>,
+    rng: &mut impl Rng,
+) -> Option<Isbn> {
+    const MAX_TRIES: usize = 100;
+    for _ in 0..MAX_TRIES {
+        let isbn = random_isbn_in(registration_group, publisher, selection, rng)?;
+        let isbn_13 = isbn.create_isbn_13();
+        let Ok(number) = isbn_13.parse() else {
+            continue;
+        };
+        if !tried.insert(number) {
+            trace!(isbn = number, "skipped already tried isbn");
+            continue;
+        }
+        if in_history(history, &isbn_13) {
+            trace!(isbn = number, "skipped isbn in history");
+            continue;
+        }
+        return Some(isbn);
+    }
+    None
+}
+
+/// 出版社の書籍コードを順番に並べたときの、start番目からlimit件のISBN
+/// orderを指定した場合は、書籍コードをその順番に並べ替える
+/// 履歴にあるISBNは除く
+/// すべての書籍コードを並べ終えた場合はNoneを返す
+fn scan_isbns(
+    registration_group: &RegistrationGroup,
+    publisher: &str,
+    start: usize,
+    limit: usize,
+    order: Option<u64>,
+    history: Option<&History>,
+) -> Option<Vec<Isbn>> {
+    let length = Isbn::publication_code_length(&registration_group.code, publisher)?;
+    let total = 10_usize.pow(length as u32);
+    if start >= total {
+        return None;
+    }
+    let permutation = order.map(|key| Permutation::new(total as u64, key));
+    let isbns = (start..(start + limit).min(total))
+        .filter_map(|index| match &permutation {
+            Some(permutation) => permutation.get(index as u64).map(|code| code as usize),
+            None => Some(index),
+        })
+        .map(|code| {
+            Isbn::from_parts(
+                registration_group.prefix.clone(),
+                registration_group.code.clone(),
+                publisher.to_string(),
+                format!("{:0width$}", code, width = length),
+            )
+        })
+        .filter(|isbn| !in_history(history, &isbn.create_isbn_13()))
+        .collect();
+    Some(isbns)
+}
+
+/// 接頭記号と国記号を確かめて登録グループを探す
+fn find_registration_group(prefix: &str, group: &str) -> Result<RegistrationGroup, IsbnError> {
+    check_code("--prefix", prefix)?;
+    check_code("--group", group)?;
+    if prefix != "978" && prefix != "979" {
+        return Err(ParseIsbnError::InvalidPrefix(prefix.to_string())).context("--prefix");
+    }
+    RegistrationGroup::find(prefix, group)
+        .cloned()
+        .ok_or_else(|| IsbnError::UnknownGroup { prefix: prefix.to_string(), group: group.to_string() })
+}
+
+/// ランダムに本を探すときの設定
+struct RandomBookOptions {
+    registration_group: RegistrationGroup,
+    /// 出版社を指定して探す場合の出版社記号
+    publisher: Option<String>,
+    /// 出版社の書籍コードを順番にすべて試す
+    scan: bool,
+    /// 書籍コードをランダムな順番で試す場合の並べ替えの鍵
+    shuffle: Option<u64>,
+    /// 日本の出版社をランダムに選ぶときの選び方
+    selection: PublisherSelection,
+    /// 見つかった本を受け入れる条件
+    filter: RecordFilter,
+    /// 見つける本の冊数
+    count: usize,
+    /// 問い合わせるISBNの数の上限(Noneの場合は見つかるまで繰り返す)
+    max_attempts: Option<usize>,
+    /// 同時に問い合わせる数
+    concurrency: usize,
+    /// 進み具合を書き出すファイル
+    checkpoint: Option<PathBuf>,
+    /// 再開する場合の、中断した時点の進み具合
+    resume: Option<Checkpoint>,
+}
+
+/// 指定された順番でAPIを試すチェーンを作る
+/// 失敗したリクエストはそれぞれのAPIで再試行する
+/// 楽天ブックスはアプリIDがない場合はエラーになる
+fn provider_chain(
+    client: &reqwest::Client,
+    providers: &[Provider],
+    rakuten: Option<&RakutenBooks>,
+    policy: &RetryPolicy,
+    sru_schema: RecordSchema,
+) -> Result<ProviderChain, IsbnError> {
+    let providers = providers
+        .iter()
+        .map(|provider| -> Result<Box<dyn MetadataProvider>, IsbnError> {
+            let inner: Box<dyn MetadataProvider> = match provider {
+                Provider::Ndl => Box::new(Ndl { client: client.clone() }),
+                Provider::Ndlsru => Box::new(NdlSru { client: client.clone(), schema: sru_schema }),
+                Provider::Openbd => Box::new(OpenBd { client: client.clone() }),
+                Provider::Openlibrary => Box::new(OpenLibrary { client: client.clone() }),
+                Provider::Rakuten => match rakuten {
+                    Some(rakuten) => Box::new(rakuten.clone()),
+                    None => {
+                        return Err(IsbnError::Other(
+                            "--rakuten-app-id (or RAKUTEN_APP_ID) is required to use the rakuten provider".into(),
+                        ))
+                    }
+                },
+                Provider::Googlebooks => Box::new(GoogleBooks { client: client.clone() }),
             };
-            padded_publication_code
+            Ok(Box::new(Retrying { inner, policy: policy.clone() }))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(ProviderChain::new(providers))
+}
+
+/// 図書館システムごとに所蔵している図書館と貸出状況を調べる
+/// 調べられなかった場合は空になる
+async fn library_availability(client: &reqwest::Client, calil: &Calil, isbn_13: &str) -> Vec<Availability> {
+    match calil.check(client, isbn_13).await {
+        Ok(availabilities) => availabilities,
+        Err(e) => {
+            eprintln!("cannot check library availability: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// 書影をダウンロードして保存したファイルのパス
+/// 書影のURLがない場合と、ダウンロードできなかった場合はNoneを返す
+async fn save_cover(client: &reqwest::Client, dir: &Path, discovery: &Discovery) -> Option<PathBuf> {
+    let url = discovery.metadata.cover_url.as_deref()?;
+    match cover::download(client, url, dir, &discovery.isbn13).await {
+        Ok(path) => Some(path),
+        Err(e) => {
+            eprintln!("cannot save the cover {}: {}", url, e);
+            None
         }
     }
+}
 
-    /// ISBN13のチェックディジットの計算
-    fn calc_check_digit_13(head_code: &String, country_code: &String, publisher_code: &String, publication_code: &String) -> String {
-        let isbn_string_without_check_digit = String::new() + &head_code + &country_code + &publisher_code + &publication_code;
-        // 奇数桁の合計
-        let mut odd_total: usize = 0;
-        for i in (0..isbn_string_without_check_digit.len()).step_by(2) {
-            let num_char = isbn_string_without_check_digit.chars().nth(i).unwrap();
-            let num = num_char as usize - 48;
-            odd_total += num;
+/// 端末に表示する書影
+/// 保存した書影があればそれを使い、なければダウンロードする
+async fn preview_cover(client: &reqwest::Client, protocol: PreviewProtocol, discovery: &Discovery) -> Option<String> {
+    let bytes = match &discovery.cover_path {
+        Some(path) => std::fs::read(path).map_err(|e| e.into()),
+        None => cover::fetch(client, discovery.metadata.cover_url.as_deref()?).await.map(|(bytes, _)| bytes),
+    };
+    let rendered = bytes.and_then(|bytes| preview::render(&bytes, protocol).map_err(|e| e.into()));
+    match rendered {
+        Ok(preview) => Some(preview),
+        Err(e) => {
+            eprintln!("cannot show the cover of {}: {}", discovery.isbn13, e);
+            None
+        }
+    }
+}
+
+/// 見つかった本のURLのQRコードを、端末に表示するかファイルに書き出す
+fn show_qr(printer: &Printer, discovery: &Discovery, path: Option<&Path>) {
+    let Some(url) = discovery.url() else {
+        eprintln!("no url to encode for {}", discovery.isbn13);
+        return;
+    };
+    let drawing = match qr::drawing(url) {
+        Ok(drawing) => drawing,
+        Err(e) => {
+            eprintln!("cannot encode {} as a qr code: {}", url, e);
+            return;
+        }
+    };
+    match path {
+        Some(path) => {
+            let path = PathBuf::from(path.to_string_lossy().replace("{isbn}", &discovery.isbn13));
+            if let Err(e) = write_drawing(&drawing, Some(&path), 8) {
+                eprintln!("cannot write qr code: {}", e);
+            }
+        }
+        None => printer.report(qr::to_blocks(&drawing.to_bitmap(1)).trim_end()),
+    }
+}
+
+/// 見つかった本をデスクトップ通知で知らせる
+/// 本文は「タイトル / 著者, 出版社, 年」とURL
+fn notify(discovery: &Discovery) {
+    let mut body = discovery.metadata.citation();
+    if let Some(url) = discovery.url() {
+        body.push('\n');
+        body.push_str(url);
+    }
+    let result = notify_rust::Notification::new()
+        .appname("isbn")
+        .summary(&format!("found {}", discovery.isbn13))
+        .body(&body)
+        .show();
+    if let Err(e) = result {
+        eprintln!("cannot show notification: {}", e);
+    }
+}
+
+/// 見つかった本について、書誌情報のほかに調べることと加えること
+struct Details {
+    /// URLを表示するサービス
+    links: Vec<LinkService>,
+    /// ブラウザで開く場合のサービス(Noneの場合は表示するサービスの最初のもの)
+    open: Option<Option<LinkService>>,
+    /// クリップボードにコピーするもの
+    copy: Option<CopyTarget>,
+    /// デスクトップ通知で知らせるかどうか
+    notify: bool,
+    /// QRコードを表示する場合の、書き出すファイル(Noneの場合は端末に表示する)
+    qr: Option<Option<PathBuf>>,
+    /// 書影を保存するディレクトリ
+    save_cover: Option<PathBuf>,
+    /// 書影を端末に表示する場合の方法
+    preview_cover: Option<PreviewProtocol>,
+    /// Calibreのmetadata.opfを書き出すディレクトリ
+    calibre: Option<PathBuf>,
+    /// 見つかった本を加えるほしい本のリスト
+    wishlist: Option<Library>,
+    /// 図書館の所蔵を調べる場合のカーリルの設定
+    calil: Option<Calil>,
+    /// 関連する本を探す場合の、検索条件ごとの件数
+    explore: Option<usize>,
+    /// シリーズの1巻目を探すかどうか
+    first_volume: bool,
+    policy: RetryPolicy,
+}
+
+impl Details {
+    async fn fill(&self, client: &reqwest::Client, discovery: &mut Discovery) {
+        discovery.links = links::links(&self.links, &discovery.isbn13, discovery.isbn10.as_deref());
+        if let Some(calil) = &self.calil {
+            discovery.libraries = library_availability(client, calil, &discovery.isbn13).await;
+        }
+        if let Some(count) = self.explore.filter(|count| *count > 0) {
+            discovery.related = explore::related(client, &self.policy, &discovery.metadata, count).await;
+        }
+        if self.first_volume {
+            discovery.first_volume = explore::first_volume(client, &self.policy, &discovery.metadata).await;
+        }
+        if let Some(dir) = &self.save_cover {
+            discovery.cover_path = save_cover(client, dir, discovery).await;
+        }
+        if let Some(dir) = &self.calibre {
+            match calibre::write_book(client, dir, discovery).await {
+                Ok(path) => discovery.calibre_path = Some(path),
+                Err(e) => eprintln!("cannot write the calibre metadata of {}: {}", discovery.isbn13, e),
+            }
+        }
+        if let Some(protocol) = self.preview_cover {
+            discovery.cover_preview = preview_cover(client, protocol, discovery).await;
+        }
+    }
+
+    /// 見つかった本を表示した後に、指定されていれば書影やQRコードを表示したり、通知したり、
+    /// クリップボードにコピーしたり、ブラウザで開いたりする
+    /// できなくても続ける
+    fn announce(&self, printer: &Printer, discovery: &Discovery) {
+        if let Some(preview) = &discovery.cover_preview {
+            printer.report(preview.trim_end_matches('\n'));
+        }
+        if let Some(path) = &self.qr {
+            show_qr(printer, discovery, path.as_deref());
+        }
+        if self.notify {
+            notify(discovery);
+        }
+        if let Some(wishlist) = &self.wishlist {
+            match wishlist.wish(&discovery.metadata) {
+                Ok(true) => printer.report(&format!("{} ... added to the wishlist", discovery.isbn13)),
+                Ok(false) => {}
+                Err(e) => eprintln!("cannot add {} to the wishlist: {}", discovery.isbn13, e),
+            }
+        }
+        if let Some(target) = self.copy {
+            match target.text(discovery) {
+                Some(text) => {
+                    if let Err(e) = clipboard::copy(&text) {
+                        eprintln!("cannot copy to the clipboard: {}", e);
+                    }
+                }
+                None => eprintln!("no url to copy for {}", discovery.isbn13),
+            }
+        }
+        let Some(service) = self.open else {
+            return;
+        };
+        let links = match service {
+            Some(service) => links::links(&[service], &discovery.isbn13, discovery.isbn10.as_deref()),
+            None => discovery.links.iter().take(1).cloned().collect(),
         };
+        if links.is_empty() {
+            eprintln!("no url to open for {}", discovery.isbn13);
+        }
+        for link in links {
+            debug!(url = %link.url, "opening in browser");
+            if let Err(e) = open::that_detached(&link.url) {
+                eprintln!("cannot open {}: {}", link.url, e);
+            }
+        }
+    }
+}
 
-        // 偶数桁の合計
-        let mut even_total: usize = 0;
-        for i in (1..isbn_string_without_check_digit.len()).step_by(2) {
-            let num_char = isbn_string_without_check_digit.chars().nth(i).unwrap();
-            let num = num_char as usize - 48;
-            even_total += num * 3;
+/// 何回目の問い合わせで何件の候補を試すかを決め、candidatesで候補を作る
+/// candidatesには、書籍コードを順番に試す場合の位置と件数を渡し、回の番号と次の回の位置と一緒に返す
+/// 再開する場合は、チェックポイントの終えた回と位置、試したISBNの数から続ける
+/// --max-attemptsは、まとめて問い合わせる場合も問い合わせたISBNの数で数える
+/// candidatesがNoneを返した場合は、候補を試し尽くしたとしてexhaustedにする
+fn plan_rounds<'a, T>(
+    batch_size: usize,
+    max_attempts: Option<usize>,
+    checkpoint: &Checkpoint,
+    exhausted: &'a Cell<bool>,
+    mut candidates: impl FnMut(usize, usize) -> Option<Vec<T>> + 'a,
+) -> impl Iterator<Item = (usize, usize, Vec<T>)> + 'a {
+    let mut remaining =
+        max_attempts.map_or(usize::MAX, |max_attempts| max_attempts.saturating_sub(checkpoint.attempts));
+    // APIによってまとめて問い合わせる件数が違うので、位置は回の番号からではなくチェックポイントの位置から数える
+    let mut offset = checkpoint.offset;
+    (checkpoint.cursor + 1..).map_while(move |round| {
+        if remaining == 0 {
+            return None;
+        }
+        let size = batch_size.min(remaining);
+        let Some(candidates) = candidates(offset, size) else {
+            exhausted.set(true);
+            return None;
         };
+        offset += size;
+        remaining -= candidates.len();
+        Some((round, offset, candidates))
+    })
+}
 
-        // チェックディジットの計算
-        let check_digit_surplus = (odd_total + even_total) % 10;
-        if check_digit_surplus == 0 {
-            String::from("0")
-        } else {
-            (10 - check_digit_surplus).to_string()
+async fn random_book(
+    client: &reqwest::Client,
+    provider: &dyn MetadataProvider,
+    details: &Details,
+    printer: &mut Printer,
+    history: Option<&History>,
+    rng: &mut StdRng,
+    options: &RandomBookOptions,
+) -> ExitCode {
+    let group = &options.registration_group;
+    let publisher = options.publisher.as_deref();
+    if random_isbn_in(group, publisher, &options.selection, rng).is_none() {
+        eprintln!("registration group {}-{} has no assigned range", group.prefix, group.code);
+        return ExitCode::FAILURE;
+    }
+    // openBDとOpen Libraryはまとめて問い合わせる
+    let batch_size = provider.batch_size();
+    // 再開する場合は、中断した時点の回数と見つかった本から続ける
+    let mut checkpoint = options.resume.clone().unwrap_or_else(|| Checkpoint {
+        prefix: group.prefix.clone(),
+        group: group.code.clone(),
+        publisher: options.publisher.clone(),
+        scan: options.scan,
+        shuffle: options.shuffle,
+        count: options.count,
+        ..Default::default()
+    });
+    let mut summary = RunSummary::new();
+    summary.attempts = checkpoint.attempts;
+    // 同じ本を2回数えないように、見つかったISBNを覚えておく
+    let mut found: HashSet<String> = checkpoint.found.iter().cloned().collect();
+    // 試したISBNは同じ実行の中では生成し直さない
+    let mut tried = HashSet::new();
+    let exhausted = Cell::new(false);
+    let candidates = plan_rounds(batch_size, options.max_attempts, &checkpoint, &exhausted, |start, size| {
+        match publisher.filter(|_| options.scan) {
+            Some(publisher) => scan_isbns(group, publisher, start, size, options.shuffle, history),
+            None => {
+                let candidates: Vec<Isbn> = (0..size)
+                    .map_while(|_| untried_isbn_in(group, publisher, &options.selection, &mut tried, history, rng))
+                    .collect();
+                Some(candidates).filter(|candidates| !candidates.is_empty())
+            }
+        }
+    });
+    // 候補をまとめて生成し、concurrency件まで同時に問い合わせる
+    // 同時に問い合わせる場合も、リクエストを始める間隔は--intervalより短くしない(再試行の設定のlimiterで待つ)
+    let mut lookups = stream::iter(candidates)
+        .map(|(round, end, candidates)| {
+            async move {
+                // 履歴ですべて除いた場合は問い合わせない
+                if candidates.is_empty() {
+                    return (round, end, candidates, Ok(Vec::new()));
+                }
+                debug!(round, candidates = candidates.len(), "looking up candidates");
+                let result = provider.lookup_batch(&candidates).await;
+                (round, end, candidates, result)
+            }
+        })
+        .buffer_unordered(options.concurrency);
+    // 同時に問い合わせると終わる順番が前後するので、終わった回を覚えておき続いた分だけcursorを進める
+    let mut completed = BTreeMap::new();
+    let mut processed: usize = 0;
+    // Ctrl+Cで中断した場合は、それまでの経過を表示して終了する
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        let next = tokio::select! {
+            next = lookups.next() => next,
+            _ = &mut interrupted => {
+                save_checkpoint(options.checkpoint.as_deref(), &checkpoint);
+                if options.checkpoint.is_some() {
+                    eprintln!("run again with --resume to continue");
+                }
+                return interrupt(&summary);
+            }
+        };
+        let Some((round, end, candidates, result)) = next else {
+            remove_checkpoint(options.checkpoint.as_deref());
+            let max_attempts = options.max_attempts.unwrap_or_default();
+            if exhausted.get() && options.scan {
+                let publisher = publisher.unwrap_or_default();
+                printer.report(&format!(
+                    "scanned all publication codes of {}-{}-{}, {} found",
+                    group.prefix,
+                    group.code,
+                    publisher,
+                    found.len()
+                ));
+                return ExitCode::SUCCESS;
+            } else if exhausted.get() {
+                printer.report(&format!("no untried isbns left in {}-{}", group.prefix, group.code));
+            } else if found.is_empty() {
+                printer.report(&format!("cannot find any books in {} times", max_attempts));
+            } else {
+                printer.report(&format!("found only {} of {} books in {} times", found.len(), checkpoint.count, max_attempts));
+            }
+            return ExitCode::FAILURE;
+        };
+        // 一定の回数ごとに進み具合を書き出す
+        completed.insert(round, end);
+        while let Some(end) = completed.remove(&(checkpoint.cursor + 1)) {
+            checkpoint.cursor += 1;
+            checkpoint.offset = end;
+        }
+        processed += 1;
+        if processed.is_multiple_of(CHECKPOINT_INTERVAL) {
+            checkpoint.attempts = summary.attempts;
+            save_checkpoint(options.checkpoint.as_deref(), &checkpoint);
+        }
+        // 再試行しても失敗した場合は、次の候補で続ける
+        let records = match result {
+            Ok(records) => records,
+            Err(e) => {
+                eprintln!("cannot search books: {}", e);
+                summary.attempts += candidates.len();
+                checkpoint.attempts = summary.attempts;
+                summary.last_isbn = candidates.last().map(|isbn| isbn.create_isbn_13());
+                continue;
+            }
+        };
+        for (isbn, record) in candidates.iter().zip(records) {
+            let isbn_13 = isbn.create_isbn_13();
+            // 試した行には出版社一覧の出版社名も表示する
+            let attempt = match publisher_list::publisher_name(isbn) {
+                Some(name) => format!("{} ({})", isbn_13, name),
+                None => isbn_13.clone(),
+            };
+            summary.attempts += 1;
+            checkpoint.attempts = summary.attempts;
+            summary.last_isbn = Some(isbn_13.clone());
+            let recorded = match &record {
+                Some(record) => history.map(|history| history.record_hit(record)),
+                None => history.map(|history| history.record_miss(&isbn_13)),
+            };
+            if let Some(Err(e)) = recorded {
+                warn!(error = %e, "cannot write history");
+            }
+            match record {
+                Some(_) if found.contains(&isbn_13) => printer.report(&format!("{} ... already found", attempt)),
+                Some(record) => match filter_record(client, &options.filter, record).await {
+                    Err(reason) => printer.report(&format!("{} ... found, but rejected by --{}", attempt, reason)),
+                    Ok(record) => {
+                        found.insert(isbn_13.clone());
+                        checkpoint.found.push(isbn_13);
+                        summary.found += 1;
+                        let mut discovery = Discovery::new(record, summary.attempts);
+                        details.fill(client, &mut discovery).await;
+                        printer.print(&discovery);
+                        details.announce(printer, &discovery);
+                    }
+                },
+                None => printer.report(&format!("{} ... not found", attempt)),
+            }
+            // 問い合わせ中のリクエストは結果を待たずに打ち切る
+            if found.len() >= checkpoint.count {
+                remove_checkpoint(options.checkpoint.as_deref());
+                return ExitCode::SUCCESS;
+            }
+        }
+    }
+}
+
+/// 見つかった本が条件に合うかを確かめる
+/// 分類のない書誌は、国立国会図書館サーチのSRUでISBNと分類を指定して検索し直し、見つかった書誌の分類を使う
+/// 条件に合わない場合は、満たさなかった条件の名前を返す
+async fn filter_record(
+    client: &reqwest::Client,
+    filter: &RecordFilter,
+    mut record: BookRecord,
+) -> Result<BookRecord, &'static str> {
+    if let (Some(ndc), None) = (&filter.ndc, &record.ndc) {
+        let sru = NdlSru { client: client.clone(), schema: RecordSchema::DcndlSimple };
+        let query = CqlQuery { ndc: Some(ndc.clone()), isbn: Some(record.isbn.clone()), ..Default::default() };
+        match sru.search(&query.to_cql(), 1, 1).await {
+            Ok(response) => record.ndc = response.records().into_iter().find_map(|record| record.ndc),
+            Err(e) => warn!(isbn = %record.isbn, error = %e, "cannot search ndc"),
         }
     }
+    match filter.rejects(&record) {
+        Some(reason) => Err(reason),
+        None => Ok(record),
+    }
+}
 
-    /// ISBN10のチェックディジットの計算
-    fn calc_check_digit_10(country_code: &String, publisher_code: &String, publication_code: &String) -> String {
-        let isbn_string_without_check_digit = String::new() + &country_code + &publisher_code + &publication_code;
+/// チェックポイントを書き出す間隔(問い合わせの回数)
+const CHECKPOINT_INTERVAL: usize = 10;
 
-        let mut total: usize = 0;
-        for i in (0..isbn_string_without_check_digit.len()) {
-            let num_chart = isbn_string_without_check_digit.chars().nth(i).unwrap();
-            let num = num_chart as usize - 48;
-            total += num * (10 - i);
+/// チェックポイントを書き出す
+/// 書き出せなくても探すのは続ける
+fn save_checkpoint(path: Option<&Path>, checkpoint: &Checkpoint) {
+    if let Some(path) = path {
+        match checkpoint.save(path) {
+            Ok(()) => debug!(path = %path.display(), cursor = checkpoint.cursor, "saved checkpoint"),
+            Err(e) => warn!(path = %path.display(), error = %e, "cannot save checkpoint"),
         }
+    }
+}
 
-        // チェックディジットの計算
-        let check_digit_surplus = total % 11;
-        if check_digit_surplus == 0 {
-            String::from("0")
-        } else if check_digit_surplus == 1 {
-            String::from("X")
-        } else {
-            (11 - check_digit_surplus).to_string()
+fn remove_checkpoint(path: Option<&Path>) {
+    if let Some(Err(e)) = path.map(Checkpoint::remove) {
+        warn!(error = %e, "cannot remove checkpoint");
+    }
+}
+
+/// 中断したときの経過をエラー出力に表示する
+/// 終了コードはSIGINTで終了した場合と同じ130にする
+fn interrupt(summary: &RunSummary) -> ExitCode {
+    eprintln!("interrupted: {}", summary);
+    ExitCode::from(130)
+}
+
+fn generate(
+    prefix: String,
+    group: String,
+    publisher: Option<String>,
+    count: usize,
+    selection: &PublisherSelection,
+    rng: &mut impl Rng,
+) -> Result<(), IsbnError> {
+    let registration_group = find_registration_group(&prefix, &group)?;
+    // 書籍コードが1桁以上残る必要がある
+    if let Some(publisher) = &publisher {
+        check_publisher(&group, publisher)?;
+    }
+    check_selection(&registration_group, publisher.as_deref(), selection)?;
+
+    for _ in 0..count {
+        let isbn = random_isbn_in(&registration_group, publisher.as_deref(), selection, rng)
+            .ok_or_else(|| IsbnError::NoAssignedRange { prefix: prefix.clone(), group: group.clone() })?;
+        // 979始まりのISBNにはISBN10がない
+        match isbn.create_isbn_10() {
+            Some(isbn_10) => println!("{} {}", isbn.create_isbn_13(), isbn_10),
+            None => println!("{}", isbn.create_isbn_13()),
         }
     }
+    Ok(())
+}
 
-    fn create_isbn_10(&self) -> String {
-        String::new()
-            + &self.country_code
-            + &self.publisher_code
-            + &self.publication_code
-            + &self.check_digit_10
+/// ISBNを1件検証する
+/// 区切り文字が含まれている場合は、区切りの位置が正しいかも確かめる
+fn validate_one(isbn: &str) -> Result<(), String> {
+    let digits: String = isbn.chars().filter(|c| *c != '-' && *c != ' ').collect();
+    match verify_check_digit(&digits) {
+        Validation::Valid => {}
+        Validation::BadLength(length) => return Err(format!("invalid length: {} digits", length)),
+        Validation::BadCharacter(c) => return Err(format!("invalid character: {:?}", c)),
+        Validation::WrongCheckDigit { expected, found } => {
+            return Err(format!("wrong check digit: expected {}, found {}", expected, found))
+        }
     }
+    // チェックディジットのxはXとして比べる
+    let hyphenated = hyphenate(&digits.to_uppercase()).map_err(|e| e.to_string())?;
+    if isbn.contains(['-', ' ']) {
+        let separated = isbn.split(['-', ' ']).filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+        if separated.to_uppercase() != hyphenated {
+            return Err(format!("misplaced hyphens: expected {}", hyphenated));
+        }
+    }
+    Ok(())
+}
+
+fn validate(isbns: Vec<String>, file: Option<PathBuf>) -> ExitCode {
+    // 引数で渡されたISBNは行番号なしで、ファイルから読んだISBNは行番号付きで表示する
+    let batch = file.is_some() || isbns.is_empty();
+    let lines: Vec<String> = if !batch {
+        isbns
+    } else {
+        let reader: Box<dyn BufRead> = match file {
+            Some(path) if path.as_os_str() != "-" => match File::open(&path) {
+                Ok(f) => Box::new(BufReader::new(f)),
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => Box::new(io::stdin().lock()),
+        };
+        match reader.lines().collect() {
+            Ok(lines) => lines,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    };
 
-    fn create_isbn_13(&self) -> String {
-        String::new()
-            + &self.head_code
-            + &self.country_code
-            + &self.publisher_code
-            + &self.publication_code
-            + &self.check_digit_13
+    let mut valid_count = 0;
+    let mut invalid_count = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let isbn = line.trim();
+        if isbn.is_empty() {
+            continue;
+        }
+        let location = if batch { format!("{}: ", i + 1) } else { String::new() };
+        match validate_one(isbn) {
+            Ok(()) => {
+                println!("{}{} ... valid", location, isbn);
+                valid_count += 1;
+            }
+            Err(reason) => {
+                println!("{}{} ... {}", location, isbn, reason);
+                invalid_count += 1;
+            }
+        }
+    }
+    if batch {
+        println!("{} checked, {} valid, {} invalid", valid_count + invalid_count, valid_count, invalid_count);
+    }
+
+    if invalid_count == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct Publisher {
-    code: String,
-    name: String,
+/// ISBNを1件変換する
+/// 変換先が入力と同じ形式の場合は、検証してハイフンを取り除いたものを返す
+fn convert_one(isbn: &str, to: Option<IsbnForm>) -> Result<String, ParseIsbnError> {
+    let digits: String = isbn.chars().filter(|c| *c != '-' && *c != ' ').collect();
+    match (digits.len(), to) {
+        (13, None | Some(IsbnForm::Isbn10)) => to_isbn10(&digits),
+        (13, Some(IsbnForm::Isbn13)) => match verify_check_digit(&digits) {
+            Validation::Valid => Ok(digits),
+            _ => to_isbn13(&digits),
+        },
+        (10, Some(IsbnForm::Isbn10)) => to_isbn13(&digits).map(|_| digits.to_uppercase()),
+        _ => to_isbn13(&digits),
+    }
 }
 
-fn read_csv() -> Result<Vec<Publisher>, Box<dyn Error>>{
-    let mut publisher_list = Vec::new();
-    // let csv_text = fs::read_to_string(file_path)?;
-    let csv_text = include_str!("../csv/isbn.csv");
-    let mut rdr = csv::Reader::from_reader(csv_text.as_bytes());
-    for result in rdr.records() {
-        let record = result?.deserialize(None)?;
-        publisher_list.push(record);
+/// 1行1件で変換結果を書き出す
+/// 変換できなかった行はエラー出力に書き、1件でもあればfalseを返す
+fn convert_lines<W: Write>(
+    lines: impl Iterator<Item = io::Result<String>>,
+    numbered: bool,
+    to: Option<IsbnForm>,
+    out: &mut W,
+) -> io::Result<bool> {
+    let mut succeeded = true;
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        let isbn = line.trim();
+        if isbn.is_empty() {
+            continue;
+        }
+        match convert_one(isbn, to) {
+            Ok(converted) => writeln!(out, "{}", converted)?,
+            Err(e) if numbered => {
+                eprintln!("{}: {}: {}", i + 1, isbn, e);
+                succeeded = false;
+            }
+            Err(e) => {
+                eprintln!("{}: {}", isbn, e);
+                succeeded = false;
+            }
+        }
     }
-    Ok(publisher_list)
+    out.flush()?;
+    Ok(succeeded)
 }
 
-async fn get_publication(client: &reqwest::Client, isbn: &String) -> reqwest::Result<String> {
-    let response = client.get("https://iss.ndl.go.jp/api/opensearch?cnt=1&isbn=".to_string() + &isbn)
-        .send()
-        .await?
-        .text()
-        .await?;
-    Ok(response)
+fn convert(isbns: Vec<String>, to: Option<IsbnForm>) -> ExitCode {
+    // 大きなファイルを流せるように、出力はまとめて書き出す
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = if isbns.is_empty() {
+        convert_lines(io::stdin().lock().lines(), true, to, &mut out)
+    } else {
+        convert_lines(isbns.into_iter().map(Ok), false, to, &mut out)
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        // パイプの先が閉じられた場合は静かに終了する
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    let client = reqwest::Client::new();
-    let mut counter = 0;
-    loop {
-        if counter > 10 {
-            println!("cannot find any books in 10 times");
-            break;
+async fn lookup(
+    client: &reqwest::Client,
+    provider: &dyn MetadataProvider,
+    details: &Details,
+    printer: &mut Printer,
+    isbn: &str,
+) -> Result<ExitCode, IsbnError> {
+    let parsed = Isbn::parse(isbn).context(isbn)?;
+    let isbn_13 = parsed.create_isbn_13();
+
+    let record = match provider.lookup(&parsed).await.context(&isbn_13)? {
+        Some(record) => record,
+        None => {
+            printer.report(&format!("{} ... not found", isbn_13));
+            return Ok(ExitCode::FAILURE);
         }
-        let publisher_list = read_csv().unwrap();
-        let mut rng = rand::thread_rng();
-        let publisher_code_index = rng.gen_range(0..publisher_list.len());
+    };
+    // 979始まりのISBNはISBN10に変換できないが、検索はできる
+    let mut discovery = Discovery::new(record, 1);
+    details.fill(client, &mut discovery).await;
+    printer.print(&discovery);
+    details.announce(printer, &discovery);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// 画像のバーコードを読み取り、チェックディジットの合うEAN-13を返す
+/// 書籍JANコードの2段目(分類コードと価格)は飛ばす
+fn scan(path: &Path) -> Result<String, IsbnError> {
+    let context = || path.display().to_string();
+    let image = image::open(path).map_err(|e| IsbnError::Other(e.into())).context(context())?.to_luma8();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    match Ean13::decode_image_by(width, height, image.as_raw(), |ean| PriceCode::from_ean(ean).is_err()) {
+        Some(ean) => Ok(ean.as_str().to_string()),
+        None => Err(IsbnError::Other("no EAN-13 barcode found".into())).context(context()),
+    }
+}
 
-        let isbn: Isbn = Isbn::new(String::from("978"), String::from("4"), publisher_list[publisher_code_index].code.to_string());
+fn hyphenate_command(isbn: &str) -> Result<(), IsbnError> {
+    println!("{}", hyphenate(isbn).context(isbn)?);
+    Ok(())
+}
 
-        // reqwest
-        let response_xml = get_publication(&client, &isbn.create_isbn_13()).await.unwrap();
+/// バーコードを描く
+/// 分類コードと価格を指定した場合は下に書籍JANコードの2段目を、アドオンを指定した場合は右にアドオンを並べる
+fn barcode(
+    isbn: &str,
+    price: Option<PriceCode>,
+    addon: Option<&Ean5>,
+    output: Option<&Path>,
+    scale: u32,
+) -> Result<(), IsbnError> {
+    let ean = barcode_ean(isbn)?;
+    if let Some(price) = price {
+        let book_code = BookCode::new(ean, price).context(isbn)?;
+        return write_drawing(&book_code.drawing(), output, scale);
+    }
+    write_drawing(&ean.drawing_with_addon(isbn_caption(&ean).as_deref(), addon), output, scale)
+}
+
+/// バーコードにするEAN-13(ISBN10はISBN13にする)
+fn barcode_ean(isbn: &str) -> Result<Ean13, IsbnError> {
+    let digits: String = isbn.chars().filter(|c| *c != '-' && *c != ' ').collect();
+    let code = if digits.len() == 10 { to_isbn13(&digits).context(isbn)? } else { digits };
+    Ean13::parse(&code).context(isbn)
+}
 
-        // parse xml
-        let element = Element::parse(response_xml.as_bytes()).unwrap();
-        let channel = element.get_child("channel").expect("cannot find channel in xml tree");
-        let total_results: usize = (channel.get_child("totalResults").expect("cannot find totalResults in xml tree"))
-            .children[0]
-            .as_text()
-            .unwrap()
-            .parse()
-            .unwrap();
-        if total_results > 0 {
-            // booklogのパスパラメータはISBN10
-            println!("https://booklog.jp/item/1/{}", isbn.create_isbn_10());
-            break;
+/// バーの上に書く文字
+/// 978と979で始まるISBNは、ハイフン区切りのISBNを書く
+fn isbn_caption(ean: &Ean13) -> Option<String> {
+    hyphenate(ean.as_str()).ok().map(|hyphenated| format!("ISBN{}", hyphenated))
+}
+
+/// ISBNのバーコードを1枚に1冊ずつ並べたラベル用紙のPDFを書き出す
+/// ISBNの並びが空の場合はファイル(省略時は標準入力)から1行1件で読む
+fn labels(isbns: Vec<String>, file: Option<PathBuf>, output: &Path, sheet: &LabelSheet) -> Result<(), IsbnError> {
+    let isbns = if file.is_none() && !isbns.is_empty() {
+        isbns
+    } else {
+        let context = || file.as_ref().map_or(String::from("-"), |path| path.display().to_string());
+        let reader: Box<dyn BufRead> = match &file {
+            Some(path) if path.as_os_str() != "-" => {
+                Box::new(BufReader::new(File::open(path).map_err(|e| IsbnError::Other(e.into())).context(context())?))
+            }
+            _ => Box::new(io::stdin().lock()),
+        };
+        let lines: io::Result<Vec<String>> = reader.lines().collect();
+        lines.map_err(|e| IsbnError::Other(e.into())).context(context())?
+    };
+    let mut drawings = Vec::new();
+    for isbn in isbns.iter().map(|isbn| isbn.trim()).filter(|isbn| !isbn.is_empty()) {
+        let ean = barcode_ean(isbn)?;
+        drawings.push(ean.drawing(isbn_caption(&ean).as_deref()));
+    }
+    let context = || output.display().to_string();
+    let file = File::create(output).map_err(|e| IsbnError::Other(e.into())).context(context())?;
+    pdf::write_labels(BufWriter::new(file), sheet, &drawings).map_err(|e| IsbnError::Other(e.into())).context(context())
+}
+
+/// 図形を書き出す
+/// 拡張子が.pngならPNG、それ以外はSVGで書き、ファイルを指定しない場合は標準出力にSVGを書く
+fn write_drawing(drawing: &Drawing, output: Option<&Path>, scale: u32) -> Result<(), IsbnError> {
+    let Some(path) = output else {
+        print!("{}", drawing.to_svg(scale));
+        return Ok(());
+    };
+    let context = || path.display().to_string();
+    let is_png = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+    if is_png {
+        let file = File::create(path).map_err(|e| IsbnError::Other(e.into())).context(context())?;
+        raster::write_png(BufWriter::new(file), &drawing.to_bitmap(scale)).map_err(IsbnError::Other).context(context())
+    } else {
+        std::fs::write(path, drawing.to_svg(scale)).map_err(|e| IsbnError::Other(e.into())).context(context())
+    }
+}
+
+async fn search(
+    sru: &NdlSru,
+    policy: &RetryPolicy,
+    cql: &str,
+    max: usize,
+    printer: &mut Printer,
+) -> Result<(), IsbnError> {
+    // 1回で取得できる件数を超える場合は続きを取得する
+    let mut start_record = 1;
+    let mut records = Vec::new();
+    let number_of_records = loop {
+        let maximum_records = max - records.len();
+        let response = policy.retry(sru.name(), || sru.search(cql, start_record, maximum_records)).await.context(cql)?;
+        let remaining = max - records.len();
+        for record in response.records().into_iter().take(remaining) {
+            // JSONやXMLの文書にまとめる形式以外は見つかったものから書き出す
+            if !printer.is_collected() {
+                printer.print_record(&record);
+            }
+            records.push(record);
+        }
+        match response.next_record_position() {
+            Some(position) if records.len() < max => start_record = position,
+            _ => break response.number_of_records(),
         }
-        println!("{} ... not found", isbn.create_isbn_13());
-        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-        counter += 1;
     };
+    if printer.is_collected() {
+        printer.print_records(&records);
+    }
+    printer.report(&format!("{} records found", number_of_records));
+    Ok(())
+}
+
+/// CQLの検索式に当てはまる書誌からランダムに1冊選んで表示する
+/// 件数を調べてから位置をランダムに選んで1件ずつ取得し、ISBNのない書誌だった場合は選び直す
+async fn random_record(
+    sru: &NdlSru,
+    policy: &RetryPolicy,
+    details: &Details,
+    printer: &mut Printer,
+    rng: &mut impl Rng,
+    cql: &str,
+    max_attempts: Option<usize>,
+) -> Result<ExitCode, IsbnError> {
+    let number_of_records = policy.retry(sru.name(), || sru.search(cql, 1, 1)).await.context(cql)?.number_of_records();
+    if number_of_records == 0 {
+        printer.report(&format!("no records match {}", cql));
+        return Ok(ExitCode::FAILURE);
+    }
+    let max_attempts = max_attempts.unwrap_or(usize::MAX);
+    for attempt in 1..=max_attempts {
+        let position = rng.gen_range(1..=number_of_records);
+        let response = policy.retry(sru.name(), || sru.search(cql, position, 1)).await.context(cql)?;
+        let Some(record) = response.records().into_iter().next() else {
+            printer.report(&format!("record {} of {} ... no isbn", position, number_of_records));
+            continue;
+        };
+        let mut discovery = Discovery::new(record, attempt);
+        details.fill(&sru.client, &mut discovery).await;
+        printer.print(&discovery);
+        details.announce(printer, &discovery);
+        return Ok(ExitCode::SUCCESS);
+    }
+    printer.report(&format!("cannot find any books with isbn in {} times", max_attempts));
+    Ok(ExitCode::FAILURE)
+}
+
+/// 出版社一覧の1行
+#[derive(Debug, Serialize)]
+struct PublisherRow<'a> {
+    code: &'a str,
+    name: &'a str,
+    length: usize,
+}
+
+/// 出版社一覧から探して表示する
+/// JSONの場合は配列、それ以外は1行1件のタブ区切りで書き出す
+fn publishers(query: Option<&str>, sort: PublisherSort, reverse: bool, format: OutputFormat) -> ExitCode {
+    let mut rows: Vec<PublisherRow> = publisher_list::registry()
+        .iter()
+        .filter(|publisher| {
+            query.is_none_or(|query| publisher.code.starts_with(query) || publisher.name.contains(query))
+        })
+        .map(|publisher| PublisherRow { code: &publisher.code, name: &publisher.name, length: publisher.code.len() })
+        .collect();
+    match sort {
+        PublisherSort::Code => rows.sort_by(|a, b| a.code.cmp(b.code)),
+        PublisherSort::Name => rows.sort_by(|a, b| a.name.cmp(b.name).then(a.code.cmp(b.code))),
+        PublisherSort::Length => rows.sort_by(|a, b| a.length.cmp(&b.length).then(a.code.cmp(b.code))),
+    }
+    if reverse {
+        rows.reverse();
+    }
+    if rows.is_empty() {
+        eprintln!("no publishers found");
+        return ExitCode::FAILURE;
+    }
+    if format == OutputFormat::Json {
+        print_json(&rows);
+        return ExitCode::SUCCESS;
+    }
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = rows.iter().try_for_each(|row| writeln!(out, "{}\t{}\t{}", row.code, row.name, row.length));
+    match result.and_then(|_| out.flush()) {
+        Ok(()) => ExitCode::SUCCESS,
+        // パイプの先が閉じられた場合は静かに終了する
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// 蔵書やほしい本のリストに加える本の書誌情報と、履歴で見つかった日時(UNIX時間)
+/// 履歴にある本は問い合わせずに履歴の書誌情報を使う
+/// 見つからなかった場合はNoneを返す
+async fn find_record(
+    provider: &dyn MetadataProvider,
+    history: Option<&History>,
+    isbn: &Isbn,
+) -> Result<Option<(BookRecord, Option<i64>)>, IsbnError> {
+    let isbn_13 = isbn.create_isbn_13();
+    let found = match history.map(|history| history.book(&isbn_13)).transpose() {
+        Ok(found) => found.flatten(),
+        Err(e) => {
+            warn!(error = %e, "cannot read history");
+            None
+        }
+    };
+    match found {
+        Some((record, found_at)) => Ok(Some((record, Some(found_at)))),
+        None => Ok(provider.lookup(isbn).await.context(&isbn_13)?.map(|record| (record, None))),
+    }
+}
+
+/// 蔵書の本を加える
+async fn library_add(
+    provider: &dyn MetadataProvider,
+    library: &Library,
+    history: Option<&History>,
+    printer: &Printer,
+    isbn: &str,
+    tags: &[String],
+    note: Option<&str>,
+) -> Result<ExitCode, IsbnError> {
+    let parsed = Isbn::parse(isbn).context(isbn)?;
+    let isbn_13 = parsed.create_isbn_13();
+    let Some((record, discovered_at)) = find_record(provider, history, &parsed).await? else {
+        printer.report(&format!("{} ... not found", isbn_13));
+        return Ok(ExitCode::FAILURE);
+    };
+    let added = library
+        .add(&record, discovered_at, tags, note)
+        .map_err(|e| IsbnError::Other(e.into()))
+        .context(format!("cannot add {} to the library", isbn_13))?;
+    let action = if added { "added" } else { "updated" };
+    printer.report(&format!("{} {} ... {}", record.isbn, record.title, action));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// 蔵書の本を書き出す
+/// テキストでは1行1冊、JSONではタグとメモも含め、Goodreadsでは見つけた日時とタグも含め、それ以外の形式では書誌情報だけを書き出す
+fn print_library_books(printer: &mut Printer, format: OutputFormat, books: &[LibraryBook]) {
+    match format {
+        _ if printer.has_template() => books.iter().for_each(|book| printer.print_record(&book.record)),
+        OutputFormat::Text => books.iter().for_each(|book| println!("{}", book.line())),
+        OutputFormat::Json => print_json(&books),
+        OutputFormat::Jsonl => books.iter().for_each(print_json_line),
+        // 見つけた日時とタグ、評価はGoodreadsの加えた日と本棚、評価にする
+        OutputFormat::Goodreads => books.iter().for_each(|book| {
+            let row = GoodreadsRow::new(&book.record, Some(&book.discovered_at), &book.tags);
+            printer.print_goodreads(row.with_rating(book.rating))
+        }),
+        _ if printer.is_collected() => {
+            printer.print_records(&books.iter().map(|book| book.record.clone()).collect::<Vec<_>>())
+        }
+        _ => books.iter().for_each(|book| printer.print_record(&book.record)),
+    }
+}
+
+/// ほしい本のリストを書き出す
+/// テキストでは1行1冊、Goodreadsでは済みにした本を読んだ本(read)の本棚にし、それ以外は蔵書と同じように書き出す
+fn print_wishlist(printer: &mut Printer, format: OutputFormat, wishes: &[WishlistBook]) {
+    match format {
+        _ if printer.has_template() => wishes.iter().for_each(|wish| printer.print_record(&wish.record)),
+        OutputFormat::Text => wishes.iter().for_each(|wish| println!("{}", wish.line())),
+        OutputFormat::Json => print_json(&wishes),
+        OutputFormat::Jsonl => wishes.iter().for_each(print_json_line),
+        OutputFormat::Goodreads => wishes.iter().for_each(|wish| {
+            let shelves = if wish.done_at.is_some() { vec![String::from("read")] } else { Vec::new() };
+            printer.print_goodreads(GoodreadsRow::new(&wish.record, Some(&wish.added_at), &shelves))
+        }),
+        _ if printer.is_collected() => {
+            printer.print_records(&wishes.iter().map(|wish| wish.record.clone()).collect::<Vec<_>>())
+        }
+        _ => wishes.iter().for_each(|wish| printer.print_record(&wish.record)),
+    }
+}
+
+/// 蔵書の1冊の書誌情報とタグ、評価、メモを表示する
+fn print_library_book(printer: &mut Printer, format: OutputFormat, book: &LibraryBook) {
+    if format != OutputFormat::Text || printer.has_template() {
+        print_library_books(printer, format, std::slice::from_ref(book));
+        return;
+    }
+    println!("{}", book.record.citation());
+    for (label, value) in book.record.metadata() {
+        println!("{}: {}", label, value);
+    }
+    if !book.tags.is_empty() {
+        println!("tags: {}", book.tags.join(", "));
+    }
+    if let Some(rating) = book.rating {
+        println!("rating: {} ({}/5)", stars(rating), rating);
+    }
+    if let Some(note) = &book.note {
+        println!("note: {}", note);
+    }
+    println!("discovered: {}", book.discovered_at);
+}
+
+/// 蔵書ファイルを開く
+fn open_library(path: &Path) -> Result<Library, IsbnError> {
+    Library::open(path)
+        .map_err(|e| IsbnError::Other(e.into()))
+        .context(format!("cannot open library {}", path.display()))
+}
+
+/// バックアップする設定ファイル、履歴、蔵書の場所
+/// 場所が決まらないもの(HOMEがない場合など)は入れない
+fn backup_items(config: Option<PathBuf>, history: Option<PathBuf>, library: Option<PathBuf>) -> Vec<Item> {
+    let config = config.or_else(Config::default_path).map(Item::config);
+    let history = history.or_else(History::default_path).map(Item::history);
+    config.into_iter().chain(history).chain(library.map(Item::library)).collect()
+}
+
+/// 履歴ファイルを開く
+fn open_history(path: &Path) -> Result<History, IsbnError> {
+    let history = History::open(path)
+        .map_err(|e| IsbnError::Other(e.into()))
+        .context(format!("cannot open history {}", path.display()))?;
+    if let Ok((tried, found)) = history.counts() {
+        debug!(path = %path.display(), tried, found, "opened history");
+    }
+    Ok(history)
+}
+
+/// HTTPクライアントを作る
+/// プロキシを指定した場合も、NO_PROXYに含まれるホストには直接接続する
+/// 応答が返ってこない場合はタイムアウトでエラーにし、再試行させる
+fn build_client(proxy: Option<&str>, connect_timeout: Duration, timeout: Duration) -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().connect_timeout(connect_timeout).timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?.no_proxy(reqwest::NoProxy::from_env()));
+    }
+    builder.build()
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// 設定を読み込んでサブコマンドを実行する
+/// 失敗した場合は、何をしていて失敗したかを添えたエラーを返す
+async fn run(cli: Cli) -> Result<ExitCode, IsbnError> {
+    let config = Config::load(cli.config.as_deref())
+        .and_then(|config| config.merge_env(std::env::vars()))
+        .map_err(|e| IsbnError::Other(e.into()))
+        .context("cannot read config")?;
+    init_tracing(cli.log_level.or(config.log_level).unwrap_or(LogLevel::Warn));
+    // コマンドラインで指定しなかった項目は環境変数、設定ファイルの順に探す
+    let providers = if cli.provider.is_empty() {
+        config.provider.clone().unwrap_or_else(|| vec![Provider::Ndl])
+    } else {
+        cli.provider
+    };
+    let format = cli.format.or(config.format).unwrap_or(OutputFormat::Text);
+    let calil_systems = if cli.calil_systems.is_empty() {
+        config.calil_systems.clone().unwrap_or_default()
+    } else {
+        cli.calil_systems
+    };
+
+    let client = build_client(
+        cli.proxy.as_deref().or(config.proxy.as_deref()),
+        Duration::from_secs(cli.connect_timeout.or(config.connect_timeout).unwrap_or(10)),
+        Duration::from_secs(cli.timeout.or(config.timeout).unwrap_or(30)),
+    );
+    let client = client.context("cannot build http client")?;
+    let rakuten = cli.rakuten_app_id.or(config.rakuten_app_id.clone()).map(|application_id| RakutenBooks {
+        client: client.clone(),
+        application_id,
+        affiliate_id: cli.rakuten_affiliate_id.or(config.rakuten_affiliate_id.clone()),
+    });
+    // 図書館システムを指定した場合だけ所蔵を調べる
+    let calil = match cli.calil_app_key.or(config.calil_app_key.clone()) {
+        Some(app_key) if !calil_systems.is_empty() => Some(Calil { app_key, system_ids: calil_systems }),
+        _ => None,
+    };
+    // 問い合わせの間隔は、ランダムに探すときも再試行するときも、すべてのAPIへのリクエストを通して守る
+    let interval = Duration::from_millis(cli.interval.or(config.interval).unwrap_or(1000));
+    let policy = RetryPolicy {
+        max_retries: cli.max_retries.or(config.max_retries).unwrap_or(RetryPolicy::default().max_retries),
+        limiter: Arc::new(RateLimiter::new(interval)),
+        ..RetryPolicy::default()
+    };
+    let sru_schema = cli.sru_schema.unwrap_or_default();
+    let provider = provider_chain(&client, &providers, rakuten.as_ref(), &policy, sru_schema)?;
+    let links = if cli.links.is_empty() {
+        config.links.clone().unwrap_or_else(|| vec![LinkService::Booklog])
+    } else {
+        cli.links
+    };
+    let library_path = cli.library.or(config.library.clone()).or_else(Library::default_path);
+    let wishlist = match (cli.wishlist, &library_path) {
+        (false, _) => None,
+        (true, Some(path)) => Some(open_library(path)?),
+        (true, None) => return Err(IsbnError::Other("--library is required to find the wishlist".into())),
+    };
+    let details = Details {
+        links,
+        open: cli.open,
+        copy: cli.copy,
+        notify: cli.notify,
+        qr: cli.qr,
+        save_cover: cli.save_cover,
+        preview_cover: cli.preview_cover,
+        calibre: cli.calibre,
+        wishlist,
+        calil,
+        explore: cli.explore,
+        first_volume: cli.first_volume,
+        policy: policy.clone(),
+    };
+    let mut printer = match &cli.template {
+        Some(template) => Printer::with_template(format, template)
+            .map_err(|e| IsbnError::Other(e.into()))
+            .context("invalid --template")?,
+        None => Printer::new(format),
+    };
+    // シードを指定しなければ毎回違うISBNを生成する
+    let mut rng = match cli.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    if let Some(path) = cli.publisher_file.or(config.publisher_file) {
+        publisher_list::use_file(&path).context(format!("cannot read publisher file {}", path.display()))?;
+    }
+    let selection = PublisherSelection {
+        weight: cli.publisher_weight.or(config.publisher_weight).unwrap_or_default(),
+        filter: cli.publisher_filter,
+        allow: config.allow_publishers.map(|codes| codes.into_iter().collect()),
+        block: config.block_publishers.unwrap_or_default().into_iter().collect(),
+    };
+    for code in selection.allow.iter().flatten().chain(&selection.block) {
+        check_code("publisher code", code).context("invalid allow_publishers or block_publishers")?;
+    }
+    let command = cli.command.unwrap_or(Command::RandomBook {
+        prefix: None,
+        group: None,
+        publisher: None,
+        scan: false,
+        shuffle: false,
+        count: None,
+        resume: false,
+        checkpoint: None,
+        filter: FilterArgs::default(),
+    });
+    match command {
+        Command::RandomBook { prefix, group, publisher, scan, shuffle, count, resume, checkpoint, filter } => {
+            let checkpoint_path = checkpoint.or_else(Checkpoint::default_path);
+            let resume = match (resume, &checkpoint_path) {
+                (false, _) => None,
+                (true, Some(path)) => Some(
+                    Checkpoint::load(path)
+                        .map_err(|e| IsbnError::Other(e.into()))
+                        .context(format!("cannot resume from {}", path.display()))?,
+                ),
+                (true, None) => return Err(IsbnError::Other("--checkpoint is required to resume".into())),
+            };
+            let (prefix, group, publisher, scan, shuffle, count) = match &resume {
+                Some(checkpoint) => (
+                    checkpoint.prefix.clone(),
+                    checkpoint.group.clone(),
+                    checkpoint.publisher.clone(),
+                    checkpoint.scan,
+                    checkpoint.shuffle,
+                    checkpoint.count,
+                ),
+                // 順番に試す場合は、冊数を指定しなければ最後まで続ける
+                None => (
+                    prefix.or(config.prefix).unwrap_or_else(|| String::from("978")),
+                    group.or(config.group).unwrap_or_else(|| String::from("4")),
+                    publisher,
+                    scan,
+                    // 再開しても同じ順番になるように、鍵はチェックポイントに残す
+                    shuffle.then(|| rng.gen()),
+                    count.unwrap_or(if scan { usize::MAX } else { 1 }),
+                ),
+            };
+            let registration_group = find_registration_group(&prefix, &group)?;
+            if let Some(publisher) = &publisher {
+                check_publisher(&group, publisher)?;
+            }
+            check_selection(&registration_group, publisher.as_deref(), &selection)?;
+            // 順番に試す場合は、--max-attemptsを指定しなければ最後まで続ける
+            let max_attempts = if cli.no_limit || (scan && cli.max_attempts.is_none()) {
+                None
+            } else {
+                cli.max_attempts.or(config.max_attempts).or(Some(10))
+            };
+            let options = RandomBookOptions {
+                registration_group,
+                publisher,
+                scan,
+                shuffle,
+                selection,
+                filter: filter.into(),
+                count,
+                checkpoint: checkpoint_path,
+                resume,
+                max_attempts,
+                concurrency: cli.concurrency.or(config.concurrency).unwrap_or(1).max(1),
+            };
+            let history = if cli.no_history {
+                None
+            } else {
+                match cli.history.or(config.history).or_else(History::default_path) {
+                    Some(path) => Some(open_history(&path)?),
+                    None => None,
+                }
+            };
+            Ok(random_book(&client, &provider, &details, &mut printer, history.as_ref(), &mut rng, &options).await)
+        }
+        Command::Generate { prefix, group, publisher, count } => {
+            let prefix = prefix.or(config.prefix).unwrap_or_else(|| String::from("978"));
+            let group = group.or(config.group).unwrap_or_else(|| String::from("4"));
+            generate(prefix, group, publisher, count, &selection, &mut rng)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Validate { isbns, file } => Ok(validate(isbns, file)),
+        Command::Convert { isbns, to } => Ok(convert(isbns, to)),
+        Command::Lookup { isbn } => lookup(&client, &provider, &details, &mut printer, &isbn).await,
+        Command::Scan { image } => {
+            let isbn = scan(&image)?;
+            lookup(&client, &provider, &details, &mut printer, &isbn).await
+        }
+        Command::Hyphenate { isbn } => {
+            hyphenate_command(&isbn)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Barcode { isbn, output, scale, c_code, price, addon } => {
+            let price_code = match (c_code, price) {
+                (Some(c_code), Some(price)) => Some(PriceCode::new(&c_code, price).context(&c_code)?),
+                _ => None,
+            };
+            let addon = addon.map(|addon| Ean5::parse(&addon).context(&addon)).transpose()?;
+            barcode(&isbn, price_code, addon.as_ref(), output.as_deref(), scale)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Labels { isbns, file, output, columns, rows, page_width, page_height, margin_x, margin_y } => {
+            let sheet = LabelSheet { page_width, page_height, columns, rows, margin_x, margin_y, ..Default::default() };
+            labels(isbns, file, &output, &sheet)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::ImportBooklog { file } => {
+            let path = cli.history.or(config.history).or_else(History::default_path);
+            let path = path.ok_or_else(|| IsbnError::Other("--history is required to import the shelf".into()))?;
+            let bytes = std::fs::read(&file)
+                .map_err(|e| IsbnError::Other(e.into()))
+                .context(format!("cannot read {}", file.display()))?;
+            let (books, skipped) = booklog::parse(&booklog::decode(&bytes));
+            let imported = open_history(&path)?
+                .record_owned(&books)
+                .map_err(|e| IsbnError::Other(e.into()))
+                .context(format!("cannot write history {}", path.display()))?;
+            eprintln!("imported {} of {} books ({} rows without isbn skipped)", imported, books.len(), skipped);
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Opds { output } => {
+            let path = cli.history.or(config.history).or_else(History::default_path);
+            let path = path.ok_or_else(|| IsbnError::Other("--history is required to find the history".into()))?;
+            let books = open_history(&path)?
+                .found_books()
+                .map_err(|e| IsbnError::Other(e.into()))
+                .context(format!("cannot read history {}", path.display()))?;
+            let self_href = output.as_ref().and_then(|output| output.file_name()).map(|name| name.to_string_lossy());
+            // 履歴ファイルを相対パスで指定しても同じIDになるように、絶対パスにしてからIDを作る
+            let id = opds::feed_id(&std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone()));
+            let feed = opds::feed(&books, &id, self_href.as_deref());
+            match &output {
+                Some(output) => std::fs::write(output, feed)
+                    .map_err(|e| IsbnError::Other(e.into()))
+                    .context(format!("cannot write {}", output.display()))?,
+                None => print!("{}", feed),
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Serve { listen, ip_rate_limit, ip_rate_burst, global_rate_limit, global_rate_burst } => {
+            // /randomは日本の出版社を出版社一覧から選ぶ
+            check_selection(&find_registration_group("978", "4")?, None, &selection)?;
+            let history = if cli.no_history {
+                None
+            } else {
+                match cli.history.or(config.history).or_else(History::default_path) {
+                    Some(path) => Some(std::sync::Mutex::new(open_history(&path)?)),
+                    None => None,
+                }
+            };
+            let per_ip =
+                ip_rate_limit.or(config.ip_rate_limit).map(|rate| Limit::new(rate, ip_rate_burst.or(config.ip_rate_burst)));
+            let global = global_rate_limit
+                .or(config.global_rate_limit)
+                .map(|rate| Limit::new(rate, global_rate_burst.or(config.global_rate_burst)));
+            let state = AppState {
+                provider: Box::new(provider),
+                generator: Box::new(move |rng| random_isbn(&selection, rng)),
+                rng: std::sync::Mutex::new(rng),
+                history,
+                max_attempts: cli.max_attempts.or(config.max_attempts).unwrap_or(10),
+                events: tokio::sync::broadcast::Sender::new(server::EVENT_CAPACITY),
+                metrics: Default::default(),
+                readiness: Default::default(),
+                throttle: Throttle::new(per_ip, global),
+            };
+            server::serve(listen, state)
+                .await
+                .map_err(|e| IsbnError::Other(e.into()))
+                .context(format!("cannot serve on {}", listen))?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Backup { file } => {
+            let items = backup_items(cli.config, cli.history.or(config.history), library_path);
+            let names = backup::backup(&file, &items)
+                .map_err(IsbnError::Other)
+                .context(format!("cannot write {}", file.display()))?;
+            eprintln!("backed up {} to {}", names.join(", "), file.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Restore { file, force } => {
+            let items = backup_items(cli.config, cli.history.or(config.history), library_path);
+            let names = backup::restore(&file, &items, force)
+                .map_err(IsbnError::Other)
+                .context(format!("cannot restore {}", file.display()))?;
+            for name in &names {
+                let item = items.iter().find(|item| item.name == *name).expect("restored item");
+                eprintln!("restored {} to {}", name, item.path.display());
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::BookCode { code } => {
+            let price = PriceCode::parse(&code).context(&code)?;
+            println!("{} ({})", price, price.c_code.describe());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::CCode { code } => {
+            let c_code = CCode::parse(&code).context(&code)?;
+            println!("{} {}", c_code, c_code.describe());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Search { cql, title, creator, publisher, ndc, from, until, max } => {
+            let query = CqlQuery { anywhere: None, title, creator, publisher, ndc, from, until, isbn: None };
+            if cql.is_none() && query.is_empty() {
+                eprintln!("specify --cql or at least one of --title, --creator, --publisher, --ndc, --from, --until");
+                return Ok(ExitCode::FAILURE);
+            }
+            let sru = NdlSru { client: client.clone(), schema: sru_schema };
+            search(&sru, &policy, &cql.unwrap_or_else(|| query.to_cql()), max, &mut printer).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::ByAuthor { author } => {
+            let query = CqlQuery { creator: Some(author), ..Default::default() };
+            let max_attempts = if cli.no_limit { None } else { cli.max_attempts.or(config.max_attempts).or(Some(10)) };
+            let sru = NdlSru { client: client.clone(), schema: sru_schema };
+            random_record(&sru, &policy, &details, &mut printer, &mut rng, &query.to_cql(), max_attempts).await
+        }
+        Command::SearchRandom { keyword } => {
+            let query = CqlQuery { anywhere: Some(keyword), ..Default::default() };
+            let max_attempts = if cli.no_limit { None } else { cli.max_attempts.or(config.max_attempts).or(Some(10)) };
+            let sru = NdlSru { client: client.clone(), schema: sru_schema };
+            random_record(&sru, &policy, &details, &mut printer, &mut rng, &query.to_cql(), max_attempts).await
+        }
+        Command::Library { command } => {
+            let path =
+                library_path.ok_or_else(|| IsbnError::Other("--library is required to find the library".into()))?;
+            let library = open_library(&path)?;
+            let context = || format!("cannot read library {}", path.display());
+            match command {
+                LibraryCommand::Add { isbn, tag, note } => {
+                    let history = match cli.history.or(config.history).or_else(History::default_path) {
+                        Some(path) if !cli.no_history => Some(open_history(&path)?),
+                        _ => None,
+                    };
+                    library_add(&provider, &library, history.as_ref(), &printer, &isbn, &tag, note.as_deref()).await
+                }
+                LibraryCommand::List { tag } => {
+                    let mut books = library.list().map_err(|e| IsbnError::Other(e.into())).context(context())?;
+                    books.retain(|book| book.has_tags(&tag));
+                    print_library_books(&mut printer, format, &books);
+                    Ok(ExitCode::SUCCESS)
+                }
+                LibraryCommand::Tag { isbn, tags, remove } => {
+                    let isbn_13 = Isbn::parse(&isbn).context(&isbn)?.create_isbn_13();
+                    let context = || format!("cannot tag {}", isbn_13);
+                    let tagged = if remove {
+                        library.untag(&isbn_13, &tags).map(|removed| removed > 0)
+                    } else {
+                        library.tag(&isbn_13, &tags)
+                    };
+                    if !tagged.map_err(|e| IsbnError::Other(e.into())).context(context())? {
+                        let reason = if remove { "no such tags" } else { "not in the library" };
+                        printer.report(&format!("{} ... {}", isbn_13, reason));
+                        return Ok(ExitCode::FAILURE);
+                    }
+                    printer.report(&format!("{} ... {}", isbn_13, if remove { "untagged" } else { "tagged" }));
+                    Ok(ExitCode::SUCCESS)
+                }
+                LibraryCommand::Rate { isbn, rating, clear: _ } => {
+                    let isbn_13 = Isbn::parse(&isbn).context(&isbn)?.create_isbn_13();
+                    let rated = library
+                        .rate(&isbn_13, rating)
+                        .map_err(|e| IsbnError::Other(e.into()))
+                        .context(format!("cannot rate {}", isbn_13))?;
+                    if !rated {
+                        printer.report(&format!("{} ... not in the library", isbn_13));
+                        return Ok(ExitCode::FAILURE);
+                    }
+                    match rating {
+                        Some(rating) => printer.report(&format!("{} ... {}", isbn_13, stars(rating))),
+                        None => printer.report(&format!("{} ... rating cleared", isbn_13)),
+                    }
+                    Ok(ExitCode::SUCCESS)
+                }
+                LibraryCommand::Remove { isbn } => {
+                    let isbn_13 = Isbn::parse(&isbn).context(&isbn)?.create_isbn_13();
+                    let removed = library
+                        .remove(&isbn_13)
+                        .map_err(|e| IsbnError::Other(e.into()))
+                        .context(format!("cannot remove {} from the library", isbn_13))?;
+                    if !removed {
+                        printer.report(&format!("{} ... not in the library", isbn_13));
+                        return Ok(ExitCode::FAILURE);
+                    }
+                    printer.report(&format!("{} ... removed", isbn_13));
+                    Ok(ExitCode::SUCCESS)
+                }
+                LibraryCommand::Search { query, tag } => {
+                    let query = query.join(" ");
+                    let mut books = library.search(&query).map_err(|e| IsbnError::Other(e.into())).context(context())?;
+                    books.retain(|book| book.has_tags(&tag));
+                    print_library_books(&mut printer, format, &books);
+                    printer.report(&format!("{} books found", books.len()));
+                    Ok(if books.is_empty() { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+                }
+                LibraryCommand::Show { isbn } => {
+                    let isbn_13 = Isbn::parse(&isbn).context(&isbn)?.create_isbn_13();
+                    match library.get(&isbn_13).map_err(|e| IsbnError::Other(e.into())).context(context())? {
+                        Some(book) => {
+                            print_library_book(&mut printer, format, &book);
+                            Ok(ExitCode::SUCCESS)
+                        }
+                        None => {
+                            printer.report(&format!("{} ... not in the library", isbn_13));
+                            Ok(ExitCode::FAILURE)
+                        }
+                    }
+                }
+            }
+        }
+        Command::Wishlist { command } => {
+            let path =
+                library_path.ok_or_else(|| IsbnError::Other("--library is required to find the wishlist".into()))?;
+            let library = open_library(&path)?;
+            let context = || format!("cannot read wishlist {}", path.display());
+            match command {
+                WishlistCommand::Add { isbn } => {
+                    let history = match cli.history.or(config.history).or_else(History::default_path) {
+                        Some(path) if !cli.no_history => Some(open_history(&path)?),
+                        _ => None,
+                    };
+                    let parsed = Isbn::parse(&isbn).context(&isbn)?;
+                    let Some((record, _)) = find_record(&provider, history.as_ref(), &parsed).await? else {
+                        printer.report(&format!("{} ... not found", parsed.create_isbn_13()));
+                        return Ok(ExitCode::FAILURE);
+                    };
+                    let added = library.wish(&record).map_err(|e| IsbnError::Other(e.into())).context(context())?;
+                    let action = if added { "added" } else { "already in the wishlist" };
+                    printer.report(&format!("{} {} ... {}", record.isbn, record.title, action));
+                    Ok(ExitCode::SUCCESS)
+                }
+                WishlistCommand::List { all } => {
+                    let wishes = library.wishlist(all).map_err(|e| IsbnError::Other(e.into())).context(context())?;
+                    print_wishlist(&mut printer, format, &wishes);
+                    Ok(ExitCode::SUCCESS)
+                }
+                WishlistCommand::Done { isbn } => {
+                    let isbn_13 = Isbn::parse(&isbn).context(&isbn)?.create_isbn_13();
+                    if !library.done(&isbn_13).map_err(|e| IsbnError::Other(e.into())).context(context())? {
+                        printer.report(&format!("{} ... not in the wishlist or already done", isbn_13));
+                        return Ok(ExitCode::FAILURE);
+                    }
+                    printer.report(&format!("{} ... done", isbn_13));
+                    Ok(ExitCode::SUCCESS)
+                }
+            }
+        }
+        Command::Publishers { command: Some(PublishersCommand::Update { url, output }), .. } => {
+            let path = output
+                .or_else(publisher_list::default_path)
+                .ok_or_else(|| IsbnError::Other("--output is required to update the publisher list".into()))?;
+            let source = url.as_deref().unwrap_or("wikidata");
+            let count = publisher_list::update(&client, url.as_deref(), &path)
+                .await
+                .context(format!("cannot update the publisher list from {}", source))?;
+            eprintln!("wrote {} publishers to {}", count, path.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Publishers { command: None, query, sort, reverse } => {
+            Ok(publishers(query.as_deref(), sort, reverse, format))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// plan_roundsで、件数の分だけ位置を並べた候補を作る
+    fn planned(
+        batch_size: usize,
+        max_attempts: Option<usize>,
+        checkpoint: &Checkpoint,
+    ) -> Vec<(usize, usize, Vec<usize>)> {
+        let exhausted = Cell::new(false);
+        let rounds = plan_rounds(batch_size, max_attempts, checkpoint, &exhausted, |start, size| {
+            Some((start..start + size).collect())
+        });
+        rounds.take(10).collect()
+    }
+
+    #[test]
+    fn test_plan_rounds() {
+        // 10件ずつ問い合わせても、--max-attemptsの25件で止まる
+        let rounds = planned(10, Some(25), &Checkpoint::default());
+        let sizes: Vec<(usize, usize)> = rounds.iter().map(|(round, _, isbns)| (*round, isbns.len())).collect();
+        assert_eq!(sizes, vec![(1, 10), (2, 10), (3, 5)]);
+        assert_eq!(rounds[2].2, (20..25).collect::<Vec<_>>());
+
+        // 再開する場合は、試した数を除いた残りだけ問い合わせる
+        let checkpoint = Checkpoint { cursor: 1, attempts: 12, ..Default::default() };
+        let rounds = planned(10, Some(25), &checkpoint);
+        let sizes: Vec<(usize, usize)> = rounds.iter().map(|(round, _, isbns)| (*round, isbns.len())).collect();
+        assert_eq!(sizes, vec![(2, 10), (3, 3)]);
+        // 上限まで試し終えていれば問い合わせない
+        let checkpoint = Checkpoint { cursor: 3, attempts: 25, ..Default::default() };
+        assert!(planned(10, Some(25), &checkpoint).is_empty());
+        // 上限がなければ続ける
+        assert_eq!(planned(10, None, &Checkpoint::default()).len(), 10);
+    }
+
+    #[test]
+    fn test_plan_rounds_resume_offset() {
+        // openBD(10件ずつ)で2回終えたところから、NDL(1件ずつ)で再開しても20番目の書籍コードから続ける
+        let checkpoint = Checkpoint { cursor: 2, offset: 20, attempts: 20, ..Default::default() };
+        let rounds = planned(1, Some(23), &checkpoint);
+        assert_eq!(rounds, vec![(3, 21, vec![20]), (4, 22, vec![21]), (5, 23, vec![22])]);
+    }
+
+    #[test]
+    fn test_scan_isbns() {
+        let group = RegistrationGroup::find("978", "4").unwrap();
+        // 出版社記号が7桁なので書籍コードは1桁(10件)
+        let codes = |isbns: Vec<Isbn>| isbns.iter().map(|isbn| isbn.publication_code().to_string()).collect::<Vec<_>>();
+        assert_eq!(codes(scan_isbns(group, "1234567", 0, 3, None, None).unwrap()), vec!["0", "1", "2"]);
+        // 最後の書籍コードを超えた分は並べない
+        assert_eq!(codes(scan_isbns(group, "1234567", 8, 5, None, None).unwrap()), vec!["8", "9"]);
+        assert!(scan_isbns(group, "1234567", 10, 5, None, None).is_none());
+        // 書籍コードの桁が残らない出版社記号は並べない
+        assert!(scan_isbns(group, "12345678", 0, 5, None, None).is_none());
+
+        // 履歴にあるISBNは除くが、位置は進める
+        let history = History::open(Path::new(":memory:")).unwrap();
+        let tried = Isbn::from_parts("978".into(), "4".into(), "1234567".into(), "1".into());
+        history.record_miss(&tried.create_isbn_13()).unwrap();
+        assert_eq!(codes(scan_isbns(group, "1234567", 0, 3, None, Some(&history)).unwrap()), vec!["0", "2"]);
+        assert_eq!(codes(scan_isbns(group, "1234567", 1, 1, None, Some(&history)).unwrap()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_scan_isbns_shuffled() {
+        let group = RegistrationGroup::find("978", "4").unwrap();
+        // 出版社記号が6桁なので書籍コードは2桁(100件)
+        let window = |key, start, limit| -> Vec<String> {
+            let isbns = scan_isbns(group, "123456", start, limit, Some(key), None).unwrap();
+            isbns.iter().map(|isbn| isbn.publication_code().to_string()).collect()
+        };
+        // 同じ鍵で続けて並べた範囲は重ならない
+        let first: HashSet<String> = window(42, 0, 30).into_iter().collect();
+        let second: HashSet<String> = window(42, 30, 30).into_iter().collect();
+        assert_eq!((first.len(), second.len()), (30, 30));
+        assert!(first.is_disjoint(&second));
+        // すべての範囲を合わせると、100件の書籍コードを1回ずつ並べる
+        let mut codes: Vec<String> = (0..4).flat_map(|round| window(42, round * 30, 30)).collect();
+        assert_eq!(codes.len(), 100);
+        codes.sort();
+        assert_eq!(codes, (0..100).map(|code| format!("{:02}", code)).collect::<Vec<_>>());
+        assert!(scan_isbns(group, "123456", 120, 30, Some(42), None).is_none());
+        // 鍵を変えると順番が変わる
+        assert_ne!(window(42, 0, 30), window(7, 0, 30));
+    }
+
+    #[test]
+    fn test_plan_rounds_short_batches() {
+        // 履歴で除いて候補が少なかった回は、その分だけ後の回で多く試す
+        let exhausted = Cell::new(false);
+        let rounds: Vec<usize> = plan_rounds(10, Some(25), &Checkpoint::default(), &exhausted, |_, size| {
+            Some(vec![(); size.min(4)])
+        })
+        .map(|(_, _, candidates)| candidates.len())
+        .collect();
+        assert_eq!(rounds, vec![4, 4, 4, 4, 4, 4, 1]);
+        assert!(!exhausted.get());
+
+        // 候補がなくなったら止めて、試し尽くしたことにする
+        let rounds = plan_rounds(10, Some(25), &Checkpoint::default(), &exhausted, |start, size| {
+            (start < 10).then(|| vec![(); size])
+        });
+        assert_eq!(rounds.count(), 1);
+        assert!(exhausted.get());
+    }
+
     #[test]
-    fn test_generate_pubalication_code() {
-        // 最大桁数の場合(7桁)
-        let country_code_7 = String::from("4");   // 日本
-        let publisher_code_7 = String::from("1");  // 旺文社
-        let publication_code7: String = Isbn::generate_publication_code(&country_code_7, &publisher_code_7);
-        assert!(publication_code7.to_string().len() == 7);
-
-        // 6桁の場合
-        let country_code_6 = String::from("4");
-        let publisher_code_6 = String::from("12");
-        let publication_code6 = Isbn::generate_publication_code(&country_code_6, &publisher_code_6);
-        assert!(publication_code6.len() == 6);
-
-        // 5桁の場合
-        let country_code_5 = String::from("4");
-        let publisher_code_5 = String::from("123");
-        let publication_code5 = Isbn::generate_publication_code(&country_code_5, &publisher_code_5);
-        assert!(publication_code5.len() == 5);
-
-        // 4桁の場合
-        let country_code_4 = String::from("4");
-        let publisher_code_4 = String::from("1234");
-        let publication_code4 = Isbn::generate_publication_code(&country_code_4, &publisher_code_4);
-        assert!(publication_code4.len() == 4);
+    fn test_validate_one() {
+        assert_eq!(validate_one("9784798171548"), Ok(()));
+        assert_eq!(validate_one("978-4-7981-7154-8"), Ok(()));
+        assert_eq!(validate_one("4 10 109205 2"), Ok(()));
+        assert_eq!(validate_one("0-8044-2957-x"), Ok(()));
+        assert_eq!(validate_one("9784798171549"), Err(String::from("wrong check digit: expected 8, found 9")));
+        assert_eq!(validate_one("4101092053"), Err(String::from("wrong check digit: expected 2, found 3")));
+        assert_eq!(validate_one("978479817154"), Err(String::from("invalid length: 12 digits")));
+        assert_eq!(validate_one("978-4-7981-7154-8-1"), Err(String::from("invalid length: 14 digits")));
+        assert_eq!(validate_one("97847981a1548"), Err(String::from("invalid character: 'a'")));
+        assert_eq!(validate_one("9784798_71548"), Err(String::from("invalid character: '_'")));
     }
 
     #[test]
-    fn test_calc_check_digit_10() {
-        // 4-10-109205
-        let country_code = String::from("4");
-        let publisher_code = String::from("10");
-        let publication_code = String::from("109205");
+    fn test_validate_one_hyphens() {
+        let misplaced = |expected| Err(format!("misplaced hyphens: expected {}", expected));
+        // 区切りの位置がRangeMessageの規則と違う
+        assert_eq!(validate_one("978-47-981-7154-8"), misplaced("978-4-7981-7154-8"));
+        assert_eq!(validate_one("4-101-09205-2"), misplaced("4-10-109205-2"));
+        // 区切りが足りない場合も違うとみなす
+        assert_eq!(validate_one("978-4798171548"), misplaced("978-4-7981-7154-8"));
+        // 続いた区切りや前後の区切りは1つとみなす
+        assert_eq!(validate_one("978--4-7981-7154-8-"), Ok(()));
+        assert_eq!(validate_one("0-804-42957-x"), misplaced("0-8044-2957-X"));
+    }
+
+    #[test]
+    fn test_convert_one() {
+        // 10桁は13桁に、13桁は10桁にする
+        assert_eq!(convert_one("4101092052", None), Ok(String::from("9784101092058")));
+        assert_eq!(convert_one("978-4-10-109205-8", None), Ok(String::from("4101092052")));
+        assert_eq!(convert_one("0-8044-2957-x", Some(IsbnForm::Isbn13)), Ok(String::from("9780804429573")));
+        assert_eq!(convert_one("9784101092058", Some(IsbnForm::Isbn10)), Ok(String::from("4101092052")));
+        // 979始まりのISBNにはISBN10がない
+        assert!(convert_one("9791032305690", None).is_err());
+        assert!(convert_one("9791032305690", Some(IsbnForm::Isbn10)).is_err());
+        // 変換先と同じ形式の場合は、検証してハイフンを取り除く
+        assert_eq!(convert_one("978-4-10-109205-8", Some(IsbnForm::Isbn13)), Ok(String::from("9784101092058")));
+        assert_eq!(convert_one("979-10-323-0569-0", Some(IsbnForm::Isbn13)), Ok(String::from("9791032305690")));
+        assert_eq!(convert_one("0-8044-2957-x", Some(IsbnForm::Isbn10)), Ok(String::from("080442957X")));
+        // 不正なISBNは変換しない
+        assert!(convert_one("9784101092059", Some(IsbnForm::Isbn13)).is_err());
+        assert!(convert_one("4101092053", Some(IsbnForm::Isbn10)).is_err());
+        assert!(convert_one("4101092053", None).is_err());
+        assert!(convert_one("410109205", None).is_err());
+        assert!(convert_one("41010920a2", None).is_err());
+    }
 
-        let check_digit_10: String = Isbn::calc_check_digit_10(&country_code, &publisher_code, &publication_code);
-        assert_eq!(check_digit_10, String::from("2"));
+    /// 利用者の設定ファイルと履歴を読まないようにして、コマンドラインの引数で実行する
+    async fn run_args(args: &[&str]) -> Result<ExitCode, IsbnError> {
+        let config = std::env::temp_dir().join(format!("isbn-main-test-{}.toml", std::process::id()));
+        std::fs::write(&config, "").unwrap();
+        let mut argv = vec!["isbn", "--config", config.to_str().unwrap(), "--no-history"];
+        argv.extend(args);
+        run(Cli::try_parse_from(argv).unwrap()).await
     }
 
     #[test]
-    fn test_calc_check_digit_13() {
-        // 978-4-7981-7154-8
-        let head_code = String::from("978");
-        let country_code = String::from("4");
-        let publisher_code = String::from("7981");
-        let publication_code = String::from("7154");
-        let expected = String::from("8");
+    fn test_check_selection() {
+        let japan = find_registration_group("978", "4").unwrap();
+        let unmatched = PublisherSelection { filter: Regex::new("^存在しない出版社$").ok(), ..Default::default() };
+        assert!(check_selection(&japan, None, &unmatched).is_err());
+        // 出版社を指定した場合や、日本以外のグループの場合は出版社一覧から選ばない
+        assert!(check_selection(&japan, Some("7981"), &unmatched).is_ok());
+        assert!(check_selection(&find_registration_group("978", "0").unwrap(), None, &unmatched).is_ok());
+        let matched = PublisherSelection { filter: Regex::new("^新潮社$").ok(), ..Default::default() };
+        assert!(check_selection(&japan, None, &matched).is_ok());
+    }
 
-        let check_digit_13: String = Isbn::calc_check_digit_13(&head_code, &country_code, &publisher_code, &publication_code);
-        assert_eq!(check_digit_13, expected);
+    #[tokio::test]
+    async fn test_publisher_filter_commands() {
+        let filter = ["--publisher-filter", "^存在しない出版社$"];
+        // 出版社を選ぶコマンドは、選べる出版社がなければエラーにする
+        for command in [&["generate"][..], &["random-book"], &["serve", "--listen", "127.0.0.1:0"]] {
+            let args: Vec<&str> = filter.iter().chain(command).copied().collect();
+            let error = run_args(&args).await.unwrap_err();
+            assert!(error.to_string().contains("no publishers left"), "{:?}: {}", command, error);
+        }
+        // 出版社を選ばないコマンドは影響を受けない
+        let args: Vec<&str> = filter.iter().chain(&["validate", "9784798171548"]).copied().collect();
+        assert_eq!(run_args(&args).await.unwrap(), ExitCode::SUCCESS);
+        let args: Vec<&str> = filter.iter().chain(&["convert", "4101092052"]).copied().collect();
+        assert_eq!(run_args(&args).await.unwrap(), ExitCode::SUCCESS);
     }
 
     #[test]
-    fn test_create_isbn_10() {
-        let isbn = Isbn::new(String::from("978"), String::from("4"), String::from("10"));
-        assert!(isbn.create_isbn_10().len() == 10);
+    fn test_publisher_selection() {
+        let codes = |codes: &[&str]| codes.iter().map(|code| code.to_string()).collect::<HashSet<String>>();
+        let shinchosha = Publisher { code: String::from("10"), name: String::from("新潮社") };
+        let shoeisha = Publisher { code: String::from("7981"), name: String::from("翔泳社") };
+        let selection = PublisherSelection { block: codes(&["10"]), ..Default::default() };
+        assert_eq!(selection.weight(&shinchosha), 0.0);
+        assert_eq!(selection.weight(&shoeisha), 1.0);
+        // 許可した出版社記号でも、選ばない出版社記号に含まれていれば選ばない
+        let allow = Some(codes(&["10", "7981"]));
+        let selection = PublisherSelection { allow, block: codes(&["10"]), ..Default::default() };
+        assert_eq!(selection.weight(&shinchosha), 0.0);
+        assert_eq!(selection.weight(&shoeisha), 1.0);
+        assert_eq!(selection.weight(&Publisher { code: String::from("06"), name: String::from("講談社") }), 0.0);
     }
 
     #[test]
-    fn test_create_isbn_13() {
-        let isbn = Isbn::new(String::from("978"), String::from("4"), String::from("10"));
-        assert!(isbn.create_isbn_13().len() == 13);
+    fn test_random_isbn_selection() {
+        let mut rng = StdRng::seed_from_u64(42);
+        // 出版社一覧にある出版社記号で確かめる
+        let codes = |range: std::ops::Range<usize>| -> HashSet<String> {
+            publisher_list::registry().iter().skip(range.start).take(range.len()).map(|p| p.code.clone()).collect()
+        };
+        // 選ばない出版社記号の出版社は一度も選ばない
+        let blocked = codes(0..10);
+        let selection = PublisherSelection { block: blocked.clone(), ..Default::default() };
+        for _ in 0..1000 {
+            let isbn = random_isbn(&selection, &mut rng).unwrap();
+            assert!(!blocked.contains(isbn.publisher_code()), "{}", isbn.create_isbn_13());
+        }
+        // 許可した出版社記号の出版社だけから選ぶ
+        let allowed = codes(10..12);
+        let selection = PublisherSelection { allow: Some(allowed.clone()), ..Default::default() };
+        let chosen: HashSet<String> =
+            (0..100).map(|_| random_isbn(&selection, &mut rng).unwrap().publisher_code().to_string()).collect();
+        assert_eq!(chosen, allowed);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,136 @@
+//! serveサブコマンドの計測値
+//! /metricsでPrometheusのテキスト形式(0.0.4)にして返す
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// /metricsのContent-Type
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// リクエストの所要時間のヒストグラムのバケットの上限(秒)
+const BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// 所要時間のヒストグラム
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// バケットごとの、上限以下だった数(累積しない)
+    counts: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(i) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.counts[i] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// リクエストを分けるラベル(メソッド、ルート、ステータスコード)
+type RequestLabels = (String, String, u16);
+
+/// サーバーを動かしてからの計測値
+#[derive(Debug, Default)]
+pub struct Metrics {
+    attempts: AtomicU64,
+    hits: AtomicU64,
+    provider_errors: AtomicU64,
+    requests: Mutex<BTreeMap<RequestLabels, Histogram>>,
+}
+
+impl Metrics {
+    /// ランダムに生成したISBNをcount件問い合わせた
+    pub fn attempted(&self, count: usize) {
+        self.attempts.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// 本がcount冊見つかった
+    pub fn hit(&self, count: usize) {
+        self.hits.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// 書誌情報のAPIへの問い合わせが(再試行しても)失敗した
+    pub fn provider_error(&self) {
+        self.provider_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// HTTPのリクエストに応えた
+    /// routeはパラメーターを含まないルート(/lookup/{isbn}など)にして、ラベルの種類が増えすぎないようにする
+    pub fn request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let mut requests = self.requests.lock().expect("metrics lock");
+        let labels = (method.to_string(), route.to_string(), status);
+        requests.entry(labels).or_default().observe(elapsed.as_secs_f64());
+    }
+
+    /// Prometheusのテキスト形式
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let counters = [
+            ("isbn_attempts_total", "ランダムに生成して問い合わせたISBNの数", &self.attempts),
+            ("isbn_hits_total", "見つかって受け入れた本の数", &self.hits),
+            ("isbn_provider_errors_total", "書誌情報のAPIへの問い合わせが失敗した回数", &self.provider_errors),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} counter", name);
+            let _ = writeln!(text, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        let name = "isbn_http_request_duration_seconds";
+        let _ = writeln!(text, "# HELP {} HTTPのリクエストに応えるまでの時間", name);
+        let _ = writeln!(text, "# TYPE {} histogram", name);
+        for ((method, route, status), histogram) in self.requests.lock().expect("metrics lock").iter() {
+            let labels = format!("method=\"{}\",route=\"{}\",status=\"{}\"", escape(method), escape(route), status);
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+                cumulative += count;
+                let _ = writeln!(text, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, cumulative);
+            }
+            let _ = writeln!(text, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, histogram.count);
+            let _ = writeln!(text, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+            let _ = writeln!(text, "{}_count{{{}}} {}", name, labels, histogram.count);
+        }
+        text
+    }
+}
+
+/// ラベルの値のエスケープ
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.attempted(3);
+        metrics.hit(1);
+        metrics.provider_error();
+        metrics.request("GET", "/random", 200, Duration::from_millis(30));
+        metrics.request("GET", "/random", 200, Duration::from_secs(60));
+        let text = metrics.render();
+        assert!(text.contains("# TYPE isbn_attempts_total counter\nisbn_attempts_total 3\n"));
+        assert!(text.contains("\nisbn_hits_total 1\n"));
+        assert!(text.contains("\nisbn_provider_errors_total 1\n"));
+        let labels = "method=\"GET\",route=\"/random\",status=\"200\"";
+        assert!(text.contains(&format!("isbn_http_request_duration_seconds_bucket{{{},le=\"0.025\"}} 0\n", labels)));
+        assert!(text.contains(&format!("isbn_http_request_duration_seconds_bucket{{{},le=\"0.05\"}} 1\n", labels)));
+        assert!(text.contains(&format!("isbn_http_request_duration_seconds_bucket{{{},le=\"30\"}} 1\n", labels)));
+        assert!(text.contains(&format!("isbn_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n", labels)));
+        assert!(text.contains(&format!("isbn_http_request_duration_seconds_sum{{{}}} 60.03\n", labels)));
+        assert!(text.contains(&format!("isbn_http_request_duration_seconds_count{{{}}} 2\n", labels)));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
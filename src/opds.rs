@@ -0,0 +1,179 @@
+//! 履歴の見つかった本の、OPDS 1.2の取得用フィード(Atom)
+//! 電子書籍リーダーのアプリで、ランダムに見つけた本を一覧できるようにする
+
+use isbn::to_isbn10;
+use std::path::Path;
+
+use crate::explore::author_name;
+use crate::export::escape_xml;
+use crate::history::FoundBook;
+use crate::output::booklog_url;
+
+/// 取得用フィードのContent-Type
+const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+
+/// 書影のURLの拡張子から画像の種類を決める(分からない場合はJPEG)
+fn image_type(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_ascii_lowercase();
+    if path.ends_with(".png") {
+        "image/png"
+    } else if path.ends_with(".gif") {
+        "image/gif"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// 1冊の本のentry
+/// 本を入手するリンクは、書誌のページがあればそのページ、なければブクログのページにする
+fn entry(book: &FoundBook) -> String {
+    let mut lines = vec![
+        format!("<title>{}</title>", escape_xml(&book.title)),
+        format!("<id>urn:isbn:{}</id>", book.isbn),
+        format!("<updated>{}</updated>", book.found_at),
+    ];
+    for author in book.authors.iter().filter_map(|author| author_name(author)) {
+        lines.push(format!("<author><name>{}</name></author>", escape_xml(&author)));
+    }
+    lines.push(format!("<dc:identifier>urn:isbn:{}</dc:identifier>", book.isbn));
+    if let Some(publisher) = &book.publisher {
+        lines.push(format!("<dc:publisher>{}</dc:publisher>", escape_xml(publisher)));
+    }
+    if let Some(issued) = &book.issued {
+        lines.push(format!("<dc:issued>{}</dc:issued>", escape_xml(issued)));
+    }
+    if let Some(language) = &book.language {
+        lines.push(format!("<dc:language>{}</dc:language>", escape_xml(language)));
+    }
+    if let Some(url) = &book.cover_url {
+        for rel in ["http://opds-spec.org/image", "http://opds-spec.org/image/thumbnail"] {
+            lines.push(format!("<link rel=\"{}\" href=\"{}\" type=\"{}\"/>", rel, escape_xml(url), image_type(url)));
+        }
+    }
+    let page = book.link.clone().or_else(|| to_isbn10(&book.isbn).ok().as_deref().and_then(booklog_url));
+    if let Some(url) = page {
+        lines.push(format!(
+            "<link rel=\"http://opds-spec.org/acquisition/buy\" href=\"{}\" type=\"text/html\"/>",
+            escape_xml(&url)
+        ));
+    }
+    let mut entry = String::from("  <entry>\n");
+    for line in lines {
+        entry.push_str(&format!("    {}\n", line));
+    }
+    entry.push_str("  </entry>\n");
+    entry
+}
+
+/// 履歴ファイルの場所から、フィードのID(urn:uuid)を作る
+/// 同じ履歴からは毎回同じIDになるように、パスの128ビットのFNV-1aハッシュからUUID(バージョン8)を作る
+pub fn feed_id(history: &Path) -> String {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    let hash = history
+        .to_string_lossy()
+        .bytes()
+        .fold(OFFSET, |hash, byte| (hash ^ u128::from(byte)).wrapping_mul(PRIME));
+    // バージョン(8)とバリアント(0b10)のビットを立てる
+    let uuid = (hash & !(0xf << 76) & !(0b11 << 62)) | (0x8 << 76) | (0b10 << 62);
+    let hex = format!("{:032x}", uuid);
+    format!("urn:uuid:{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// 見つかった本の一覧のフィード
+/// フィードの更新日時は最も新しく見つかった本の日時(本がない場合はUNIX時間の0)にする
+/// idはfeed_idで作ったフィードのID、self_hrefはフィード自身のURL(ファイルに書き出す場合はファイル名)
+pub fn feed(books: &[FoundBook], id: &str, self_href: Option<&str>) -> String {
+    let updated = books.iter().map(|book| book.found_at.as_str()).max().unwrap_or("1970-01-01T00:00:00Z");
+    let mut feed = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/terms/\" ",
+        "xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n",
+    ));
+    feed.push_str(&format!("  <id>{}</id>\n", escape_xml(id)));
+    feed.push_str("  <title>isbn history</title>\n");
+    feed.push_str(&format!("  <updated>{}</updated>\n", updated));
+    feed.push_str("  <author><name>isbn</name></author>\n");
+    if let Some(href) = self_href {
+        let link = format!("<link rel=\"self\" href=\"{}\" type=\"{}\"/>", escape_xml(href), ACQUISITION_TYPE);
+        feed.push_str(&format!("  {}\n", link));
+    }
+    for book in books {
+        feed.push_str(&entry(book));
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book() -> FoundBook {
+        FoundBook {
+            isbn: String::from("9784101010014"),
+            title: String::from("吾輩は猫である"),
+            authors: vec![String::from("夏目漱石 著")],
+            publisher: Some(String::from("新潮社")),
+            cover_url: Some(String::from("https://cover.openbd.jp/9784101010014.jpg")),
+            found_at: String::from("2024-05-01T12:34:56Z"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let expected = r#"  <entry>
+    <title>吾輩は猫である</title>
+    <id>urn:isbn:9784101010014</id>
+    <updated>2024-05-01T12:34:56Z</updated>
+    <author><name>夏目漱石</name></author>
+    <dc:identifier>urn:isbn:9784101010014</dc:identifier>
+    <dc:publisher>新潮社</dc:publisher>
+    <link rel="http://opds-spec.org/image" href="https://cover.openbd.jp/9784101010014.jpg" type="image/jpeg"/>
+    <link rel="http://opds-spec.org/image/thumbnail" href="https://cover.openbd.jp/9784101010014.jpg" type="image/jpeg"/>
+    <link rel="http://opds-spec.org/acquisition/buy" href="https://booklog.jp/item/1/4101010013" type="text/html"/>
+  </entry>
+"#;
+        assert_eq!(entry(&book()), expected);
+        let book = FoundBook { link: Some(String::from("https://example.com/?a=1&b=2")), cover_url: None, ..book() };
+        assert!(entry(&book).contains("href=\"https://example.com/?a=1&amp;b=2\""));
+    }
+
+    #[test]
+    fn test_feed() {
+        let older = FoundBook { found_at: String::from("2023-01-01T00:00:00Z"), ..book() };
+        let id = feed_id(Path::new("/home/user/.local/share/isbn/history.sqlite3"));
+        let feed = feed(&[book(), older], &id, Some("history.xml"));
+        assert!(feed.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<feed "));
+        assert!(feed.contains(&format!("  <id>{}</id>\n  <title>", id)));
+        assert!(feed.contains("  <updated>2024-05-01T12:34:56Z</updated>\n  <author>"));
+        assert!(feed.contains(&format!("<link rel=\"self\" href=\"history.xml\" type=\"{}\"/>", ACQUISITION_TYPE)));
+        assert_eq!(feed.matches("<entry>").count(), 2);
+        assert!(feed.ends_with("</feed>\n"));
+        let empty = super::feed(&[], &id, None);
+        assert!(empty.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+        assert!(!empty.contains("rel=\"self\""));
+    }
+
+    #[test]
+    fn test_feed_id() {
+        let id = feed_id(Path::new("/home/user/.local/share/isbn/history.sqlite3"));
+        // 同じ履歴からは同じID、別の履歴からは別のIDになる
+        assert_eq!(id, feed_id(Path::new("/home/user/.local/share/isbn/history.sqlite3")));
+        assert_ne!(id, feed_id(Path::new("/tmp/history.sqlite3")));
+        // urn:uuid:xxxxxxxx-xxxx-8xxx-[89ab]xxx-xxxxxxxxxxxx
+        let uuid = id.strip_prefix("urn:uuid:").unwrap();
+        let groups: Vec<usize> = uuid.split('-').map(str::len).collect();
+        assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+        assert!(uuid.chars().all(|c| c == '-' || c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+        assert_eq!(&uuid[14..15], "8");
+        assert!("89ab".contains(&uuid[19..20]));
+    }
+
+    #[test]
+    fn test_image_type() {
+        assert_eq!(image_type("https://example.com/a.PNG"), "image/png");
+        assert_eq!(image_type("http://books.google.com/books/content?id=1&img=1"), "image/jpeg");
+    }
+}
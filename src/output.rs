@@ -0,0 +1,551 @@
+//! 見つかった本の出力形式
+
+use clap::ValueEnum;
+use handlebars::{Handlebars, TemplateError};
+use isbn::{to_isbn10, Isbn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+use crate::export;
+use crate::links::Link;
+use crate::provider::calil::Availability;
+use crate::publisher_list::publisher_name;
+use crate::record::BookRecord;
+
+/// 出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// 1項目1行のテキスト
+    Text,
+    /// JSON
+    Json,
+    /// 1件1行のJSON(JSON Lines)
+    Jsonl,
+    /// カンマ区切り(1行目は見出し)
+    Csv,
+    /// タブ区切り(1行目は見出し)
+    Tsv,
+    /// BibTeXの@book
+    Bibtex,
+    /// RIS(EndNoteやZoteroで読み込める形式)
+    Ris,
+    /// CSL-JSONの配列(Zoteroやciteprocで読み込める形式)
+    Csl,
+    /// Dublin Core XML(OAI-DCのoai_dc:dcの一覧、機関リポジトリのツールで読み込める形式)
+    Dc,
+    /// GoodreadsのインポートのCSV(Title, Author, ISBN13, Date Added, Shelvesなど)
+    Goodreads,
+}
+
+/// 見つかった本と、見つけるまでの情報
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Discovery {
+    pub isbn13: String,
+    /// 979始まりのISBNにはISBN10がない
+    pub isbn10: Option<String>,
+    pub booklog_url: Option<String>,
+    /// 出版社一覧にある出版社名(日本の出版社だけ)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher_name: Option<String>,
+    /// --linksで指定したサービスの本のページ
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+    /// 見つけるまでに試したISBNの数
+    pub attempts: usize,
+    pub metadata: BookRecord,
+    /// 図書館システムごとの所蔵状況
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub libraries: Vec<Availability>,
+    /// 同じ著者の本や同じ分類の本(--exploreを指定した場合)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub related: Vec<BookRecord>,
+    /// シリーズの1巻目(--first-volumeを指定して、2巻目以降が見つかった場合)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_volume: Option<BookRecord>,
+    /// 保存した書影のファイル(--save-coverを指定した場合)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub cover_path: Option<PathBuf>,
+    /// Calibreのmetadata.opfを書き出したディレクトリ(--calibreを指定した場合)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub calibre_path: Option<PathBuf>,
+    /// 端末に表示する書影(--preview-coverを指定した場合)
+    #[serde(skip)]
+    pub cover_preview: Option<String>,
+}
+
+impl Discovery {
+    pub fn new(metadata: BookRecord, attempts: usize) -> Self {
+        let isbn10 = to_isbn10(&metadata.isbn).ok();
+        Discovery {
+            isbn13: metadata.isbn.clone(),
+            booklog_url: isbn10.as_deref().and_then(booklog_url),
+            isbn10,
+            publisher_name: Isbn::parse(&metadata.isbn).ok().as_ref().and_then(publisher_name).map(String::from),
+            attempts,
+            metadata,
+            links: Vec::new(),
+            libraries: Vec::new(),
+            related: Vec::new(),
+            first_volume: None,
+            cover_path: None,
+            calibre_path: None,
+            cover_preview: None,
+        }
+    }
+
+    /// 表示するURLのうち最初のもの(--linksのサービスのURLがなければブクログのURL)
+    pub fn url(&self) -> Option<&str> {
+        self.links.first().map(|link| link.url.as_str()).or(self.booklog_url.as_deref())
+    }
+
+    fn print_text(&self) {
+        println!("{}", self.metadata.citation());
+        for (label, value) in self.metadata.metadata() {
+            println!("{}: {}", label, value);
+        }
+        if self.links.is_empty() {
+            if let Some(booklog_url) = &self.booklog_url {
+                println!("{}", booklog_url);
+            }
+        }
+        for link in &self.links {
+            println!("{}", link.url);
+        }
+        for availability in &self.libraries {
+            print_availability(availability);
+        }
+        if let Some(record) = &self.first_volume {
+            let volume = record.volume.as_deref().unwrap_or_default();
+            println!("first volume: {} {} {}", record.isbn, record.title, volume);
+        }
+        for record in &self.related {
+            println!("related: {} {} / {}", record.isbn, record.title, record.authors.join(", "));
+        }
+        if let Some(path) = &self.cover_path {
+            println!("cover: {}", path.display());
+        }
+        if let Some(path) = &self.calibre_path {
+            println!("calibre: {}", path.display());
+        }
+    }
+}
+
+/// CSVとTSVの1行
+#[derive(Debug, Serialize)]
+struct Row<'a> {
+    isbn13: &'a str,
+    isbn10: String,
+    title: &'a str,
+    author: String,
+    publisher: &'a str,
+    pubdate: &'a str,
+    url: String,
+}
+
+impl<'a> From<&'a BookRecord> for Row<'a> {
+    fn from(record: &'a BookRecord) -> Self {
+        let isbn10 = to_isbn10(&record.isbn).unwrap_or_default();
+        Row {
+            isbn13: &record.isbn,
+            url: booklog_url(&isbn10).unwrap_or_default(),
+            isbn10,
+            title: &record.title,
+            author: record.authors.join(", "),
+            publisher: record.publisher.as_deref().unwrap_or_default(),
+            pubdate: record.issued.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// GoodreadsのインポートのCSVの1行
+#[derive(Debug, Serialize)]
+pub struct GoodreadsRow<'a> {
+    #[serde(rename = "Title")]
+    title: &'a str,
+    #[serde(rename = "Author")]
+    author: String,
+    #[serde(rename = "Additional Authors")]
+    additional_authors: String,
+    #[serde(rename = "ISBN")]
+    isbn: String,
+    #[serde(rename = "ISBN13")]
+    isbn13: &'a str,
+    #[serde(rename = "My Rating")]
+    my_rating: Option<u8>,
+    #[serde(rename = "Publisher")]
+    publisher: &'a str,
+    #[serde(rename = "Year Published")]
+    year_published: Option<u32>,
+    #[serde(rename = "Date Added")]
+    date_added: String,
+    #[serde(rename = "Shelves")]
+    shelves: String,
+}
+
+impl<'a> GoodreadsRow<'a> {
+    /// date_addedは加えた日時(RFC 3339、指定しない場合はGoodreadsが読み込んだ日になる)
+    /// 本棚を指定しない場合は「読みたい本(to-read)」にする
+    pub fn new(record: &'a BookRecord, date_added: Option<&str>, shelves: &[String]) -> Self {
+        let mut authors = export::author_names(record).into_iter();
+        GoodreadsRow {
+            title: &record.title,
+            author: authors.next().unwrap_or_default(),
+            additional_authors: authors.collect::<Vec<_>>().join(", "),
+            isbn: to_isbn10(&record.isbn).unwrap_or_default(),
+            isbn13: &record.isbn,
+            my_rating: None,
+            publisher: record.publisher.as_deref().unwrap_or_default(),
+            year_published: record.year(),
+            // GoodreadsのCSVの日付はYYYY/MM/DD
+            date_added: date_added.and_then(|date| date.get(..10)).unwrap_or_default().replace('-', "/"),
+            shelves: if shelves.is_empty() { String::from("to-read") } else { shelves.join(", ") },
+        }
+    }
+
+    /// 評価(1〜5)を付ける
+    pub fn with_rating(self, rating: Option<u8>) -> Self {
+        GoodreadsRow { my_rating: rating, ..self }
+    }
+}
+
+/// booklogの本のページのURL
+/// booklogのパスパラメータはISBN10で、ISBN10がない場合はNoneになる
+pub fn booklog_url(isbn10: &str) -> Option<String> {
+    if isbn10.is_empty() {
+        None
+    } else {
+        Some(format!("https://booklog.jp/item/1/{}", isbn10))
+    }
+}
+
+/// テンプレートで使える値
+/// 書誌情報の項目もトップレベルに置き、著者はカンマ区切りの `author` でも使えるようにする
+/// 「タイトル / 著者, 出版社, 年」の1行は `citation` で使える
+fn template_context(value: Value, record: &BookRecord) -> Value {
+    let mut context = match value {
+        Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    };
+    if let Ok(Value::Object(metadata)) = serde_json::to_value(record) {
+        for (key, value) in metadata {
+            context.entry(key).or_insert(value);
+        }
+    }
+    let isbn10 = to_isbn10(&record.isbn).ok();
+    context.entry("isbn13").or_insert_with(|| Value::from(record.isbn.clone()));
+    context.entry("booklog_url").or_insert_with(|| isbn10.as_deref().and_then(booklog_url).into());
+    context.entry("isbn10").or_insert_with(|| isbn10.into());
+    context.insert(String::from("author"), Value::from(record.authors.join(", ")));
+    context.insert(String::from("citation"), Value::from(record.citation()));
+    Value::Object(context)
+}
+
+/// 見つかった本を指定した形式で標準出力に書き出す
+/// CSVとTSVの見出しは最初の1件の前にだけ書く
+pub struct Printer {
+    format: OutputFormat,
+    /// 指定した場合は出力形式より優先する
+    template: Option<Handlebars<'static>>,
+    header_written: bool,
+}
+
+impl Printer {
+    pub fn new(format: OutputFormat) -> Self {
+        Printer { format, template: None, header_written: false }
+    }
+
+    /// Handlebarsのテンプレート(`{{title}} - {{booklog_url}}` など)で1件ずつ書き出す
+    pub fn with_template(format: OutputFormat, template: &str) -> Result<Self, TemplateError> {
+        let mut handlebars = Handlebars::new();
+        // HTMLではないのでエスケープしない
+        handlebars.register_escape_fn(handlebars::no_escape);
+        handlebars.register_template_string("book", template)?;
+        Ok(Printer { format, template: Some(handlebars), header_written: false })
+    }
+
+    /// 検索結果の一覧を1つのJSONの配列やXMLの文書としてまとめて書き出す必要があるかどうか
+    /// (JSON、CSL-JSON、Dublin Core XML)
+    pub fn is_collected(&self) -> bool {
+        self.template.is_none() && matches!(self.format, OutputFormat::Json | OutputFormat::Csl | OutputFormat::Dc)
+    }
+
+    /// Handlebarsのテンプレートで書き出すかどうか
+    pub fn has_template(&self) -> bool {
+        self.template.is_some()
+    }
+
+    /// まとめて書き出す検索結果の一覧を、JSONの配列かXMLの文書で書き出す
+    pub fn print_records(&self, records: &[BookRecord]) {
+        match self.format {
+            OutputFormat::Csl => print_json(&records.iter().map(export::csl).collect::<Vec<_>>()),
+            OutputFormat::Dc => print!("{}", export::dublin_core_document(&records.iter().collect::<Vec<_>>())),
+            _ => print_json(&records),
+        }
+    }
+
+    /// 検索の途中経過などのメッセージを表示する
+    /// テキスト以外の形式では、結果だけを標準出力に書くためにエラー出力に書く
+    pub fn report(&self, message: &str) {
+        if self.template.is_none() && self.format == OutputFormat::Text {
+            println!("{}", message);
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+
+    pub fn print(&mut self, discovery: &Discovery) {
+        if let Some(template) = &self.template {
+            let context = template_context(serde_json::to_value(discovery).unwrap_or_default(), &discovery.metadata);
+            render(template, &context);
+            return;
+        }
+        match self.format {
+            OutputFormat::Text => discovery.print_text(),
+            OutputFormat::Json => print_json(discovery),
+            OutputFormat::Jsonl => print_json_line(discovery),
+            OutputFormat::Csv | OutputFormat::Tsv => self.write_row(Row::from(&discovery.metadata)),
+            OutputFormat::Bibtex => println!("{}", export::bibtex(&discovery.metadata)),
+            OutputFormat::Ris => println!("{}", export::ris(&discovery.metadata)),
+            // そのまま読み込めるように、1件でも配列にする
+            OutputFormat::Csl => print_json(&[export::csl(&discovery.metadata)]),
+            OutputFormat::Dc => print!("{}", export::dublin_core_document(&[&discovery.metadata])),
+            OutputFormat::Goodreads => self.write_row(GoodreadsRow::new(&discovery.metadata, None, &[])),
+        }
+    }
+
+    /// 検索結果の一覧の1件を書き出す
+    /// JSONは一覧をまとめて書き出す必要があるので、呼び出し側で扱う
+    pub fn print_record(&mut self, record: &BookRecord) {
+        if let Some(template) = &self.template {
+            render(template, &template_context(Value::Null, record));
+            return;
+        }
+        match self.format {
+            OutputFormat::Text => println!("{} {}", record.isbn, record.title),
+            OutputFormat::Json => print_json(record),
+            OutputFormat::Jsonl => print_json_line(record),
+            OutputFormat::Csv | OutputFormat::Tsv => self.write_row(Row::from(record)),
+            OutputFormat::Bibtex => println!("{}", export::bibtex(record)),
+            OutputFormat::Ris => println!("{}", export::ris(record)),
+            OutputFormat::Csl => print_json(&[export::csl(record)]),
+            OutputFormat::Dc => print!("{}", export::dublin_core_document(&[record])),
+            OutputFormat::Goodreads => self.write_row(GoodreadsRow::new(record, None, &[])),
+        }
+    }
+
+    /// GoodreadsのインポートのCSVの1行を書き出す(加えた日時と本棚が分かる場合)
+    pub fn print_goodreads(&mut self, row: GoodreadsRow) {
+        self.write_row(row);
+    }
+
+    fn write_row<T: Serialize>(&mut self, row: T) {
+        let delimiter = if self.format == OutputFormat::Tsv { b'\t' } else { b',' };
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(!self.header_written)
+            .from_writer(io::stdout().lock());
+        // パイプの先が閉じられた場合は書き出せなくても続ける
+        let _ = writer.serialize(row).and_then(|_| Ok(writer.flush()?));
+        self.header_written = true;
+    }
+}
+
+/// 図書館システムの所蔵している図書館と貸出状況を表示する
+fn print_availability(availability: &Availability) {
+    // 検索が終わらなかった場合や、エラーになった場合
+    if availability.status != "OK" && availability.status != "Cache" {
+        let status = if availability.status.is_empty() { "unknown" } else { availability.status.as_str() };
+        println!("library: {} ... {}", availability.system_id, status);
+        return;
+    }
+    if availability.libraries.is_empty() {
+        println!("library: {} ... not held", availability.system_id);
+        return;
+    }
+    for (library, status) in &availability.libraries {
+        println!("library: {} {} ... {}", availability.system_id, library, status);
+    }
+    if !availability.reserve_url.is_empty() {
+        println!("reserve: {}", availability.reserve_url);
+    }
+}
+
+/// 整形したJSONで書き出す
+pub fn print_json<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("cannot write json: {}", e),
+    }
+}
+
+/// 1件を1行のJSONで書き出す
+/// パイプの先ですぐに読めるように、1件ごとにフラッシュする
+pub fn print_json_line<T: Serialize>(value: &T) {
+    let json = match serde_json::to_string(value) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("cannot write json: {}", e);
+            return;
+        }
+    };
+    let mut out = io::stdout().lock();
+    // パイプの先が閉じられた場合は書き出せなくても続ける
+    let _ = writeln!(out, "{}", json).and_then(|_| out.flush());
+}
+
+/// ランダムに本を探したときの経過
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    started: Instant,
+    /// 試したISBNの数
+    pub attempts: usize,
+    /// 見つかった本の数
+    pub found: usize,
+    /// 最後に試したISBN
+    pub last_isbn: Option<String>,
+}
+
+impl RunSummary {
+    pub fn new() -> Self {
+        RunSummary { started: Instant::now(), attempts: 0, found: 0, last_isbn: None }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} attempts, {} found, {:.1}s elapsed, last isbn: {}",
+            self.attempts,
+            self.found,
+            self.elapsed().as_secs_f64(),
+            self.last_isbn.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+/// テンプレートに値を埋め込んで1行で書き出す
+fn render(template: &Handlebars, context: &Value) {
+    match template.render("book", context) {
+        Ok(text) => {
+            let mut out = io::stdout().lock();
+            let _ = writeln!(out, "{}", text).and_then(|_| out.flush());
+        }
+        Err(e) => eprintln!("cannot render template: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovery_json() {
+        let record = BookRecord {
+            isbn: String::from("9784101010014"),
+            title: String::from("吾輩は猫である"),
+            source: "ndl",
+            ..Default::default()
+        };
+        let discovery = Discovery::new(record, 3);
+        let json = serde_json::to_value(&discovery).unwrap();
+        assert_eq!(json["isbn13"], "9784101010014");
+        assert_eq!(json["isbn10"], "4101010013");
+        assert_eq!(json["booklog_url"], "https://booklog.jp/item/1/4101010013");
+        assert_eq!(json["attempts"], 3);
+        assert_eq!(json["metadata"]["title"], "吾輩は猫である");
+        assert!(json.get("libraries").is_none());
+        // JSON Linesでは1件が1行になる
+        assert!(!serde_json::to_string(&discovery).unwrap().contains('\n'));
+    }
+
+    #[test]
+    fn test_template_context() {
+        let record = BookRecord {
+            isbn: String::from("9784101010014"),
+            title: String::from("吾輩は猫である"),
+            authors: vec![String::from("夏目漱石"), String::from("注解者")],
+            source: "ndl",
+            ..Default::default()
+        };
+        let mut handlebars = Handlebars::new();
+        handlebars.register_escape_fn(handlebars::no_escape);
+        handlebars.register_template_string("book", "- [{{title}}]({{booklog_url}}) {{author}} ({{attempts}})").unwrap();
+        let discovery = Discovery::new(record.clone(), 2);
+        let context = template_context(serde_json::to_value(&discovery).unwrap(), &record);
+        assert_eq!(
+            handlebars.render("book", &context).unwrap(),
+            "- [吾輩は猫である](https://booklog.jp/item/1/4101010013) 夏目漱石, 注解者 (2)"
+        );
+        // 検索結果にはattemptsがない
+        let context = template_context(Value::Null, &record);
+        assert_eq!(context["isbn10"], "4101010013");
+        assert!(context.get("attempts").is_none());
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let mut summary = RunSummary::new();
+        assert!(summary.to_string().starts_with("0 attempts, 0 found, "));
+        assert!(summary.to_string().ends_with("last isbn: -"));
+        summary.attempts = 12;
+        summary.last_isbn = Some(String::from("9784798171548"));
+        assert!(summary.to_string().starts_with("12 attempts, 0 found, "));
+        assert!(summary.to_string().ends_with("last isbn: 9784798171548"));
+    }
+
+    #[test]
+    fn test_row() {
+        let record = BookRecord {
+            isbn: String::from("9794798171547"),
+            title: String::from("Title, with comma"),
+            authors: vec![String::from("A"), String::from("B")],
+            source: "ndl",
+            ..Default::default()
+        };
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(Row::from(&record)).unwrap();
+        let csv_text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        // 979始まりのISBNはISBN10とbooklogのURLが空になる
+        assert_eq!(
+            csv_text,
+            "isbn13,isbn10,title,author,publisher,pubdate,url\n9794798171547,,\"Title, with comma\",\"A, B\",,,\n"
+        );
+    }
+
+    #[test]
+    fn test_goodreads_row() {
+        let record = BookRecord {
+            isbn: String::from("9784101010014"),
+            title: String::from("吾輩は猫である"),
+            authors: vec![String::from("夏目漱石 著"), String::from("訳者 訳")],
+            publisher: Some(String::from("新潮社")),
+            issued: Some(String::from("2003.6")),
+            ..Default::default()
+        };
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let shelves = [String::from("文庫"), String::from("小説")];
+        let row = GoodreadsRow::new(&record, Some("2024-05-01T12:34:56Z"), &shelves).with_rating(Some(4));
+        writer.serialize(row).unwrap();
+        writer.serialize(GoodreadsRow::new(&record, None, &[])).unwrap();
+        let csv_text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            csv_text,
+            "Title,Author,Additional Authors,ISBN,ISBN13,My Rating,Publisher,Year Published,Date Added,Shelves\n\
+             吾輩は猫である,夏目漱石,訳者,4101010013,9784101010014,4,新潮社,2003,2024/05/01,\"文庫, 小説\"\n\
+             吾輩は猫である,夏目漱石,訳者,4101010013,9784101010014,,新潮社,2003,,to-read\n"
+        );
+    }
+}
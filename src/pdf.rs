@@ -0,0 +1,213 @@
+//! バーコードをラベル用紙に並べたPDFの書き出し
+//! バーは塗りつぶした長方形、文字はPDFの標準フォント(Courier)で描く
+
+use isbn::{Drawing, TEXT_HEIGHT};
+use std::io::{self, Write};
+
+/// 1mmのポイント数
+const POINTS_PER_MM: f64 = 72.0 / 25.4;
+
+/// Courierの1文字の幅(フォントの大きさに対する比)
+const COURIER_WIDTH: f64 = 0.6;
+
+/// ラベル用紙の大きさと並べ方(単位はmm)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelSheet {
+    pub page_width: f64,
+    pub page_height: f64,
+    pub columns: u32,
+    pub rows: u32,
+    /// 用紙の左右の余白
+    pub margin_x: f64,
+    /// 用紙の上下の余白
+    pub margin_y: f64,
+    /// ラベルの内側の余白
+    pub padding: f64,
+}
+
+impl Default for LabelSheet {
+    /// A4の24面(3列8行)
+    fn default() -> Self {
+        LabelSheet {
+            page_width: 210.0,
+            page_height: 297.0,
+            columns: 3,
+            rows: 8,
+            margin_x: 7.0,
+            margin_y: 10.0,
+            padding: 2.0,
+        }
+    }
+}
+
+impl LabelSheet {
+    /// 1ページのラベルの数
+    pub fn labels_per_page(&self) -> usize {
+        (self.columns * self.rows).max(1) as usize
+    }
+
+    /// 1枚のラベルの幅と高さ
+    fn label_size(&self) -> (f64, f64) {
+        let width = (self.page_width - self.margin_x * 2.0) / f64::from(self.columns.max(1));
+        let height = (self.page_height - self.margin_y * 2.0) / f64::from(self.rows.max(1));
+        (width, height)
+    }
+
+    /// index番目のラベルのページと、左上の位置(用紙の左上から)
+    /// 左から右、上から下の順に並べる
+    fn placement(&self, index: usize) -> (usize, f64, f64) {
+        let page = index / self.labels_per_page();
+        let index = index % self.labels_per_page();
+        let columns = self.columns.max(1) as usize;
+        let (width, height) = self.label_size();
+        let x = self.margin_x + width * (index % columns) as f64;
+        let y = self.margin_y + height * (index / columns) as f64;
+        (page, x, y)
+    }
+}
+
+/// 図形を1枚ずつラベルに描いたPDFを書き出す
+/// 図形はラベルの内側に収まるように縦横比を保って拡大し、中央に置く
+pub fn write_labels(mut writer: impl Write, sheet: &LabelSheet, drawings: &[Drawing]) -> io::Result<()> {
+    let mut pages: Vec<String> = Vec::new();
+    let (label_width, label_height) = sheet.label_size();
+    let (inner_width, inner_height) = (label_width - sheet.padding * 2.0, label_height - sheet.padding * 2.0);
+    for (i, drawing) in drawings.iter().enumerate() {
+        let (page, x, y) = sheet.placement(i);
+        if page == pages.len() {
+            pages.push(String::new());
+        }
+        let scale =
+            (inner_width / f64::from(drawing.width.max(1))).min(inner_height / f64::from(drawing.height.max(1)));
+        let left = x + sheet.padding + (inner_width - f64::from(drawing.width) * scale) / 2.0;
+        let top = y + sheet.padding + (inner_height - f64::from(drawing.height) * scale) / 2.0;
+        draw(&mut pages[page], drawing, sheet.page_height, left, top, scale);
+    }
+    if pages.is_empty() {
+        pages.push(String::new());
+    }
+    writer.write_all(&document(sheet, &pages))
+}
+
+/// 図形を描くPDFの命令を追加する
+/// left、top、scaleの単位はmm(scaleは1モジュール幅)で、PDFの座標(左下が原点のポイント)に変換する
+fn draw(content: &mut String, drawing: &Drawing, page_height: f64, left: f64, top: f64, scale: f64) {
+    let x = |modules: u32| (left + f64::from(modules) * scale) * POINTS_PER_MM;
+    let y = |modules: u32| (page_height - top - f64::from(modules) * scale) * POINTS_PER_MM;
+    let length = |modules: u32| f64::from(modules) * scale * POINTS_PER_MM;
+    for bar in &drawing.bars {
+        let (width, height) = (length(bar.width), length(bar.height));
+        content.push_str(&format!("{:.2} {:.2} {:.2} {:.2} re\n", x(bar.x), y(bar.y + bar.height), width, height));
+    }
+    if !drawing.bars.is_empty() {
+        content.push_str("f\n");
+    }
+    let size = length(TEXT_HEIGHT + 2);
+    for text in &drawing.texts {
+        // 1文字がcellの幅になるように横に伸縮する
+        let stretch = 100.0 * length(text.cell) / (size * COURIER_WIDTH);
+        content.push_str(&format!(
+            "BT /F1 {:.2} Tf {:.1} Tz {:.2} {:.2} Td ({}) Tj ET\n",
+            size,
+            stretch,
+            x(text.x),
+            y(text.y + TEXT_HEIGHT),
+            escape(&text.text)
+        ));
+    }
+}
+
+/// PDFの文字列に入れられるようにする
+/// 括弧とバックスラッシュはエスケープし、WinAnsiEncodingにない文字は?にする
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '¥' => escaped.push_str("\\245"),
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+/// ページの内容からPDFの文書を組み立てる
+fn document(sheet: &LabelSheet, pages: &[String]) -> Vec<u8> {
+    let (width, height) = (sheet.page_width * POINTS_PER_MM, sheet.page_height * POINTS_PER_MM);
+    // 1: カタログ、2: ページの一覧、3: フォント、4以降: ページと内容を交互に
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 4 + i * 2)).collect();
+    let mut objects = vec![
+        String::from("<< /Type /Catalog /Pages 2 0 R >>"),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()),
+        String::from("<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>"),
+    ];
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            concat!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] ",
+                "/Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>"
+            ),
+            width,
+            height,
+            5 + i * 2
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content));
+    }
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+    let xref = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref));
+    pdf.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use isbn::Ean13;
+
+    #[test]
+    fn test_placement() {
+        let sheet = LabelSheet { columns: 2, rows: 2, ..Default::default() };
+        assert_eq!(sheet.labels_per_page(), 4);
+        assert_eq!(sheet.placement(0), (0, 7.0, 10.0));
+        assert_eq!(sheet.placement(1), (0, 105.0, 10.0));
+        assert_eq!(sheet.placement(2), (0, 7.0, 148.5));
+        assert_eq!(sheet.placement(4), (1, 7.0, 10.0));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("C0193 ¥750E"), "C0193 \\245750E");
+        assert_eq!(escape("(a\\b)"), "\\(a\\\\b\\)");
+        assert_eq!(escape("本"), "?");
+    }
+
+    #[test]
+    fn test_write_labels() {
+        let drawing = Ean13::parse("9784101010014").unwrap().drawing(Some("ISBN978-4-10-101001-4"));
+        let sheet = LabelSheet { columns: 2, rows: 1, ..Default::default() };
+        let mut pdf = Vec::new();
+        write_labels(&mut pdf, &sheet, &[drawing.clone(), drawing.clone(), drawing]).unwrap();
+        let pdf = String::from_utf8(pdf).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/Count 2"));
+        assert!(pdf.contains("(ISBN978-4-10-101001-4) Tj"));
+        // xrefの位置にあるオブジェクトが正しい
+        let offset: usize = pdf.lines().find(|line| line.ends_with(" 00000 n ")).unwrap()[..10].parse().unwrap();
+        assert!(pdf[offset..].starts_with("1 0 obj"));
+        let startxref: usize = pdf.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert!(pdf[startxref..].starts_with("xref"));
+    }
+}
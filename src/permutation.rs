@@ -0,0 +1,97 @@
+/// 0からsize-1までの番号を、鍵で決まる順番に並べ替える置換
+/// Feistel構造で番号を並べ替え、範囲の外に出た場合は範囲に入るまで繰り返す(cycle walking)
+/// 同じ鍵なら毎回同じ順番になり、どの番号もちょうど1回ずつ現れる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permutation {
+    size: u64,
+    key: u64,
+    /// Feistel構造の左右それぞれのビット数
+    half_bits: u32,
+}
+
+impl Permutation {
+    /// Feistel構造を繰り返す回数
+    const ROUNDS: u64 = 4;
+
+    pub fn new(size: u64, key: u64) -> Self {
+        // 2^(2*half_bits) >= size になる最小のhalf_bits
+        let bits = u64::BITS - size.saturating_sub(1).leading_zeros();
+        let half_bits = bits.div_ceil(2).max(1);
+        Permutation { size, key, half_bits }
+    }
+
+    /// 並べ替える番号の数
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// index番目に現れる番号
+    /// indexがsize以上の場合はNoneを返す
+    pub fn get(&self, index: u64) -> Option<u64> {
+        if index >= self.size {
+            return None;
+        }
+        // 範囲の外に出ても、同じ置換を繰り返せばいずれ範囲に戻る
+        let mut value = self.encrypt(index);
+        while value >= self.size {
+            value = self.encrypt(value);
+        }
+        Some(value)
+    }
+
+    /// 0から順番に並べ替えた番号
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.size).filter_map(|index| self.get(index))
+    }
+
+    fn encrypt(&self, value: u64) -> u64 {
+        let mask = (1 << self.half_bits) - 1;
+        let mut left = value >> self.half_bits;
+        let mut right = value & mask;
+        for round in 0..Self::ROUNDS {
+            let next = left ^ (self.round_function(round, right) & mask);
+            left = right;
+            right = next;
+        }
+        (left << self.half_bits) | right
+    }
+
+    /// splitmix64で鍵と回数と右半分を混ぜる
+    fn round_function(&self, round: u64, value: u64) -> u64 {
+        let mut z = self
+            .key
+            .wrapping_add(round.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .wrapping_add(value.wrapping_mul(0xbf58_476d_1ce4_e5b9));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        // どの番号もちょうど1回ずつ現れる
+        for size in [0, 1, 2, 10, 1000, 12345] {
+            let permutation = Permutation::new(size, 42);
+            let mut values: Vec<u64> = permutation.iter().collect();
+            values.sort();
+            assert_eq!(values, (0..size).collect::<Vec<u64>>());
+            assert_eq!(permutation.get(size), None);
+        }
+    }
+
+    #[test]
+    fn test_key() {
+        // 同じ鍵なら同じ順番になり、違う鍵なら違う順番になる
+        let first: Vec<u64> = Permutation::new(1000, 1).iter().collect();
+        let second: Vec<u64> = Permutation::new(1000, 1).iter().collect();
+        let other: Vec<u64> = Permutation::new(1000, 2).iter().collect();
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_ne!(first, (0..1000).collect::<Vec<u64>>());
+    }
+}
@@ -0,0 +1,224 @@
+//! 書影を端末に表示する
+//! kittyのグラフィックスプロトコルかsixelに対応した端末では画像のまま、それ以外の端末では色付きの半角ブロックで描く
+
+use clap::ValueEnum;
+use image::RgbImage;
+
+/// 書影を表示する方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PreviewProtocol {
+    /// 環境変数から端末を判断する
+    Auto,
+    /// kittyのグラフィックスプロトコル(kitty、WezTerm、Ghosttyなど)
+    Kitty,
+    /// sixel(foot、mlterm、xterm -ti vt340など)
+    Sixel,
+    /// 24ビットカラーの半角ブロック(▀)
+    Mosaic,
+}
+
+/// kittyとsixelで表示する画像の最大の大きさ(ピクセル)
+const IMAGE_SIZE: (u32, u32) = (240, 360);
+
+/// 半角ブロックで表示する場合の最大の大きさ(文字数、1文字に上下2ピクセル)
+const MOSAIC_SIZE: (u32, u32) = (32, 48);
+
+/// kittyのグラフィックスプロトコルで1回に送るbase64の長さ
+const KITTY_CHUNK: usize = 4096;
+
+impl PreviewProtocol {
+    /// Autoの場合に、環境変数から使える方法を選ぶ
+    fn resolve(self, env: impl Fn(&str) -> Option<String>) -> Self {
+        if self != PreviewProtocol::Auto {
+            return self;
+        }
+        let term = env("TERM").unwrap_or_default();
+        let program = env("TERM_PROGRAM").unwrap_or_default();
+        let kitty = env("KITTY_WINDOW_ID").is_some() || term.contains("kitty");
+        if kitty || ["WezTerm", "ghostty"].contains(&program.as_str()) {
+            PreviewProtocol::Kitty
+        } else if ["foot", "mlterm", "yaft"].iter().any(|name| term.starts_with(name)) || term.contains("sixel") {
+            PreviewProtocol::Sixel
+        } else {
+            PreviewProtocol::Mosaic
+        }
+    }
+}
+
+/// 画像のファイルの中身を、端末に出力する文字列にする
+pub fn render(bytes: &[u8], protocol: PreviewProtocol) -> image::ImageResult<String> {
+    let image = image::load_from_memory(bytes)?;
+    let thumbnail = |(width, height): (u32, u32)| image.thumbnail(width, height).to_rgb8();
+    let rendered = match protocol.resolve(|name| std::env::var(name).ok()) {
+        PreviewProtocol::Kitty => kitty(&thumbnail(IMAGE_SIZE)),
+        PreviewProtocol::Sixel => sixel(&thumbnail(IMAGE_SIZE)),
+        PreviewProtocol::Auto | PreviewProtocol::Mosaic => mosaic(&thumbnail(MOSAIC_SIZE)),
+    };
+    Ok(rendered)
+}
+
+/// kittyのグラフィックスプロトコルで、24ビットの画素をそのまま送る
+fn kitty(image: &RgbImage) -> String {
+    let encoded = base64(image.as_raw());
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut output = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        // base64の文字はASCIIなので、分割してもUTF-8として正しい
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            let (width, height) = image.dimensions();
+            output.push_str(&format!("\x1b_Gf=24,a=T,s={},v={},m={};{}\x1b\\", width, height, more, chunk));
+        } else {
+            output.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    output.push('\n');
+    output
+}
+
+/// sixelで描く
+/// 色はRGBそれぞれ6段階の216色に減らす
+fn sixel(image: &RgbImage) -> String {
+    let level = |value: u8| u16::from(value) * 6 / 256;
+    let index = |x: u32, y: u32| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        level(r) * 36 + level(g) * 6 + level(b)
+    };
+    let (width, height) = image.dimensions();
+    let mut output = format!("\x1bPq\"1;1;{};{}", width, height);
+    for color in 0..216u16 {
+        let percent = |level: u16| level * 100 / 5;
+        let (r, g, b) = (percent(color / 36), percent(color / 6 % 6), percent(color % 6));
+        output.push_str(&format!("#{};2;{};{};{}", color, r, g, b));
+    }
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut colors: Vec<u16> =
+            rows.clone().flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| index(x, y)).collect();
+        colors.sort_unstable();
+        colors.dedup();
+        for color in colors {
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| rows.clone().filter(|y| index(x, *y) == color).fold(0, |bits, y| bits | (1 << (y - band))))
+                .map(|bits: u8| 63 + bits)
+                .collect();
+            output.push_str(&format!("#{}{}$", color, run_length(&sixels)));
+        }
+        output.push('-');
+    }
+    output.push_str("\x1b\\\n");
+    output
+}
+
+/// sixelの文字の並びを、同じ文字が4つ以上続く部分は「!回数文字」にして縮める
+fn run_length(sixels: &[u8]) -> String {
+    let mut output = String::new();
+    let mut i = 0;
+    while i < sixels.len() {
+        let count = sixels[i..].iter().take_while(|sixel| **sixel == sixels[i]).count();
+        let c = char::from(sixels[i]);
+        if count > 3 {
+            output.push_str(&format!("!{}{}", count, c));
+        } else {
+            output.extend(std::iter::repeat_n(c, count));
+        }
+        i += count;
+    }
+    output
+}
+
+/// 上半分の半角ブロックの文字色と背景色で、1文字に上下2ピクセルを描く
+fn mosaic(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let mut output = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let [r, g, b] = image.get_pixel(x, y).0;
+            output.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+            // 高さが奇数の場合、最後の行は上半分だけを描く
+            if y + 1 < height {
+                let [r, g, b] = image.get_pixel(x, y + 1).0;
+                output.push_str(&format!("\x1b[48;2;{};{};{}m", r, g, b));
+            } else {
+                output.push_str("\x1b[49m");
+            }
+            output.push('▀');
+        }
+        output.push_str("\x1b[0m\n");
+    }
+    output
+}
+
+/// base64で符号化する(パディングあり)
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, byte)| n | u32::from(*byte) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(char::from(ALPHABET[(n >> (18 - i * 6)) as usize & 0b111111]));
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let env = |vars: &'static [(&str, &str)]| {
+            move |name: &str| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+        };
+        assert_eq!(PreviewProtocol::Auto.resolve(env(&[("TERM", "xterm-kitty")])), PreviewProtocol::Kitty);
+        assert_eq!(PreviewProtocol::Auto.resolve(env(&[("TERM_PROGRAM", "WezTerm")])), PreviewProtocol::Kitty);
+        assert_eq!(PreviewProtocol::Auto.resolve(env(&[("TERM", "foot")])), PreviewProtocol::Sixel);
+        assert_eq!(PreviewProtocol::Auto.resolve(env(&[("TERM", "xterm-256color")])), PreviewProtocol::Mosaic);
+        assert_eq!(PreviewProtocol::Sixel.resolve(env(&[("TERM", "xterm-kitty")])), PreviewProtocol::Sixel);
+    }
+
+    #[test]
+    fn test_mosaic() {
+        let image = RgbImage::from_fn(2, 3, |_, y| if y == 0 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) });
+        let output = mosaic(&image);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀"));
+        assert_eq!(lines[1].matches("\x1b[49m").count(), 2);
+    }
+
+    #[test]
+    fn test_sixel() {
+        let image = RgbImage::from_pixel(5, 7, Rgb([255, 255, 255]));
+        let output = sixel(&image);
+        assert!(output.starts_with("\x1bPq\"1;1;5;7"));
+        // 白は215番で、1つ目の帯は6行すべて、2つ目の帯は1行だけ
+        assert!(output.contains("#215!5~$-#215!5@$-"));
+        assert!(output.ends_with("\x1b\\\n"));
+        assert_eq!(run_length(b"~~~@"), "~~~@");
+    }
+
+    #[test]
+    fn test_kitty() {
+        let image = RgbImage::new(40, 40);
+        let output = kitty(&image);
+        // 4800バイトのbase64は6400文字なので、2回に分けて送る
+        assert!(output.starts_with("\x1b_Gf=24,a=T,s=40,v=40,m=1;AAAA"));
+        assert!(output.contains("\x1b_Gm=0;"));
+    }
+}
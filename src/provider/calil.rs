@@ -0,0 +1,142 @@
+//! カーリルの図書館APIで、本の所蔵と貸出状況を調べる
+//! 検索は非同期に行われるので、結果がそろうまでsessionを指定してポーリングする
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use super::retry::check_response;
+use super::Result;
+
+const CHECK_ENDPOINT: &str = "https://api.calil.jp/check";
+
+/// ポーリングの間隔(カーリルは2秒以上あけることを求めている)
+const POLLING_INTERVAL: Duration = Duration::from_secs(2);
+
+/// ポーリングする最大の回数
+const MAX_POLLING: usize = 15;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CheckResponse {
+    session: String,
+    #[serde(rename = "continue")]
+    continue_polling: u8,
+    /// ISBN → 図書館システムID → 状況
+    books: HashMap<String, HashMap<String, SystemStatus>>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+struct SystemStatus {
+    status: String,
+    reserveurl: String,
+    /// 図書館名 → 貸出状況
+    libkey: BTreeMap<String, String>,
+}
+
+/// 図書館システムごとの所蔵状況
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Availability {
+    pub system_id: String,
+    /// OK, Cache, Running, Error のいずれか
+    pub status: String,
+    /// 予約ページのURL(所蔵していない場合は空文字)
+    pub reserve_url: String,
+    /// 所蔵している図書館の名前と貸出状況
+    #[schema(value_type = Vec<Vec<String>>)]
+    pub libraries: Vec<(String, String)>,
+}
+
+/// カーリルのアプリケーションキーと、調べる図書館システムのID
+#[derive(Debug, Clone)]
+pub struct Calil {
+    pub app_key: String,
+    pub system_ids: Vec<String>,
+}
+
+impl Calil {
+    /// ISBNの本の所蔵状況を、指定した図書館システムの順番で返す
+    pub async fn check(&self, client: &reqwest::Client, isbn: &str) -> Result<Vec<Availability>> {
+        let system_ids = self.system_ids.join(",");
+        let mut response = self
+            .request(client, &[("isbn", isbn), ("systemid", system_ids.as_str())])
+            .await?;
+        for _ in 0..MAX_POLLING {
+            if response.continue_polling == 0 {
+                break;
+            }
+            tokio::time::sleep(POLLING_INTERVAL).await;
+            let session = response.session.clone();
+            response = self.request(client, &[("session", session.as_str())]).await?;
+        }
+        Ok(self.availabilities(response, isbn))
+    }
+
+    /// 4xxや5xx、429はJSONとして読まずにエラーにする
+    async fn request(&self, client: &reqwest::Client, query: &[(&str, &str)]) -> Result<CheckResponse> {
+        let response = client
+            .get(CHECK_ENDPOINT)
+            .query(&[("appkey", self.app_key.as_str()), ("format", "json"), ("callback", "no")])
+            .query(query)
+            .send()
+            .await?;
+        Ok(check_response(response)?.json().await?)
+    }
+
+    fn availabilities(&self, mut response: CheckResponse, isbn: &str) -> Vec<Availability> {
+        let systems = response.books.remove(isbn).unwrap_or_default();
+        self.system_ids
+            .iter()
+            .map(|system_id| {
+                let status = systems.get(system_id).cloned().unwrap_or_default();
+                Availability {
+                    system_id: system_id.clone(),
+                    status: status.status,
+                    reserve_url: status.reserveurl,
+                    libraries: status.libkey.into_iter().collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE_JSON: &str = r#"{
+  "session": "3e1f2c",
+  "continue": 0,
+  "books": {
+    "9784101010014": {
+      "Tokyo_Setagaya": {
+        "status": "OK",
+        "reserveurl": "https://libweb.city.setagaya.tokyo.jp/?isbn=9784101010014",
+        "libkey": { "中央": "貸出中", "経堂": "貸出可" }
+      },
+      "Aomori_Pref": { "status": "OK", "reserveurl": "", "libkey": {} }
+    }
+  }
+}"#;
+
+    #[test]
+    fn test_availabilities() {
+        let calil = Calil {
+            app_key: String::from("key"),
+            system_ids: vec![String::from("Tokyo_Setagaya"), String::from("Aomori_Pref"), String::from("Unknown")],
+        };
+        let response: CheckResponse = serde_json::from_str(RESPONSE_JSON).unwrap();
+        let availabilities = calil.availabilities(response, "9784101010014");
+        assert_eq!(availabilities.len(), 3);
+        assert_eq!(availabilities[0].status, "OK");
+        assert_eq!(
+            availabilities[0].libraries,
+            vec![(String::from("中央"), String::from("貸出中")), (String::from("経堂"), String::from("貸出可"))]
+        );
+        assert!(availabilities[1].libraries.is_empty());
+        // レスポンスにない図書館システムは状況が空になる
+        assert_eq!(availabilities[2].status, "");
+    }
+}
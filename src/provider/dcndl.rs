@@ -0,0 +1,153 @@
+//! SRUのdcndl形式(DC-NDLのRDF/XML)の書誌
+//! dcndl_simple形式より詳しく、件名や国立国会図書館分類、形態を含む
+
+use super::ndl::{join_extent, pages, series_title};
+use super::sru::normalize_isbn;
+use super::xml::Element;
+use crate::record::{non_empty, BookRecord};
+
+/// rdf:Description の rdf:value に値が入っている場合はその値、そうでなければ要素の中の文字列
+fn value(element: &Element) -> String {
+    match element.child("Description").and_then(|description| description.child("value")) {
+        Some(value) => value.text(),
+        None => element.text(),
+    }
+}
+
+/// foaf:Agent の foaf:name に名前が入っている場合はその名前、そうでなければ要素の中の文字列
+fn agent_name(element: &Element) -> String {
+    match element.child("Agent").and_then(|agent| agent.child("name")) {
+        Some(name) => name.text(),
+        None => element.text(),
+    }
+}
+
+/// 分類のURI(例: http://id.ndl.go.jp/class/ndc9/913.6)から、分類表の名前と分類記号を取り出す
+fn classification(uri: &str) -> Option<(&str, &str)> {
+    let path = uri.split("/class/").nth(1)?;
+    path.split_once('/')
+}
+
+/// recordDataの中のrdf:RDFから書誌情報を作る
+/// dcndl:BibResource がない場合と、ISBNがない場合はNoneを返す
+/// 著者は dc:creator(責任表示)を使い、なければ dcterms:creator の典拠の名前を使う
+pub(super) fn dcndl_record(rdf: &Element) -> Option<BookRecord> {
+    let resource = rdf.descendants("BibResource").into_iter().next()?;
+    let mut record = BookRecord { source: "ndlsru", ..Default::default() };
+    record.link = rdf
+        .descendants("BibAdminResource")
+        .first()
+        .and_then(|admin| admin.attribute("about"))
+        .map(String::from);
+    let is_isbn = |field: &Element| field.attribute("datatype").is_some_and(|datatype| datatype.ends_with("/ISBN"));
+    let mut agents = Vec::new();
+    let mut date = None;
+    for field in &resource.children {
+        match field.name.as_str() {
+            "identifier" if record.isbn.is_empty() && is_isbn(field) => {
+                record.isbn = normalize_isbn(&field.text()).unwrap_or_default();
+            }
+            "title" if record.title.is_empty() => record.title = value(field),
+            "creator" if field.child("Agent").is_some() => agents.push(agent_name(field)),
+            "creator" => record.authors.push(field.text()),
+            "publisher" if record.publisher.is_none() => record.publisher = non_empty(agent_name(field)),
+            "issued" if record.issued.is_none() => record.issued = non_empty(field.text()),
+            "date" if date.is_none() => date = non_empty(field.text()),
+            "seriesTitle" if record.series.is_none() => record.series = non_empty(series_title(&value(field))),
+            "volume" if record.volume.is_none() => record.volume = non_empty(value(field)),
+            "subject" => match field.attribute("resource").and_then(classification) {
+                Some((scheme, notation)) if scheme.starts_with("ndc") => {
+                    record.ndc.get_or_insert_with(|| notation.to_string());
+                }
+                Some(("ndlc", notation)) => {
+                    record.ndlc.get_or_insert_with(|| notation.to_string());
+                }
+                Some(_) => {}
+                None => record.subjects.extend(non_empty(value(field))),
+            },
+            "extent" => {
+                let extent = field.text();
+                record.pages = record.pages.or_else(|| pages(&extent));
+                record.extent = Some(join_extent(record.extent.take(), &extent));
+            }
+            "language" if record.language.is_none() => record.language = non_empty(field.text()),
+            "materialType" if record.material_type.is_none() => {
+                record.material_type = field.attribute("label").map(String::from);
+            }
+            _ => {}
+        }
+    }
+    if record.isbn.is_empty() {
+        return None;
+    }
+    if record.authors.is_empty() {
+        record.authors = agents;
+    }
+    record.issued = record.issued.or(date);
+    Some(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RDF_XML: &str = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:rdfs="http://www.w3.org/2000/01/rdf-schema#" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcndl="http://ndl.go.jp/dcndl/terms/" xmlns:foaf="http://xmlns.com/foaf/0.1/">
+  <dcndl:BibAdminResource rdf:about="https://ndlsearch.ndl.go.jp/books/R100000002-I000004191191">
+    <dcndl:bibRecordCategory>R100000002</dcndl:bibRecordCategory>
+  </dcndl:BibAdminResource>
+  <dcndl:BibResource rdf:about="https://ndlsearch.ndl.go.jp/books/R100000002-I000004191191#material">
+    <dcterms:identifier rdf:datatype="http://ndl.go.jp/dcndl/terms/JPNO">20000001</dcterms:identifier>
+    <dcterms:identifier rdf:datatype="http://ndl.go.jp/dcndl/terms/ISBN">4-10-101001-3</dcterms:identifier>
+    <dcterms:title>吾輩は猫である</dcterms:title>
+    <dc:title><rdf:Description><rdf:value>吾輩は猫である</rdf:value></rdf:Description></dc:title>
+    <dcndl:seriesTitle><rdf:Description><rdf:value>新潮文庫 ; な-1-1</rdf:value></rdf:Description></dcndl:seriesTitle>
+    <dcterms:creator><foaf:Agent rdf:about="http://id.ndl.go.jp/auth/entity/00054222"><foaf:name>夏目, 漱石, 1867-1916</foaf:name></foaf:Agent></dcterms:creator>
+    <dc:creator>夏目漱石 著</dc:creator>
+    <dcterms:publisher><foaf:Agent><foaf:name>新潮社</foaf:name><dcndl:location>東京</dcndl:location></foaf:Agent></dcterms:publisher>
+    <dcterms:date>2003.6</dcterms:date>
+    <dcterms:subject><rdf:Description rdf:about="http://id.ndl.go.jp/auth/ndlsh/00000001"><rdf:value>小説 (日本)</rdf:value></rdf:Description></dcterms:subject>
+    <dcterms:subject rdf:resource="http://id.ndl.go.jp/class/ndlc/KH321"/>
+    <dcterms:subject rdf:resource="http://id.ndl.go.jp/class/ndc9/913.6"/>
+    <dcterms:language rdf:datatype="http://purl.org/dc/terms/ISO639-2">jpn</dcterms:language>
+    <dcterms:extent>610p</dcterms:extent>
+    <dcterms:extent>16cm</dcterms:extent>
+    <dcndl:materialType rdf:resource="http://ndl.go.jp/ndltype/Book" rdfs:label="図書"/>
+  </dcndl:BibResource>
+</rdf:RDF>"#;
+
+    #[test]
+    fn test_dcndl_record() {
+        let record = dcndl_record(&Element::parse(RDF_XML).unwrap()).unwrap();
+        assert_eq!(record.isbn, "9784101010014");
+        assert_eq!(record.title, "吾輩は猫である");
+        assert_eq!(record.authors, vec![String::from("夏目漱石 著")]);
+        assert_eq!(record.publisher.as_deref(), Some("新潮社"));
+        assert_eq!(record.issued.as_deref(), Some("2003.6"));
+        assert_eq!(record.series.as_deref(), Some("新潮文庫"));
+        assert_eq!(record.ndc.as_deref(), Some("913.6"));
+        assert_eq!(record.ndlc.as_deref(), Some("KH321"));
+        assert_eq!(record.subjects, vec![String::from("小説 (日本)")]);
+        assert_eq!(record.extent.as_deref(), Some("610p ; 16cm"));
+        assert_eq!(record.pages, Some(610));
+        assert_eq!(record.language.as_deref(), Some("jpn"));
+        assert_eq!(record.material_type.as_deref(), Some("図書"));
+        assert_eq!(record.link.as_deref(), Some("https://ndlsearch.ndl.go.jp/books/R100000002-I000004191191"));
+    }
+
+    #[test]
+    fn test_agents() {
+        // 責任表示がない場合は典拠の名前を使う
+        let rdf_xml = RDF_XML.replace("<dc:creator>夏目漱石 著</dc:creator>", "");
+        let record = dcndl_record(&Element::parse(&rdf_xml).unwrap()).unwrap();
+        assert_eq!(record.authors, vec![String::from("夏目, 漱石, 1867-1916")]);
+        let rdf_xml = RDF_XML.replace("/ISBN", "/JPNO");
+        assert_eq!(dcndl_record(&Element::parse(&rdf_xml).unwrap()), None);
+    }
+
+    #[test]
+    fn test_classification() {
+        assert_eq!(classification("http://id.ndl.go.jp/class/ndc9/913.6"), Some(("ndc9", "913.6")));
+        assert_eq!(classification("http://id.ndl.go.jp/class/ndlc/KH321"), Some(("ndlc", "KH321")));
+        assert_eq!(classification("http://id.ndl.go.jp/auth/ndlsh/00000001"), None);
+    }
+}
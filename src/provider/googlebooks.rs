@@ -0,0 +1,133 @@
+//! Google Books API
+//! APIキーなしで利用でき、海外の本も多く登録されている
+
+use async_trait::async_trait;
+use isbn::Isbn;
+use serde::Deserialize;
+
+use super::retry::check_response;
+use super::{MetadataProvider, Result};
+use crate::record::{non_empty, BookRecord};
+
+const VOLUMES_ENDPOINT: &str = "https://www.googleapis.com/books/v1/volumes";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct VolumesResponse {
+    items: Vec<Volume>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Volume {
+    volume_info: VolumeInfo,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct VolumeInfo {
+    title: String,
+    subtitle: String,
+    authors: Vec<String>,
+    publisher: String,
+    published_date: String,
+    page_count: Option<u32>,
+    /// ISO 639-1の2文字の記号
+    language: String,
+    image_links: Option<ImageLinks>,
+    info_link: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ImageLinks {
+    thumbnail: String,
+}
+
+impl VolumeInfo {
+    fn into_record(self, isbn_13: &str) -> BookRecord {
+        let title = if self.subtitle.is_empty() {
+            self.title
+        } else {
+            format!("{}: {}", self.title, self.subtitle)
+        };
+        BookRecord {
+            isbn: isbn_13.to_string(),
+            title,
+            authors: self.authors,
+            publisher: non_empty(self.publisher),
+            issued: non_empty(self.published_date),
+            pages: self.page_count,
+            language: non_empty(self.language),
+            cover_url: self.image_links.and_then(|links| non_empty(links.thumbnail)),
+            link: non_empty(self.info_link),
+            source: "googlebooks",
+            ..Default::default()
+        }
+    }
+}
+
+/// Google Books
+pub struct GoogleBooks {
+    pub client: reqwest::Client,
+}
+
+#[async_trait]
+impl MetadataProvider for GoogleBooks {
+    fn name(&self) -> &'static str {
+        "googlebooks"
+    }
+
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+        let isbn_13 = isbn.create_isbn_13();
+        let response = self.client.get(VOLUMES_ENDPOINT)
+            .query(&[("q", format!("isbn:{}", isbn_13))])
+            .send()
+            .await?;
+        let response: VolumesResponse = check_response(response)?.json().await?;
+        Ok(response.items.into_iter().next().map(|volume| volume.volume_info.into_record(&isbn_13)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE_JSON: &str = r#"{
+  "kind": "books#volumes",
+  "totalItems": 1,
+  "items": [
+    {
+      "volumeInfo": {
+        "title": "Polymer Chemistry",
+        "subtitle": "An Introduction",
+        "authors": ["Malcolm P. Stevens"],
+        "publisher": "Oxford University Press",
+        "publishedDate": "1999",
+        "pageCount": 551,
+        "language": "en",
+        "imageLinks": { "thumbnail": "http://books.google.com/books/content?id=1" },
+        "infoLink": "http://books.google.co.jp/books?id=1"
+      }
+    }
+  ]
+}"#;
+
+    #[test]
+    fn test_into_record() {
+        let response: VolumesResponse = serde_json::from_str(RESPONSE_JSON).unwrap();
+        let volume = response.items.into_iter().next().unwrap();
+        let record = volume.volume_info.into_record("9780306406157");
+        assert_eq!(record.title, "Polymer Chemistry: An Introduction");
+        assert_eq!(record.authors, vec![String::from("Malcolm P. Stevens")]);
+        assert_eq!(record.pages, Some(551));
+        assert_eq!(record.language.as_deref(), Some("en"));
+        assert_eq!(record.source, "googlebooks");
+    }
+
+    #[test]
+    fn test_parse_not_found() {
+        let response: VolumesResponse = serde_json::from_str(r#"{"kind": "books#volumes", "totalItems": 0}"#).unwrap();
+        assert!(response.items.is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! SRUのMARCXML形式(MARC21)の書誌
+
+use super::ndl::{pages, series_title};
+use super::sru::normalize_isbn;
+use super::xml::Element;
+use crate::record::{non_empty, BookRecord};
+
+/// MARCのフィールド(datafield)
+struct DataField<'a>(&'a Element);
+
+impl DataField<'_> {
+    /// サブフィールドの値(ISBDの区切り記号を取り除いたもの)
+    fn subfields(&self, codes: &str) -> Vec<String> {
+        self.0
+            .children
+            .iter()
+            .filter(|subfield| subfield.name == "subfield")
+            .filter(|subfield| subfield.attribute("code").is_some_and(|code| codes.contains(code)))
+            .map(|subfield| trim_punctuation(&subfield.text()))
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    /// 最初のサブフィールドの値
+    fn subfield(&self, code: &str) -> Option<String> {
+        self.subfields(code).into_iter().next()
+    }
+}
+
+/// 値の末尾に付いているISBDの区切り記号(" /"、" :"、" ;"、","、".")を取り除く
+fn trim_punctuation(value: &str) -> String {
+    value.trim().trim_end_matches([' ', '/', ':', ';', ',', '.', '=']).trim().to_string()
+}
+
+/// recordDataの中のMARCのレコードから書誌情報を作る
+/// ISBN(020)がない場合はNoneを返す
+pub(super) fn marc_record(marc: &Element) -> Option<BookRecord> {
+    let fields: Vec<(String, DataField)> = marc
+        .children
+        .iter()
+        .filter(|field| field.name == "datafield")
+        .map(|field| (field.attribute("tag").unwrap_or_default().to_string(), DataField(field)))
+        .collect();
+    let field = |tag: &str| fields.iter().find(|(t, _)| t == tag).map(|(_, field)| field);
+    let each = |tags: &[&str]| {
+        fields.iter().filter(|(tag, _)| tags.contains(&tag.as_str())).map(|(_, field)| field).collect::<Vec<_>>()
+    };
+    // 020 $a には "9784101010014 (文庫)" のように付記が続くことがある
+    let isbn = each(&["020"]).iter().find_map(|field| {
+        let value = field.subfield("a")?;
+        normalize_isbn(value.split_whitespace().next()?)
+    })?;
+    let mut record = BookRecord { isbn, source: "ndlsru", ..Default::default() };
+    if let Some(title) = field("245") {
+        record.title = title.subfields("ab").join(" : ");
+        record.volume = non_empty(title.subfields("np").join(" "));
+    }
+    record.authors = each(&["100", "110", "700", "710"]).iter().filter_map(|field| field.subfield("a")).collect();
+    // 出版者と出版年は、古い書誌では260、新しい書誌では264にある
+    if let Some(publication) = field("264").or_else(|| field("260")) {
+        record.publisher = publication.subfield("b");
+        record.issued = publication.subfield("c");
+    }
+    if let Some(description) = field("300") {
+        let extent = description.subfields("abc").join(" ; ");
+        record.pages = pages(&extent);
+        record.extent = non_empty(extent);
+    }
+    if let Some(series) = field("490") {
+        record.series = series.subfield("a").map(|title| series_title(&title));
+        record.volume = record.volume.or_else(|| series.subfield("v"));
+    }
+    // 084 $2 で分類表を区別する(kktbは国立国会図書館分類表、njbは日本十進分類法)
+    for classification in each(&["084"]) {
+        let scheme = classification.subfield("2").unwrap_or_default();
+        if scheme == "kktb" {
+            record.ndlc = record.ndlc.or_else(|| classification.subfield("a"));
+        } else if scheme.starts_with("njb") {
+            record.ndc = record.ndc.or_else(|| classification.subfield("a"));
+        }
+    }
+    // 件名の細目は " -- " でつなぐ
+    record.subjects = each(&["600", "610", "650", "651"])
+        .iter()
+        .map(|subject| subject.subfields("axyz").join(" -- "))
+        .filter(|subject| !subject.is_empty())
+        .collect();
+    // 言語は008の35〜37桁目
+    record.language = marc
+        .children
+        .iter()
+        .find(|field| field.name == "controlfield" && field.attribute("tag") == Some("008"))
+        .and_then(|field| field.text().get(35..38).map(String::from))
+        .filter(|language| language.chars().all(|c| c.is_ascii_lowercase()));
+    Some(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARC_XML: &str = r#"<record xmlns="http://www.loc.gov/MARC21/slim">
+  <leader>00000cam a2200000 i 4500</leader>
+  <controlfield tag="001">000004191191</controlfield>
+  <controlfield tag="008">030601s2003    ja |||||||||||||||||jpn  </controlfield>
+  <datafield tag="020" ind1=" " ind2=" "><subfield code="a">4101010013 (文庫)</subfield></datafield>
+  <datafield tag="084" ind1=" " ind2=" "><subfield code="a">KH321</subfield><subfield code="2">kktb</subfield></datafield>
+  <datafield tag="084" ind1=" " ind2=" "><subfield code="a">913.6</subfield><subfield code="2">njb/09</subfield></datafield>
+  <datafield tag="100" ind1="1" ind2=" "><subfield code="a">夏目, 漱石,</subfield><subfield code="d">1867-1916</subfield></datafield>
+  <datafield tag="245" ind1="1" ind2="0"><subfield code="a">吾輩は猫である /</subfield><subfield code="c">夏目漱石 著</subfield></datafield>
+  <datafield tag="264" ind1=" " ind2="1"><subfield code="a">東京 :</subfield><subfield code="b">新潮社,</subfield><subfield code="c">2003.6.</subfield></datafield>
+  <datafield tag="300" ind1=" " ind2=" "><subfield code="a">610p ;</subfield><subfield code="c">16cm</subfield></datafield>
+  <datafield tag="490" ind1="0" ind2=" "><subfield code="a">新潮文庫 ;</subfield><subfield code="v">な-1-1</subfield></datafield>
+  <datafield tag="650" ind1=" " ind2="7"><subfield code="a">小説 (日本)</subfield><subfield code="2">ndlsh</subfield></datafield>
+  <datafield tag="700" ind1="1" ind2=" "><subfield code="a">注解者,</subfield></datafield>
+</record>"#;
+
+    #[test]
+    fn test_marc_record() {
+        let record = marc_record(&Element::parse(MARC_XML).unwrap()).unwrap();
+        assert_eq!(record.isbn, "9784101010014");
+        assert_eq!(record.title, "吾輩は猫である");
+        assert_eq!(record.authors, vec![String::from("夏目, 漱石"), String::from("注解者")]);
+        assert_eq!(record.publisher.as_deref(), Some("新潮社"));
+        assert_eq!(record.issued.as_deref(), Some("2003.6"));
+        assert_eq!(record.extent.as_deref(), Some("610p ; 16cm"));
+        assert_eq!(record.pages, Some(610));
+        assert_eq!(record.series.as_deref(), Some("新潮文庫"));
+        assert_eq!(record.volume.as_deref(), Some("な-1-1"));
+        assert_eq!(record.ndlc.as_deref(), Some("KH321"));
+        assert_eq!(record.ndc.as_deref(), Some("913.6"));
+        assert_eq!(record.subjects, vec![String::from("小説 (日本)")]);
+        assert_eq!(record.language.as_deref(), Some("jpn"));
+    }
+
+    #[test]
+    fn test_no_isbn() {
+        let marc_xml = MARC_XML.replace("tag=\"020\"", "tag=\"022\"");
+        assert_eq!(marc_record(&Element::parse(&marc_xml).unwrap()), None);
+    }
+
+    #[test]
+    fn test_trim_punctuation() {
+        assert_eq!(trim_punctuation("吾輩は猫である /"), "吾輩は猫である");
+        assert_eq!(trim_punctuation(" 2003.6. "), "2003.6");
+        assert_eq!(trim_punctuation("[2003]"), "[2003]");
+    }
+}
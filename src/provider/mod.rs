@@ -0,0 +1,179 @@
+//! 書誌情報を検索するAPIのクライアント
+
+use async_trait::async_trait;
+use isbn::Isbn;
+use std::error::Error;
+use tracing::{debug, warn};
+
+use crate::record::BookRecord;
+
+pub mod calil;
+mod dcndl;
+pub mod googlebooks;
+mod marcxml;
+pub mod ndl;
+pub mod onix;
+pub mod openbd;
+pub mod openlibrary;
+pub mod rakuten;
+pub mod ratelimit;
+pub mod retry;
+pub mod sru;
+mod xml;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// ISBNから書誌情報を検索するAPI
+#[async_trait]
+pub trait MetadataProvider: Send + Sync {
+    /// ログや出力で使うAPIの名前
+    fn name(&self) -> &'static str;
+
+    /// ISBNの書誌情報を検索する
+    /// 見つからなかった場合はNoneを返す
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>>;
+
+    /// 1回のリクエストでまとめて検索できるISBNの数
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    /// 複数のISBNの書誌情報を検索する
+    /// 結果は渡したISBNと同じ順番で、見つからなかったISBNはNoneになる
+    async fn lookup_batch(&self, isbns: &[Isbn]) -> Result<Vec<Option<BookRecord>>> {
+        let mut records = Vec::new();
+        for isbn in isbns {
+            records.push(self.lookup(isbn).await?);
+        }
+        Ok(records)
+    }
+}
+
+/// 複数のAPIを順番に試す
+/// 見つからなかったり、エラーになったりした場合は次のAPIで検索する
+pub struct ProviderChain {
+    providers: Vec<Box<dyn MetadataProvider>>,
+}
+
+impl ProviderChain {
+    pub fn new(providers: Vec<Box<dyn MetadataProvider>>) -> Self {
+        ProviderChain { providers }
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for ProviderChain {
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+        let records = self.lookup_batch(std::slice::from_ref(isbn)).await?;
+        Ok(records.into_iter().next().flatten())
+    }
+
+    /// 最初のAPIでまとめて検索できる数
+    fn batch_size(&self) -> usize {
+        self.providers.first().map_or(1, |provider| provider.batch_size())
+    }
+
+    /// 見つからなかったISBNだけを次のAPIで検索する
+    /// すべてのAPIがエラーになった場合は最後のエラーを返す
+    async fn lookup_batch(&self, isbns: &[Isbn]) -> Result<Vec<Option<BookRecord>>> {
+        let mut records: Vec<Option<BookRecord>> = vec![None; isbns.len()];
+        let mut last_error = None;
+        let mut succeeded = false;
+        for provider in &self.providers {
+            let remaining: Vec<usize> = (0..isbns.len()).filter(|i| records[*i].is_none()).collect();
+            if remaining.is_empty() {
+                break;
+            }
+            let candidates: Vec<Isbn> = remaining.iter().map(|i| isbns[*i].clone()).collect();
+            debug!(provider = provider.name(), remaining = candidates.len(), "looking up remaining isbns");
+            for (indices, chunk) in remaining.chunks(provider.batch_size()).zip(candidates.chunks(provider.batch_size())) {
+                match provider.lookup_batch(chunk).await {
+                    Ok(found) => {
+                        succeeded = true;
+                        for (i, record) in indices.iter().zip(found) {
+                            records[*i] = record;
+                        }
+                    }
+                    Err(e) => {
+                        warn!(provider = provider.name(), error = %e, "lookup failed");
+                        last_error = Some(e);
+                    }
+                }
+            }
+        }
+        match last_error {
+            Some(e) if !succeeded => Err(e),
+            _ => Ok(records),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 決まったISBNだけ見つかるAPI
+    struct Fixed {
+        name: &'static str,
+        isbns: Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl MetadataProvider for Fixed {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+            let isbn_13 = isbn.create_isbn_13();
+            if self.isbns.contains(&isbn_13.as_str()) {
+                Ok(Some(BookRecord { isbn: isbn_13, source: self.name, ..Default::default() }))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+
+    /// 必ずエラーになるAPI
+    struct Failing;
+
+    #[async_trait]
+    impl MetadataProvider for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn lookup(&self, _isbn: &Isbn) -> Result<Option<BookRecord>> {
+            Err("unavailable".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_fallback() {
+        let chain = ProviderChain::new(vec![
+            Box::new(Failing),
+            Box::new(Fixed { name: "first", isbns: vec!["9784798171548"] }),
+            Box::new(Fixed { name: "second", isbns: vec!["9784798171548", "9784101092058"] }),
+        ]);
+        let isbns = vec![
+            Isbn::parse("9784798171548").unwrap(),
+            Isbn::parse("9784101092058").unwrap(),
+            Isbn::parse("9780306406157").unwrap(),
+        ];
+        let records = chain.lookup_batch(&isbns).await.unwrap();
+        assert_eq!(records[0].as_ref().unwrap().source, "first");
+        assert_eq!(records[1].as_ref().unwrap().source, "second");
+        assert!(records[2].is_none());
+    }
+
+    #[tokio::test]
+    async fn test_chain_error() {
+        let chain = ProviderChain::new(vec![Box::new(Failing)]);
+        let isbn = Isbn::parse("9784798171548").unwrap();
+        assert!(chain.lookup(&isbn).await.is_err());
+    }
+}
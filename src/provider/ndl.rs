@@ -0,0 +1,322 @@
+//! 国立国会図書館サーチのOpenSearch API
+
+use async_trait::async_trait;
+use isbn::Isbn;
+use serde::Deserialize;
+use tracing::{debug, trace};
+
+use super::retry::check_response;
+use super::{MetadataProvider, Result};
+use crate::record::BookRecord;
+
+const OPENSEARCH_ENDPOINT: &str = "https://iss.ndl.go.jp/api/opensearch";
+
+pub async fn get_publication(client: &reqwest::Client, isbn: &str) -> Result<String> {
+    let response = client.get(format!("{}?cnt=1&isbn={}", OPENSEARCH_ENDPOINT, isbn))
+        .send()
+        .await?;
+    Ok(check_response(response)?.text().await?)
+}
+
+/// OpenSearchのレスポンス(RSS 2.0)
+#[derive(Debug, Deserialize)]
+pub struct OpenSearchResponse {
+    channel: Channel,
+}
+
+#[derive(Debug, Deserialize)]
+struct Channel {
+    #[serde(rename = "totalResults")]
+    total_results: usize,
+    #[serde(rename = "item", default)]
+    items: Vec<Item>,
+}
+
+/// itemの子要素は名前空間を区別せずローカル名で読み込む
+/// titleとdc:titleのように同じローカル名の要素が離れて現れることがあるため、子要素を順番に受け取る
+#[derive(Debug, Default, Deserialize)]
+struct Item {
+    #[serde(rename = "$value", default)]
+    fields: Vec<DcField>,
+}
+
+/// DC-NDL(国立国会図書館ダブリンコアメタデータ記述)の要素
+/// OpenSearchのitemとSRUのdcndl_simple形式で共通に使う
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) enum DcField {
+    Title(String),
+    Link(String),
+    Creator(String),
+    Publisher(String),
+    Issued(String),
+    Date(String),
+    SeriesTitle(String),
+    Volume(String),
+    /// 大きさやページ数(例: 318p ; 20cm)
+    Extent(String),
+    Identifier(Typed),
+    Subject(Typed),
+    /// 本文の言語(ISO 639-2の3文字の記号)
+    Language(Typed),
+    /// 資料の種別(図書、雑誌、地図など)
+    MaterialType(Labeled),
+    /// OpenSearchのitemに含まれる資料の種別
+    Category(String),
+    #[serde(other)]
+    Other,
+}
+
+/// xsi:type属性で種類が区別される要素
+/// 属性も名前空間を区別せずローカル名で読み込む
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct Typed {
+    #[serde(rename = "@type")]
+    kind: Option<String>,
+    #[serde(rename = "$text")]
+    pub(super) value: String,
+}
+
+/// rdfs:label属性で名前が、rdf:resource属性でURIが指定される要素
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct Labeled {
+    #[serde(rename = "@label")]
+    label: Option<String>,
+    #[serde(rename = "@resource")]
+    resource: Option<String>,
+}
+
+impl OpenSearchResponse {
+    /// OpenSearchのレスポンスを解析する
+    pub fn parse(response_xml: &str) -> std::result::Result<OpenSearchResponse, quick_xml::DeError> {
+        quick_xml::de::from_str(response_xml)
+    }
+
+    /// 検索結果の件数
+    pub fn total_results(&self) -> usize {
+        self.channel.total_results
+    }
+
+    /// 最初のitemから書誌情報を取り出す
+    pub fn first_record(&self, isbn_13: &str) -> Option<BookRecord> {
+        let item = self.channel.items.first()?;
+        Some(dc_record(&item.fields, isbn_13))
+    }
+}
+
+/// DC-NDLの要素から書誌情報を作る
+/// 同じ項目が複数ある場合は最初のものを使う(著者はすべて使う)
+/// 出版年月日はdcterms:issuedを使い、なければdc:dateを使う
+pub(super) fn dc_record(fields: &[DcField], isbn_13: &str) -> BookRecord {
+    let mut record = BookRecord { isbn: isbn_13.to_string(), source: "ndl", ..Default::default() };
+    let mut date = None;
+    for field in fields {
+        match field {
+            DcField::Title(title) if record.title.is_empty() => record.title = title.trim().to_string(),
+            DcField::Link(link) => {
+                record.link.get_or_insert_with(|| link.trim().to_string());
+            }
+            DcField::Creator(creator) => record.authors.push(creator.trim().to_string()),
+            DcField::Publisher(publisher) => {
+                record.publisher.get_or_insert_with(|| publisher.trim().to_string());
+            }
+            DcField::Issued(issued) => {
+                record.issued.get_or_insert_with(|| issued.trim().to_string());
+            }
+            DcField::Date(value) => {
+                date.get_or_insert_with(|| value.trim().to_string());
+            }
+            DcField::SeriesTitle(series) => {
+                record.series.get_or_insert_with(|| series_title(series));
+            }
+            DcField::Volume(volume) => {
+                record.volume.get_or_insert_with(|| volume.trim().to_string());
+            }
+            // 分類は xsi:type で区別される(例: dcndl:NDC10, dcndl:NDLC)
+            DcField::Subject(subject) if subject.is("dcndl:NDC") => {
+                record.ndc.get_or_insert_with(|| subject.value.trim().to_string());
+            }
+            DcField::Subject(subject) if subject.is("dcndl:NDLC") => {
+                record.ndlc.get_or_insert_with(|| subject.value.trim().to_string());
+            }
+            // 種類のないものと国立国会図書館件名標目(dcndl:NDLSH)は件名とみなす
+            DcField::Subject(subject) if subject.kind.is_none() || subject.is("dcndl:NDLSH") => {
+                let heading = subject.value.trim();
+                if !heading.is_empty() {
+                    record.subjects.push(heading.to_string());
+                }
+            }
+            // 名前がない場合は、URIの最後の部分(例: http://ndl.go.jp/ndltype/Book のBook)を使う
+            DcField::MaterialType(material) => {
+                let name = material
+                    .label
+                    .as_deref()
+                    .or_else(|| material.resource.as_deref()?.rsplit('/').next())
+                    .map(|name| name.trim().to_string());
+                if let Some(name) = name.filter(|name| !name.is_empty()) {
+                    record.material_type.get_or_insert(name);
+                }
+            }
+            DcField::Extent(extent) => {
+                record.extent = Some(join_extent(record.extent.take(), extent));
+                record.pages = record.pages.or_else(|| pages(extent));
+            }
+            DcField::Language(language) => {
+                record.language.get_or_insert_with(|| language.value.trim().to_string());
+            }
+            DcField::Category(category) => {
+                record.material_type.get_or_insert_with(|| category.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+    record.issued = record.issued.or(date);
+    record
+}
+
+/// シリーズ名の記述からシリーズ名を取り出す
+/// "新潮文庫 ; な-1-1" のように ; の後ろに続くシリーズ番号は取り除く
+pub(super) fn series_title(series: &str) -> String {
+    series.split(';').next().unwrap_or_default().trim().to_string()
+}
+
+/// 大きさやページ数の記述からページ数を取り出す
+/// "318p ; 20cm" や "xii, 318p" のように、数字の直後にpが続くところをページ数とみなす
+pub(super) fn pages(extent: &str) -> Option<u32> {
+    let mut digits = String::new();
+    for c in extent.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            'p' | 'P' if !digits.is_empty() => return digits.parse().ok(),
+            _ => digits.clear(),
+        }
+    }
+    None
+}
+
+/// 形態の記述をつなげる
+/// "318p" と "20cm" のように分かれている場合は "318p ; 20cm" にする
+pub(super) fn join_extent(extent: Option<String>, part: &str) -> String {
+    match extent {
+        Some(extent) => format!("{} ; {}", extent, part.trim()),
+        None => part.trim().to_string(),
+    }
+}
+
+impl Typed {
+    /// xsi:typeが指定した接頭辞で始まるかどうか
+    pub(super) fn is(&self, kind: &str) -> bool {
+        self.kind.as_deref().is_some_and(|k| k.starts_with(kind))
+    }
+}
+
+
+/// 国立国会図書館サーチ
+pub struct Ndl {
+    pub client: reqwest::Client,
+}
+
+#[async_trait]
+impl MetadataProvider for Ndl {
+    fn name(&self) -> &'static str {
+        "ndl"
+    }
+
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+        let isbn_13 = isbn.create_isbn_13();
+        let response_xml = get_publication(&self.client, &isbn_13).await?;
+        trace!(%response_xml, "opensearch response");
+        let response = OpenSearchResponse::parse(&response_xml)?;
+        debug!(isbn = %isbn_13, total_results = response.total_results(), "parsed opensearch response");
+        if response.total_results() == 0 {
+            return Ok(None);
+        }
+        Ok(response.first_record(&isbn_13))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dcndl="http://ndl.go.jp/dcndl/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:openSearch="http://a9.com/-/spec/opensearchrss/1.0/" version="2.0">
+  <channel>
+    <title>9784101010014 - 国立国会図書館サーチ OpenSearch</title>
+    <openSearch:totalResults>1</openSearch:totalResults>
+    <item>
+      <title>吾輩は猫である</title>
+      <dc:title>吾輩は猫である</dc:title>
+      <link>https://iss.ndl.go.jp/books/R100000002-I000004191191-00</link>
+      <author>夏目漱石 著</author>
+      <dc:creator>夏目漱石 著</dc:creator>
+      <dc:creator>注解者 著</dc:creator>
+      <dcndl:seriesTitle>新潮文庫</dcndl:seriesTitle>
+      <dcndl:volume>上</dcndl:volume>
+      <dc:publisher>新潮社</dc:publisher>
+      <dcterms:issued xsi:type="dcterms:W3CDTF">2003</dcterms:issued>
+      <dc:subject xsi:type="dcndl:NDLC">KH321</dc:subject>
+      <dc:subject>小説 (日本)</dc:subject>
+      <dc:subject xsi:type="dcndl:NDC10">913.6</dc:subject>
+      <dcterms:extent>318p ; 16cm</dcterms:extent>
+      <dc:language xsi:type="dcterms:ISO639-2">jpn</dc:language>
+      <dcndl:materialType rdf:resource="http://ndl.go.jp/ndltype/Book" rdfs:label="図書"/>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn test_total_results() {
+        assert_eq!(OpenSearchResponse::parse(RESPONSE_XML).unwrap().total_results(), 1);
+    }
+
+    #[test]
+    fn test_first_record() {
+        let record = OpenSearchResponse::parse(RESPONSE_XML).unwrap().first_record("9784101010014").unwrap();
+        assert_eq!(record.title, "吾輩は猫である");
+        assert_eq!(record.authors, vec![String::from("夏目漱石 著"), String::from("注解者 著")]);
+        assert_eq!(record.publisher.as_deref(), Some("新潮社"));
+        assert_eq!(record.issued.as_deref(), Some("2003"));
+        assert_eq!(record.series.as_deref(), Some("新潮文庫"));
+        assert_eq!(record.volume.as_deref(), Some("上"));
+        assert_eq!(record.ndc.as_deref(), Some("913.6"));
+        assert_eq!(record.material_type.as_deref(), Some("図書"));
+        assert_eq!(record.language.as_deref(), Some("jpn"));
+        assert_eq!(record.pages, Some(318));
+        assert_eq!(record.ndlc.as_deref(), Some("KH321"));
+        assert_eq!(record.subjects, vec![String::from("小説 (日本)")]);
+        assert_eq!(record.extent.as_deref(), Some("318p ; 16cm"));
+        assert_eq!(record.source, "ndl");
+    }
+
+    #[test]
+    fn test_date() {
+        let fields = [DcField::Date(String::from("2003.4")), DcField::Title(String::from("吾輩は猫である"))];
+        assert_eq!(dc_record(&fields, "9784101010014").issued.as_deref(), Some("2003.4"));
+        let fields = [DcField::Date(String::from("2003.4")), DcField::Issued(String::from("2003"))];
+        assert_eq!(dc_record(&fields, "9784101010014").issued.as_deref(), Some("2003"));
+    }
+
+    #[test]
+    fn test_series_title() {
+        assert_eq!(series_title("新潮文庫 ; な-1-1"), "新潮文庫");
+        assert_eq!(series_title(" ジャンプ・コミックス "), "ジャンプ・コミックス");
+    }
+
+    #[test]
+    fn test_pages() {
+        assert_eq!(pages("318p ; 20cm"), Some(318));
+        assert_eq!(pages("xii, 318p"), Some(318));
+        assert_eq!(pages("1冊 ; 20cm"), None);
+        assert_eq!(pages("p"), None);
+    }
+
+    #[test]
+    fn test_parse_unexpected() {
+        // エラーページなどはパニックせずにエラーにする
+        assert!(OpenSearchResponse::parse("<html><body>Service Unavailable</body></html>").is_err());
+        assert!(OpenSearchResponse::parse("not xml").is_err());
+    }
+}
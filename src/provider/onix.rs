@@ -0,0 +1,347 @@
+//! openBDが返すONIX 3.0(JSON)のうち、書誌情報に使う部分
+//! 項目名はONIXのリファレンスの名前(PascalCase)のまま読み込み、コードは出版業界のONIXのコードリストに従う
+
+use serde::Deserialize;
+
+/// ONIXの商品(1冊の本)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Product {
+    pub descriptive_detail: DescriptiveDetail,
+    pub publishing_detail: PublishingDetail,
+    pub product_supply: ProductSupply,
+}
+
+/// 商品の形態、タイトル、著者、ページ数など
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct DescriptiveDetail {
+    /// 商品の形態(List 150、例: BA 書籍)
+    pub product_form: String,
+    /// 判型(List 175、例: B111 文庫)
+    pub product_form_detail: String,
+    pub contributor: Vec<Contributor>,
+    pub extent: Vec<Extent>,
+    pub language: Vec<Language>,
+}
+
+/// 著者や訳者などの、著作に関わった人
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Contributor {
+    /// 役割(List 17、例: A01 著、B06 訳)
+    pub contributor_role: Vec<String>,
+    pub person_name: Text,
+}
+
+/// 文字列(読みのcollationkeyは使わない)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Text {
+    pub content: String,
+}
+
+/// ページ数などの分量
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Extent {
+    /// 分量の種類(List 23、11が本文のページ数)
+    pub extent_type: String,
+    pub extent_value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Language {
+    /// 言語の役割(List 22、01が本文の言語)
+    pub language_role: String,
+    /// ISO 639-2の言語の記号
+    pub language_code: String,
+}
+
+/// 出版社と発売日
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct PublishingDetail {
+    pub imprint: Imprint,
+    pub publisher: Publisher,
+    pub publishing_date: Vec<PublishingDate>,
+}
+
+/// 発行元の名前
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Imprint {
+    pub imprint_name: String,
+}
+
+/// 発売元の名前
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Publisher {
+    pub publisher_name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct PublishingDate {
+    /// 日付の種類(List 163、01が発売日)
+    pub publishing_date_role: String,
+    /// YYYYMMDD(日や月がないこともある)
+    pub date: String,
+}
+
+/// 価格
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct ProductSupply {
+    pub supply_detail: SupplyDetail,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct SupplyDetail {
+    pub price: Vec<Price>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Price {
+    /// 価格の種類(List 58、01と03が税抜、02と04が税込)
+    pub price_type: String,
+    pub price_amount: String,
+    pub currency_code: String,
+}
+
+/// 著者の役割(List 17のうち本でよく使われるもの)
+const CONTRIBUTOR_ROLES: &[(&str, &str)] = &[
+    ("A01", "著"),
+    ("A03", "脚本"),
+    ("A06", "作曲"),
+    ("A08", "写真"),
+    ("A12", "イラスト"),
+    ("A21", "解説"),
+    ("A38", "原著"),
+    ("B01", "編"),
+    ("B06", "訳"),
+    ("B20", "監修"),
+];
+
+/// 判型(List 175のうち日本の本の判型)
+const FORM_DETAILS: &[(&str, &str)] = &[
+    ("B108", "A5判"),
+    ("B109", "B5判"),
+    ("B110", "B6判"),
+    ("B111", "文庫"),
+    ("B112", "新書"),
+    ("B119", "四六判"),
+    ("B120", "四六判変型"),
+    ("B121", "A4判"),
+    ("B126", "AB判"),
+    ("B128", "菊判"),
+    ("B130", "B4判"),
+];
+
+impl Contributor {
+    /// 役割の名前(分からない役割はNone)
+    pub fn role(&self) -> Option<&'static str> {
+        let role = self.contributor_role.first()?;
+        CONTRIBUTOR_ROLES.iter().find(|(code, _)| code == role).map(|(_, name)| *name)
+    }
+
+    /// 「夏目漱石 著」のような、名前と役割の表記
+    pub fn credit(&self) -> String {
+        let name = self.person_name.content.trim();
+        match self.role() {
+            Some(role) => format!("{} {}", name, role),
+            None => name.to_string(),
+        }
+    }
+}
+
+impl Price {
+    /// 税込の価格かどうか
+    pub fn is_tax_included(&self) -> bool {
+        self.price_type == "02" || self.price_type == "04"
+    }
+}
+
+impl Product {
+    /// 著者の一覧(役割の付いた表記)
+    pub fn credits(&self) -> Vec<String> {
+        self.descriptive_detail
+            .contributor
+            .iter()
+            .filter(|contributor| !contributor.person_name.content.trim().is_empty())
+            .map(Contributor::credit)
+            .collect()
+    }
+
+    /// 日本円の税抜価格
+    /// 税抜の価格がない場合は最初の日本円の価格を使う
+    pub fn price(&self) -> Option<u32> {
+        let prices: Vec<&Price> = self
+            .product_supply
+            .supply_detail
+            .price
+            .iter()
+            .filter(|price| price.currency_code.is_empty() || price.currency_code == "JPY")
+            .collect();
+        let price = prices.iter().find(|price| !price.is_tax_included()).or(prices.first())?;
+        price.price_amount.parse().ok()
+    }
+
+    /// 本文のページ数
+    pub fn pages(&self) -> Option<u32> {
+        let extent = self.descriptive_detail.extent.iter().find(|extent| extent.extent_type == "11")?;
+        extent.extent_value.parse().ok()
+    }
+
+    /// 商品の形態の名前(判型が分かる場合は「文庫」「四六判」のように判型にする)
+    pub fn format(&self) -> Option<String> {
+        let detail = &self.descriptive_detail;
+        if let Some((_, name)) = FORM_DETAILS.iter().find(|(code, _)| *code == detail.product_form_detail) {
+            return Some(name.to_string());
+        }
+        match detail.product_form.as_str() {
+            "BA" | "BZ" => Some(String::from("書籍")),
+            "BB" => Some(String::from("上製本")),
+            "BC" => Some(String::from("並製本")),
+            "" => None,
+            _ => Some(detail.product_form.clone()),
+        }
+    }
+
+    /// 国立国会図書館サーチと同じ資料種別の名前(List 150のBで始まる形態は図書)
+    pub fn material_type(&self) -> Option<String> {
+        self.descriptive_detail.product_form.starts_with('B').then(|| String::from("図書"))
+    }
+
+    /// 本文の言語
+    pub fn language(&self) -> Option<String> {
+        let languages = &self.descriptive_detail.language;
+        let language = languages.iter().find(|language| language.language_role == "01").or(languages.first())?;
+        Some(language.language_code.clone()).filter(|code| !code.is_empty())
+    }
+
+    /// 発売日(YYYYMMDD、日や月がないこともある)
+    pub fn publishing_date(&self) -> Option<String> {
+        let dates = &self.publishing_detail.publishing_date;
+        let date = dates.iter().find(|date| date.publishing_date_role == "01").or(dates.first())?;
+        Some(date.date.trim().to_string()).filter(|date| !date.is_empty())
+    }
+
+    /// 出版社の名前(発行元、なければ発売元)
+    pub fn publisher(&self) -> Option<String> {
+        let detail = &self.publishing_detail;
+        [&detail.imprint.imprint_name, &detail.publisher.publisher_name]
+            .into_iter()
+            .map(|name| name.trim())
+            .find(|name| !name.is_empty())
+            .map(String::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONIX_JSON: &str = r#"{
+  "RecordReference": "9784101010014",
+  "DescriptiveDetail": {
+    "ProductComposition": "00",
+    "ProductForm": "BA",
+    "ProductFormDetail": "B111",
+    "TitleDetail": {
+      "TitleType": "01",
+      "TitleElement": { "TitleElementLevel": "01", "TitleText": { "content": "吾輩は猫である" } }
+    },
+    "Contributor": [
+      {
+        "SequenceNumber": "1",
+        "ContributorRole": ["A01"],
+        "PersonName": { "content": "夏目漱石", "collationkey": "ナツメ ソウセキ" }
+      },
+      { "SequenceNumber": "2", "ContributorRole": ["A21"], "PersonName": { "content": "注解者" } },
+      { "SequenceNumber": "3", "ContributorRole": ["Z99"], "PersonName": { "content": "協力者" } }
+    ],
+    "Language": [{ "LanguageRole": "01", "LanguageCode": "jpn", "CountryCode": "JP" }],
+    "Extent": [{ "ExtentType": "11", "ExtentValue": "590", "ExtentUnit": "03" }],
+    "Subject": [
+      { "MainSubject": "", "SubjectSchemeIdentifier": "78", "SubjectCode": "0193" },
+      { "SubjectSchemeIdentifier": "79", "SubjectCode": "13" }
+    ]
+  },
+  "PublishingDetail": {
+    "Imprint": { "ImprintName": "新潮社" },
+    "PublishingDate": [{ "PublishingDateRole": "01", "Date": "20030601" }]
+  },
+  "ProductSupply": {
+    "SupplyDetail": {
+      "ProductAvailability": "99",
+      "Price": [
+        { "PriceType": "04", "PriceAmount": "781", "CurrencyCode": "JPY" },
+        { "PriceType": "03", "PriceAmount": "710", "CurrencyCode": "JPY" }
+      ]
+    }
+  }
+}"#;
+
+    #[test]
+    fn test_product() {
+        let product: Product = serde_json::from_str(ONIX_JSON).unwrap();
+        assert_eq!(product.credits(), vec!["夏目漱石 著", "注解者 解説", "協力者"]);
+        assert_eq!(product.price(), Some(710));
+        assert_eq!(product.pages(), Some(590));
+        assert_eq!(product.format().as_deref(), Some("文庫"));
+        assert_eq!(product.language().as_deref(), Some("jpn"));
+        assert_eq!(product.publisher().as_deref(), Some("新潮社"));
+        assert_eq!(product.publishing_date().as_deref(), Some("20030601"));
+    }
+
+    #[test]
+    fn test_empty() {
+        let product: Product = serde_json::from_str("{}").unwrap();
+        assert_eq!(product, Product::default());
+        assert!(product.credits().is_empty());
+        assert_eq!(product.price(), None);
+        assert_eq!(product.format(), None);
+        assert_eq!(product.material_type(), None);
+    }
+
+    #[test]
+    fn test_format() {
+        let product = |form: &str, detail: &str| Product {
+            descriptive_detail: DescriptiveDetail {
+                product_form: form.to_string(),
+                product_form_detail: detail.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(product("BA", "B119").format().as_deref(), Some("四六判"));
+        assert_eq!(product("BA", "B108").format().as_deref(), Some("A5判"));
+        assert_eq!(product("BB", "").format().as_deref(), Some("上製本"));
+        assert_eq!(product("BA", "").format().as_deref(), Some("書籍"));
+        assert_eq!(product("BB", "").material_type().as_deref(), Some("図書"));
+        assert_eq!(product("DA", "").material_type(), None);
+    }
+
+    #[test]
+    fn test_price() {
+        let price = |price_type: &str, amount: &str| Price {
+            price_type: price_type.to_string(),
+            price_amount: amount.to_string(),
+            currency_code: String::from("JPY"),
+        };
+        let product = |prices: Vec<Price>| Product {
+            product_supply: ProductSupply { supply_detail: SupplyDetail { price: prices } },
+            ..Default::default()
+        };
+        // 税込の価格しかない場合はその価格を使う
+        assert_eq!(product(vec![price("04", "781")]).price(), Some(781));
+        assert_eq!(product(vec![price("02", "781"), price("01", "710")]).price(), Some(710));
+        let dollar = Price { currency_code: String::from("USD"), ..price("01", "15") };
+        assert_eq!(product(vec![dollar]).price(), None);
+    }
+}
@@ -0,0 +1,234 @@
+//! openBDのAPI
+//! 複数のISBNをまとめて問い合わせることができ、ONIX形式の詳しい書誌情報を返す
+
+use async_trait::async_trait;
+use isbn::Isbn;
+use serde::Deserialize;
+
+use super::onix::Product;
+use super::retry::check_response;
+use super::{MetadataProvider, Result};
+use crate::record::{non_empty, BookRecord};
+
+const OPENBD_ENDPOINT: &str = "https://api.openbd.jp/v1/get";
+
+/// ランダムに本を探すときに1回のリクエストでまとめて問い合わせるISBNの数
+pub const BATCH_SIZE: usize = 10;
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    summary: Summary,
+    #[serde(default)]
+    onix: Product,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Summary {
+    isbn: String,
+    title: String,
+    volume: String,
+    series: String,
+    publisher: String,
+    pubdate: String,
+    cover: String,
+    author: String,
+}
+
+/// openBDの書誌情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenBdBook {
+    pub isbn: String,
+    pub title: String,
+    pub volume: String,
+    pub series: String,
+    pub author: String,
+    pub publisher: String,
+    pub pubdate: String,
+    /// 書影のURL(ない場合は空文字)
+    pub cover: String,
+    /// ONIXの著者(「夏目漱石 著」のように役割を付けたもの)
+    pub contributors: Vec<String>,
+    /// 税抜きの価格(円)
+    pub price: Option<u32>,
+    /// ページ数
+    pub pages: Option<u32>,
+    /// 判型や製本の種類(文庫、四六判など)
+    pub format: Option<String>,
+    /// 資料種別(書籍の形態なら図書)
+    pub material_type: Option<String>,
+    /// 本文の言語(ISO 639-2)
+    pub language: Option<String>,
+}
+
+impl From<Entry> for OpenBdBook {
+    /// 出版社と出版年月日は、summaryになければONIXのものを使う
+    fn from(entry: Entry) -> Self {
+        let (summary, onix) = (entry.summary, entry.onix);
+        OpenBdBook {
+            isbn: summary.isbn,
+            title: summary.title,
+            volume: summary.volume,
+            series: summary.series,
+            author: summary.author,
+            publisher: non_empty(summary.publisher).or_else(|| onix.publisher()).unwrap_or_default(),
+            pubdate: non_empty(summary.pubdate).or_else(|| onix.publishing_date()).unwrap_or_default(),
+            cover: summary.cover,
+            contributors: onix.credits(),
+            price: onix.price(),
+            pages: onix.pages(),
+            format: onix.format(),
+            material_type: onix.material_type(),
+            language: onix.language(),
+        }
+    }
+}
+
+impl From<OpenBdBook> for BookRecord {
+    /// 著者はONIXの役割付きのものを使い、なければsummaryのものを使う
+    fn from(book: OpenBdBook) -> Self {
+        let authors =
+            if book.contributors.is_empty() { non_empty(book.author).into_iter().collect() } else { book.contributors };
+        BookRecord {
+            isbn: book.isbn,
+            title: book.title,
+            authors,
+            publisher: non_empty(book.publisher),
+            issued: non_empty(book.pubdate),
+            series: non_empty(book.series),
+            volume: non_empty(book.volume),
+            price: book.price,
+            pages: book.pages,
+            format: book.format,
+            material_type: book.material_type,
+            language: book.language,
+            cover_url: non_empty(book.cover),
+            source: "openbd",
+            ..Default::default()
+        }
+    }
+}
+
+/// 複数のISBNの書誌情報をまとめて取得する
+/// 結果は渡したISBNと同じ順番で、見つからなかったISBNはNoneになる
+pub async fn get_books(client: &reqwest::Client, isbns: &[String]) -> Result<Vec<Option<OpenBdBook>>> {
+    let response = client.get(OPENBD_ENDPOINT)
+        .query(&[("isbn", isbns.join(","))])
+        .send()
+        .await?;
+    let entries: Vec<Option<Entry>> = check_response(response)?.json().await?;
+    Ok(entries.into_iter().map(|entry| entry.map(OpenBdBook::from)).collect())
+}
+
+/// openBD
+pub struct OpenBd {
+    pub client: reqwest::Client,
+}
+
+#[async_trait]
+impl MetadataProvider for OpenBd {
+    fn name(&self) -> &'static str {
+        "openbd"
+    }
+
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+        let records = self.lookup_batch(std::slice::from_ref(isbn)).await?;
+        Ok(records.into_iter().next().flatten())
+    }
+
+    fn batch_size(&self) -> usize {
+        BATCH_SIZE
+    }
+
+    async fn lookup_batch(&self, isbns: &[Isbn]) -> Result<Vec<Option<BookRecord>>> {
+        let isbns: Vec<String> = isbns.iter().map(|isbn| isbn.create_isbn_13()).collect();
+        let books = get_books(&self.client, &isbns).await?;
+        Ok(books.into_iter().map(|book| book.map(BookRecord::from)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Material, RecordFilter};
+
+    const RESPONSE_JSON: &str = r#"[
+  {
+    "onix": {
+      "RecordReference": "9784101010014",
+      "DescriptiveDetail": {
+        "ProductForm": "BA",
+        "ProductFormDetail": "B111",
+        "Contributor": [{ "SequenceNumber": "1", "ContributorRole": ["A01"], "PersonName": { "content": "夏目漱石" } }],
+        "Extent": [{ "ExtentType": "11", "ExtentValue": "590", "ExtentUnit": "03" }]
+      },
+      "ProductSupply": {
+        "SupplyDetail": {
+          "ProductAvailability": "99",
+          "Price": [{ "PriceType": "03", "PriceAmount": "710", "CurrencyCode": "JPY" }]
+        }
+      }
+    },
+    "summary": {
+      "isbn": "9784101010014",
+      "title": "吾輩は猫である",
+      "volume": "",
+      "series": "新潮文庫",
+      "publisher": "新潮社",
+      "pubdate": "2003-06",
+      "cover": "https://cover.openbd.jp/9784101010014.jpg",
+      "author": "夏目漱石／著"
+    }
+  },
+  null
+]"#;
+
+    #[test]
+    fn test_parse_books() {
+        let entries: Vec<Option<Entry>> = serde_json::from_str(RESPONSE_JSON).unwrap();
+        let books: Vec<Option<OpenBdBook>> = entries.into_iter().map(|entry| entry.map(OpenBdBook::from)).collect();
+        assert_eq!(books.len(), 2);
+        let book = books[0].as_ref().unwrap();
+        assert_eq!(book.title, "吾輩は猫である");
+        assert_eq!(book.author, "夏目漱石／著");
+        assert_eq!(book.contributors, vec!["夏目漱石 著"]);
+        assert_eq!(book.price, Some(710));
+        assert_eq!(book.pages, Some(590));
+        assert_eq!(book.format.as_deref(), Some("文庫"));
+        assert!(books[1].is_none());
+    }
+
+    #[test]
+    fn test_record() {
+        let entries: Vec<Option<Entry>> = serde_json::from_str(RESPONSE_JSON).unwrap();
+        let record = BookRecord::from(OpenBdBook::from(entries.into_iter().next().unwrap().unwrap()));
+        assert_eq!(record.title, "吾輩は猫である");
+        // 空の巻次はNoneにする
+        assert_eq!(record.volume, None);
+        assert_eq!(record.series.as_deref(), Some("新潮文庫"));
+        assert_eq!(record.price, Some(710));
+        assert_eq!(record.authors, vec![String::from("夏目漱石 著")]);
+        assert_eq!(record.format.as_deref(), Some("文庫"));
+        assert_eq!(record.material_type.as_deref(), Some("図書"));
+    }
+
+    #[test]
+    fn test_material_filter() {
+        // openBDで見つかった本も--material bookで受け入れる
+        let entries: Vec<Option<Entry>> = serde_json::from_str(RESPONSE_JSON).unwrap();
+        let record = BookRecord::from(OpenBdBook::from(entries.into_iter().next().unwrap().unwrap()));
+        let filter = RecordFilter { material: Some(Material::Book), ..Default::default() };
+        assert_eq!(filter.rejects(&record), None);
+        let filter = RecordFilter { material: Some(Material::Serial), ..Default::default() };
+        assert_eq!(filter.rejects(&record), Some("material"));
+    }
+
+    #[test]
+    fn test_without_onix() {
+        let entry_json = r#"{ "summary": { "isbn": "9784101010014", "author": "夏目漱石／著" } }"#;
+        let entry: Entry = serde_json::from_str(entry_json).unwrap();
+        let record = BookRecord::from(OpenBdBook::from(entry));
+        assert_eq!(record.authors, vec![String::from("夏目漱石／著")]);
+        assert_eq!(record.price, None);
+    }
+}
@@ -0,0 +1,195 @@
+//! Open LibraryのBooks API
+//! 複数のISBNをまとめて問い合わせることができ、書影のURLも返す
+
+use async_trait::async_trait;
+use isbn::Isbn;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::retry::check_response;
+use super::{MetadataProvider, Result};
+use crate::record::{non_empty, BookRecord};
+
+const BOOKS_ENDPOINT: &str = "https://openlibrary.org/api/books";
+
+/// ランダムに本を探すときに1回のリクエストでまとめて問い合わせるISBNの数
+pub const BATCH_SIZE: usize = 10;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Entry {
+    url: String,
+    title: String,
+    subtitle: String,
+    authors: Vec<Name>,
+    publishers: Vec<Name>,
+    publish_date: String,
+    number_of_pages: Option<u32>,
+    cover: Option<Cover>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Name {
+    name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Cover {
+    small: String,
+    medium: String,
+    large: String,
+}
+
+/// Open Libraryの書誌情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenLibraryBook {
+    pub url: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub publishers: Vec<String>,
+    pub publish_date: String,
+    pub number_of_pages: Option<u32>,
+    /// 書影のURL(ない場合は空文字)
+    pub cover: String,
+}
+
+impl From<Entry> for OpenLibraryBook {
+    fn from(entry: Entry) -> Self {
+        let title = if entry.subtitle.is_empty() {
+            entry.title
+        } else {
+            format!("{}: {}", entry.title, entry.subtitle)
+        };
+        // 大きい書影を優先する
+        let cover = entry
+            .cover
+            .map(|cover| [cover.large, cover.medium, cover.small].into_iter().find(|url| !url.is_empty()).unwrap_or_default())
+            .unwrap_or_default();
+        OpenLibraryBook {
+            url: entry.url,
+            title,
+            authors: entry.authors.into_iter().map(|author| author.name).collect(),
+            publishers: entry.publishers.into_iter().map(|publisher| publisher.name).collect(),
+            publish_date: entry.publish_date,
+            number_of_pages: entry.number_of_pages,
+            cover,
+        }
+    }
+}
+
+impl OpenLibraryBook {
+    /// ISBNを付けて共通の書誌情報にする
+    /// レスポンスにはISBNが含まれないので、問い合わせたISBNを使う
+    pub fn into_record(self, isbn_13: &str) -> BookRecord {
+        BookRecord {
+            isbn: isbn_13.to_string(),
+            title: self.title,
+            authors: self.authors,
+            publisher: non_empty(self.publishers.join(", ")),
+            issued: non_empty(self.publish_date),
+            pages: self.number_of_pages,
+            cover_url: non_empty(self.cover),
+            link: non_empty(self.url),
+            source: "openlibrary",
+            ..Default::default()
+        }
+    }
+}
+
+/// レスポンスはbibkeyをキーにしたオブジェクトなので、渡したISBNの順番に並べ直す
+fn order_by_isbns(mut entries: HashMap<String, Entry>, isbns: &[String]) -> Vec<Option<OpenLibraryBook>> {
+    isbns
+        .iter()
+        .map(|isbn| entries.remove(&format!("ISBN:{}", isbn)).map(OpenLibraryBook::from))
+        .collect()
+}
+
+/// 複数のISBNの書誌情報をまとめて取得する
+/// 結果は渡したISBNと同じ順番で、見つからなかったISBNはNoneになる
+pub async fn get_books(client: &reqwest::Client, isbns: &[String]) -> Result<Vec<Option<OpenLibraryBook>>> {
+    let bibkeys: Vec<String> = isbns.iter().map(|isbn| format!("ISBN:{}", isbn)).collect();
+    let response = client.get(BOOKS_ENDPOINT)
+        .query(&[("bibkeys", bibkeys.join(",").as_str()), ("format", "json"), ("jscmd", "data")])
+        .send()
+        .await?;
+    let entries: HashMap<String, Entry> = check_response(response)?.json().await?;
+    Ok(order_by_isbns(entries, isbns))
+}
+
+/// Open Library
+pub struct OpenLibrary {
+    pub client: reqwest::Client,
+}
+
+#[async_trait]
+impl MetadataProvider for OpenLibrary {
+    fn name(&self) -> &'static str {
+        "openlibrary"
+    }
+
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+        let records = self.lookup_batch(std::slice::from_ref(isbn)).await?;
+        Ok(records.into_iter().next().flatten())
+    }
+
+    fn batch_size(&self) -> usize {
+        BATCH_SIZE
+    }
+
+    async fn lookup_batch(&self, isbns: &[Isbn]) -> Result<Vec<Option<BookRecord>>> {
+        let isbns: Vec<String> = isbns.iter().map(|isbn| isbn.create_isbn_13()).collect();
+        let books = get_books(&self.client, &isbns).await?;
+        Ok(books
+            .into_iter()
+            .zip(&isbns)
+            .map(|(book, isbn_13)| book.map(|book| book.into_record(isbn_13)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE_JSON: &str = r#"{
+  "ISBN:9780306406157": {
+    "url": "https://openlibrary.org/books/OL2386518M/Polymer_chemistry",
+    "title": "Polymer chemistry",
+    "subtitle": "an introduction",
+    "authors": [{ "url": "https://openlibrary.org/authors/OL1A", "name": "Malcolm P. Stevens" }],
+    "publishers": [{ "name": "Plenum Press" }],
+    "publish_date": "1975",
+    "number_of_pages": 452,
+    "cover": {
+      "small": "https://covers.openlibrary.org/b/id/1-S.jpg",
+      "medium": "https://covers.openlibrary.org/b/id/1-M.jpg",
+      "large": "https://covers.openlibrary.org/b/id/1-L.jpg"
+    }
+  }
+}"#;
+
+    #[test]
+    fn test_order_by_isbns() {
+        let entries: HashMap<String, Entry> = serde_json::from_str(RESPONSE_JSON).unwrap();
+        let isbns = vec![String::from("9784798171548"), String::from("9780306406157")];
+        let books = order_by_isbns(entries, &isbns);
+        assert!(books[0].is_none());
+        let book = books[1].as_ref().unwrap();
+        assert_eq!(book.title, "Polymer chemistry: an introduction");
+        assert_eq!(book.authors, vec![String::from("Malcolm P. Stevens")]);
+        assert_eq!(book.number_of_pages, Some(452));
+        assert_eq!(book.cover, "https://covers.openlibrary.org/b/id/1-L.jpg");
+    }
+
+    #[test]
+    fn test_into_record() {
+        let entries: HashMap<String, Entry> = serde_json::from_str(RESPONSE_JSON).unwrap();
+        let books = order_by_isbns(entries, &[String::from("9780306406157")]);
+        let record = books.into_iter().next().flatten().unwrap().into_record("9780306406157");
+        assert_eq!(record.isbn, "9780306406157");
+        assert_eq!(record.publisher.as_deref(), Some("Plenum Press"));
+        assert_eq!(record.pages, Some(452));
+    }
+}
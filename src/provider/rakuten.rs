@@ -0,0 +1,189 @@
+//! 楽天ブックス書籍検索API
+//! 利用にはアプリID(applicationId)が必要で、価格や在庫状況、アフィリエイトURLを返す
+
+use async_trait::async_trait;
+use isbn::Isbn;
+use serde::Deserialize;
+
+use super::retry::check_response;
+use super::{MetadataProvider, Result};
+use crate::record::{non_empty, BookRecord};
+
+const BOOKS_BOOK_ENDPOINT: &str = "https://app.rakuten.co.jp/services/api/BooksBook/Search/20170404";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SearchResponse {
+    #[serde(rename = "Items")]
+    items: Vec<ItemWrapper>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ItemWrapper {
+    #[serde(rename = "Item")]
+    item: Item,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Item {
+    title: String,
+    author: String,
+    publisher_name: String,
+    sales_date: String,
+    item_price: Option<u32>,
+    item_url: String,
+    affiliate_url: String,
+    availability: String,
+    large_image_url: String,
+}
+
+/// 楽天ブックスの書誌情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RakutenBook {
+    pub title: String,
+    pub author: String,
+    pub publisher: String,
+    pub sales_date: String,
+    /// 税込みの価格(円)
+    pub price: Option<u32>,
+    /// 商品ページのURL(アフィリエイトIDを指定した場合はアフィリエイトURL)
+    pub url: String,
+    pub availability: String,
+    /// 書影のURL(ない場合は空文字)
+    pub cover: String,
+}
+
+impl From<Item> for RakutenBook {
+    fn from(item: Item) -> Self {
+        let url = if item.affiliate_url.is_empty() { item.item_url } else { item.affiliate_url };
+        RakutenBook {
+            title: item.title,
+            author: item.author,
+            publisher: item.publisher_name,
+            sales_date: item.sales_date,
+            price: item.item_price,
+            url,
+            availability: availability_label(&item.availability).to_string(),
+            cover: item.large_image_url,
+        }
+    }
+}
+
+/// 在庫状況のコードを表示用の文字列にする
+fn availability_label(code: &str) -> &str {
+    match code {
+        "1" => "在庫あり",
+        "2" => "通常3～7日程度で発送",
+        "3" => "通常3～9日程度で発送",
+        "4" => "メーカー取り寄せ",
+        "5" => "予約受付中",
+        "6" => "メーカーに在庫確認",
+        _ => code,
+    }
+}
+
+impl RakutenBook {
+    /// ISBNを付けて共通の書誌情報にする
+    pub fn into_record(self, isbn_13: &str) -> BookRecord {
+        BookRecord {
+            isbn: isbn_13.to_string(),
+            title: self.title,
+            authors: non_empty(self.author).into_iter().collect(),
+            publisher: non_empty(self.publisher),
+            issued: non_empty(self.sales_date),
+            price: self.price,
+            availability: non_empty(self.availability),
+            cover_url: non_empty(self.cover),
+            link: non_empty(self.url),
+            source: "rakuten",
+            ..Default::default()
+        }
+    }
+}
+
+/// 楽天ブックス
+/// 楽天ウェブサービスのアプリIDとアフィリエイトIDを持つ
+#[derive(Debug, Clone)]
+pub struct RakutenBooks {
+    pub client: reqwest::Client,
+    pub application_id: String,
+    pub affiliate_id: Option<String>,
+}
+
+impl RakutenBooks {
+    /// ISBNの書誌情報を取得する
+    /// 楽天ブックスの書籍検索APIはISBNを1件ずつしか指定できない
+    pub async fn get_book(&self, isbn: &str) -> Result<Option<RakutenBook>> {
+        let mut query = vec![
+            ("format", "json"),
+            ("isbn", isbn),
+            ("applicationId", self.application_id.as_str()),
+        ];
+        if let Some(affiliate_id) = &self.affiliate_id {
+            query.push(("affiliateId", affiliate_id.as_str()));
+        }
+        let response = self.client.get(BOOKS_BOOK_ENDPOINT)
+            .query(&query)
+            .send()
+            .await?;
+        let response: SearchResponse = check_response(response)?.json().await?;
+        Ok(response.items.into_iter().next().map(|wrapper| RakutenBook::from(wrapper.item)))
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for RakutenBooks {
+    fn name(&self) -> &'static str {
+        "rakuten"
+    }
+
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+        let isbn_13 = isbn.create_isbn_13();
+        let book = self.get_book(&isbn_13).await?;
+        Ok(book.map(|book| book.into_record(&isbn_13)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE_JSON: &str = r#"{
+  "count": 1,
+  "page": 1,
+  "Items": [
+    {
+      "Item": {
+        "title": "吾輩は猫である",
+        "author": "夏目 漱石",
+        "publisherName": "新潮社",
+        "isbn": "9784101010014",
+        "salesDate": "2003年06月",
+        "itemPrice": 781,
+        "itemUrl": "https://books.rakuten.co.jp/rb/1/",
+        "affiliateUrl": "",
+        "availability": "1",
+        "largeImageUrl": "https://thumbnail.image.rakuten.co.jp/1.jpg"
+      }
+    }
+  ]
+}"#;
+
+    #[test]
+    fn test_parse_book() {
+        let response: SearchResponse = serde_json::from_str(RESPONSE_JSON).unwrap();
+        let book = RakutenBook::from(response.items.into_iter().next().unwrap().item);
+        assert_eq!(book.title, "吾輩は猫である");
+        assert_eq!(book.price, Some(781));
+        assert_eq!(book.availability, "在庫あり");
+        // アフィリエイトURLがない場合は商品ページのURL
+        assert_eq!(book.url, "https://books.rakuten.co.jp/rb/1/");
+    }
+
+    #[test]
+    fn test_parse_not_found() {
+        let response: SearchResponse = serde_json::from_str(r#"{"count": 0, "Items": []}"#).unwrap();
+        assert!(response.items.is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+//! 並行して問い合わせる場合のリクエストの間隔の制限
+
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// すべてのリクエストを通して、始める間隔をinterval以上空ける
+pub struct RateLimiter {
+    interval: Duration,
+    /// 次のリクエストを始めてよい時刻
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        RateLimiter { interval, next: Mutex::new(Instant::now()) }
+    }
+
+    /// 次のリクエストを始めてよい時刻まで待つ
+    /// 待っている間に呼ばれた場合は、順番にintervalずつ後ろの時刻を割り当てる
+    pub async fn wait(&self) {
+        let start = {
+            let mut next = self.next.lock().await;
+            let start = (*next).max(Instant::now());
+            *next = start + self.interval;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+
+    /// untilまでリクエストを始めない(APIにRetry-Afterで待つように言われた場合など)
+    /// すでにuntilより後まで待つことになっている場合は変えない
+    pub async fn pause_until(&self, until: Instant) {
+        let mut next = self.next.lock().await;
+        *next = (*next).max(until);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+
+    #[tokio::test]
+    async fn test_wait() {
+        let limiter = RateLimiter::new(Duration::from_millis(20));
+        let started = Instant::now();
+        // 同時に待っても1回ずつ間隔を空ける
+        join_all((0..4).map(|_| limiter.wait())).await;
+        assert!(started.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[tokio::test]
+    async fn test_pause_until() {
+        let limiter = RateLimiter::new(Duration::ZERO);
+        let started = Instant::now();
+        limiter.pause_until(started + Duration::from_millis(50)).await;
+        // 前の時刻にしても早くならない
+        limiter.pause_until(started).await;
+        join_all((0..2).map(|_| limiter.wait())).await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+}
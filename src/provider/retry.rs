@@ -0,0 +1,359 @@
+//! 失敗したリクエストの再試行
+
+use async_trait::async_trait;
+use isbn::Isbn;
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, warn, Instrument};
+
+use super::ratelimit::RateLimiter;
+use super::{MetadataProvider, Result};
+use crate::record::BookRecord;
+
+/// APIから429(Too Many Requests)や503(Service Unavailable)が返された
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    pub status: StatusCode,
+    /// Retry-Afterで指定された待ち時間
+    pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.retry_after {
+            Some(retry_after) => write!(f, "{} (retry after {}s)", self.status, retry_after.as_secs()),
+            None => write!(f, "{}", self.status),
+        }
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Retry-Afterの値を待ち時間にする
+/// 秒数とHTTP日付のどちらの形式でも指定できる
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    // 過去の日付は待たない
+    Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// レスポンスのステータスを確かめる
+/// 429と503はRetry-Afterを読んでRateLimitedにし、それ以外のエラーはreqwestのエラーにする
+pub fn check_response(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    debug!(url = %response.url(), %status, "received response");
+    if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now()));
+        return Err(Box::new(RateLimited { status, retry_after }));
+    }
+    Ok(response.error_for_status()?)
+}
+
+/// 再試行すれば成功するかもしれないエラーか
+/// 429・503、タイムアウト、接続の失敗、5xxだけを再試行し、4xxや応答の解析の失敗はすぐに返す
+fn is_transient(error: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    if error.is::<RateLimited>() {
+        return true;
+    }
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(e) => e.is_timeout() || e.is_connect() || e.status().is_some_and(|status| status.is_server_error()),
+        None => false,
+    }
+}
+
+/// 再試行の回数と待ち時間
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// 最初の試行に加えて再試行する回数
+    pub max_retries: u32,
+    /// 1回目の再試行までの待ち時間
+    pub base_delay: Duration,
+    /// 待ち時間の上限
+    pub max_delay: Duration,
+    /// Retry-Afterで待つ時間の上限
+    /// 壊れたAPIが長すぎる時間を返しても、すべてのリクエストを止め続けないようにする
+    pub max_retry_after: Duration,
+    /// すべてのリクエストで共有する、リクエストを始める間隔の制限
+    /// 最初の試行も再試行もこれを待ち、Retry-Afterが指定されたら同時に問い合わせているものもその時刻まで待たせる
+    pub limiter: Arc<RateLimiter>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_retry_after: Duration::from_secs(60),
+            limiter: Arc::new(RateLimiter::new(Duration::ZERO)),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// retry回目(0始まり)の再試行までの待ち時間
+    /// 待ち時間は再試行のたびに2倍になり、同時に再試行が集中しないように後半の半分をランダムにする
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let delay = self.base_delay.saturating_mul(2u32.saturating_pow(retry)).min(self.max_delay);
+        let half = delay / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+    }
+
+    /// 間隔の制限を待ってから試し、一時的なエラーで失敗した場合は待ってから再試行する
+    /// 再試行しても失敗した場合や、再試行しても成功しないエラーの場合は最後のエラーを返す
+    /// 1回ごとの試行をspanにし、かかった時間をログに出す
+    pub async fn retry<T, F, Fut>(&self, name: &str, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            self.limiter.wait().await;
+            let started = Instant::now();
+            let result = f().instrument(tracing::debug_span!("request", provider = name, attempt = retry + 1)).await;
+            debug!(provider = name, elapsed = ?started.elapsed(), ok = result.is_ok(), "request finished");
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            // Retry-Afterが指定されていれば、ほかのリクエストもその時刻まで待たせる
+            let retry_after = error
+                .downcast_ref::<RateLimited>()
+                .and_then(|rate_limited| rate_limited.retry_after)
+                .map(|retry_after| retry_after.min(self.max_retry_after));
+            if let Some(retry_after) = retry_after {
+                self.limiter.pause_until(tokio::time::Instant::now() + retry_after).await;
+            }
+            if retry >= self.max_retries || !is_transient(error.as_ref()) {
+                return Err(error);
+            }
+            match retry_after {
+                Some(retry_after) => warn!(provider = name, error = %error, delay = ?retry_after, "retrying request"),
+                None => {
+                    let delay = self.backoff(retry);
+                    warn!(provider = name, error = %error, ?delay, "retrying request");
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            retry += 1;
+        }
+    }
+}
+
+/// 失敗したリクエストを再試行するAPI
+pub struct Retrying {
+    pub inner: Box<dyn MetadataProvider>,
+    pub policy: RetryPolicy,
+}
+
+#[async_trait]
+impl MetadataProvider for Retrying {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+        self.policy.retry(self.inner.name(), || self.inner.lookup(isbn)).await
+    }
+
+    fn batch_size(&self) -> usize {
+        self.inner.batch_size()
+    }
+
+    async fn lookup_batch(&self, isbns: &[Isbn]) -> Result<Vec<Option<BookRecord>>> {
+        self.policy.retry(self.inner.name(), || self.inner.lookup_batch(isbns)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            let first = policy.backoff(0);
+            assert!(Duration::from_millis(50) <= first && first <= Duration::from_millis(100));
+            let third = policy.backoff(2);
+            assert!(Duration::from_millis(200) <= third && third <= Duration::from_millis(400));
+            // 上限を超えない
+            assert!(policy.backoff(9) <= Duration::from_millis(1000));
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now), Some(Duration::from_secs(90)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after() {
+        // バックオフより長くても、Retry-Afterの時間だけ待つ
+        let policy = RetryPolicy {
+            max_retries: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let calls = AtomicU32::new(0);
+        let started = tokio::time::Instant::now();
+        let result = policy
+            .retry("test", || async {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    let rate_limited = RateLimited {
+                        status: StatusCode::TOO_MANY_REQUESTS,
+                        retry_after: Some(Duration::from_millis(50)),
+                    };
+                    Err(rate_limited.into())
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_limit() {
+        // 秒数が大きすぎるRetry-Afterでも溢れず、上限までしか待たない
+        let retry_after = parse_retry_after("18446744073709551615", SystemTime::now());
+        assert_eq!(retry_after, Some(Duration::from_secs(u64::MAX)));
+        let policy = RetryPolicy {
+            max_retries: 1,
+            max_retry_after: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let calls = AtomicU32::new(0);
+        let started = tokio::time::Instant::now();
+        let result = policy
+            .retry("test", || async {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(RateLimited { status: StatusCode::TOO_MANY_REQUESTS, retry_after }.into())
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        let elapsed = started.elapsed();
+        assert!(Duration::from_millis(50) <= elapsed && elapsed < Duration::from_secs(5));
+        // ほかのリクエストも上限までしか待たない
+        let started = tokio::time::Instant::now();
+        policy.clone().retry("other", || async { Ok(()) }).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_pauses_others() {
+        // Retry-Afterを受け取ったら、同じ制限を使うほかのリクエストも待たせる
+        let policy = RetryPolicy { max_retries: 0, ..Default::default() };
+        let started = tokio::time::Instant::now();
+        let result: Result<()> = policy
+            .retry("test", || async {
+                let rate_limited = RateLimited {
+                    status: StatusCode::SERVICE_UNAVAILABLE,
+                    retry_after: Some(Duration::from_millis(50)),
+                };
+                Err(rate_limited.into())
+            })
+            .await;
+        assert!(result.is_err());
+        let other = policy.clone();
+        other.retry("other", || async { Ok(()) }).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let unavailable = || RateLimited { status: StatusCode::SERVICE_UNAVAILABLE, retry_after: None };
+        // 2回失敗しても3回目で成功する
+        let calls = AtomicU32::new(0);
+        let result = policy
+            .retry("test", || async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(unavailable().into())
+                } else {
+                    Ok(1)
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+        // 再試行の回数を超えて失敗した場合はエラーにする
+        let calls = AtomicU32::new(0);
+        let result: Result<()> = policy
+            .retry("test", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(unavailable().into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_permanent() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        // 404は再試行しない
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let app = axum::Router::new().fallback(|| async { axum::http::StatusCode::NOT_FOUND });
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let client = reqwest::Client::new();
+        let calls = AtomicU32::new(0);
+        let result: Result<()> = policy
+            .retry("test", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                check_response(client.get(&url).send().await?).map(|_| ())
+            })
+            .await;
+        assert_eq!(result.unwrap_err().downcast_ref::<reqwest::Error>().unwrap().status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // 応答の解析の失敗も再試行しない
+        let calls = AtomicU32::new(0);
+        let result: Result<serde_json::Value> = policy
+            .retry("test", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(serde_json::from_str("{")?)
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,379 @@
+//! 国立国会図書館サーチのSRU API
+//! CQLでタイトルや著者、分類、出版年の範囲を指定して検索できる
+
+use async_trait::async_trait;
+use clap::ValueEnum;
+use isbn::{to_isbn13, Isbn};
+use serde::Deserialize;
+use tracing::{debug, trace};
+
+use super::dcndl::dcndl_record;
+use super::marcxml::marc_record;
+use super::ndl::{dc_record, DcField};
+use super::retry::check_response;
+use super::xml::Element;
+use super::{MetadataProvider, Result};
+use crate::record::BookRecord;
+
+const SRU_ENDPOINT: &str = "https://ndlsearch.ndl.go.jp/api/sru";
+
+/// 1回のリクエストで取得できる最大件数
+pub const MAX_RECORDS: usize = 500;
+
+/// 取得する書誌の形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RecordSchema {
+    /// DC-NDL(簡略版)
+    #[default]
+    DcndlSimple,
+    /// DC-NDL(RDF/XML)、件名や国立国会図書館分類、形態を含む
+    Dcndl,
+    /// MARC21のMARCXML
+    Marcxml,
+}
+
+impl RecordSchema {
+    /// recordSchemaに指定する名前
+    fn name(self) -> &'static str {
+        match self {
+            RecordSchema::DcndlSimple => "dcndl_simple",
+            RecordSchema::Dcndl => "dcndl",
+            RecordSchema::Marcxml => "marcxml",
+        }
+    }
+}
+
+/// CQLの検索条件
+/// 指定した条件はすべてANDでつなぐ
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CqlQuery {
+    /// タイトルや著者、件名などのいずれかに含まれる語
+    pub anywhere: Option<String>,
+    pub title: Option<String>,
+    pub creator: Option<String>,
+    pub publisher: Option<String>,
+    /// 日本十進分類法の分類記号(前方一致)
+    pub ndc: Option<String>,
+    /// 出版年の範囲の始まり(YYYY, YYYY-MM, YYYY-MM-DD)
+    pub from: Option<String>,
+    /// 出版年の範囲の終わり
+    pub until: Option<String>,
+    pub isbn: Option<String>,
+}
+
+impl CqlQuery {
+    /// CQLの文字列にする
+    pub fn to_cql(&self) -> String {
+        [
+            ("anywhere", &self.anywhere),
+            ("title", &self.title),
+            ("creator", &self.creator),
+            ("publisher", &self.publisher),
+            ("ndc", &self.ndc),
+            ("from", &self.from),
+            ("until", &self.until),
+            ("isbn", &self.isbn),
+        ]
+        .iter()
+        .filter_map(|(index, value)| {
+            let value = value.as_ref()?;
+            // 値の中の \ と " はエスケープする
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+            Some(format!("{}=\"{}\"", index, escaped))
+        })
+        .collect::<Vec<_>>()
+        .join(" AND ")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_cql().is_empty()
+    }
+}
+
+/// searchRetrieveのレスポンス
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchRetrieveResponse {
+    #[serde(default)]
+    number_of_records: usize,
+    next_record_position: Option<usize>,
+    #[serde(default)]
+    records: Records,
+    #[serde(default)]
+    diagnostics: Option<Diagnostics>,
+    /// dcndl形式とMARCXML形式の書誌(dcndl_simple形式の場合はNone)
+    #[serde(skip)]
+    rich_records: Option<Vec<BookRecord>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Records {
+    #[serde(rename = "record", default)]
+    records: Vec<Record>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    record_data: RecordData,
+}
+
+/// dcndl形式とMARCXML形式の場合はdcがない
+#[derive(Debug, Deserialize)]
+struct RecordData {
+    #[serde(default)]
+    dc: Option<Dc>,
+}
+
+/// dcndl_simple形式の書誌
+#[derive(Debug, Deserialize)]
+struct Dc {
+    #[serde(rename = "$value", default)]
+    fields: Vec<DcField>,
+}
+
+/// 不正なCQLなどのエラー
+#[derive(Debug, Default, Deserialize)]
+struct Diagnostics {
+    #[serde(default)]
+    diagnostic: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Diagnostic {
+    message: String,
+    details: String,
+}
+
+impl SearchRetrieveResponse {
+    /// searchRetrieveのレスポンスを解析する
+    /// SRUのエラー(diagnostics)が含まれている場合はエラーにする
+    pub fn parse(response_xml: &str) -> Result<SearchRetrieveResponse> {
+        let response: SearchRetrieveResponse = quick_xml::de::from_str(response_xml)?;
+        if let Some(diagnostic) = response.diagnostics.as_ref().and_then(|diagnostics| diagnostics.diagnostic.first()) {
+            return Err(format!("{}: {}", diagnostic.message.trim(), diagnostic.details.trim()).into());
+        }
+        Ok(response)
+    }
+
+    /// 書誌の形式を指定して、searchRetrieveのレスポンスを解析する
+    /// dcndl形式とMARCXML形式の書誌は、recordDataの中の要素を順番に読み込む
+    pub fn parse_schema(response_xml: &str, schema: RecordSchema) -> Result<SearchRetrieveResponse> {
+        let mut response = Self::parse(response_xml)?;
+        if schema != RecordSchema::DcndlSimple {
+            let root = Element::parse(response_xml)?;
+            let records = root
+                .descendants("recordData")
+                .into_iter()
+                .filter_map(|data| data.children.first())
+                .filter_map(|data| match schema {
+                    RecordSchema::Marcxml => marc_record(data),
+                    _ => dcndl_record(data),
+                })
+                .collect();
+            response.rich_records = Some(records);
+        }
+        Ok(response)
+    }
+
+    /// 検索条件に一致した件数
+    pub fn number_of_records(&self) -> usize {
+        self.number_of_records
+    }
+
+    /// 続きを取得するときの開始位置(続きがない場合はNone)
+    pub fn next_record_position(&self) -> Option<usize> {
+        self.next_record_position.filter(|position| *position > 0)
+    }
+
+    /// 書誌情報の一覧
+    /// ISBNのない書誌は含めない
+    pub fn records(&self) -> Vec<BookRecord> {
+        if let Some(records) = &self.rich_records {
+            return records.clone();
+        }
+        self.records
+            .records
+            .iter()
+            .filter_map(|record| {
+                let fields = &record.record_data.dc.as_ref()?.fields;
+                let isbn_13 = fields.iter().find_map(|field| match field {
+                    DcField::Identifier(identifier) if identifier.is("dcndl:ISBN") => normalize_isbn(&identifier.value),
+                    _ => None,
+                })?;
+                Some(BookRecord { source: "ndlsru", ..dc_record(fields, &isbn_13) })
+            })
+            .collect()
+    }
+}
+
+/// ハイフン付きのISBN10やISBN13をハイフンなしのISBN13にする
+pub(super) fn normalize_isbn(identifier: &str) -> Option<String> {
+    let digits: String = identifier.chars().filter(|c| *c != '-' && *c != ' ').collect();
+    match digits.len() {
+        10 => to_isbn13(&digits).ok(),
+        13 => Some(digits),
+        _ => None,
+    }
+}
+
+/// 国立国会図書館サーチのSRU
+pub struct NdlSru {
+    pub client: reqwest::Client,
+    pub schema: RecordSchema,
+}
+
+impl NdlSru {
+    /// CQLで検索する
+    /// start_recordは1始まりで、maximum_recordsは最大500件
+    pub async fn search(&self, cql: &str, start_record: usize, maximum_records: usize) -> Result<SearchRetrieveResponse> {
+        let response = self.client.get(SRU_ENDPOINT)
+            .query(&[
+                ("operation", "searchRetrieve"),
+                ("version", "1.2"),
+                ("recordSchema", self.schema.name()),
+                ("recordPacking", "xml"),
+                ("query", cql),
+                ("startRecord", &start_record.to_string()),
+                ("maximumRecords", &maximum_records.min(MAX_RECORDS).to_string()),
+            ])
+            .send()
+            .await?;
+        let response_xml = check_response(response)?.text().await?;
+        trace!(%response_xml, "searchRetrieve response");
+        let response = SearchRetrieveResponse::parse_schema(&response_xml, self.schema)?;
+        debug!(cql, number_of_records = response.number_of_records(), "parsed searchRetrieve response");
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for NdlSru {
+    fn name(&self) -> &'static str {
+        "ndlsru"
+    }
+
+    async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+        let query = CqlQuery { isbn: Some(isbn.create_isbn_13()), ..Default::default() };
+        let response = self.search(&query.to_cql(), 1, 1).await?;
+        Ok(response.records().into_iter().next().map(|record| BookRecord { isbn: isbn.create_isbn_13(), ..record }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<searchRetrieveResponse xmlns="http://www.loc.gov/zing/srw/">
+  <version>1.2</version>
+  <numberOfRecords>2</numberOfRecords>
+  <nextRecordPosition>0</nextRecordPosition>
+  <records>
+    <record>
+      <recordSchema>info:ndl/dcndl_simple</recordSchema>
+      <recordPacking>xml</recordPacking>
+      <recordData>
+        <dcndl_simple:dc xmlns:dcndl_simple="http://ndl.go.jp/dcndl/dcndl_simple/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dcndl="http://ndl.go.jp/dcndl/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+          <dc:title>吾輩は猫である</dc:title>
+          <dc:creator>夏目漱石 著</dc:creator>
+          <dcndl:seriesTitle>新潮文庫</dcndl:seriesTitle>
+          <dc:publisher>新潮社</dc:publisher>
+          <dcterms:issued xsi:type="dcterms:W3CDTF">2003</dcterms:issued>
+          <dc:identifier xsi:type="dcndl:ISBN">4-10-101001-3</dc:identifier>
+          <dc:identifier xsi:type="dcndl:JPNO">20000001</dc:identifier>
+          <dc:subject xsi:type="dcndl:NDC9">913.6</dc:subject>
+        </dcndl_simple:dc>
+      </recordData>
+      <recordPosition>1</recordPosition>
+    </record>
+    <record>
+      <recordSchema>info:ndl/dcndl_simple</recordSchema>
+      <recordPacking>xml</recordPacking>
+      <recordData>
+        <dcndl_simple:dc xmlns:dcndl_simple="http://ndl.go.jp/dcndl/dcndl_simple/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+          <dc:title>ISBNのない資料</dc:title>
+        </dcndl_simple:dc>
+      </recordData>
+      <recordPosition>2</recordPosition>
+    </record>
+  </records>
+</searchRetrieveResponse>"#;
+
+    #[test]
+    fn test_to_cql() {
+        let query = CqlQuery {
+            title: Some(String::from("吾輩は\"猫\"")),
+            ndc: Some(String::from("913")),
+            from: Some(String::from("2000")),
+            until: Some(String::from("2010")),
+            ..Default::default()
+        };
+        assert_eq!(query.to_cql(), r#"title="吾輩は\"猫\"" AND ndc="913" AND from="2000" AND until="2010""#);
+        assert!(CqlQuery::default().is_empty());
+        let query = CqlQuery { anywhere: Some(String::from("宇宙")), ..Default::default() };
+        assert_eq!(query.to_cql(), r#"anywhere="宇宙""#);
+    }
+
+    #[test]
+    fn test_records() {
+        let response = SearchRetrieveResponse::parse(RESPONSE_XML).unwrap();
+        assert_eq!(response.number_of_records(), 2);
+        assert_eq!(response.next_record_position(), None);
+        let records = response.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].isbn, "9784101010014");
+        assert_eq!(records[0].title, "吾輩は猫である");
+        assert_eq!(records[0].ndc.as_deref(), Some("913.6"));
+        assert_eq!(records[0].source, "ndlsru");
+    }
+
+    #[test]
+    fn test_parse_schema() {
+        let response_xml = r#"<searchRetrieveResponse xmlns="http://www.loc.gov/zing/srw/">
+  <version>1.2</version>
+  <numberOfRecords>1</numberOfRecords>
+  <records>
+    <record>
+      <recordSchema>info:srw/schema/1/marcxml-v1.1</recordSchema>
+      <recordPacking>xml</recordPacking>
+      <recordData>
+        <marc:record xmlns:marc="http://www.loc.gov/MARC21/slim">
+          <marc:datafield tag="020"><marc:subfield code="a">9784101010014</marc:subfield></marc:datafield>
+          <marc:datafield tag="245"><marc:subfield code="a">吾輩は猫である /</marc:subfield></marc:datafield>
+          <marc:datafield tag="650"><marc:subfield code="a">小説 (日本)</marc:subfield></marc:datafield>
+        </marc:record>
+      </recordData>
+      <recordPosition>1</recordPosition>
+    </record>
+  </records>
+</searchRetrieveResponse>"#;
+        let response = SearchRetrieveResponse::parse_schema(response_xml, RecordSchema::Marcxml).unwrap();
+        assert_eq!(response.number_of_records(), 1);
+        let records = response.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].title, "吾輩は猫である");
+        assert_eq!(records[0].subjects, vec![String::from("小説 (日本)")]);
+        // dcndl_simple形式として読むと、dcがないので書誌はない
+        assert!(SearchRetrieveResponse::parse(response_xml).unwrap().records().is_empty());
+        let response = SearchRetrieveResponse::parse_schema(RESPONSE_XML, RecordSchema::DcndlSimple).unwrap();
+        assert_eq!(response.records().len(), 1);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let response_xml = r#"<searchRetrieveResponse xmlns="http://www.loc.gov/zing/srw/">
+  <version>1.2</version>
+  <numberOfRecords>0</numberOfRecords>
+  <diagnostics>
+    <diagnostic xmlns="http://www.loc.gov/zing/srw/diagnostic/">
+      <uri>info:srw/diagnostic/1/10</uri>
+      <message>Query syntax error</message>
+      <details>title=</details>
+    </diagnostic>
+  </diagnostics>
+</searchRetrieveResponse>"#;
+        assert!(SearchRetrieveResponse::parse(response_xml).is_err());
+    }
+}
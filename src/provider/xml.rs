@@ -0,0 +1,122 @@
+//! 名前空間を区別せずローカル名で読み込むXMLの要素の木
+//! dcndl(RDF/XML)やMARCXMLのように、同じ名前の要素が入れ子になったり離れて現れたりする形式を読むのに使う
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::Result;
+
+/// XMLの要素
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(super) struct Element {
+    /// 名前空間の接頭辞を除いた名前
+    pub(super) name: String,
+    attributes: Vec<(String, String)>,
+    pub(super) children: Vec<Element>,
+    /// 子要素を除いた、要素の直下の文字列
+    text: String,
+}
+
+impl Element {
+    /// XMLの文書を読み込み、最上位の要素を返す
+    pub(super) fn parse(xml: &str) -> Result<Element> {
+        let mut reader = Reader::from_str(xml);
+        // 開いている要素(最初の要素は最上位の要素を受け取るための仮のもの)
+        let mut stack = vec![Element::default()];
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => stack.push(Element::start(&start)?),
+                Event::Empty(start) => {
+                    let element = Element::start(&start)?;
+                    stack.last_mut().ok_or("unbalanced xml")?.children.push(element);
+                }
+                Event::End(_) => {
+                    let element = stack.pop().ok_or("unbalanced xml")?;
+                    stack.last_mut().ok_or("unbalanced xml")?.children.push(element);
+                }
+                Event::Text(text) => {
+                    let text = text.unescape()?;
+                    stack.last_mut().ok_or("unbalanced xml")?.text.push_str(&text);
+                }
+                Event::CData(data) => {
+                    let text = String::from_utf8_lossy(&data).into_owned();
+                    stack.last_mut().ok_or("unbalanced xml")?.text.push_str(&text);
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        let mut document = stack.pop().filter(|_| stack.is_empty()).ok_or("unclosed xml element")?;
+        document.children.pop().ok_or_else(|| "empty xml document".into())
+    }
+
+    /// 開始タグから、子要素のない要素を作る
+    fn start(start: &BytesStart) -> Result<Element> {
+        let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+        let mut attributes = Vec::new();
+        for attribute in start.attributes() {
+            let attribute = attribute?;
+            let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+            attributes.push((key, attribute.unescape_value()?.into_owned()));
+        }
+        Ok(Element { name, attributes, ..Default::default() })
+    }
+
+    /// ローカル名で指定した属性の値
+    pub(super) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// 指定した名前の最初の子要素
+    pub(super) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// 子孫の要素のうち、指定した名前の要素(文書の順)
+    /// 見つかった要素の中はそれ以上探さない
+    pub(super) fn descendants<'a>(&'a self, name: &str) -> Vec<&'a Element> {
+        let mut found = Vec::new();
+        for child in &self.children {
+            if child.name == name {
+                found.push(child);
+            } else {
+                found.extend(child.descendants(name));
+            }
+        }
+        found
+    }
+
+    /// 要素の中の文字列(子要素の中の文字列も含め、前後の空白は取り除く)
+    pub(super) fn text(&self) -> String {
+        let mut text = self.text.clone();
+        for child in &self.children {
+            text.push_str(&child.text());
+        }
+        text.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let xml = r#"<?xml version="1.0"?>
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <dc:title>吾輩は &amp; 猫</dc:title>
+  <dc:creator><rdf:Description rdf:about="x"><rdf:value> 夏目漱石 </rdf:value></rdf:Description></dc:creator>
+  <dc:subject rdf:resource="http://id.ndl.go.jp/class/ndc9/913.6"/>
+</rdf:RDF>"#;
+        let root = Element::parse(xml).unwrap();
+        assert_eq!(root.name, "RDF");
+        assert_eq!(root.children.len(), 3);
+        assert_eq!(root.child("title").unwrap().text(), "吾輩は & 猫");
+        assert_eq!(root.child("creator").unwrap().text(), "夏目漱石");
+        assert_eq!(root.descendants("Description")[0].attribute("about"), Some("x"));
+        assert_eq!(root.child("subject").unwrap().attribute("resource"), Some("http://id.ndl.go.jp/class/ndc9/913.6"));
+        assert!(Element::parse("<a><b></a>").is_err());
+        assert!(Element::parse("<a><b>").is_err());
+        assert!(Element::parse("").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::OnceLock;
+
+use crate::error::IsbnError;
+use crate::trie::PrefixTrie;
+
+// build.rsが埋め込みの出版社CSVから作る PUBLISHERS
+include!(concat!(env!("OUT_DIR"), "/publishers.rs"));
+
+/// 出版社記号と出版社名
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Publisher {
+    pub code: String,
+    pub name: String,
+}
+
+/// 出版社記号の一覧
+#[derive(Debug, Clone, Default)]
+pub struct PublisherRegistry {
+    publishers: Vec<Publisher>,
+    /// 埋め込みの出版社CSVから作ったものか(出版社記号はビルド時に作った表で引く)
+    embedded: bool,
+    /// 出版社記号からpublishersの位置を引く表(埋め込みでない場合に、初めて使うときに作る)
+    index: OnceLock<HashMap<String, usize>>,
+    /// 出版社記号の木(初めて使うときに作る)
+    trie: OnceLock<PrefixTrie>,
+}
+
+impl PublisherRegistry {
+    /// 出版社の一覧から作る
+    pub fn new(publishers: Vec<Publisher>) -> Self {
+        PublisherRegistry { publishers, embedded: false, index: OnceLock::new(), trie: OnceLock::new() }
+    }
+
+    /// 埋め込みの出版社CSV(日本の出版社)から作る
+    /// CSVはビルド時に表にしてあるので、実行時には解析しない
+    /// 一度だけ作り、以降は同じものを返す
+    pub fn embedded() -> &'static PublisherRegistry {
+        static REGISTRY: OnceLock<PublisherRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            let publishers = PUBLISHERS
+                .entries()
+                .map(|(code, name)| Publisher { code: code.to_string(), name: name.to_string() })
+                .collect();
+            PublisherRegistry { embedded: true, ..PublisherRegistry::new(publishers) }
+        })
+    }
+
+    /// 埋め込みのものと同じ形式(ヘッダー行のない「出版社記号,出版社名」)のCSVから作る
+    pub fn from_reader(reader: impl Read) -> Result<Self, IsbnError> {
+        Ok(PublisherRegistry::new(parse_csv(reader)?))
+    }
+
+    /// 出版社記号が一致する出版社を探す
+    /// 同じ出版社記号が複数ある場合は最初のものを返す
+    pub fn find_by_code(&self, code: &str) -> Option<&Publisher> {
+        let index = if self.embedded {
+            PUBLISHERS.get_index(code)
+        } else {
+            let index = self.index.get_or_init(|| {
+                let mut index = HashMap::new();
+                for (i, publisher) in self.publishers.iter().enumerate() {
+                    index.entry(publisher.code.clone()).or_insert(i);
+                }
+                index
+            });
+            index.get(code).copied()
+        };
+        self.publishers.get(index?)
+    }
+
+    /// 国記号に続く数字列から、先頭が出版社記号に一致する出版社を探す
+    /// 一致する出版社記号が複数ある場合は最も長いものを選ぶ
+    pub fn find_by_prefix(&self, digits: &str) -> Option<&Publisher> {
+        let trie = self.trie.get_or_init(|| PrefixTrie::new(self.publishers.iter().map(|publisher| publisher.code.as_str())));
+        let (index, _) = trie.longest_prefix(digits)?;
+        self.publishers.get(index)
+    }
+
+    /// 出版社名に指定した文字列を含む出版社を探す
+    pub fn find_by_name(&self, name: &str) -> Vec<&Publisher> {
+        self.publishers.iter().filter(|publisher| publisher.name.contains(name)).collect()
+    }
+
+    /// ランダムに出版社を1つ選ぶ
+    pub fn random(&self) -> Option<&Publisher> {
+        self.random_with_rng(&mut rand::thread_rng())
+    }
+
+    /// 渡した乱数生成器で出版社を1つ選ぶ
+    pub fn random_with_rng(&self, rng: &mut impl Rng) -> Option<&Publisher> {
+        if self.publishers.is_empty() {
+            return None;
+        }
+        Some(&self.publishers[rng.gen_range(0..self.publishers.len())])
+    }
+
+    /// 出版社ごとの重みに比例した確率で出版社を1つ選ぶ
+    /// 重みがすべて0の場合や、負の値や無限大を含む場合はNoneを返す
+    pub fn random_weighted_with_rng(&self, rng: &mut impl Rng, weight: impl Fn(&Publisher) -> f64) -> Option<&Publisher> {
+        let distribution = WeightedIndex::new(self.publishers.iter().map(weight)).ok()?;
+        Some(&self.publishers[distribution.sample(rng)])
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Publisher> {
+        self.publishers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.publishers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.publishers.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a PublisherRegistry {
+    type Item = &'a Publisher;
+    type IntoIter = std::slice::Iter<'a, Publisher>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+fn parse_csv(reader: impl Read) -> Result<Vec<Publisher>, IsbnError> {
+    let mut publisher_list = Vec::new();
+    // CSVにはヘッダー行がない
+    let mut rdr = csv::ReaderBuilder::new().has_headers(false).from_reader(reader);
+    for result in rdr.records() {
+        let record = result?.deserialize(None)?;
+        publisher_list.push(record);
+    }
+    Ok(publisher_list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_by_code() {
+        let registry = PublisherRegistry::embedded();
+        assert_eq!(registry.find_by_code("10").unwrap().name, "新潮社");
+        assert!(registry.find_by_code("99999").is_none());
+    }
+
+    #[test]
+    fn test_find_by_prefix() {
+        let registry = PublisherRegistry::embedded();
+        // 4-10-109205-2
+        assert_eq!(registry.find_by_prefix("101092052").unwrap().name, "新潮社");
+        assert_eq!(registry.find_by_prefix("254").unwrap().code, "254");
+        assert!(PublisherRegistry::default().find_by_prefix("101092052").is_none());
+    }
+
+    #[test]
+    fn test_find_by_name() {
+        let registry = PublisherRegistry::embedded();
+        let codes: Vec<&str> = registry.find_by_name("朝倉書店").iter().map(|publisher| publisher.code.as_str()).collect();
+        assert_eq!(codes, vec!["254", "256"]);
+        assert!(registry.find_by_name("存在しない出版社").is_empty());
+    }
+
+    #[test]
+    fn test_embedded() {
+        // 実行時に解析した場合と同じ内容になり、ビルド時の表で引いても同じ出版社になる
+        let parsed = PublisherRegistry::new(parse_csv(include_str!("../csv/isbn.csv").as_bytes()).unwrap());
+        let embedded = PublisherRegistry::embedded();
+        assert_eq!(embedded.iter().collect::<Vec<&Publisher>>(), parsed.iter().collect::<Vec<&Publisher>>());
+        for publisher in &parsed {
+            assert_eq!(embedded.find_by_code(&publisher.code), Some(publisher));
+        }
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let registry = PublisherRegistry::from_reader("10,新潮社\n7981,翔泳社\n".as_bytes()).unwrap();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.find_by_code("7981").unwrap().name, "翔泳社");
+        assert!(registry.find_by_code("798").is_none());
+        // 同じ出版社記号が複数ある場合は最初のもの
+        let registry = PublisherRegistry::from_reader("10,新潮社\n10,新潮社(重複)\n".as_bytes()).unwrap();
+        assert_eq!(registry.find_by_code("10").unwrap().name, "新潮社");
+        assert!(PublisherRegistry::from_reader("10\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_random_weighted_with_rng() {
+        let registry = PublisherRegistry::new(vec![
+            Publisher { code: String::from("10"), name: String::from("新潮社") },
+            Publisher { code: String::from("7981"), name: String::from("翔泳社") },
+        ]);
+        let mut rng = rand::thread_rng();
+        // 重みが0の出版社は選ばない
+        for _ in 0..100 {
+            let publisher = registry.random_weighted_with_rng(&mut rng, |publisher| {
+                if publisher.code == "10" {
+                    1.0
+                } else {
+                    0.0
+                }
+            });
+            assert_eq!(publisher.unwrap().code, "10");
+        }
+        assert!(registry.random_weighted_with_rng(&mut rng, |_| 0.0).is_none());
+        assert!(PublisherRegistry::default().random_weighted_with_rng(&mut rng, |_| 1.0).is_none());
+    }
+
+    #[test]
+    fn test_iter() {
+        let registry = PublisherRegistry::embedded();
+        assert_eq!(registry.iter().count(), registry.len());
+        // ヘッダー行として読み飛ばさない
+        assert_eq!(registry.iter().next().unwrap().name, "岩波書店");
+        assert!(registry.random().is_some());
+    }
+}
@@ -0,0 +1,247 @@
+//! 更新した出版社一覧(~/.local/share/isbn/publishers.csv)
+//! `isbn publishers update` で取得したもので、ファイルがあれば埋め込みの出版社CSVより優先する
+//! 取得元は既定ではWikidataのISBN出版者記号(P3035)で、--urlで「出版社記号,出版社名」のCSVかTSVも指定できる
+//! --publisher-fileで指定したファイルはさらに優先する
+
+use isbn::{Isbn, IsbnError, Publisher, PublisherRegistry};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, warn};
+
+use crate::provider::retry::check_response;
+use crate::provider::Result;
+
+/// 既定の出版社一覧の場所
+/// 履歴ファイルと同じディレクトリに置く
+pub fn default_path() -> Option<PathBuf> {
+    let data_home = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("share"),
+    };
+    Some(data_home.join("isbn").join("publishers.csv"))
+}
+
+/// 出版社一覧の既定の取得元(WikidataのSPARQLエンドポイント)
+const WIKIDATA_ENDPOINT: &str = "https://query.wikidata.org/sparql";
+
+/// WikidataのISBN出版者記号(P3035)のうち日本(978-4-)のものと、出版社の名前
+/// 結果のCSVは「prefix,publisherLabel」の見出しの行と「978-4-10,新潮社」のような行になる
+const WIKIDATA_QUERY: &str = r#"SELECT ?prefix ?publisherLabel WHERE {
+  ?publisher wdt:P3035 ?prefix .
+  FILTER(STRSTARTS(?prefix, "978-4-"))
+  SERVICE wikibase:label { bd:serviceParam wikibase:language "ja,en". }
+}"#;
+
+static REGISTRY: OnceLock<Option<PublisherRegistry>> = OnceLock::new();
+
+/// ランダムに出版社を選ぶときなどに使う出版社一覧
+/// 更新した出版社一覧があればそれを使い、なければ(読み込めない場合も)埋め込みのものを使う
+pub fn registry() -> &'static PublisherRegistry {
+    REGISTRY
+        .get_or_init(|| default_path().and_then(|path| load(&path)))
+        .as_ref()
+        .unwrap_or_else(PublisherRegistry::embedded)
+}
+
+/// ISBNの出版社名(日本の出版社だけ)
+pub fn publisher_name(isbn: &Isbn) -> Option<&'static str> {
+    if isbn.head_code() != "978" || isbn.country_code() != "4" {
+        return None;
+    }
+    registry().find_by_code(isbn.publisher_code()).map(|publisher| publisher.name.as_str())
+}
+
+/// 指定したファイルの出版社一覧を使う
+/// registryより先に呼ぶ必要がある
+pub fn use_file(path: &Path) -> std::result::Result<(), IsbnError> {
+    let file = fs::File::open(path).map_err(|e| IsbnError::Other(e.into()))?;
+    let registry = PublisherRegistry::from_reader(file)?;
+    debug!(path = %path.display(), publishers = registry.len(), "loaded publisher file");
+    if REGISTRY.set(Some(registry)).is_err() {
+        warn!(path = %path.display(), "publisher list is already loaded");
+    }
+    Ok(())
+}
+
+fn load(path: &Path) -> Option<PublisherRegistry> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "cannot read publisher list");
+            return None;
+        }
+    };
+    match PublisherRegistry::from_reader(file) {
+        Ok(registry) => {
+            debug!(path = %path.display(), publishers = registry.len(), "loaded publisher list");
+            Some(registry)
+        }
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "cannot read publisher list");
+            None
+        }
+    }
+}
+
+/// 出版社記号の全角数字やハイフン、空白を取り除く
+/// 「978-4-10」のように接頭記号と国記号から書いたもの(Wikidataの形式)は、国記号の後だけにする
+/// 1桁から7桁の数字にならない場合(見出しの行など)はNoneを返す
+fn normalize_code(code: &str) -> Option<String> {
+    let code = code.strip_prefix("978-4-").unwrap_or(code);
+    let code: String = code
+        .chars()
+        .filter(|c| !matches!(c, '-' | '‐' | '－' | ' ' | '　'))
+        .map(|c| match c {
+            '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap_or(c),
+            _ => c,
+        })
+        .collect();
+    let valid = (1..=7).contains(&code.len()) && code.chars().all(|c| c.is_ascii_digit());
+    valid.then_some(code)
+}
+
+/// 取得した出版社一覧を、埋め込みのCSVと同じ「出版社記号,出版社名」の並びにする
+/// カンマ区切りとタブ区切りのどちらでも読み、3列目以降は無視する
+/// 名前がWikidataの項目のID(Q123など、日本語と英語の名前がない項目)の行は読み飛ばす
+/// 同じ出版社記号が複数ある場合は最初のものを使い、ISBNの順に並べる
+pub fn normalize(text: &str) -> Vec<Publisher> {
+    let delimiter = match text.lines().find(|line| !line.trim().is_empty()) {
+        Some(line) if line.contains('\t') => b'\t',
+        _ => b',',
+    };
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let mut codes = HashSet::new();
+    let mut publishers: Vec<Publisher> = rdr
+        .records()
+        .filter_map(|record| record.ok())
+        .filter_map(|record| {
+            let code = normalize_code(record.get(0)?)?;
+            let name = record.get(1).filter(|name| !name.is_empty() && !is_item_id(name))?;
+            Some(Publisher { code, name: name.to_string() })
+        })
+        .filter(|publisher| codes.insert(publisher.code.clone()))
+        .collect();
+    publishers.sort_by(|a, b| a.code.cmp(&b.code));
+    publishers
+}
+
+/// Wikidataの項目のIDか
+fn is_item_id(name: &str) -> bool {
+    name.strip_prefix('Q').is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// 出版社一覧をヘッダー行のないCSVで書き出す
+/// 書き込み途中で中断しても壊れないように、一時ファイルに書いてから置き換える
+fn save(publishers: &[Publisher], path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("csv.tmp");
+    let mut wtr = csv::WriterBuilder::new().has_headers(false).from_path(&tmp)?;
+    for publisher in publishers {
+        wtr.serialize(publisher)?;
+    }
+    wtr.flush()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// 出版社一覧を取得して、pathに書き出す
+/// urlを省略した場合はWikidataから取得し、Wikidataにない出版社記号は埋め込みの出版社CSVのものを残す
+/// 書き出した出版社の数を返す
+pub async fn update(client: &reqwest::Client, url: Option<&str>, path: &Path) -> Result<usize> {
+    let publishers = match url {
+        Some(url) => {
+            let response = check_response(client.get(url).send().await?)?;
+            let publishers = normalize(&response.text().await?);
+            if publishers.is_empty() {
+                return Err(format!("no publishers found in {}", url).into());
+            }
+            publishers
+        }
+        None => {
+            let request = client
+                .get(WIKIDATA_ENDPOINT)
+                .query(&[("query", WIKIDATA_QUERY)])
+                .header(reqwest::header::ACCEPT, "text/csv")
+                // WikidataはUser-Agentのないリクエストを断る
+                .header(reqwest::header::USER_AGENT, concat!("isbn/", env!("CARGO_PKG_VERSION")));
+            let fetched = normalize(&check_response(request.send().await?)?.text().await?);
+            if fetched.is_empty() {
+                return Err(format!("no publishers found in {}", WIKIDATA_ENDPOINT).into());
+            }
+            merge(fetched, PublisherRegistry::embedded())
+        }
+    };
+    save(&publishers, path)?;
+    Ok(publishers.len())
+}
+
+/// 取得した出版社一覧に、含まれていない出版社記号の出版社を足してISBNの順に並べる
+fn merge(mut publishers: Vec<Publisher>, base: &PublisherRegistry) -> Vec<Publisher> {
+    let codes: HashSet<String> = publishers.iter().map(|publisher| publisher.code.clone()).collect();
+    publishers.extend(base.iter().filter(|publisher| !codes.contains(&publisher.code)).cloned());
+    publishers.sort_by(|a, b| a.code.cmp(&b.code));
+    publishers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        // 見出しの行や記号・名前のない行は読み飛ばし、全角数字やハイフンは直す
+        let text = "出版社記号,出版社名\n１０,新潮社\n7981 , 翔泳社 ,東京都\n2-54,朝倉書店\n10,重複\n,名前だけ\n256,\n";
+        let publishers = normalize(text);
+        let publishers: Vec<(&str, &str)> =
+            publishers.iter().map(|publisher| (publisher.code.as_str(), publisher.name.as_str())).collect();
+        assert_eq!(publishers, vec![("10", "新潮社"), ("254", "朝倉書店"), ("7981", "翔泳社")]);
+    }
+
+    #[test]
+    fn test_normalize_wikidata() {
+        // query.wikidata.orgがAccept: text/csvで返すSPARQLの結果の形
+        let text = "prefix,publisherLabel\r\n978-4-10,新潮社\r\n978-4-7981,翔泳社\r\n978-4-00,岩波書店\r\n\
+                    978-4-06,\"講談社, Ltd.\"\r\n978-4-99999,Q123456\r\n978-4-10,Shinchosha\r\n";
+        let publishers = normalize(text);
+        let publishers: Vec<(&str, &str)> =
+            publishers.iter().map(|publisher| (publisher.code.as_str(), publisher.name.as_str())).collect();
+        assert_eq!(publishers, vec![("00", "岩波書店"), ("06", "講談社, Ltd."), ("10", "新潮社"), ("7981", "翔泳社")]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = PublisherRegistry::from_reader("10,新潮社(旧)\n254,朝倉書店\n".as_bytes()).unwrap();
+        let merged = merge(normalize("978-4-10,新潮社\n978-4-7981,翔泳社\n"), &base);
+        let merged: Vec<(&str, &str)> =
+            merged.iter().map(|publisher| (publisher.code.as_str(), publisher.name.as_str())).collect();
+        assert_eq!(merged, vec![("10", "新潮社"), ("254", "朝倉書店"), ("7981", "翔泳社")]);
+    }
+
+    #[test]
+    fn test_normalize_tsv() {
+        let publishers = normalize("10\t新潮社\n7981\t翔泳社\n");
+        assert_eq!(publishers.len(), 2);
+        assert_eq!(publishers[1], Publisher { code: String::from("7981"), name: String::from("翔泳社") });
+    }
+
+    #[test]
+    fn test_save() {
+        let path = std::env::temp_dir().join(format!("isbn-publishers-{}", std::process::id())).join("publishers.csv");
+        let publishers = normalize("10,新潮社\n7981,翔泳社\n");
+        save(&publishers, &path).unwrap();
+        let registry = load(&path).unwrap();
+        assert_eq!(registry.iter().cloned().collect::<Vec<Publisher>>(), publishers);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,73 @@
+//! 見つかった本のURLのQRコード
+//! 端末に表示する場合は、1文字で上下2モジュールを表す
+
+use isbn::{Bitmap, Drawing, Rect};
+use qrcode::types::QrError;
+use qrcode::{Color, QrCode};
+
+/// QRコードの周りの余白(モジュール数)
+const QUIET_ZONE: u32 = 4;
+
+/// 余白を含めたQRコードの図形(単位はモジュール)
+pub fn drawing(text: &str) -> Result<Drawing, QrError> {
+    let code = QrCode::new(text)?;
+    let width = code.width() as u32;
+    let colors = code.to_colors();
+    let mut drawing =
+        Drawing { width: width + QUIET_ZONE * 2, height: width + QUIET_ZONE * 2, ..Default::default() };
+    for (y, row) in colors.chunks(width as usize).enumerate() {
+        for (x, color) in row.iter().enumerate() {
+            if *color == Color::Dark {
+                let (x, y) = (x as u32 + QUIET_ZONE, y as u32 + QUIET_ZONE);
+                drawing.bars.push(Rect { x, y, width: 1, height: 1 });
+            }
+        }
+    }
+    Ok(drawing)
+}
+
+/// 端末に表示する文字列
+/// 暗い背景の端末で読み取れるように、白いモジュールを文字で埋める
+pub fn to_blocks(bitmap: &Bitmap) -> String {
+    let mut blocks = String::new();
+    for y in (0..bitmap.height).step_by(2) {
+        for x in 0..bitmap.width {
+            // 高さが奇数の場合、最後の行の下は白とみなす
+            let bottom_dark = y + 1 < bitmap.height && bitmap.get(x, y + 1);
+            blocks.push(match (bitmap.get(x, y), bottom_dark) {
+                (false, false) => '█',
+                (false, true) => '▀',
+                (true, false) => '▄',
+                (true, true) => ' ',
+            });
+        }
+        blocks.push('\n');
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drawing() {
+        let drawing = drawing("https://booklog.jp/item/1/4101010013").unwrap();
+        // 余白の内側の左上には位置検出パターンがある
+        let bitmap = drawing.to_bitmap(1);
+        assert!(!bitmap.get(QUIET_ZONE - 1, QUIET_ZONE));
+        assert!(bitmap.get(QUIET_ZONE, QUIET_ZONE));
+        assert!(bitmap.get(QUIET_ZONE + 6, QUIET_ZONE + 6));
+        assert!(!bitmap.get(QUIET_ZONE + 1, QUIET_ZONE + 1));
+        assert_eq!(drawing.width, drawing.height);
+    }
+
+    #[test]
+    fn test_to_blocks() {
+        let mut bitmap = Bitmap::new(3, 3);
+        bitmap.fill(0, 0, 1, 2);
+        bitmap.fill(1, 1, 1, 1);
+        bitmap.fill(2, 2, 1, 1);
+        assert_eq!(to_blocks(&bitmap), " ▀█\n██▄\n");
+    }
+}
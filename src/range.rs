@@ -0,0 +1,203 @@
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+use crate::error::IsbnError;
+
+/// ISBN International Agencyが公開しているRangeMessage.xmlの内容
+#[derive(Debug, Clone)]
+pub struct RangeMessage {
+    pub message_date: String,
+    /// 接頭記号(978, 979)ごとの国記号の桁数の規則
+    pub ucc_prefixes: Vec<RangeGroup>,
+    /// 国記号(978-4など)ごとの出版社記号の桁数の規則
+    pub groups: Vec<RangeGroup>,
+}
+
+/// 接頭記号または国記号と、その後に続く要素の桁数の規則
+#[derive(Debug, Clone)]
+pub struct RangeGroup {
+    /// `978` や `978-4` のようなハイフン区切りの記号
+    pub prefix: String,
+    pub agency: String,
+    pub rules: Vec<Rule>,
+}
+
+/// 後続の7桁がstartからendの範囲にある場合、次の要素はlength桁になる
+/// lengthが0の範囲は未割り当て
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub start: u32,
+    pub end: u32,
+    pub length: usize,
+}
+
+impl RangeMessage {
+    /// 埋め込みのRangeMessage.xmlを読み込む
+    /// 一度だけ解析し、以降は同じものを返す
+    /// 埋め込みのXMLはテストで解析できることを確かめているので、解析できない場合はビルドの誤りとして扱う
+    pub fn embedded() -> &'static RangeMessage {
+        static RANGE_MESSAGE: OnceLock<RangeMessage> = OnceLock::new();
+        RANGE_MESSAGE.get_or_init(|| {
+            let xml_text = include_str!("../xml/RangeMessage.xml");
+            Self::parse(xml_text).expect("embedded RangeMessage.xml is broken")
+        })
+    }
+
+    /// RangeMessage.xmlの文字列を解析する
+    pub fn parse(xml_text: &str) -> Result<RangeMessage, IsbnError> {
+        let message: RangeMessageXml = quick_xml::de::from_str(xml_text)?;
+        let ucc_prefixes = message.ucc_prefixes.ucc.into_iter().map(RangeGroup::from_xml).collect::<Result<_, _>>()?;
+        let groups = message.registration_groups.group.into_iter().map(RangeGroup::from_xml).collect::<Result<_, _>>()?;
+        Ok(RangeMessage { message_date: message.message_date.trim().to_string(), ucc_prefixes, groups })
+    }
+
+    /// 接頭記号の規則を探す
+    pub fn find_ucc_prefix(&self, prefix: &str) -> Option<&RangeGroup> {
+        self.ucc_prefixes.iter().find(|group| group.prefix == prefix)
+    }
+
+    /// `978-4` のような接頭記号と国記号の組から国記号の規則を探す
+    pub fn find_group(&self, prefix: &str) -> Option<&RangeGroup> {
+        self.groups.iter().find(|group| group.prefix == prefix)
+    }
+
+    /// チェックディジットを含むISBN13の数字列から、国記号と出版社記号の桁数を求める
+    /// 未割り当ての範囲に含まれる場合はNoneを返す
+    pub fn segment_lengths(&self, isbn13: &str) -> Option<(usize, usize)> {
+        if isbn13.len() != 13 || !isbn13.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let head_code = &isbn13[..3];
+        let group_length = self.find_ucc_prefix(head_code)?.length_of(&isbn13[3..12])?;
+        let group_code = &isbn13[3..3 + group_length];
+        let registrant_length = self
+            .find_group(&format!("{}-{}", head_code, group_code))?
+            .length_of(&isbn13[3 + group_length..12])?;
+        // 書籍コードが1桁以上残らない規則は不正
+        if group_length + registrant_length >= 9 {
+            return None;
+        }
+        Some((group_length, registrant_length))
+    }
+}
+
+impl RangeGroup {
+    /// 続く数字列の先頭7桁から、次の要素の桁数を求める
+    /// 7桁に満たない場合は0を補って比較する
+    pub fn length_of(&self, digits: &str) -> Option<usize> {
+        let mut head: String = digits.chars().take(7).collect();
+        while head.len() < 7 {
+            head.push('0');
+        }
+        let value: u32 = head.parse().ok()?;
+        self.rules
+            .iter()
+            .find(|rule| rule.start <= value && value <= rule.end)
+            .map(|rule| rule.length)
+            .filter(|length| *length > 0)
+    }
+}
+
+/// RangeMessage.xmlの要素をそのまま読み込むための型
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RangeMessageXml {
+    #[serde(default)]
+    message_date: String,
+    #[serde(rename = "EAN.UCCPrefixes")]
+    ucc_prefixes: UccPrefixesXml,
+    registration_groups: RegistrationGroupsXml,
+}
+
+#[derive(Debug, Deserialize)]
+struct UccPrefixesXml {
+    #[serde(rename = "EAN.UCC", default)]
+    ucc: Vec<GroupXml>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegistrationGroupsXml {
+    #[serde(rename = "Group", default)]
+    group: Vec<GroupXml>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GroupXml {
+    prefix: String,
+    #[serde(default)]
+    agency: String,
+    rules: RulesXml,
+}
+
+#[derive(Debug, Deserialize)]
+struct RulesXml {
+    #[serde(rename = "Rule", default)]
+    rule: Vec<RuleXml>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RuleXml {
+    range: String,
+    length: usize,
+}
+
+impl RangeGroup {
+    /// 範囲の文字列(`0000000-5999999`)が不正な場合はエラーを返す
+    fn from_xml(group: GroupXml) -> Result<RangeGroup, IsbnError> {
+        let rules = group
+            .rules
+            .rule
+            .into_iter()
+            .map(|rule| {
+                let range = rule.range.trim();
+                let invalid = || IsbnError::InvalidRange(range.to_string());
+                let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+                Ok(Rule {
+                    start: start.parse().map_err(|_| invalid())?,
+                    end: end.parse().map_err(|_| invalid())?,
+                    length: rule.length,
+                })
+            })
+            .collect::<Result<_, IsbnError>>()?;
+        Ok(RangeGroup { prefix: group.prefix.trim().to_string(), agency: group.agency.trim().to_string(), rules })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded() {
+        let range_message = RangeMessage::embedded();
+        assert!(range_message.find_ucc_prefix("978").is_some());
+        assert_eq!(range_message.find_group("978-4").unwrap().agency, "Japan");
+    }
+
+    #[test]
+    fn test_parse_broken() {
+        assert!(matches!(
+            RangeMessage::parse("<ISBNRangeMessage><MessageDate>x</MessageDate></ISBNRangeMessage>"),
+            Err(IsbnError::Xml(_))
+        ));
+        let xml_text = "<ISBNRangeMessage><EAN.UCCPrefixes><EAN.UCC><Prefix>978</Prefix><Rules><Rule>\
+            <Range>0000000</Range><Length>1</Length></Rule></Rules></EAN.UCC></EAN.UCCPrefixes>\
+            <RegistrationGroups></RegistrationGroups></ISBNRangeMessage>";
+        assert!(matches!(RangeMessage::parse(xml_text), Err(IsbnError::InvalidRange(range)) if range == "0000000"));
+    }
+
+    #[test]
+    fn test_segment_lengths() {
+        let range_message = RangeMessage::embedded();
+        // 978-4-7981-7154-8
+        assert_eq!(range_message.segment_lengths("9784798171548"), Some((1, 4)));
+        // 978-4-10-109205-8
+        assert_eq!(range_message.segment_lengths("9784101092058"), Some((1, 2)));
+        // 978-0-306-40615-7
+        assert_eq!(range_message.segment_lengths("9780306406157"), Some((1, 3)));
+        // 978-6xxは未割り当ての範囲を含む
+        assert_eq!(range_message.segment_lengths("9786999999999"), None);
+    }
+}
@@ -0,0 +1,37 @@
+//! 白黒の画素の並びをPNGで書き出す
+
+use isbn::Bitmap;
+use std::io::Write;
+
+use crate::provider::Result;
+
+/// 8ビットのグレースケールのPNGで書き出す
+pub fn write_png(writer: impl Write, bitmap: &Bitmap) -> Result<()> {
+    let mut encoder = png::Encoder::new(writer, bitmap.width, bitmap.height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let data: Vec<u8> = bitmap.rows().flatten().map(|dark| if *dark { 0 } else { 255 }).collect();
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_png() {
+        let mut bitmap = Bitmap::new(4, 2);
+        bitmap.fill(1, 0, 2, 1);
+        let mut png_data = Vec::new();
+        write_png(&mut png_data, &bitmap).unwrap();
+        let decoder = png::Decoder::new(png_data.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (4, 2));
+        assert_eq!(&pixels[..8], &[255, 0, 0, 255, 255, 255, 255, 255]);
+    }
+}
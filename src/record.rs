@@ -0,0 +1,214 @@
+//! 各APIから取得した書誌情報を共通の形にまとめたもの
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 書誌情報
+/// 履歴や蔵書にはJSONで保存し、読み出すときに元に戻す
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct BookRecord {
+    /// ハイフンなしのISBN13
+    pub isbn: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub publisher: Option<String>,
+    /// 出版年月日(APIによって形式が異なる)
+    pub issued: Option<String>,
+    pub series: Option<String>,
+    pub volume: Option<String>,
+    pub pages: Option<u32>,
+    /// 日本十進分類法の分類記号
+    pub ndc: Option<String>,
+    /// 国立国会図書館分類表の分類記号
+    pub ndlc: Option<String>,
+    /// 件名(国立国会図書館件名標目など)
+    pub subjects: Vec<String>,
+    /// 大きさやページ数などの形態(例: 318p ; 20cm)
+    pub extent: Option<String>,
+    /// 資料の種別(国立国会図書館サーチの図書、雑誌、地図など)
+    pub material_type: Option<String>,
+    /// 判型や製本の種類(openBDの文庫、四六判など)
+    pub format: Option<String>,
+    /// 本文の言語(APIによってISO 639-2の jpn か、ISO 639-1の ja のような記号)
+    pub language: Option<String>,
+    /// 価格(円)
+    pub price: Option<u32>,
+    /// 在庫状況
+    pub availability: Option<String>,
+    /// 書影のURL
+    pub cover_url: Option<String>,
+    /// APIでの書誌のページ
+    pub link: Option<String>,
+    /// 書誌情報を取得したAPIの名前(保存したものを読み出した場合は空)
+    #[serde(skip_deserializing)]
+    pub source: &'static str,
+}
+
+impl BookRecord {
+    /// 表示する書誌情報
+    /// 値のない項目は含めない
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        [
+            ("title", self.title.clone()),
+            ("volume", optional(&self.volume)),
+            ("series", optional(&self.series)),
+            ("author", self.authors.join(", ")),
+            ("publisher", optional(&self.publisher)),
+            ("issued", optional(&self.issued)),
+            ("ndc", optional(&self.ndc)),
+            ("ndlc", optional(&self.ndlc)),
+            ("subjects", self.subjects.join("; ")),
+            ("material", optional(&self.material_type)),
+            ("format", optional(&self.format)),
+            ("language", optional(&self.language)),
+            ("pages", self.pages.map(|pages| format!("{}p", pages)).unwrap_or_default()),
+            ("extent", optional(&self.extent)),
+            ("price", self.price.map(|price| format!("{}円", price)).unwrap_or_default()),
+            ("availability", optional(&self.availability)),
+            ("cover", optional(&self.cover_url)),
+            ("link", optional(&self.link)),
+            ("source", self.source.to_string()),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+    }
+
+    /// 「タイトル / 著者, 出版社, 年」の1行の書誌
+    /// 値のない項目は含めない
+    pub fn citation(&self) -> String {
+        let mut citation = self.title.clone();
+        let details: Vec<String> = [
+            Some(self.authors.join(", ")).filter(|authors| !authors.is_empty()),
+            self.publisher.clone(),
+            self.year().map(|year| year.to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !details.is_empty() {
+            citation.push_str(" / ");
+            citation.push_str(&details.join(", "));
+        }
+        citation
+    }
+
+    /// 出版年月日から取り出した西暦の年
+    /// APIによって "2003", "2003.4", "2003-04-01", "[2003]" のように形式が異なるので、最初の4桁の数字を年とみなす
+    pub fn year(&self) -> Option<u32> {
+        let chars: Vec<char> = self.issued.as_deref()?.chars().collect();
+        chars
+            .split(|c| !c.is_ascii_digit())
+            .find(|digits| digits.len() == 4)
+            .and_then(|digits| digits.iter().collect::<String>().parse().ok())
+    }
+
+    /// 巻次の番号
+    /// "3", "第3巻", "巻3", "３" のように最初の数字を番号とみなし、"上" や "前編" は1巻目とみなす
+    pub fn volume_number(&self) -> Option<u32> {
+        let volume = self.volume.as_deref()?.trim();
+        let digits: String = volume
+            .chars()
+            .map(|c| match c {
+                '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap_or(c),
+                _ => c,
+            })
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        match digits.parse() {
+            Ok(number) => Some(number),
+            Err(_) if volume.starts_with('上') || volume.starts_with("前") => Some(1),
+            Err(_) => None,
+        }
+    }
+}
+
+/// 空文字の項目はNoneにする
+pub fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata() {
+        let record = BookRecord {
+            isbn: String::from("9784101010014"),
+            title: String::from("吾輩は猫である"),
+            authors: vec![String::from("夏目漱石")],
+            series: Some(String::from("新潮文庫")),
+            price: Some(710),
+            source: "openbd",
+            ..Default::default()
+        };
+        assert_eq!(
+            record.metadata(),
+            vec![
+                ("title", String::from("吾輩は猫である")),
+                ("series", String::from("新潮文庫")),
+                ("author", String::from("夏目漱石")),
+                ("price", String::from("710円")),
+                ("source", String::from("openbd")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let record = BookRecord {
+            isbn: String::from("9784101010014"),
+            title: String::from("吾輩は猫である"),
+            pages: Some(610),
+            source: "openbd",
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(serde_json::from_str::<BookRecord>(&json).unwrap(), BookRecord { source: "", ..record });
+        let record: BookRecord = serde_json::from_str(r#"{"isbn": "9784101010014"}"#).unwrap();
+        assert!(record.title.is_empty());
+    }
+
+    #[test]
+    fn test_citation() {
+        let record = BookRecord {
+            title: String::from("吾輩は猫である"),
+            authors: vec![String::from("夏目漱石 著")],
+            publisher: Some(String::from("新潮社")),
+            issued: Some(String::from("2003.6")),
+            ..Default::default()
+        };
+        assert_eq!(record.citation(), "吾輩は猫である / 夏目漱石 著, 新潮社, 2003");
+        let record = BookRecord { title: String::from("吾輩は猫である"), ..Default::default() };
+        assert_eq!(record.citation(), "吾輩は猫である");
+    }
+
+    #[test]
+    fn test_year() {
+        let issued = |issued: &str| BookRecord { issued: Some(issued.to_string()), ..Default::default() };
+        assert_eq!(issued("2003").year(), Some(2003));
+        assert_eq!(issued("平成15.4").year(), None);
+        assert_eq!(issued("2003-04-01").year(), Some(2003));
+        assert_eq!(issued("12.2003").year(), Some(2003));
+        assert_eq!(BookRecord::default().year(), None);
+    }
+
+    #[test]
+    fn test_volume_number() {
+        let volume = |volume: &str| BookRecord { volume: Some(volume.to_string()), ..Default::default() };
+        assert_eq!(volume("3").volume_number(), Some(3));
+        assert_eq!(volume("第12巻").volume_number(), Some(12));
+        assert_eq!(volume("巻１").volume_number(), Some(1));
+        assert_eq!(volume("上").volume_number(), Some(1));
+        assert_eq!(volume("下").volume_number(), None);
+        assert_eq!(BookRecord::default().volume_number(), None);
+    }
+}
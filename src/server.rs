@@ -0,0 +1,879 @@
+//! serveサブコマンドのHTTPサーバー
+//! ランダムに本を探す、ISBNで書誌を探す、ISBNを検証・変換するAPIをJSONで返す
+//! 問い合わせたISBNと見つかった本はServer-Sent Eventsでも流す
+//! WebSocketでは、探し始めたり止めたり、見つかった本を受け入れる条件を変えたりしながら見つかった本を受け取れる
+//! APIの仕様はOpenAPIの文書(/openapi.json)にし、/docsのSwagger UIで読めるようにする
+//! 問い合わせた数や見つかった数、リクエストの所要時間は/metricsでPrometheusの形式で返す
+//! ロードバランサーなどから確かめられるように、/healthzと/readyzで動いているかと使えるかを返す
+//! 書誌情報のAPIに負担をかけすぎないように、APIのリクエストはクライアントごとと全体の数を制限し、超えたら429を返す
+
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, MatchedPath, Path, Request, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures::stream::{self, Stream, StreamExt};
+use isbn::{hyphenate, Isbn};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::filter::RecordFilter;
+use crate::history::History;
+use crate::metrics::{self, Metrics};
+use crate::output::Discovery;
+use crate::provider::MetadataProvider;
+use crate::publisher_list;
+use crate::record::BookRecord;
+use crate::throttle::Throttle;
+
+/// /eventsを受け取るのが遅れたときに溜めておくイベントの数(溢れた分は飛ばす)
+pub const EVENT_CAPACITY: usize = 256;
+
+/// /readyzで書誌情報のAPIに問い合わせられるかを確かめた結果を使い回す時間
+/// 頻繁に確かめられてもAPIに問い合わせすぎないようにする
+const READINESS_TTL: Duration = Duration::from_secs(30);
+
+/// /readyzで問い合わせるISBN(見つかっても見つからなくても、応答があれば使えるとみなす)
+const READINESS_ISBN: &str = "9784101010014";
+
+/// ランダムにISBNを生成する関数(出版社の選び方はコマンドラインと設定ファイルに従う)
+pub type Generator = Box<dyn Fn(&mut StdRng) -> Option<Isbn> + Send + Sync>;
+
+/// リクエストの間で共有する状態
+pub struct AppState {
+    /// 書誌情報のAPI(問い合わせの間隔は、すべてのリクエストを通して再試行の設定で守る)
+    pub provider: Box<dyn MetadataProvider>,
+    pub generator: Generator,
+    pub rng: Mutex<StdRng>,
+    /// 試したISBNと見つかった本を記録する履歴(履歴にあるISBNは問い合わせない)
+    pub history: Option<Mutex<History>>,
+    /// /randomで1回のリクエストごとに問い合わせるISBNの数の上限
+    pub max_attempts: usize,
+    /// /eventsで流す、問い合わせたISBNと見つかった本
+    pub events: broadcast::Sender<RouletteEvent>,
+    pub metrics: Metrics,
+    /// 書誌情報のAPIに最後に問い合わせられるかを確かめた時刻と、問い合わせられなかった場合のエラー
+    pub readiness: tokio::sync::Mutex<Option<(Instant, Option<String>)>>,
+    /// APIのリクエストの、クライアントごとと全体の数の制限
+    pub throttle: Throttle,
+}
+
+/// /eventsで流すイベント(SSEのイベント名はtype)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RouletteEvent {
+    /// ISBNを1件問い合わせた
+    Attempt {
+        isbn13: String,
+        /// 出版社一覧にある出版社名(日本の出版社だけ)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        publisher_name: Option<&'static str>,
+        found: bool,
+        /// 見つかったが受け入れなかった場合の、満たさなかった条件の名前
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rejected: Option<&'static str>,
+        /// このリクエスト(WebSocketの場合はstartしてから)で何件目か
+        attempts: usize,
+    },
+    /// 本が見つかった
+    Hit(Box<Discovery>),
+}
+
+impl RouletteEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            RouletteEvent::Attempt { .. } => "attempt",
+            RouletteEvent::Hit(_) => "hit",
+        }
+    }
+}
+
+impl AppState {
+    /// 履歴にもこのリクエストで試したものにもない、ISBNをcount件まで生成する
+    /// 何度生成しても試したものばかりの場合は、それまでに生成した分だけを返す
+    fn candidates(&self, count: usize, tried: &mut HashSet<String>) -> Vec<Isbn> {
+        const MAX_TRIES: usize = 100;
+        let mut rng = self.rng.lock().expect("rng lock");
+        let mut candidates = Vec::new();
+        for _ in 0..count * MAX_TRIES {
+            if candidates.len() >= count {
+                break;
+            }
+            let Some(isbn) = (self.generator)(&mut rng) else {
+                break;
+            };
+            let isbn_13 = isbn.create_isbn_13();
+            if tried.insert(isbn_13.clone()) && !self.in_history(&isbn_13) {
+                candidates.push(isbn);
+            }
+        }
+        candidates
+    }
+
+    /// 履歴にあるISBNか(履歴を読めなかった場合は、試していないものとして扱う)
+    fn in_history(&self, isbn_13: &str) -> bool {
+        let Some(history) = &self.history else {
+            return false;
+        };
+        history.lock().expect("history lock").contains(isbn_13).unwrap_or_else(|e| {
+            warn!(error = %e, "cannot read history");
+            false
+        })
+    }
+
+    /// 候補のISBNをcount件(APIでまとめて問い合わせられる数まで)生成して1回問い合わせる
+    /// 問い合わせたISBNと、条件に合って見つかった本のイベントを返し、/eventsにも流す
+    async fn step(
+        &self,
+        count: usize,
+        filter: &RecordFilter,
+        tried: &mut HashSet<String>,
+        attempts: &mut usize,
+    ) -> Result<Vec<RouletteEvent>, ApiError> {
+        // openBDとOpen Libraryはまとめて問い合わせる
+        let candidates = self.candidates(count.min(self.provider.batch_size()), tried);
+        if candidates.is_empty() {
+            return Err(ApiError(StatusCode::NOT_FOUND, String::from("no untried isbns left")));
+        }
+        debug!(candidates = candidates.len(), "looking up candidates");
+        let records = self
+            .provider
+            .lookup_batch(&candidates)
+            .await
+            .map_err(|e| {
+                self.metrics.provider_error();
+                ApiError(StatusCode::BAD_GATEWAY, format!("cannot search books: {}", e))
+            })?;
+        self.metrics.attempted(candidates.len());
+        let mut events = Vec::new();
+        for (isbn, record) in candidates.iter().zip(records) {
+            *attempts += 1;
+            self.record(isbn, record.as_ref());
+            let rejected = record.as_ref().and_then(|record| filter.rejects(record));
+            events.push(RouletteEvent::Attempt {
+                isbn13: isbn.create_isbn_13(),
+                publisher_name: publisher_list::publisher_name(isbn),
+                found: record.is_some(),
+                rejected,
+                attempts: *attempts,
+            });
+            if let Some(record) = record.filter(|_| rejected.is_none()) {
+                self.metrics.hit(1);
+                events.push(RouletteEvent::Hit(Box::new(Discovery::new(record, *attempts))));
+            }
+        }
+        for event in &events {
+            // /eventsを受け取っているクライアントがいなければ捨てる
+            let _ = self.events.send(event.clone());
+        }
+        Ok(events)
+    }
+
+    /// 問い合わせた結果を履歴に記録する
+    fn record(&self, isbn: &Isbn, record: Option<&BookRecord>) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let history = history.lock().expect("history lock");
+        let recorded = match record {
+            Some(record) => history.record_hit(record),
+            None => history.record_miss(&isbn.create_isbn_13()),
+        };
+        if let Err(e) = recorded {
+            warn!(error = %e, "cannot write history");
+        }
+    }
+}
+
+/// APIのエラー(ErrorResponseのJSONで返す)
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+/// エラーの応答
+#[derive(Debug, Serialize, ToSchema)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorResponse { error: self.1 })).into_response()
+    }
+}
+
+/// ISBNの検証結果
+#[derive(Debug, Serialize, ToSchema)]
+struct ValidateResponse {
+    isbn: String,
+    valid: bool,
+    /// 正しくない場合の理由
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    isbn13: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hyphenated: Option<String>,
+}
+
+/// ISBNの変換結果
+#[derive(Debug, Serialize, ToSchema)]
+struct ConvertResponse {
+    /// 979始まりのISBNにはISBN10がない
+    isbn10: Option<String>,
+    isbn13: String,
+    hyphenated: String,
+}
+
+/// ランダムにISBNを生成して、実在する本が見つかるまで問い合わせる
+/// --max-attempts回問い合わせても見つからなかった場合は404を返す
+#[utoipa::path(
+    get,
+    path = "/random",
+    responses(
+        (status = 200, description = "見つかった本", body = Discovery),
+        (status = 404, description = "見つからなかった", body = ErrorResponse),
+        (status = 502, description = "書誌情報のAPIに問い合わせられなかった", body = ErrorResponse),
+        (status = 429, description = "リクエストが多すぎる(Retry-Afterの秒数の後に再試行する)", body = ErrorResponse),
+    ),
+)]
+async fn random(State(state): State<Arc<AppState>>) -> Result<Json<Discovery>, ApiError> {
+    let filter = RecordFilter::default();
+    let mut tried = HashSet::new();
+    let mut attempts = 0;
+    while attempts < state.max_attempts {
+        let events = state.step(state.max_attempts - attempts, &filter, &mut tried, &mut attempts).await?;
+        if let Some(RouletteEvent::Hit(discovery)) = events.into_iter().find(|event| event.name() == "hit") {
+            return Ok(Json(*discovery));
+        }
+    }
+    Err(ApiError(StatusCode::NOT_FOUND, format!("cannot find any books in {} times", attempts)))
+}
+
+/// ISBNの書誌情報を探す
+#[utoipa::path(
+    get,
+    path = "/lookup/{isbn}",
+    params(("isbn" = String, Path, description = "ISBN10かISBN13(ハイフンで区切ってもよい)")),
+    responses(
+        (status = 200, description = "見つかった本", body = Discovery),
+        (status = 400, description = "ISBNが正しくない", body = ErrorResponse),
+        (status = 404, description = "見つからなかった", body = ErrorResponse),
+        (status = 502, description = "書誌情報のAPIに問い合わせられなかった", body = ErrorResponse),
+        (status = 429, description = "リクエストが多すぎる(Retry-Afterの秒数の後に再試行する)", body = ErrorResponse),
+    ),
+)]
+async fn lookup(State(state): State<Arc<AppState>>, Path(isbn): Path<String>) -> Result<Json<Discovery>, ApiError> {
+    let parsed = Isbn::parse(&isbn).map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("{}: {}", isbn, e)))?;
+    let isbn_13 = parsed.create_isbn_13();
+    match state.provider.lookup(&parsed).await {
+        Ok(Some(record)) => Ok(Json(Discovery::new(record, 1))),
+        Ok(None) => Err(ApiError(StatusCode::NOT_FOUND, format!("{} not found", isbn_13))),
+        Err(e) => {
+            state.metrics.provider_error();
+            Err(ApiError(StatusCode::BAD_GATEWAY, format!("{}: {}", isbn_13, e)))
+        }
+    }
+}
+
+/// ISBNを検証する(正しくないISBNも200で、validをfalseにして返す)
+#[utoipa::path(
+    get,
+    path = "/validate/{isbn}",
+    params(("isbn" = String, Path, description = "ISBN10かISBN13(ハイフンで区切ってもよい)")),
+    responses(
+        (status = 200, description = "検証結果", body = ValidateResponse),
+        (status = 429, description = "リクエストが多すぎる(Retry-Afterの秒数の後に再試行する)", body = ErrorResponse),
+    ),
+)]
+async fn validate(Path(isbn): Path<String>) -> Json<ValidateResponse> {
+    let response = match Isbn::parse(&isbn) {
+        Ok(parsed) => {
+            let isbn13 = parsed.create_isbn_13();
+            let hyphenated = hyphenate(&isbn13).ok();
+            ValidateResponse { isbn, valid: true, reason: None, isbn13: Some(isbn13), hyphenated }
+        }
+        Err(e) => ValidateResponse { isbn, valid: false, reason: Some(e.to_string()), isbn13: None, hyphenated: None },
+    };
+    Json(response)
+}
+
+/// ISBN10とISBN13を相互に変換し、ハイフンで区切ったものも返す
+#[utoipa::path(
+    get,
+    path = "/convert/{isbn}",
+    params(("isbn" = String, Path, description = "ISBN10かISBN13(ハイフンで区切ってもよい)")),
+    responses(
+        (status = 200, description = "変換結果", body = ConvertResponse),
+        (status = 400, description = "ISBNが正しくない", body = ErrorResponse),
+        (status = 429, description = "リクエストが多すぎる(Retry-Afterの秒数の後に再試行する)", body = ErrorResponse),
+    ),
+)]
+async fn convert(Path(isbn): Path<String>) -> Result<Json<ConvertResponse>, ApiError> {
+    let parsed = Isbn::parse(&isbn).map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("{}: {}", isbn, e)))?;
+    let isbn13 = parsed.create_isbn_13();
+    let hyphenated = hyphenate(&isbn13).map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("{}: {}", isbn, e)))?;
+    Ok(Json(ConvertResponse { isbn10: parsed.create_isbn_10(), isbn13, hyphenated }))
+}
+
+/// 問い合わせたISBNと見つかった本をServer-Sent Eventsで流す
+/// 受け取るのが遅れて溜まりすぎた分は飛ばし、Ctrl+Cでサーバーを止めるときはつないだままのストリームも閉じる
+#[utoipa::path(
+    get,
+    path = "/events",
+    responses(
+        (status = 200, description = "イベントのストリーム", body = RouletteEvent, content_type = "text/event-stream"),
+        (status = 429, description = "リクエストが多すぎる(Retry-Afterの秒数の後に再試行する)", body = ErrorResponse),
+    ),
+)]
+async fn events(State(state): State<Arc<AppState>>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = stream::unfold(state.events.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(RecvError::Lagged(skipped)) => debug!(skipped, "skipped events"),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    let stream = stream.filter_map(|event| async move {
+        match Event::default().event(event.name()).json_data(&event) {
+            Ok(sse) => Some(Ok(sse)),
+            Err(e) => {
+                warn!(error = %e, "cannot serialize event");
+                None
+            }
+        }
+    });
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    Sse::new(stream.take_until(shutdown)).keep_alive(KeepAlive::default())
+}
+
+/// WebSocketでクライアントから受け取るメッセージ
+#[derive(Debug, Deserialize, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SessionCommand {
+    /// 探し始める(countを指定した場合はcount冊見つかったら止める)
+    Start {
+        #[serde(default)]
+        count: Option<usize>,
+    },
+    /// 探すのを止める
+    Stop,
+    /// 見つかった本を受け入れる条件を変える(探している途中でも次の問い合わせから使う)
+    Filter(RecordFilter),
+}
+
+/// WebSocketでクライアントに送る、問い合わせたISBNと見つかった本以外のメッセージ
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SessionReply {
+    Started { count: Option<usize> },
+    Stopped { found: usize, attempts: usize },
+    Filter(RecordFilter),
+    Error { message: String },
+}
+
+/// WebSocketの1つの接続で探している状態
+#[derive(Debug, Default)]
+struct Session {
+    running: bool,
+    /// 見つける冊数(Noneの場合はstopするまで探す)
+    count: Option<usize>,
+    found: usize,
+    attempts: usize,
+    filter: RecordFilter,
+    tried: HashSet<String>,
+}
+
+impl Session {
+    /// クライアントから受け取ったメッセージで状態を変え、クライアントに返すメッセージを返す
+    fn handle(&mut self, text: &str) -> SessionReply {
+        let command = match serde_json::from_str(text) {
+            Ok(command) => command,
+            Err(e) => return SessionReply::Error { message: format!("invalid message: {}", e) },
+        };
+        match command {
+            SessionCommand::Start { count: Some(0) } => {
+                SessionReply::Error { message: String::from("count must be at least 1") }
+            }
+            SessionCommand::Start { count } => {
+                let filter = std::mem::take(&mut self.filter);
+                *self = Session { running: true, count, filter, ..Session::default() };
+                SessionReply::Started { count }
+            }
+            SessionCommand::Stop => self.stop(),
+            SessionCommand::Filter(filter) => {
+                self.filter = filter;
+                SessionReply::Filter(self.filter.clone())
+            }
+        }
+    }
+
+    fn stop(&mut self) -> SessionReply {
+        self.running = false;
+        SessionReply::Stopped { found: self.found, attempts: self.attempts }
+    }
+}
+
+/// メッセージをJSONにして送る(接続が切れていればfalseを返す)
+async fn send(socket: &mut WebSocket, message: &impl Serialize) -> bool {
+    match serde_json::to_string(message) {
+        Ok(text) => socket.send(Message::Text(text.into())).await.is_ok(),
+        Err(e) => {
+            warn!(error = %e, "cannot serialize message");
+            true
+        }
+    }
+}
+
+/// WebSocketの1つの接続で、startからstopまで(countを指定した場合は見つかるまで)繰り返し問い合わせる
+/// 問い合わせている途中でメッセージを受け取った場合は、その問い合わせを打ち切ってメッセージを先に処理する
+async fn session(state: Arc<AppState>, mut socket: WebSocket) {
+    let mut session = Session::default();
+    loop {
+        let message = if session.running {
+            let remaining = session.count.map_or(usize::MAX, |count| count - session.found);
+            tokio::select! {
+                message = socket.recv() => message,
+                result = state.step(remaining, &session.filter, &mut session.tried, &mut session.attempts) => {
+                    let events = match result {
+                        Ok(events) => events,
+                        // 試し尽くした場合は止め、APIのエラーの場合は次の候補で続ける
+                        Err(e) => {
+                            if e.0 == StatusCode::NOT_FOUND {
+                                session.running = false;
+                            }
+                            if !send(&mut socket, &SessionReply::Error { message: e.1 }).await {
+                                return;
+                            }
+                            continue;
+                        }
+                    };
+                    for event in &events {
+                        if let RouletteEvent::Hit(_) = event {
+                            session.found += 1;
+                        }
+                        if !send(&mut socket, event).await {
+                            return;
+                        }
+                    }
+                    if session.count.is_some_and(|count| session.found >= count)
+                        && !send(&mut socket, &session.stop()).await
+                    {
+                        return;
+                    }
+                    continue;
+                }
+            }
+        } else {
+            socket.recv().await
+        };
+        let reply = match message {
+            Some(Ok(Message::Text(text))) => session.handle(&text),
+            Some(Ok(Message::Close(_))) | None => return,
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => {
+                debug!(error = %e, "websocket closed");
+                return;
+            }
+        };
+        if !send(&mut socket, &reply).await {
+            return;
+        }
+    }
+}
+
+/// WebSocketの接続を受け付ける
+/// クライアントはSessionCommandを送り、SessionReplyとRouletteEventを受け取る
+#[utoipa::path(
+    get,
+    path = "/ws",
+    responses(
+        (status = 101, description = "WebSocketに切り替えた"),
+        (status = 429, description = "リクエストが多すぎる(Retry-Afterの秒数の後に再試行する)", body = ErrorResponse),
+    ),
+)]
+async fn websocket(State(state): State<Arc<AppState>>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| session(state, socket))
+}
+
+/// /healthzと/readyzの応答
+#[derive(Debug, Serialize, ToSchema)]
+struct HealthResponse {
+    /// ok、ready、unavailableのいずれか
+    status: &'static str,
+    /// 出版社一覧の出版社の数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publishers: Option<usize>,
+    /// 書誌情報のAPIに問い合わせられなかった場合のエラー
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// サーバーが動いているか(リクエストに応えられれば常に200)
+#[utoipa::path(get, path = "/healthz", responses((status = 200, description = "動いている", body = HealthResponse)))]
+async fn healthz() -> Json<HealthResponse> {
+    Json(HealthResponse { status: "ok", publishers: None, error: None })
+}
+
+/// リクエストに応えられるか(出版社一覧を読み込めていて、書誌情報のAPIに問い合わせられるか)
+/// APIに問い合わせられるかは、READINESS_TTLの間は前に確かめた結果を使う
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "使える", body = HealthResponse),
+        (status = 503, description = "使えない", body = HealthResponse),
+    ),
+)]
+async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthResponse>) {
+    let publishers = publisher_list::registry().len();
+    let error = {
+        let mut readiness = state.readiness.lock().await;
+        match &*readiness {
+            Some((checked_at, error)) if checked_at.elapsed() < READINESS_TTL => error.clone(),
+            _ => {
+                let isbn = Isbn::parse(READINESS_ISBN).expect("valid isbn");
+                let error = state.provider.lookup(&isbn).await.err().map(|e| e.to_string());
+                if error.is_some() {
+                    state.metrics.provider_error();
+                }
+                *readiness = Some((Instant::now(), error.clone()));
+                error
+            }
+        }
+    };
+    let error = if publishers == 0 { Some(String::from("no publishers loaded")) } else { error };
+    let status = if error.is_none() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let health = HealthResponse {
+        status: if error.is_none() { "ready" } else { "unavailable" },
+        publishers: Some(publishers),
+        error,
+    };
+    (status, Json(health))
+}
+
+/// サーバーを動かしてからの計測値をPrometheusのテキスト形式で返す
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, description = "計測値", body = String, content_type = "text/plain")),
+)]
+async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], state.metrics.render())
+}
+
+/// リクエストごとに、応える(SSEとWebSocketは応答を始める)までの時間を計測する
+async fn track(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string()).unwrap_or_default();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    state.metrics.request(&method, &route, response.status().as_u16(), started.elapsed());
+    response
+}
+
+/// リクエストの数の制限を超えたクライアントには、受け付けられるようになるまでの秒数をRetry-Afterにつけて429を返す
+/// リバースプロキシの後ろで動かす場合は、プロキシのIPアドレスごとに制限することになる
+async fn throttle(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let ip = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+    match state.throttle.check(ip, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            debug!(?ip, ?wait, "too many requests");
+            too_many_requests(wait)
+        }
+    }
+}
+
+/// 429の応答(Retry-Afterは切り上げた秒数)
+fn too_many_requests(wait: Duration) -> Response {
+    let seconds = (wait.as_secs() + u64::from(wait.subsec_nanos() > 0)).max(1);
+    let mut response = ApiError(StatusCode::TOO_MANY_REQUESTS, String::from("too many requests")).into_response();
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+/// APIの仕様
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "isbn", description = "ランダムなISBNで実在する本を探すAPI"),
+    paths(random, lookup, validate, convert, events, websocket, metrics, healthz, readyz),
+    components(schemas(SessionCommand, SessionReply)),
+)]
+struct ApiDoc;
+
+/// 仕様のOpenAPIの文書
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// /openapi.jsonを読み込むSwagger UIのページ(Swagger UIはCDNから読み込む)
+const DOCS: &str = r##"<!DOCTYPE html>
+<html lang="ja">
+<head>
+  <meta charset="utf-8">
+  <title>isbn API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
+
+/// Swagger UIでAPIの仕様を読む
+async fn docs() -> Html<&'static str> {
+    Html(DOCS)
+}
+
+/// APIのルーティング
+/// 仕様、計測値と、動いているかを確かめるルートは数を制限しない
+fn router(state: Arc<AppState>) -> Router {
+    let api = Router::new()
+        .route("/random", get(random))
+        .route("/lookup/{isbn}", get(lookup))
+        .route("/validate/{isbn}", get(validate))
+        .route("/convert/{isbn}", get(convert))
+        .route("/events", get(events))
+        .route("/ws", get(websocket))
+        .route_layer(middleware::from_fn_with_state(state.clone(), throttle));
+    Router::new()
+        .merge(api)
+        .route("/openapi.json", get(openapi))
+        .route("/docs", get(docs))
+        .route("/metrics", get(metrics))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route_layer(middleware::from_fn_with_state(state.clone(), track))
+        .with_state(state)
+}
+
+/// addrで待ち受けて、Ctrl+Cで止めるまでリクエストに応える
+pub async fn serve(addr: SocketAddr, state: AppState) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, router(Arc::new(state)).into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use rand::SeedableRng;
+    use std::time::Duration;
+
+    use crate::provider::Result;
+
+    /// 決まったISBNだけ見つかるAPI
+    struct Fixed(Vec<&'static str>);
+
+    #[async_trait]
+    impl MetadataProvider for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        async fn lookup(&self, isbn: &Isbn) -> Result<Option<BookRecord>> {
+            let isbn_13 = isbn.create_isbn_13();
+            Ok(self.0.contains(&isbn_13.as_str()).then(|| BookRecord { isbn: isbn_13, ..Default::default() }))
+        }
+    }
+
+    /// 必ずエラーになるAPI
+    struct Failing;
+
+    #[async_trait]
+    impl MetadataProvider for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn lookup(&self, _isbn: &Isbn) -> Result<Option<BookRecord>> {
+            Err("unavailable".into())
+        }
+    }
+
+    /// 決まった順番でISBNを生成する
+    fn state(isbns: &'static [&'static str], found: Vec<&'static str>, max_attempts: usize) -> Arc<AppState> {
+        let next = Mutex::new(isbns.iter());
+        Arc::new(AppState {
+            provider: Box::new(Fixed(found)),
+            generator: Box::new(move |_| next.lock().unwrap().next().map(|isbn| Isbn::parse(isbn).unwrap())),
+            rng: Mutex::new(StdRng::seed_from_u64(0)),
+            history: None,
+            max_attempts,
+            events: broadcast::Sender::new(EVENT_CAPACITY),
+            metrics: Metrics::default(),
+            readiness: Default::default(),
+            throttle: Throttle::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_random() {
+        let isbns = &["9784101092058", "9784101092058", "9784798171548"];
+        let Json(discovery) = random(State(state(isbns, vec!["9784798171548"], 10))).await.unwrap();
+        assert_eq!(discovery.isbn13, "9784798171548");
+        // 同じISBNは2回数えない
+        assert_eq!(discovery.attempts, 2);
+
+        let limited = state(isbns, vec!["9784798171548"], 1);
+        let mut receiver = limited.events.subscribe();
+        let e = random(State(limited)).await.unwrap_err();
+        assert_eq!(e.0, StatusCode::NOT_FOUND);
+        let event = serde_json::to_value(receiver.try_recv().unwrap()).unwrap();
+        let expected = serde_json::json!({
+            "type": "attempt",
+            "isbn13": "9784101092058",
+            "publisher_name": "新潮社",
+            "found": false,
+            "attempts": 1,
+        });
+        assert_eq!(event, expected);
+        assert!(receiver.try_recv().is_err());
+        let e = random(State(state(&[], vec![], 10))).await.unwrap_err();
+        assert_eq!(e.1, "no untried isbns left");
+    }
+
+    #[tokio::test]
+    async fn test_random_events() {
+        let state = state(&["9784798171548"], vec!["9784798171548"], 10);
+        let mut receiver = state.events.subscribe();
+        let Json(found) = random(State(state.clone())).await.unwrap();
+        let attempt = receiver.try_recv().unwrap();
+        assert_eq!(attempt.name(), "attempt");
+        let RouletteEvent::Hit(discovery) = receiver.try_recv().unwrap() else {
+            panic!("expected a hit");
+        };
+        assert_eq!(discovery.isbn13, found.isbn13);
+        let metrics = state.metrics.render();
+        assert!(metrics.contains("\nisbn_attempts_total 1\n"));
+        assert!(metrics.contains("\nisbn_hits_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_step_filter() {
+        let state = state(&["9784798171548"], vec!["9784798171548"], 10);
+        let filter = RecordFilter { ndc: Some(String::from("913")), ..Default::default() };
+        let (mut tried, mut attempts) = (HashSet::new(), 0);
+        let events = state.step(10, &filter, &mut tried, &mut attempts).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], RouletteEvent::Attempt { found: true, rejected: Some("ndc"), attempts: 1, .. }));
+        let e = state.step(10, &filter, &mut tried, &mut attempts).await.unwrap_err();
+        assert_eq!(e.0, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::default();
+        let reply = session.handle(r#"{"type": "filter", "ndc": "913", "languages": ["jpn"]}"#);
+        let languages = vec![String::from("jpn")];
+        let filter = RecordFilter { ndc: Some(String::from("913")), languages, ..Default::default() };
+        assert!(matches!(&reply, SessionReply::Filter(reply) if *reply == filter));
+        session.attempts = 5;
+        assert!(matches!(session.handle(r#"{"type": "start", "count": 3}"#), SessionReply::Started { count: Some(3) }));
+        // 探し始めるときに数え直すが、条件はそのまま使う
+        assert!(session.running);
+        assert_eq!((session.attempts, &session.filter), (0, &filter));
+        session.found = 1;
+        assert!(matches!(session.handle(r#"{"type": "stop"}"#), SessionReply::Stopped { found: 1, attempts: 0 }));
+        assert!(!session.running);
+        assert!(matches!(session.handle(r#"{"type": "start", "count": 0}"#), SessionReply::Error { .. }));
+        assert!(matches!(session.handle("start"), SessionReply::Error { .. }));
+        assert!(!session.running);
+    }
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let state = state(&[], vec!["9784798171548"], 10);
+        let Json(discovery) = lookup(State(state.clone()), Path(String::from("4798171549"))).await.unwrap();
+        assert_eq!(discovery.isbn13, "9784798171548");
+        let e = lookup(State(state.clone()), Path(String::from("9784101092058"))).await.unwrap_err();
+        assert_eq!(e.0, StatusCode::NOT_FOUND);
+        let e = lookup(State(state), Path(String::from("978479817154"))).await.unwrap_err();
+        assert_eq!(e.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let Json(response) = validate(Path(String::from("4798171549"))).await;
+        assert!(response.valid);
+        assert_eq!(response.hyphenated.as_deref(), Some("978-4-7981-7154-8"));
+        let Json(response) = validate(Path(String::from("9784798171549"))).await;
+        assert!(!response.valid);
+        assert!(response.reason.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let Json(health) = healthz().await;
+        assert_eq!(health.status, "ok");
+        let (status, Json(health)) = readyz(State(state(&[], vec![], 10))).await;
+        assert_eq!((status, health.status), (StatusCode::OK, "ready"));
+        assert!(health.publishers.is_some_and(|publishers| publishers > 0));
+
+        let mut failing = Arc::into_inner(state(&[], vec![], 10)).unwrap();
+        failing.provider = Box::new(Failing);
+        let failing = Arc::new(failing);
+        let (status, Json(health)) = readyz(State(failing.clone())).await;
+        assert_eq!((status, health.error.as_deref()), (StatusCode::SERVICE_UNAVAILABLE, Some("unavailable")));
+        assert!(failing.metrics.render().contains("\nisbn_provider_errors_total 1\n"));
+        // 確かめた結果は使い回す
+        let (status, _) = readyz(State(failing.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(failing.metrics.render().contains("\nisbn_provider_errors_total 1\n"));
+    }
+
+    #[test]
+    fn test_too_many_requests() {
+        let response = too_many_requests(Duration::from_millis(1500));
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+        // 1秒より短くても0にはしない
+        assert_eq!(too_many_requests(Duration::from_millis(10)).headers()[header::RETRY_AFTER], "1");
+        assert_eq!(too_many_requests(Duration::from_secs(3)).headers()[header::RETRY_AFTER], "3");
+    }
+
+    #[test]
+    fn test_openapi() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        for path in ["/random", "/lookup/{isbn}", "/validate/{isbn}", "/convert/{isbn}", "/events", "/ws"] {
+            assert!(paths.contains_key(path), "{}", path);
+        }
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        for schema in ["Discovery", "BookRecord", "RecordFilter", "RouletteEvent", "SessionCommand", "ErrorResponse"] {
+            assert!(schemas.contains_key(schema), "{}", schema);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_convert() {
+        let Json(response) = convert(Path(String::from("978-4-7981-7154-8"))).await.unwrap();
+        assert_eq!(response.isbn10.as_deref(), Some("4798171549"));
+        assert_eq!(response.isbn13, "9784798171548");
+        assert_eq!(response.hyphenated, "978-4-7981-7154-8");
+        assert!(convert(Path(String::from("abc"))).await.is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! serveサブコマンドの、クライアント(IPアドレス)ごとと全体のリクエストの数の制限
+//! トークンバケットで、1分あたりの平均の回数を超えないようにしながら、burst回までは続けてリクエストできる
+
+use std::collections::{BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv6Addr};
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// クライアントごとのバケットを持つ数の上限(超える場合は最後に使ったのが最も前のものを捨てる)
+const MAX_CLIENTS: usize = 1024;
+
+/// リクエストの数の制限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    /// 1分あたりのリクエストの数
+    pub per_minute: NonZeroU32,
+    /// 続けてリクエストできる数(バケットの大きさ)
+    pub burst: NonZeroU32,
+}
+
+impl Limit {
+    /// burstを省略した場合は1分間分にする
+    pub fn new(per_minute: NonZeroU32, burst: Option<NonZeroU32>) -> Limit {
+        Limit { per_minute, burst: burst.unwrap_or(per_minute) }
+    }
+
+    /// 1秒あたりに溜まるトークンの数
+    fn per_second(&self) -> f64 {
+        f64::from(self.per_minute.get()) / 60.0
+    }
+}
+
+/// トークンバケット
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &Limit, now: Instant) -> Bucket {
+        Bucket { tokens: f64::from(limit.burst.get()), updated: now }
+    }
+
+    /// nowまでに溜まったトークンを足す
+    fn refill(&mut self, limit: &Limit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second()).min(f64::from(limit.burst.get()));
+        self.updated = self.updated.max(now);
+    }
+
+    /// トークンが1つ溜まるまでの時間(すでに溜まっていればNone)
+    fn wait(&self, limit: &Limit) -> Option<Duration> {
+        (self.tokens < 1.0).then(|| Duration::from_secs_f64((1.0 - self.tokens) / limit.per_second()))
+    }
+
+}
+
+/// クライアントを分ける鍵
+/// IPv6は1つの回線に/64がまとめて割り当てられることが多いので、/64ごとにまとめる
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(Ipv6Addr::from(ip.to_bits() & !u128::from(u64::MAX))),
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// クライアントごとのバケット
+#[derive(Debug, Default)]
+struct Clients {
+    buckets: HashMap<IpAddr, Bucket>,
+    /// 最後に使った時刻の順のクライアント(捨てるものを選ぶのに使う)
+    order: BTreeSet<(Instant, IpAddr)>,
+}
+
+impl Clients {
+    /// クライアントのバケットをnowまで溜めて返す
+    /// 初めてのクライアントで上限に達している場合は、最後に使ったのが最も前のクライアントを捨てる
+    fn bucket(&mut self, limit: &Limit, key: IpAddr, now: Instant) -> &mut Bucket {
+        match self.buckets.get(&key) {
+            Some(bucket) => {
+                self.order.remove(&(bucket.updated, key));
+            }
+            None => {
+                if self.buckets.len() >= MAX_CLIENTS {
+                    if let Some((_, oldest)) = self.order.pop_first() {
+                        self.buckets.remove(&oldest);
+                    }
+                }
+            }
+        }
+        let bucket = self.buckets.entry(key).or_insert_with(|| Bucket::full(limit, now));
+        bucket.refill(limit, now);
+        self.order.insert((bucket.updated, key));
+        bucket
+    }
+}
+
+/// クライアントごとと全体のリクエストの数の制限(どちらも省略できる)
+#[derive(Debug, Default)]
+pub struct Throttle {
+    per_ip: Option<(Limit, Mutex<Clients>)>,
+    global: Option<(Limit, Mutex<Bucket>)>,
+}
+
+impl Throttle {
+    pub fn new(per_ip: Option<Limit>, global: Option<Limit>) -> Throttle {
+        let now = Instant::now();
+        Throttle {
+            per_ip: per_ip.map(|limit| (limit, Mutex::new(Clients::default()))),
+            global: global.map(|limit| (limit, Mutex::new(Bucket::full(&limit, now)))),
+        }
+    }
+
+    /// nowのリクエストを受け付けられればトークンを使う
+    /// どちらかの制限を超える場合はトークンを使わずに、受け付けられるようになるまでの時間を返す
+    /// クライアントのIPアドレスがわからない場合は全体の制限だけを確かめる
+    /// クライアントのバケットはIPアドレス(IPv6は/64)ごとに分け、上限の数までしか持たない
+    pub fn check(&self, ip: Option<IpAddr>, now: Instant) -> Result<(), Duration> {
+        let mut clients = self.per_ip.as_ref().map(|(limit, clients)| (limit, clients.lock().expect("throttle lock")));
+        let client = match (&mut clients, ip) {
+            (Some((limit, clients)), Some(ip)) => Some((*limit, clients.bucket(limit, client_key(ip), now))),
+            _ => None,
+        };
+        let mut global = self.global.as_ref().map(|(limit, bucket)| (limit, bucket.lock().expect("throttle lock")));
+        if let Some((limit, bucket)) = &mut global {
+            bucket.refill(limit, now);
+        }
+        let client_wait = client.as_ref().and_then(|(limit, bucket)| bucket.wait(limit));
+        let global_wait = global.as_ref().and_then(|(limit, bucket)| bucket.wait(limit));
+        if let Some(wait) = client_wait.max(global_wait) {
+            return Err(wait);
+        }
+        if let Some((_, bucket)) = client {
+            bucket.tokens -= 1.0;
+        }
+        if let Some((_, bucket)) = &mut global {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(per_minute: u32, burst: u32) -> Limit {
+        Limit::new(NonZeroU32::new(per_minute).unwrap(), NonZeroU32::new(burst))
+    }
+
+    #[test]
+    fn test_limit_new() {
+        assert_eq!(Limit::new(NonZeroU32::new(30).unwrap(), None), limit(30, 30));
+    }
+
+    #[test]
+    fn test_per_ip() {
+        let throttle = Throttle::new(Some(limit(60, 2)), None);
+        let now = Instant::now();
+        let a = Some(IpAddr::from([192, 0, 2, 1]));
+        let b = Some(IpAddr::from([192, 0, 2, 2]));
+        assert_eq!(throttle.check(a, now), Ok(()));
+        assert_eq!(throttle.check(a, now), Ok(()));
+        // 1分に60回なので、1秒で1つ溜まる
+        assert_eq!(throttle.check(a, now), Err(Duration::from_secs(1)));
+        assert_eq!(throttle.check(b, now), Ok(()));
+        assert_eq!(throttle.check(a, now + Duration::from_millis(500)), Err(Duration::from_millis(500)));
+        assert_eq!(throttle.check(a, now + Duration::from_secs(1)), Ok(()));
+        // IPアドレスがわからなければ制限しない
+        assert_eq!(throttle.check(None, now), Ok(()));
+    }
+
+    #[test]
+    fn test_global() {
+        let throttle = Throttle::new(Some(limit(60, 2)), Some(limit(120, 3)));
+        let now = Instant::now();
+        let a = Some(IpAddr::from([192, 0, 2, 1]));
+        let b = Some(IpAddr::from([192, 0, 2, 2]));
+        assert_eq!(throttle.check(a, now), Ok(()));
+        assert_eq!(throttle.check(a, now), Ok(()));
+        assert_eq!(throttle.check(b, now), Ok(()));
+        assert_eq!(throttle.check(b, now), Err(Duration::from_millis(500)));
+        assert_eq!(throttle.check(None, now), Err(Duration::from_millis(500)));
+        // 全体の制限で断った場合は、クライアントのトークンを使わない
+        let later = now + Duration::from_millis(500);
+        assert_eq!(throttle.check(b, later), Ok(()));
+        assert_eq!(throttle.check(a, later), Err(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_forget_clients() {
+        // トークンを使ったばかりのバケットばかりでも、上限より多くは持たない
+        let throttle = Throttle::new(Some(limit(60, 2)), None);
+        let now = Instant::now();
+        let ip = |i: usize| Some(IpAddr::from((i as u32).to_be_bytes()));
+        for i in 0..MAX_CLIENTS * 2 {
+            assert_eq!(throttle.check(ip(i), now + Duration::from_millis(i as u64)), Ok(()));
+        }
+        let (_, clients) = throttle.per_ip.as_ref().unwrap();
+        {
+            let clients = clients.lock().unwrap();
+            assert_eq!(clients.buckets.len(), MAX_CLIENTS);
+            assert_eq!(clients.order.len(), MAX_CLIENTS);
+            // 最後に使ったのが前のものから捨てる
+            assert!(!clients.buckets.contains_key(&ip(MAX_CLIENTS - 1).unwrap()));
+            assert!(clients.buckets.contains_key(&ip(MAX_CLIENTS).unwrap()));
+        }
+        // 使い続けているクライアントは捨てない
+        let later = now + Duration::from_secs(10);
+        assert_eq!(throttle.check(ip(MAX_CLIENTS), later), Ok(()));
+        assert_eq!(throttle.check(ip(0), later), Ok(()));
+        let clients = clients.lock().unwrap();
+        assert_eq!(clients.buckets.len(), MAX_CLIENTS);
+        assert!(clients.buckets.contains_key(&ip(MAX_CLIENTS).unwrap()));
+        assert!(!clients.buckets.contains_key(&ip(MAX_CLIENTS + 1).unwrap()));
+    }
+
+    #[test]
+    fn test_client_key() {
+        let throttle = Throttle::new(Some(limit(60, 1)), None);
+        let now = Instant::now();
+        // 同じ/64のIPv6アドレスは1つのクライアントとして数える
+        let a: IpAddr = "2001:db8:1:2::1".parse().unwrap();
+        let b: IpAddr = "2001:db8:1:2:ffff::2".parse().unwrap();
+        let c: IpAddr = "2001:db8:1:3::1".parse().unwrap();
+        assert_eq!(throttle.check(Some(a), now), Ok(()));
+        assert!(throttle.check(Some(b), now).is_err());
+        assert_eq!(throttle.check(Some(c), now), Ok(()));
+        let mapped: IpAddr = "::ffff:192.0.2.1".parse().unwrap();
+        assert_eq!(client_key(mapped), IpAddr::from([192, 0, 2, 1]));
+    }
+}
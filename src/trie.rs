@@ -0,0 +1,80 @@
+/// 数字の記号(出版社記号など)を1桁ずつたどる木
+/// 国記号に続く数字列から、どこまでが登録された記号かを求めるのに使う
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PrefixTrie {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    children: [Option<usize>; 10],
+    /// この節点で終わる記号の番号
+    value: Option<usize>,
+}
+
+impl PrefixTrie {
+    /// 記号の並びから作る
+    /// 数字以外を含む記号は無視し、同じ記号が複数ある場合は最初のものを使う
+    pub(crate) fn new<'a>(codes: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut trie = PrefixTrie { nodes: vec![Node::default()] };
+        for (index, code) in codes.into_iter().enumerate() {
+            trie.insert(code, index);
+        }
+        trie
+    }
+
+    fn insert(&mut self, code: &str, value: usize) {
+        if code.is_empty() || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return;
+        }
+        let mut node = 0;
+        for b in code.bytes() {
+            let digit = usize::from(b - b'0');
+            node = match self.nodes[node].children[digit] {
+                Some(child) => child,
+                None => {
+                    self.nodes.push(Node::default());
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[digit] = Some(child);
+                    child
+                }
+            };
+        }
+        self.nodes[node].value.get_or_insert(value);
+    }
+
+    /// 数字列の先頭に一致する記号のうち最も長いものの、番号と桁数
+    pub(crate) fn longest_prefix(&self, digits: &str) -> Option<(usize, usize)> {
+        let mut node = 0;
+        let mut found = None;
+        for (length, b) in digits.bytes().enumerate() {
+            if !b.is_ascii_digit() {
+                break;
+            }
+            let Some(child) = self.nodes.get(node).and_then(|node| node.children[usize::from(b - b'0')]) else {
+                break;
+            };
+            node = child;
+            if let Some(value) = self.nodes[node].value {
+                found = Some((value, length + 1));
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix() {
+        let trie = PrefixTrie::new(["10", "7981", "798", "254", "x1", "10"]);
+        assert_eq!(trie.longest_prefix("101092052"), Some((0, 2)));
+        assert_eq!(trie.longest_prefix("798171549"), Some((1, 4)));
+        assert_eq!(trie.longest_prefix("798071549"), Some((2, 3)));
+        assert_eq!(trie.longest_prefix("25"), None);
+        assert_eq!(trie.longest_prefix("x1"), None);
+        assert_eq!(PrefixTrie::default().longest_prefix("10"), None);
+    }
+}
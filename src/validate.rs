@@ -0,0 +1,79 @@
+use crate::isbn::{check_digit_10, check_digit_13, ParseIsbnError};
+
+/// チェックディジットの検証結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    /// 正しいISBN
+    Valid,
+    /// 桁数が10桁でも13桁でもない
+    BadLength(usize),
+    /// 数字(とISBN10末尾のX)以外の文字が含まれている
+    BadCharacter(char),
+    /// チェックディジットが一致しない
+    WrongCheckDigit { expected: char, found: char },
+}
+
+/// ハイフンなしのISBN10またはISBN13のチェックディジットを検証する
+/// 桁数でISBN10かISBN13かを判別する
+pub fn verify_check_digit(isbn: &str) -> Validation {
+    let length = isbn.chars().count();
+    if length != 10 && length != 13 {
+        return Validation::BadLength(length);
+    }
+    for (i, c) in isbn.chars().enumerate() {
+        // ISBN10の最後の桁だけはXを許容する
+        let is_check_digit_x = length == 10 && i == 9 && (c == 'X' || c == 'x');
+        if !c.is_ascii_digit() && !is_check_digit_x {
+            return Validation::BadCharacter(c);
+        }
+    }
+
+    // 数字とXだけであることを確かめたので、バイト単位で分割できる
+    let (body, check_digit) = isbn.split_at(length - 1);
+    let expected = if length == 10 { check_digit_10(body) } else { check_digit_13(body) };
+    let found = char::from(check_digit.as_bytes()[0]).to_ascii_uppercase();
+    if expected == found {
+        Validation::Valid
+    } else {
+        Validation::WrongCheckDigit { expected, found }
+    }
+}
+
+/// チェックディジットを検証し、正しくなければ解析エラーとして返す
+pub(crate) fn check(isbn: &str) -> Result<(), ParseIsbnError> {
+    match verify_check_digit(isbn) {
+        Validation::Valid => Ok(()),
+        Validation::BadLength(length) => Err(ParseIsbnError::InvalidLength(length)),
+        Validation::BadCharacter(c) => Err(ParseIsbnError::InvalidCharacter(c)),
+        Validation::WrongCheckDigit { expected, found } => {
+            Err(ParseIsbnError::InvalidCheckDigit { expected: expected.to_string(), found: found.to_string() })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_check_digit() {
+        assert_eq!(verify_check_digit("9784798171548"), Validation::Valid);
+        assert_eq!(verify_check_digit("4101092052"), Validation::Valid);
+        // 小文字のxも許容する
+        assert_eq!(verify_check_digit("406202952x"), verify_check_digit("406202952X"));
+    }
+
+    #[test]
+    fn test_verify_check_digit_error() {
+        assert_eq!(verify_check_digit("97847981715"), Validation::BadLength(11));
+        assert_eq!(verify_check_digit("97847981X1548"), Validation::BadCharacter('X'));
+        assert_eq!(
+            verify_check_digit("9784798171540"),
+            Validation::WrongCheckDigit { expected: '8', found: '0' }
+        );
+        assert_eq!(
+            verify_check_digit("4101092050"),
+            Validation::WrongCheckDigit { expected: '2', found: '0' }
+        );
+    }
+}